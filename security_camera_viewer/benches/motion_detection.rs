@@ -0,0 +1,72 @@
+//! Per-frame cost of motion detection on VGA frames
+//!
+//! Run with `cargo bench --bench motion_detection`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use image::{Rgba, RgbaImage};
+use security_camera_viewer::motion_detector::{MotionDetectionConfig, MotionDetector};
+
+/// Textured VGA frame with a bright square that moves 8 px per frame
+fn vga_frame(frame: u32) -> RgbaImage {
+    RgbaImage::from_fn(640, 480, |x, y| {
+        let offset = (frame * 8) % 400;
+        if (120 + offset..200 + offset).contains(&x) && (200..280).contains(&y) {
+            Rgba([230, 230, 230, 255])
+        } else {
+            let v = (60 + (x * 7 + y * 13) % 80) as u8;
+            Rgba([v, v / 2 + 40, v, 255])
+        }
+    })
+}
+
+fn encode_jpeg(image: &RgbaImage) -> Vec<u8> {
+    let rgb = image::DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85).encode_image(&rgb).unwrap();
+    jpeg
+}
+
+fn detector(downscale: u32) -> MotionDetector {
+    MotionDetector::new(MotionDetectionConfig { enabled: true, downscale, ..MotionDetectionConfig::default() })
+}
+
+fn bench_rgba(c: &mut Criterion) {
+    let frames: Vec<RgbaImage> = (0..8).map(vga_frame).collect();
+    let mut group = c.benchmark_group("vga_rgba");
+    for downscale in [1, 2, 4, 8] {
+        let mut detector = detector(downscale);
+        let mut i = 0;
+        group.bench_function(format!("downscale_{}", downscale), |b| b.iter(|| {
+            i = (i + 1) % frames.len();
+            black_box(detector.detect(&frames[i]))
+        }));
+    }
+    group.finish();
+}
+
+fn bench_jpeg(c: &mut Criterion) {
+    let frames: Vec<Vec<u8>> = (0..8).map(|frame| encode_jpeg(&vga_frame(frame))).collect();
+    let mut group = c.benchmark_group("vga_jpeg");
+
+    // Full decode to RGBA, then detection at full resolution (the previous CLI path)
+    let mut full = detector(1);
+    let mut i = 0;
+    group.bench_function("full_decode", |b| b.iter(|| {
+        i = (i + 1) % frames.len();
+        let image = image::load_from_memory(&frames[i]).unwrap().to_rgba8();
+        black_box(full.detect(&image))
+    }));
+
+    for downscale in [1, 8] {
+        let mut detector = detector(downscale);
+        let mut i = 0;
+        group.bench_function(format!("scaled_decode_{}", downscale), |b| b.iter(|| {
+            i = (i + 1) % frames.len();
+            black_box(detector.detect_jpeg(&frames[i]).unwrap())
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_rgba, bench_jpeg);
+criterion_main!(benches);
@@ -0,0 +1,550 @@
+//! 古い録画のアーカイブ変換
+//!
+//! 指定日数より前に終了したMJPEG録画（1フレーム約50KB）を、よりコンパクトな形式に変換する。
+//!
+//! - MJPEG（再量子化）: 各フレームを低い品質でJPEGに再エンコードする。インデックスの撮影時刻はそのまま
+//! - MP4: 録画と同じ`RecordingWriter`でエンコードする。撮影間隔の空白（間引き・欠落）は
+//!   直前のフレームを繰り返して埋め、再生時間を実時間に合わせる
+//!
+//! 変換結果は一時ファイルに書き、フレーム数とインデックスを検証してから元の録画と置き換え、
+//! カタログに`recording_archived`を記録する。元より小さくならなかった場合は元の録画を残す。
+//! ライブ映像の受信を妨げないように、処理するフレーム数を毎秒`max_fps`までに抑える。
+//! 暗号化された録画は同じ鍵で暗号化したまま変換する（鍵がなければ変換しない）。
+
+use crate::catalog::{Catalog, RecordingEntry};
+use crate::crypto::{self, KeySource};
+use crate::manifest;
+use crate::metadata;
+use crate::mjpeg::{self, IndexEntry, MjpegWriter};
+use crate::mp4_recorder::Mp4EncoderConfig;
+use crate::recording::{RecordingFormat, RecordingWriter};
+use crate::recovery;
+use chrono::{DateTime, Duration, Local};
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, ImageFormat};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+
+/// インデックスがない録画のフレームレート（GUIの録画と同じ）
+const FALLBACK_FPS: u32 = 11;
+
+/// アーカイブ設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveConfig {
+    /// これより前（日数）に終了した録画を変換する
+    pub older_than_days: u32,
+    /// 変換後の形式
+    pub format: RecordingFormat,
+    /// MJPEGに再エンコードする場合のJPEG品質（1-100）
+    pub jpeg_quality: u8,
+    /// MP4に変換する場合のエンコード設定
+    pub mp4_config: Mp4EncoderConfig,
+    /// 1秒あたりに処理するフレーム数の上限（0なら制限なし）
+    pub max_fps: u32,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            older_than_days: 30,
+            format: RecordingFormat::Mjpeg,
+            jpeg_quality: 60,
+            mp4_config: Mp4EncoderConfig::default(),
+            max_fps: 30,
+        }
+    }
+}
+
+/// 変換結果（1録画分）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedRecording {
+    /// 元の録画ファイル
+    pub original: PathBuf,
+    /// 変換後のファイル（置き換えなかった場合は元のファイル）
+    pub path: PathBuf,
+    pub format: RecordingFormat,
+    pub frame_count: u64,
+    pub original_bytes: u64,
+    pub size_bytes: u64,
+    /// 元の録画を置き換えた（falseなら変換しても小さくならなかった）
+    pub replaced: bool,
+}
+
+/// 変換対象の録画（終了済み・未変換のMJPEGで、`now`の`older_than_days`日前より前に終了したもの）
+pub fn archive_candidates(catalog: &Catalog, older_than_days: u32, now: DateTime<Local>) -> io::Result<Vec<RecordingEntry>> {
+    let cutoff = now - Duration::days(older_than_days as i64);
+    Ok(catalog.recordings()?
+        .into_iter()
+        .filter(|entry| entry.format == "mjpeg" && entry.archived_at.is_none())
+        .filter(|entry| entry.ended_at.is_some_and(|ended| ended < cutoff))
+        .filter(|entry| entry.path.exists())
+        .collect())
+}
+
+/// 古い録画をまとめて変換し、カタログを更新する
+///
+/// 1件の変換に失敗しても残りの録画は続けて変換する。`cancel`が立つと処理中の変換を中止して戻る。
+/// 変換後のファイルへの署名（マニフェスト）は呼び出し側で行う。
+///
+/// # Errors
+/// - カタログを読み書きできない場合
+pub fn archive_old_recordings(
+    catalog: &Catalog,
+    config: &ArchiveConfig,
+    key: Option<&KeySource>,
+    cancel: &AtomicBool,
+) -> io::Result<Vec<ArchivedRecording>> {
+    let mut archived = Vec::new();
+
+    for entry in archive_candidates(catalog, config.older_than_days, Local::now())? {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let result = match archive_recording(&entry, config, key, cancel) {
+            Ok(result) => result,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => break,
+            Err(e) => {
+                warn!("Archiving {:?} failed: {}", entry.path, e);
+                continue;
+            }
+        };
+
+        // 置き換えなかった録画も記録して、次回から対象にしない
+        let format = if result.replaced { result.format.extension() } else { entry.format.as_str() };
+        catalog.recording_archived(&entry.id, &result.path, format, result.size_bytes)?;
+
+        info!("Archived {:?} -> {:?}: {} frames, {:.2} MB -> {:.2} MB",
+              result.original, result.path, result.frame_count,
+              result.original_bytes as f64 / 1_048_576.0, result.size_bytes as f64 / 1_048_576.0);
+        archived.push(result);
+    }
+
+    Ok(archived)
+}
+
+/// MJPEG録画を1件変換する（カタログは更新しない）
+///
+/// # Errors
+/// - 録画が暗号化されていて鍵がない場合
+/// - 変換後のファイルの検証に失敗した場合（元の録画はそのまま残る）
+/// - `cancel`が立った場合（`ErrorKind::Interrupted`）
+pub fn archive_recording(
+    entry: &RecordingEntry,
+    config: &ArchiveConfig,
+    key: Option<&KeySource>,
+    cancel: &AtomicBool,
+) -> io::Result<ArchivedRecording> {
+    let original = entry.path.clone();
+    let original_bytes = fs::metadata(&original)?.len();
+    // 暗号化された録画は同じ鍵で暗号化したまま変換する
+    let key = if crypto::is_encrypted(&original)? {
+        Some(key.ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "recording is encrypted, a key is required"))?)
+    } else {
+        None
+    };
+
+    let target = original.with_extension(config.format.extension());
+    if target != original && target.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} already exists", target)));
+    }
+    let mut temp_name = target.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".archiving");
+    let temp = target.with_file_name(temp_name);
+
+    let result = match config.format {
+        RecordingFormat::Mjpeg => requantize_mjpeg(&original, &temp, config, key, cancel),
+        RecordingFormat::Mp4 => transcode_mp4(&original, &temp, config, key, cancel),
+    };
+    let frame_count = match result {
+        Ok(frame_count) => frame_count,
+        Err(e) => {
+            remove_temp(&temp);
+            return Err(e);
+        }
+    };
+
+    let size_bytes = fs::metadata(&temp)?.len();
+    if size_bytes >= original_bytes {
+        info!("Archived copy of {:?} is not smaller ({} >= {} bytes), keeping the original", original, size_bytes, original_bytes);
+        remove_temp(&temp);
+        return Ok(ArchivedRecording {
+            path: original.clone(),
+            original,
+            format: RecordingFormat::Mjpeg,
+            frame_count,
+            original_bytes,
+            size_bytes: original_bytes,
+            replaced: false,
+        });
+    }
+
+    replace_recording(&original, &temp, &target, config.format)?;
+    Ok(ArchivedRecording {
+        original,
+        path: target,
+        format: config.format,
+        frame_count,
+        original_bytes,
+        size_bytes,
+        replaced: true,
+    })
+}
+
+/// フレームを再エンコードしてMJPEGに書き、検証する
+fn requantize_mjpeg(
+    original: &Path,
+    temp: &Path,
+    config: &ArchiveConfig,
+    key: Option<&KeySource>,
+    cancel: &AtomicBool,
+) -> io::Result<u64> {
+    let index = read_capture_times(original)?;
+    let fallback_start = crate::clip_export::recording_start(original).unwrap_or_else(Local::now);
+    let mut writer = MjpegWriter::create_with_key(temp, key)?;
+    let mut throttle = Throttle::new(config.max_fps);
+    let mut frame_no = 0u64;
+
+    mjpeg::for_each_frame(crypto::open_recording(original, key)?, |span, jpeg| {
+        check_cancel(cancel)?;
+        throttle.wait();
+        let captured_at = index.as_ref()
+            .and_then(|index| index.get(&span.offset).copied())
+            .unwrap_or_else(|| fallback_start + Duration::milliseconds((frame_no * 1000 / FALLBACK_FPS as u64) as i64));
+        frame_no += 1;
+
+        let requantized = reencode_jpeg(jpeg, config.jpeg_quality)?;
+        // 再エンコードで大きくなるフレーム（元から低品質など）はそのまま残す
+        let frame = if requantized.len() < jpeg.len() { &requantized[..] } else { jpeg };
+        writer.write_frame_at(frame, captured_at)
+    })?;
+    writer.finish()?;
+
+    // 全フレームが書かれ、インデックスがファイル内のフレーム位置と一致すること
+    let written = mjpeg::scan_frames(crypto::open_recording(temp, key)?)?;
+    let spans: Vec<_> = mjpeg::read_index(&mjpeg::index_path(temp))?.iter().map(|entry| entry.span).collect();
+    if written.frames.len() as u64 != frame_no || spans != written.frames || written.valid_len != written.total_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+            "archived copy has {} frames and {} index entries, expected {}", written.frames.len(), spans.len(), frame_no
+        )));
+    }
+    Ok(frame_no)
+}
+
+/// フレームをMP4にエンコードし、検証する
+fn transcode_mp4(
+    original: &Path,
+    temp: &Path,
+    config: &ArchiveConfig,
+    key: Option<&KeySource>,
+    cancel: &AtomicBool,
+) -> io::Result<u64> {
+    let index = read_capture_times(original)?;
+    let fps = index.as_ref().map_or(FALLBACK_FPS, |index| typical_fps(index.values().copied().collect()));
+    let frame_ms = 1000 / fps as i64;
+    let mut writer = RecordingWriter::create(temp, RecordingFormat::Mp4, fps, &config.mp4_config, key.cloned())?;
+    let mut throttle = Throttle::new(config.max_fps);
+    let mut first_at = None;
+    let mut written = 0u64;
+
+    let scan = mjpeg::for_each_frame(crypto::open_recording(original, key)?, |span, jpeg| {
+        check_cancel(cancel)?;
+        throttle.wait();
+        // 撮影時刻の空白は直前のフレームで埋める
+        if let Some(captured_at) = index.as_ref().and_then(|index| index.get(&span.offset).copied()) {
+            let start = *first_at.get_or_insert(captured_at);
+            let due = ((captured_at - start).num_milliseconds() / frame_ms) as u64;
+            while written > 0 && written < due {
+                writer.skip_frame()?;
+                written += 1;
+            }
+        }
+        writer.write_frame(jpeg)?;
+        written += 1;
+        Ok(())
+    });
+    let segments = match scan {
+        Ok(_) => writer.finish()?,
+        Err(e) => {
+            let _ = writer.finish();
+            return Err(e);
+        }
+    };
+    if !segments.is_empty() {
+        for segment in &segments {
+            remove_temp(segment);
+        }
+        return Err(io::Error::other("ffmpeg was restarted during the conversion"));
+    }
+
+    // 全フレームが完全なフラグメントに入っていること
+    let (valid_len, samples, total_len) = recovery::scan_fragmented_mp4(crypto::open_recording(temp, key)?)?;
+    if samples != written || valid_len != total_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+            "archived MP4 has {} frames, expected {}", samples, written
+        )));
+    }
+    Ok(written)
+}
+
+/// 変換結果で元の録画を置き換える
+///
+/// 元のマニフェストは内容と合わなくなるため削除する（呼び出し側で署名し直す）。
+fn replace_recording(original: &Path, temp: &Path, target: &Path, format: RecordingFormat) -> io::Result<()> {
+    fs::rename(temp, target)?;
+    match format {
+        RecordingFormat::Mjpeg => fs::rename(mjpeg::index_path(temp), mjpeg::index_path(target))?,
+        RecordingFormat::Mp4 => {
+            fs::remove_file(original)?;
+            remove_if_exists(&mjpeg::index_path(original))?;
+        }
+    }
+
+    remove_if_exists(&manifest::manifest_path(original))?;
+    let sidecar = metadata::sidecar_path(original);
+    if target != original && sidecar.exists() {
+        fs::rename(&sidecar, metadata::sidecar_path(target))?;
+    }
+    Ok(())
+}
+
+/// インデックスのフレーム位置→撮影時刻（インデックスがなければNone）
+fn read_capture_times(recording: &Path) -> io::Result<Option<HashMap<u64, DateTime<Local>>>> {
+    match mjpeg::read_index(&mjpeg::index_path(recording)) {
+        Ok(entries) => Ok(Some(entries.into_iter().map(|IndexEntry { captured_at, span }| (span.offset, captured_at)).collect())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 撮影間隔の中央値から求めたフレームレート（間引き・欠落による長い間隔に左右されない）
+fn typical_fps(mut times: Vec<DateTime<Local>>) -> u32 {
+    times.sort();
+    let mut intervals: Vec<i64> = times.windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_milliseconds())
+        .filter(|&ms| ms > 0)
+        .collect();
+    if intervals.is_empty() {
+        return FALLBACK_FPS;
+    }
+    intervals.sort_unstable();
+    let median = intervals[intervals.len() / 2];
+    ((1000.0 / median as f64).round() as u32).clamp(1, 60)
+}
+
+fn reencode_jpeg(jpeg: &[u8], quality: u8) -> io::Result<Vec<u8>> {
+    let image = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .to_rgb8();
+    let mut output = Vec::with_capacity(jpeg.len() / 2);
+    JpegEncoder::new_with_quality(&mut output, quality.clamp(1, 100))
+        .encode(&image, image.width(), image.height(), ColorType::Rgb8)
+        .map_err(io::Error::other)?;
+    Ok(output)
+}
+
+fn check_cancel(cancel: &AtomicBool) -> io::Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        Err(io::Error::new(io::ErrorKind::Interrupted, "archiving cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 変換途中の一時ファイル（とインデックス）を削除する
+fn remove_temp(path: &Path) {
+    for file in [path.to_path_buf(), mjpeg::index_path(path)] {
+        if let Err(e) = remove_if_exists(&file) {
+            warn!("Failed to remove {:?}: {}", file, e);
+        }
+    }
+}
+
+/// 処理するフレーム数を毎秒`max_fps`までに抑える
+struct Throttle {
+    interval: Option<std::time::Duration>,
+    next: Instant,
+}
+
+impl Throttle {
+    fn new(max_fps: u32) -> Self {
+        Self {
+            interval: (max_fps > 0).then(|| std::time::Duration::from_secs(1) / max_fps),
+            next: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self) {
+        let Some(interval) = self.interval else { return };
+        let now = Instant::now();
+        if self.next > now {
+            thread::sleep(self.next - now);
+        }
+        self.next = self.next.max(now) + interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{CatalogRecord, RecordingTrigger};
+    use chrono::TimeZone;
+    use image::{Rgb, RgbImage};
+
+    fn at(day: u32, ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, day, 12, 0, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    /// 細かい模様の高品質JPEG（再量子化で小さくなる）
+    fn frame(seed: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(160, 120, |x, y| {
+            let v = ((x * 31 + y * 17 + seed * 7) % 251) as u8;
+            Rgb([v, v.wrapping_mul(3), 255 - v])
+        });
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 95).encode(&image, 160, 120, ColorType::Rgb8).unwrap();
+        jpeg
+    }
+
+    /// 録画ファイルを作り、カタログに`day`日に終了した録画として記録する
+    fn record(catalog: &Catalog, path: &Path, day: u32, key: Option<&KeySource>) -> Vec<DateTime<Local>> {
+        let times: Vec<_> = [0, 91, 182, 2000, 2091].iter().map(|&ms| at(day, ms)).collect();
+        let mut writer = MjpegWriter::create_with_key(path, key).unwrap();
+        for (i, t) in times.iter().enumerate() {
+            writer.write_frame_at(&frame(i as u32), *t).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let id = crate::catalog::recording_id(path);
+        catalog.append(&CatalogRecord::RecordingStarted {
+            id: id.clone(),
+            path: path.to_path_buf(),
+            trigger: RecordingTrigger::Manual,
+            format: "mjpeg".to_string(),
+            started_at: times[0],
+        }).unwrap();
+        catalog.append(&CatalogRecord::RecordingFinished {
+            id,
+            ended_at: *times.last().unwrap(),
+            frame_count: times.len() as u64,
+            size_bytes: fs::metadata(path).unwrap().len(),
+            recovered: false,
+            segments: Vec::new(),
+        }).unwrap();
+        times
+    }
+
+    fn config() -> ArchiveConfig {
+        ArchiveConfig { older_than_days: 7, max_fps: 0, ..ArchiveConfig::default() }
+    }
+
+    #[test]
+    fn test_requantize_old_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let old = dir.path().join("manual_old.mjpeg");
+        let recent = dir.path().join("manual_recent.mjpeg");
+        let times = record(&catalog, &old, 1, None);
+        record(&catalog, &recent, 20, None);
+        fs::write(manifest::manifest_path(&old), "{}").unwrap();
+        let old_bytes = fs::metadata(&old).unwrap().len();
+
+        let now = at(21, 0);
+        let candidates = archive_candidates(&catalog, 7, now).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].path, old);
+
+        let result = archive_recording(&candidates[0], &config(), None, &AtomicBool::new(false)).unwrap();
+        assert!(result.replaced);
+        assert_eq!(result.path, old);
+        assert_eq!(result.frame_count, 5);
+        assert!(result.size_bytes < old_bytes);
+        catalog.recording_archived(&candidates[0].id, &result.path, "mjpeg", result.size_bytes).unwrap();
+
+        // 撮影時刻はそのまま、一時ファイルと古いマニフェストは残らない
+        let index = mjpeg::read_index(&mjpeg::index_path(&old)).unwrap();
+        assert_eq!(index.iter().map(|entry| entry.captured_at).collect::<Vec<_>>(), times);
+        assert_eq!(mjpeg::scan_file(&old).unwrap().frames.len(), 5);
+        assert!(!manifest::manifest_path(&old).exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 5);
+
+        let entry = catalog.recordings().unwrap().into_iter().find(|e| e.path == old).unwrap();
+        assert_eq!(entry.size_bytes, result.size_bytes);
+        assert!(entry.archived_at.is_some());
+        assert!(archive_candidates(&catalog, 7, now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_recording_needs_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let key = KeySource::generate_key_file(&dir.path().join("archive.key")).unwrap();
+        let path = dir.path().join("motion_old.mjpeg");
+        record(&catalog, &path, 1, Some(&key));
+        let original = fs::read(&path).unwrap();
+
+        let archived = archive_old_recordings(&catalog, &config(), None, &AtomicBool::new(false)).unwrap();
+        assert!(archived.is_empty());
+        assert_eq!(fs::read(&path).unwrap(), original);
+
+        let archived = archive_old_recordings(&catalog, &config(), Some(&key), &AtomicBool::new(false)).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert!(archived[0].replaced);
+        assert!(crypto::is_encrypted(&path).unwrap());
+        let frames = mjpeg::scan_frames(crypto::open_recording(&path, Some(&key)).unwrap()).unwrap();
+        assert_eq!(frames.frames.len(), 5);
+    }
+
+    #[test]
+    fn test_cancel_keeps_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let path = dir.path().join("manual_old.mjpeg");
+        record(&catalog, &path, 1, None);
+        let original = fs::read(&path).unwrap();
+
+        let entry = &catalog.recordings().unwrap()[0];
+        let err = archive_recording(entry, &config(), None, &AtomicBool::new(true)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert!(!path.with_extension("mjpeg.archiving").exists());
+        assert_eq!(archive_candidates(&catalog, 7, at(21, 0)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_typical_fps_ignores_gaps() {
+        let times: Vec<_> = [0, 91, 182, 5000, 5091, 5182].iter().map(|&ms| at(1, ms)).collect();
+        assert_eq!(typical_fps(times), 11);
+        assert_eq!(typical_fps(vec![at(1, 0)]), FALLBACK_FPS);
+    }
+
+    #[test]
+    #[ignore = "requires ffmpeg"]
+    fn test_transcode_to_mp4() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let path = dir.path().join("manual_old.mjpeg");
+        record(&catalog, &path, 1, None);
+
+        let config = ArchiveConfig { format: RecordingFormat::Mp4, ..config() };
+        let archived = archive_old_recordings(&catalog, &config, None, &AtomicBool::new(false)).unwrap();
+        assert_eq!(archived[0].path, path.with_extension("mp4"));
+        // 2秒の空白は直前のフレームで埋める
+        assert_eq!(archived[0].frame_count, 24);
+        assert!(!path.exists());
+        assert_eq!(catalog.recordings().unwrap()[0].format, "mp4");
+    }
+}
@@ -0,0 +1,883 @@
+//! 録画カタログモジュール
+//!
+//! 録画ファイル、動き検知イベント、スナップショット、カメラへのいたずらを、録画ディレクトリ内の
+//! JSON Lines形式のインデックス（`catalog.jsonl`）に記録する。
+//! 追記専用のため、録画中にプロセスが落ちても記録済みの行は失われない。
+//! 読み出し時に各行を畳み込んで録画単位のエントリを再構成し、検索APIを提供する。
+//! 動きイベントには、動きが最大だったフレームの動きの外接矩形と、イベント全体で動きがあった範囲を
+//! 画像サイズに対する割合で記録する（解像度が変わっても比較できる）。
+//! いたずらは録画の有無に関係なく、開始時と終了時に記録する（終了しないまま電源を切られても開始は残る）。
+
+use crate::motion_detector::BoundingBox;
+use crate::motion_event::MotionEvent;
+use crate::tamper_detector::{TamperEvent, TamperKind};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// カタログファイル名（録画ディレクトリ直下）
+pub const CATALOG_FILENAME: &str = "catalog.jsonl";
+
+/// 録画の開始理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingTrigger {
+    /// 手動録画
+    Manual,
+    /// 動き検知録画
+    Motion,
+    /// タイムラプス録画
+    Timelapse,
+    /// スケジュールによる常時録画
+    Scheduled,
+}
+
+impl fmt::Display for RecordingTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingTrigger::Manual => write!(f, "manual"),
+            RecordingTrigger::Motion => write!(f, "motion"),
+            RecordingTrigger::Timelapse => write!(f, "timelapse"),
+            RecordingTrigger::Scheduled => write!(f, "scheduled"),
+        }
+    }
+}
+
+/// カタログの1行（追記単位）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum CatalogRecord {
+    /// 録画開始
+    RecordingStarted {
+        id: String,
+        path: PathBuf,
+        trigger: RecordingTrigger,
+        /// ファイル形式（拡張子: "mjpeg", "mp4"）
+        format: String,
+        started_at: DateTime<Local>,
+    },
+    /// 録画終了
+    RecordingFinished {
+        id: String,
+        ended_at: DateTime<Local>,
+        frame_count: u64,
+        size_bytes: u64,
+        /// 起動時リカバリで終了処理された（クラッシュ・電源断）
+        #[serde(default)]
+        recovered: bool,
+        /// 録画途中でffmpegが再起動した場合の追加セグメント
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        segments: Vec<PathBuf>,
+    },
+    /// 古い録画をアーカイブ用に変換した（`path`は変換後のファイル）
+    RecordingArchived {
+        id: String,
+        path: PathBuf,
+        format: String,
+        size_bytes: u64,
+        archived_at: DateTime<Local>,
+    },
+    /// 録画中に発生した動きイベント
+    MotionEvent {
+        recording_id: String,
+        started_at: DateTime<Local>,
+        ended_at: DateTime<Local>,
+        /// 動き割合の最大値（%）
+        peak_motion_ratio: f32,
+        /// 動きが検知されたフレーム数
+        frame_count: u64,
+        /// 動き割合が最大だったフレームの動きの外接矩形
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        boxes: Vec<MotionBox>,
+        /// イベント全体で動きがあった範囲（全フレームの外接矩形を合わせたもの）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extent: Option<MotionBox>,
+    },
+    /// スナップショット
+    Snapshot {
+        /// 元のJPEGファイル
+        path: PathBuf,
+        /// デコードしたフレームのPNG（保存した場合）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        png: Option<PathBuf>,
+        captured_at: DateTime<Local>,
+    },
+    /// カメラへのいたずら（同じ開始時刻の記録は後のもので置き換える）
+    Tamper {
+        kind: TamperKind,
+        started_at: DateTime<Local>,
+        /// 終了時刻（開始時の記録ではNone）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ended_at: Option<DateTime<Local>>,
+    },
+}
+
+/// 動きの外接矩形（画像の幅・高さに対する0.0-1.0の割合）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MotionBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl MotionBox {
+    /// 画素の外接矩形を画像サイズで割合に変換
+    pub fn from_bbox(bbox: &BoundingBox, image_width: u32, image_height: u32) -> Self {
+        let (w, h) = (image_width.max(1) as f32, image_height.max(1) as f32);
+        Self {
+            x: bbox.x as f32 / w,
+            y: bbox.y as f32 / h,
+            width: bbox.width as f32 / w,
+            height: bbox.height as f32 / h,
+        }
+    }
+
+    /// 2つの矩形を囲む矩形
+    pub fn union(&self, other: &MotionBox) -> MotionBox {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        MotionBox {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// 動きイベント
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionEventEntry {
+    pub recording_id: String,
+    pub started_at: DateTime<Local>,
+    pub ended_at: DateTime<Local>,
+    /// 動き割合の最大値（%）
+    pub peak_motion_ratio: f32,
+    pub frame_count: u64,
+    /// 動き割合が最大だったフレームの動きの外接矩形
+    pub boxes: Vec<MotionBox>,
+    /// イベント全体で動きがあった範囲
+    pub extent: Option<MotionBox>,
+}
+
+impl MotionEventEntry {
+    /// イベント継続時間
+    pub fn duration(&self) -> Duration {
+        self.ended_at - self.started_at
+    }
+}
+
+/// スナップショット
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub path: PathBuf,
+    pub png: Option<PathBuf>,
+    pub captured_at: DateTime<Local>,
+}
+
+/// カメラへのいたずら
+#[derive(Debug, Clone, PartialEq)]
+pub struct TamperEntry {
+    pub kind: TamperKind,
+    pub started_at: DateTime<Local>,
+    /// 終了時刻（継続中・終了前に停止した場合None）
+    pub ended_at: Option<DateTime<Local>>,
+}
+
+/// 録画1件分のエントリ（カタログの行を畳み込んだもの）
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingEntry {
+    pub id: String,
+    pub path: PathBuf,
+    pub trigger: RecordingTrigger,
+    pub format: String,
+    pub started_at: DateTime<Local>,
+    /// 終了時刻（未終了・異常終了の場合None）
+    pub ended_at: Option<DateTime<Local>>,
+    pub frame_count: u64,
+    pub size_bytes: u64,
+    /// 起動時リカバリで終了処理された
+    pub recovered: bool,
+    /// `path`に続く追加セグメント（ffmpeg再起動時）
+    pub segments: Vec<PathBuf>,
+    /// アーカイブ用に変換した時刻（未変換の場合None）
+    pub archived_at: Option<DateTime<Local>>,
+    pub motion_events: Vec<MotionEventEntry>,
+}
+
+impl RecordingEntry {
+    /// 録画時間（未終了の場合None）
+    pub fn duration(&self) -> Option<Duration> {
+        self.ended_at.map(|end| end - self.started_at)
+    }
+}
+
+/// 検索対象のイベント種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Recording,
+    Motion,
+    Snapshot,
+    Tamper,
+}
+
+impl FromStr for EventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "recording" | "recordings" => Ok(EventType::Recording),
+            "motion" => Ok(EventType::Motion),
+            "snapshot" | "snapshots" => Ok(EventType::Snapshot),
+            "tamper" | "tampering" => Ok(EventType::Tamper),
+            other => Err(format!("unknown event type '{}' (expected: recording, motion, snapshot, tamper)", other)),
+        }
+    }
+}
+
+/// 検索条件
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    /// この時刻以降に終わったイベント
+    pub since: Option<DateTime<Local>>,
+    /// この時刻より前に始まったイベント
+    pub until: Option<DateTime<Local>>,
+    /// 種別（Noneの場合すべて）
+    pub event_type: Option<EventType>,
+}
+
+/// 検索結果のイベント
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatalogEvent {
+    Recording(RecordingEntry),
+    Motion {
+        event: MotionEventEntry,
+        /// イベントを含む録画ファイル
+        path: PathBuf,
+    },
+    Snapshot(SnapshotEntry),
+    Tamper(TamperEntry),
+}
+
+impl EventQuery {
+    /// 期間 [start, end] が検索範囲と重なるかどうか
+    fn overlaps(&self, start: DateTime<Local>, end: DateTime<Local>) -> bool {
+        self.since.is_none_or(|since| end >= since) && self.until.is_none_or(|until| start < until)
+    }
+
+    /// 種別が検索対象かどうか
+    fn includes(&self, event_type: EventType) -> bool {
+        self.event_type.is_none_or(|t| t == event_type)
+    }
+}
+
+impl CatalogEvent {
+    /// イベント開始時刻
+    pub fn started_at(&self) -> DateTime<Local> {
+        match self {
+            CatalogEvent::Recording(entry) => entry.started_at,
+            CatalogEvent::Motion { event, .. } => event.started_at,
+            CatalogEvent::Snapshot(snapshot) => snapshot.captured_at,
+            CatalogEvent::Tamper(tamper) => tamper.started_at,
+        }
+    }
+}
+
+/// 録画ファイルパスからカタログIDを生成（ファイル名）
+pub fn recording_id(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string_lossy().into_owned())
+}
+
+/// 録画カタログ
+pub struct Catalog {
+    /// catalog.jsonlのパス
+    path: PathBuf,
+}
+
+impl Catalog {
+    /// 録画ディレクトリのカタログを開く
+    ///
+    /// ファイルは最初の追記時に作成される。
+    pub fn new(recording_dir: &Path) -> Self {
+        Self {
+            path: recording_dir.join(CATALOG_FILENAME),
+        }
+    }
+
+    /// カタログファイルのパス
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 1行追記
+    ///
+    /// クラッシュで最後の行が改行なしで途切れていた場合は、先に改行を書いて新しい行をつなげない
+    /// （つなげると新しい行も壊れた行として読み飛ばされ、リカバリーの記録が失われる）。
+    pub fn append(&self, record: &CatalogRecord) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&self.path)?;
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+    /// 録画開始を記録し、カタログIDを返す
+    pub fn recording_started(&self, path: &Path, trigger: RecordingTrigger, format: &str) -> io::Result<String> {
+        let id = recording_id(path);
+        self.append(&CatalogRecord::RecordingStarted {
+            id: id.clone(),
+            path: path.to_path_buf(),
+            trigger,
+            format: format.to_string(),
+            started_at: Local::now(),
+        })?;
+        Ok(id)
+    }
+
+    /// 録画終了を記録
+    ///
+    /// `segments`には`path`以降の追加セグメントを渡す（通常は空）。
+    pub fn recording_finished(&self, id: &str, frame_count: u64, size_bytes: u64, segments: &[PathBuf]) -> io::Result<()> {
+        self.append(&CatalogRecord::RecordingFinished {
+            id: id.to_string(),
+            ended_at: Local::now(),
+            frame_count,
+            size_bytes,
+            recovered: false,
+            segments: segments.to_vec(),
+        })
+    }
+
+    /// アーカイブ用の変換を記録
+    pub fn recording_archived(&self, id: &str, path: &Path, format: &str, size_bytes: u64) -> io::Result<()> {
+        self.append(&CatalogRecord::RecordingArchived {
+            id: id.to_string(),
+            path: path.to_path_buf(),
+            format: format.to_string(),
+            size_bytes,
+            archived_at: Local::now(),
+        })
+    }
+
+    /// 終了した動きイベントを記録（終了時刻がなければ最後に動きがあった時刻）
+    pub fn motion_event(&self, recording_id: &str, event: &MotionEvent) -> io::Result<()> {
+        self.append(&CatalogRecord::MotionEvent {
+            recording_id: recording_id.to_string(),
+            started_at: event.started_at,
+            ended_at: event.ended_at.unwrap_or(event.last_motion_at),
+            peak_motion_ratio: event.peak_motion_ratio,
+            frame_count: event.frame_count,
+            boxes: event.boxes.clone(),
+            extent: event.extent,
+        })
+    }
+
+    /// いたずらイベントを記録（開始時と終了時に呼ぶ）
+    pub fn tamper_event(&self, event: &TamperEvent) -> io::Result<()> {
+        self.append(&CatalogRecord::Tamper {
+            kind: event.kind,
+            started_at: event.started_at,
+            ended_at: event.ended_at,
+        })
+    }
+
+    /// スナップショットを記録
+    pub fn snapshot_taken(&self, path: &Path, png: Option<&Path>, captured_at: DateTime<Local>) -> io::Result<()> {
+        self.append(&CatalogRecord::Snapshot {
+            path: path.to_path_buf(),
+            png: png.map(Path::to_path_buf),
+            captured_at,
+        })
+    }
+
+    /// 全行を読み込む
+    ///
+    /// 書き込み途中で途切れた行などの不正な行は警告を出して読み飛ばす。
+    pub fn records(&self) -> io::Result<Vec<CatalogRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut records = Vec::new();
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!("{:?}:{}: skipping malformed catalog line: {}", self.path, line_no + 1, e),
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// 録画エントリ一覧（開始時刻順）
+    pub fn recordings(&self) -> io::Result<Vec<RecordingEntry>> {
+        Ok(fold_records(self.records()?))
+    }
+
+    /// イベント検索（開始時刻順）
+    pub fn query(&self, query: &EventQuery) -> io::Result<Vec<CatalogEvent>> {
+        let records = self.records()?;
+        let mut events = Vec::new();
+
+        if query.includes(EventType::Snapshot) {
+            for record in &records {
+                if let CatalogRecord::Snapshot { path, png, captured_at } = record {
+                    if query.overlaps(*captured_at, *captured_at) {
+                        events.push(CatalogEvent::Snapshot(SnapshotEntry {
+                            path: path.clone(),
+                            png: png.clone(),
+                            captured_at: *captured_at,
+                        }));
+                    }
+                }
+            }
+        }
+
+        if query.includes(EventType::Tamper) {
+            let mut tampers: Vec<TamperEntry> = Vec::new();
+            for record in &records {
+                if let CatalogRecord::Tamper { kind, started_at, ended_at } = record {
+                    let entry = TamperEntry { kind: *kind, started_at: *started_at, ended_at: *ended_at };
+                    match tampers.iter_mut().find(|t| (t.kind, t.started_at) == (*kind, *started_at)) {
+                        Some(existing) => *existing = entry,
+                        None => tampers.push(entry),
+                    }
+                }
+            }
+            // 終了していないいたずらは現在まで続いているとみなす
+            events.extend(tampers.into_iter()
+                .filter(|t| query.overlaps(t.started_at, t.ended_at.unwrap_or_else(Local::now)))
+                .map(CatalogEvent::Tamper));
+        }
+
+        for entry in fold_records(records) {
+            if query.includes(EventType::Motion) {
+                for event in &entry.motion_events {
+                    if query.overlaps(event.started_at, event.ended_at) {
+                        events.push(CatalogEvent::Motion {
+                            event: event.clone(),
+                            path: entry.path.clone(),
+                        });
+                    }
+                }
+            }
+
+            // 未終了の録画は現在まで続いているとみなす
+            let end = entry.ended_at.unwrap_or_else(Local::now);
+            if query.includes(EventType::Recording) && query.overlaps(entry.started_at, end) {
+                events.push(CatalogEvent::Recording(entry));
+            }
+        }
+
+        events.sort_by_key(|event| event.started_at());
+        Ok(events)
+    }
+}
+
+/// カタログ行を録画エントリに畳み込む
+fn fold_records(records: Vec<CatalogRecord>) -> Vec<RecordingEntry> {
+    let mut entries: Vec<RecordingEntry> = Vec::new();
+
+    for record in records {
+        match record {
+            CatalogRecord::RecordingStarted { id, path, trigger, format, started_at } => {
+                entries.push(RecordingEntry {
+                    id,
+                    path,
+                    trigger,
+                    format,
+                    started_at,
+                    ended_at: None,
+                    frame_count: 0,
+                    size_bytes: 0,
+                    recovered: false,
+                    segments: Vec::new(),
+                    archived_at: None,
+                    motion_events: Vec::new(),
+                });
+            }
+            CatalogRecord::RecordingFinished { id, ended_at, frame_count, size_bytes, recovered, segments } => {
+                // 同名ファイルの再録画に備えて、最後に開始されたものを更新する
+                if let Some(entry) = entries.iter_mut().rev().find(|e| e.id == id) {
+                    entry.ended_at = Some(ended_at);
+                    entry.frame_count = frame_count;
+                    entry.size_bytes = size_bytes;
+                    entry.recovered = recovered;
+                    entry.segments = segments;
+                } else {
+                    warn!("Catalog: finish record for unknown recording {}", id);
+                }
+            }
+            CatalogRecord::RecordingArchived { id, path, format, size_bytes, archived_at } => {
+                if let Some(entry) = entries.iter_mut().rev().find(|e| e.id == id) {
+                    entry.path = path;
+                    entry.format = format;
+                    entry.size_bytes = size_bytes;
+                    entry.archived_at = Some(archived_at);
+                } else {
+                    warn!("Catalog: archive record for unknown recording {}", id);
+                }
+            }
+            CatalogRecord::MotionEvent { recording_id, started_at, ended_at, peak_motion_ratio, frame_count, boxes, extent } => {
+                if let Some(entry) = entries.iter_mut().rev().find(|e| e.id == recording_id) {
+                    entry.motion_events.push(MotionEventEntry {
+                        recording_id,
+                        started_at,
+                        ended_at,
+                        peak_motion_ratio,
+                        frame_count,
+                        boxes,
+                        extent,
+                    });
+                } else {
+                    warn!("Catalog: motion event for unknown recording {}", recording_id);
+                }
+            }
+            CatalogRecord::Snapshot { .. } | CatalogRecord::Tamper { .. } => {}
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.started_at);
+    entries
+}
+
+/// コマンドライン用の時刻指定をパース
+///
+/// 対応形式:
+/// - 相対指定: `30s`, `15m`, `2h`, `7d`（現在から遡る）
+/// - `today`, `yesterday`
+/// - RFC 3339: `2026-01-02T02:14:00+09:00`
+/// - ローカル時刻: `2026-01-02 02:14[:00]`, `2026-01-02T02:14:00`, `2026-01-02`
+pub fn parse_time_arg(s: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+
+    match s {
+        "today" => return local_midnight(now.date_naive()),
+        "yesterday" => return local_midnight(now.date_naive() - Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(unit) = s.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        if let Ok(amount) = s[..s.len() - 1].parse::<i64>() {
+            let duration = match unit {
+                's' => Duration::seconds(amount),
+                'm' => Duration::minutes(amount),
+                'h' => Duration::hours(amount),
+                'd' => Duration::days(amount),
+                _ => return Err(format!("unknown time unit '{}' in '{}'", unit, s)),
+            };
+            return Ok(now - duration);
+        }
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Local));
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+            return Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .ok_or_else(|| format!("'{}' does not exist in the local time zone", s));
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return local_midnight(date);
+    }
+
+    Err(format!("cannot parse time '{}'", s))
+}
+
+/// ローカル日付の0時
+fn local_midnight(date: NaiveDate) -> Result<DateTime<Local>, String> {
+    let naive = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("midnight of {} does not exist in the local time zone", date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Local> {
+        parse_time_arg(s, Local::now()).unwrap()
+    }
+
+    fn sample_catalog(dir: &Path) -> Catalog {
+        let catalog = Catalog::new(dir);
+
+        catalog.append(&CatalogRecord::RecordingStarted {
+            id: "manual_1.mjpeg".to_string(),
+            path: dir.join("manual_1.mjpeg"),
+            trigger: RecordingTrigger::Manual,
+            format: "mjpeg".to_string(),
+            started_at: at("2026-01-02 10:00:00"),
+        }).unwrap();
+        catalog.append(&CatalogRecord::RecordingFinished {
+            id: "manual_1.mjpeg".to_string(),
+            ended_at: at("2026-01-02 10:05:00"),
+            frame_count: 3300,
+            size_bytes: 165_000_000,
+            recovered: false,
+            segments: Vec::new(),
+        }).unwrap();
+
+        catalog.append(&CatalogRecord::RecordingStarted {
+            id: "motion_1.mp4".to_string(),
+            path: dir.join("motion_1.mp4"),
+            trigger: RecordingTrigger::Motion,
+            format: "mp4".to_string(),
+            started_at: at("2026-01-02 22:00:00"),
+        }).unwrap();
+        catalog.append(&CatalogRecord::MotionEvent {
+            recording_id: "motion_1.mp4".to_string(),
+            started_at: at("2026-01-02 22:00:10"),
+            ended_at: at("2026-01-02 22:00:20"),
+            peak_motion_ratio: 12.5,
+            frame_count: 110,
+            boxes: vec![MotionBox { x: 0.25, y: 0.5, width: 0.1, height: 0.2 }],
+            extent: Some(MotionBox { x: 0.2, y: 0.4, width: 0.3, height: 0.4 }),
+        }).unwrap();
+        catalog.append(&CatalogRecord::RecordingFinished {
+            id: "motion_1.mp4".to_string(),
+            ended_at: at("2026-01-02 22:01:00"),
+            frame_count: 660,
+            size_bytes: 2_000_000,
+            recovered: false,
+            segments: Vec::new(),
+        }).unwrap();
+
+        catalog
+    }
+
+    #[test]
+    fn test_fold_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = sample_catalog(dir.path());
+
+        let recordings = catalog.recordings().unwrap();
+        assert_eq!(recordings.len(), 2);
+
+        assert_eq!(recordings[0].id, "manual_1.mjpeg");
+        assert_eq!(recordings[0].frame_count, 3300);
+        assert_eq!(recordings[0].duration(), Some(Duration::minutes(5)));
+        assert!(recordings[0].motion_events.is_empty());
+
+        assert_eq!(recordings[1].trigger, RecordingTrigger::Motion);
+        assert_eq!(recordings[1].motion_events.len(), 1);
+        assert_eq!(recordings[1].motion_events[0].peak_motion_ratio, 12.5);
+        assert_eq!(recordings[1].motion_events[0].boxes, vec![MotionBox { x: 0.25, y: 0.5, width: 0.1, height: 0.2 }]);
+        assert_eq!(recordings[1].motion_events[0].extent, Some(MotionBox { x: 0.2, y: 0.4, width: 0.3, height: 0.4 }));
+    }
+
+    #[test]
+    fn test_query_by_type_and_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = sample_catalog(dir.path());
+
+        let motion = catalog.query(&EventQuery {
+            event_type: Some(EventType::Motion),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(motion.len(), 1);
+        assert!(matches!(&motion[0], CatalogEvent::Motion { path, .. } if path.ends_with("motion_1.mp4")));
+
+        let evening = catalog.query(&EventQuery {
+            since: Some(at("2026-01-02 12:00:00")),
+            event_type: Some(EventType::Recording),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(evening.len(), 1);
+        assert!(matches!(&evening[0], CatalogEvent::Recording(entry) if entry.id == "motion_1.mp4"));
+
+        let morning = catalog.query(&EventQuery {
+            until: Some(at("2026-01-02 12:00:00")),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(morning.len(), 1);
+
+        let all = catalog.query(&EventQuery::default()).unwrap();
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = sample_catalog(dir.path());
+        let jpeg = dir.path().join("snapshots/snapshot_1.jpg");
+        let png = dir.path().join("snapshots/snapshot_1.png");
+        catalog.snapshot_taken(&jpeg, Some(&png), at("2026-01-02 10:02:00")).unwrap();
+
+        // スナップショットは録画エントリに影響しない
+        assert_eq!(catalog.recordings().unwrap().len(), 2);
+
+        let snapshots = catalog.query(&EventQuery {
+            event_type: Some(EventType::Snapshot),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(snapshots, vec![CatalogEvent::Snapshot(SnapshotEntry {
+            path: jpeg,
+            png: Some(png),
+            captured_at: at("2026-01-02 10:02:00"),
+        })]);
+
+        // 時刻順に録画・動きイベントと並ぶ
+        let morning = catalog.query(&EventQuery {
+            until: Some(at("2026-01-02 12:00:00")),
+            ..Default::default()
+        }).unwrap();
+        assert!(matches!(&morning[..], [CatalogEvent::Recording(_), CatalogEvent::Snapshot(_)]));
+    }
+
+    #[test]
+    fn test_tamper_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = sample_catalog(dir.path());
+        let mut event = TamperEvent {
+            id: 1,
+            kind: TamperKind::Covered,
+            started_at: at("2026-01-02 23:00:00"),
+            ended_at: None,
+            measurements: Default::default(),
+        };
+        catalog.tamper_event(&event).unwrap();
+
+        // 開始だけの記録は継続中
+        let query = EventQuery { event_type: Some(EventType::Tamper), ..Default::default() };
+        let expected = |ended_at| vec![CatalogEvent::Tamper(TamperEntry {
+            kind: TamperKind::Covered,
+            started_at: at("2026-01-02 23:00:00"),
+            ended_at,
+        })];
+        assert_eq!(catalog.query(&query).unwrap(), expected(None));
+
+        // 終了の記録で置き換わる
+        event.ended_at = Some(at("2026-01-02 23:05:00"));
+        catalog.tamper_event(&event).unwrap();
+        assert_eq!(catalog.query(&query).unwrap(), expected(Some(at("2026-01-02 23:05:00"))));
+        assert_eq!(catalog.recordings().unwrap().len(), 2);
+        assert!(catalog.query(&EventQuery { since: Some(at("2026-01-02 23:06:00")), ..query }).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unfinished_recording_and_torn_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+
+        let id = catalog.recording_started(&dir.path().join("motion_2.mjpeg"), RecordingTrigger::Motion, "mjpeg").unwrap();
+        assert_eq!(id, "motion_2.mjpeg");
+
+        // 書き込み途中でクラッシュした行
+        let mut file = OpenOptions::new().append(true).open(catalog.path()).unwrap();
+        file.write_all(b"{\"record\":\"recording_fini").unwrap();
+
+        let recordings = catalog.recordings().unwrap();
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].ended_at, None);
+        assert_eq!(recordings[0].duration(), None);
+
+        // 途切れた行の後に追記した記録（リカバリーの録画終了など）は、次の行として読める
+        catalog.recording_finished(&id, 12, 3400, &[]).unwrap();
+        let recordings = catalog.recordings().unwrap();
+        assert_eq!(recordings.len(), 1);
+        assert!(recordings[0].ended_at.is_some());
+        assert_eq!((recordings[0].frame_count, recordings[0].size_bytes), (12, 3400));
+    }
+
+    #[test]
+    fn test_missing_catalog_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(&dir.path().join("none"));
+        assert!(catalog.recordings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_motion_event_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = sample_catalog(dir.path());
+        let event = MotionEvent {
+            id: 1,
+            started_at: at("2026-01-02 22:00:30"),
+            last_motion_at: at("2026-01-02 22:00:31"),
+            ended_at: Some(at("2026-01-02 22:00:32")),
+            peak_motion_ratio: 8.5,
+            frame_count: 3,
+            boxes: vec![MotionBox { x: 0.5, y: 0.2, width: 0.2, height: 0.4 }],
+            extent: Some(MotionBox { x: 0.1, y: 0.1, width: 0.8, height: 0.8 }),
+        };
+        catalog.motion_event("motion_1.mp4", &event).unwrap();
+
+        let recordings = catalog.recordings().unwrap();
+        let recorded = &recordings[1].motion_events[1];
+        assert_eq!((recorded.started_at, recorded.ended_at), (event.started_at, at("2026-01-02 22:00:32")));
+        assert_eq!((recorded.peak_motion_ratio, recorded.frame_count), (8.5, 3));
+        assert_eq!((&recorded.boxes, recorded.extent), (&event.boxes, event.extent));
+    }
+
+    #[test]
+    fn test_motion_event_without_boxes_parses() {
+        let line = r#"{"record":"motion_event","recording_id":"motion_1.mp4","started_at":"2026-01-02T22:00:10+09:00","ended_at":"2026-01-02T22:00:20+09:00","peak_motion_ratio":3.0,"frame_count":5}"#;
+        match serde_json::from_str::<CatalogRecord>(line).unwrap() {
+            CatalogRecord::MotionEvent { boxes, extent, .. } => assert!(boxes.is_empty() && extent.is_none()),
+            other => panic!("unexpected record: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_time_arg() {
+        let now = at("2026-01-02 12:00:00");
+
+        assert_eq!(parse_time_arg("2h", now).unwrap(), at("2026-01-02 10:00:00"));
+        assert_eq!(parse_time_arg("1d", now).unwrap(), at("2026-01-01 12:00:00"));
+        assert_eq!(parse_time_arg("today", now).unwrap(), at("2026-01-02 00:00:00"));
+        assert_eq!(parse_time_arg("yesterday", now).unwrap(), at("2026-01-01"));
+        assert_eq!(parse_time_arg("2026-01-02 02:14", now).unwrap(), at("2026-01-02T02:14:00"));
+        assert_eq!(
+            parse_time_arg("2026-01-02T02:14:00+00:00", now).unwrap(),
+            DateTime::parse_from_rfc3339("2026-01-02T02:14:00Z").unwrap().with_timezone(&Local)
+        );
+
+        assert!(parse_time_arg("2x", now).is_err());
+        assert!(parse_time_arg("last tuesday", now).is_err());
+    }
+
+    #[test]
+    fn test_event_type_from_str() {
+        assert_eq!("motion".parse::<EventType>().unwrap(), EventType::Motion);
+        assert_eq!("Recording".parse::<EventType>().unwrap(), EventType::Recording);
+        assert_eq!("snapshot".parse::<EventType>().unwrap(), EventType::Snapshot);
+        assert_eq!("tamper".parse::<EventType>().unwrap(), EventType::Tamper);
+        assert!("clip".parse::<EventType>().is_err());
+    }
+}
@@ -0,0 +1,543 @@
+//! クリップ書き出しモジュール
+//!
+//! 録画から指定した時刻範囲を切り出し、別の形式で書き出す。
+//!
+//! - MJPEG録画: インデックス（`<ファイル>.idx`）の撮影時刻でフレームを選ぶ。
+//!   インデックスがない場合は録画開始時刻から11fpsで並んでいるとみなす
+//! - MP4録画: 録画開始時刻からのオフセットでffmpegに切り出させる
+//!
+//! 出力形式はMJPEG、MP4、AVI、JPEG連番、GIF、WebP（アニメーション）。
+//! MJPEG録画からのMJPEG、JPEG連番、GIFの書き出しにはffmpegは不要。
+//! 暗号化された録画は復号しながら読み、平文で書き出す。
+
+use crate::catalog::{self, Catalog};
+use crate::crypto::{self, KeySource};
+use crate::mjpeg::{self, MjpegWriter};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, NaiveTime, TimeZone};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageFormat, RgbaImage};
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+
+/// インデックスがないMJPEG録画のフレームレート（GUIの録画と同じ）
+const FALLBACK_FPS: f64 = 11.0;
+/// GIFエンコードの速度（1-30、大きいほど速く粗い）
+const GIF_SPEED: i32 = 10;
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipFormat {
+    Mjpeg,
+    Mp4,
+    Avi,
+    /// JPEG連番（出力はディレクトリ）
+    Jpeg,
+    Gif,
+    /// アニメーションWebP
+    Webp,
+}
+
+impl ClipFormat {
+    pub const ALL: [ClipFormat; 6] = [
+        ClipFormat::Mjpeg,
+        ClipFormat::Mp4,
+        ClipFormat::Avi,
+        ClipFormat::Jpeg,
+        ClipFormat::Gif,
+        ClipFormat::Webp,
+    ];
+
+    /// 出力パスの拡張子から形式を推定（拡張子がなければJPEG連番のディレクトリ）
+    pub fn from_output_path(path: &Path) -> Option<Self> {
+        match path.extension() {
+            None => Some(ClipFormat::Jpeg),
+            Some(ext) => ext.to_str()?.to_ascii_lowercase().parse().ok(),
+        }
+    }
+
+    /// 出力ファイルの拡張子（JPEG連番はディレクトリなのでNone）
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            ClipFormat::Mjpeg => Some("mjpeg"),
+            ClipFormat::Mp4 => Some("mp4"),
+            ClipFormat::Avi => Some("avi"),
+            ClipFormat::Jpeg => None,
+            ClipFormat::Gif => Some("gif"),
+            ClipFormat::Webp => Some("webp"),
+        }
+    }
+}
+
+impl fmt::Display for ClipFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipFormat::Mjpeg => write!(f, "mjpeg"),
+            ClipFormat::Mp4 => write!(f, "mp4"),
+            ClipFormat::Avi => write!(f, "avi"),
+            ClipFormat::Jpeg => write!(f, "jpeg"),
+            ClipFormat::Gif => write!(f, "gif"),
+            ClipFormat::Webp => write!(f, "webp"),
+        }
+    }
+}
+
+impl FromStr for ClipFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mjpeg" | "mjpg" => Ok(ClipFormat::Mjpeg),
+            "mp4" => Ok(ClipFormat::Mp4),
+            "avi" => Ok(ClipFormat::Avi),
+            "jpeg" | "jpg" => Ok(ClipFormat::Jpeg),
+            "gif" => Ok(ClipFormat::Gif),
+            "webp" => Ok(ClipFormat::Webp),
+            _ => Err(format!("unknown clip format '{}' (expected mjpeg, mp4, avi, jpeg, gif or webp)", s)),
+        }
+    }
+}
+
+/// 書き出し結果
+#[derive(Debug, Clone)]
+pub struct ClipSummary {
+    /// 書き出したフレーム数（ffmpegで切り出した場合はNone）
+    pub frames: Option<u64>,
+    pub output: PathBuf,
+}
+
+/// クリップの開始・終了時刻をパース
+///
+/// `catalog::parse_time_arg`の形式に加えて、次を受け付ける:
+/// - 録画開始からのオフセット: `+90s`, `+2m`, `+1h`, `+90`（秒）
+/// - 録画日の時刻: `02:14`, `02:14:30`（録画開始より前なら翌日）
+pub fn parse_clip_time(
+    s: &str,
+    recording_start: Option<DateTime<Local>>,
+    now: DateTime<Local>,
+) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    let needs_start = || recording_start.ok_or_else(|| format!("'{}' needs the recording start time, which is unknown", s));
+
+    if let Some(offset) = s.strip_prefix('+') {
+        let (amount, unit) = match offset.chars().last() {
+            Some(c) if c.is_ascii_alphabetic() => (&offset[..offset.len() - 1], c),
+            _ => (offset, 's'),
+        };
+        let amount: f64 = amount.parse().map_err(|_| format!("cannot parse offset '{}'", s))?;
+        let seconds = match unit {
+            's' => amount,
+            'm' => amount * 60.0,
+            'h' => amount * 3600.0,
+            _ => return Err(format!("unknown time unit '{}' in '{}'", unit, s)),
+        };
+        return Ok(needs_start()? + Duration::milliseconds((seconds * 1000.0).round() as i64));
+    }
+
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            let start = needs_start()?;
+            let time_on = |date| {
+                Local.from_local_datetime(&NaiveDateTime::new(date, time)).earliest()
+                    .ok_or_else(|| format!("'{}' does not exist in the local time zone", s))
+            };
+            let at = time_on(start.date_naive())?;
+            return if at < start { time_on(start.date_naive() + Duration::days(1)) } else { Ok(at) };
+        }
+    }
+
+    catalog::parse_time_arg(s, now)
+}
+
+/// 録画の開始時刻（カタログ、なければファイル名の日時）
+pub fn recording_start(path: &Path) -> Option<DateTime<Local>> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let id = catalog::recording_id(path);
+    if let Some(entry) = Catalog::new(dir).recordings().ok()?.into_iter().find(|e| e.id == id) {
+        return Some(entry.started_at);
+    }
+
+    // manual_20260102_021400.mjpeg のようなファイル名
+    let stem = path.file_stem()?.to_str()?;
+    stem.char_indices().find_map(|(i, _)| {
+        let naive = NaiveDateTime::parse_from_str(stem.get(i..i + 15)?, "%Y%m%d_%H%M%S").ok()?;
+        Local.from_local_datetime(&naive).earliest()
+    })
+}
+
+/// 録画の`start`〜`end`を`format`で`output`に書き出す
+///
+/// # Errors
+/// - 時刻範囲が空の場合
+/// - 暗号化された録画で鍵がない場合
+/// - ffmpegが必要な形式でffmpegが使えない場合
+pub fn export_clip(
+    recording: &Path,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    format: ClipFormat,
+    output: &Path,
+    key: Option<&KeySource>,
+) -> io::Result<ClipSummary> {
+    if end <= start {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "clip end must be after its start"));
+    }
+
+    let is_mp4 = recording.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
+    let frames = if is_mp4 {
+        export_from_mp4(recording, start, end, format, output, key)?;
+        None
+    } else {
+        Some(export_from_mjpeg(recording, start, end, format, output, key)?)
+    };
+
+    Ok(ClipSummary { frames, output: output.to_path_buf() })
+}
+
+/// MJPEG録画からフレームを選んで書き出す
+fn export_from_mjpeg(
+    recording: &Path,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    format: ClipFormat,
+    output: &Path,
+    key: Option<&KeySource>,
+) -> io::Result<u64> {
+    let index = match mjpeg::read_index(&mjpeg::index_path(recording)) {
+        Ok(entries) => Some(entries.into_iter().map(|e| (e.span.offset, e.captured_at)).collect::<HashMap<_, _>>()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    // 出力のフレームレートは範囲内のフレームの撮影間隔から求める
+    let (fallback_start, fps) = match &index {
+        Some(index) => {
+            let mut times: Vec<_> = index.values().filter(|t| (start..=end).contains(*t)).collect();
+            times.sort();
+            let fps = match (times.first(), times.last()) {
+                (Some(first), Some(last)) if times.len() > 1 && last > first => {
+                    (times.len() - 1) as f64 / ((**last - **first).num_milliseconds() as f64 / 1000.0)
+                }
+                _ => FALLBACK_FPS,
+            };
+            (None, fps)
+        }
+        None => {
+            warn!("{:?} has no frame index, assuming {} fps from the recording start", recording, FALLBACK_FPS);
+            let recording_start = recording_start(recording).ok_or_else(|| io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} has no frame index and its start time is unknown", recording),
+            ))?;
+            (Some(recording_start), FALLBACK_FPS)
+        }
+    };
+
+    let mut reader = crypto::open_recording(recording, key)?;
+    let mut sink = ClipSink::create(format, output, fps)?;
+    let mut frame_no = 0u64;
+    mjpeg::for_each_frame(&mut reader, |span, jpeg| {
+        let captured_at = match (&index, fallback_start) {
+            (Some(index), _) => index.get(&span.offset).copied(),
+            (None, Some(t0)) => Some(t0 + Duration::milliseconds((frame_no as f64 * 1000.0 / FALLBACK_FPS) as i64)),
+            (None, None) => None,
+        };
+        frame_no += 1;
+
+        match captured_at {
+            Some(t) if (start..=end).contains(&t) => sink.write(jpeg, t),
+            _ => Ok(()),
+        }
+    })?;
+
+    sink.finish()
+}
+
+/// 書き出し先
+enum ClipSink {
+    Mjpeg { writer: MjpegWriter, count: u64 },
+    Jpeg { dir: PathBuf, count: u64 },
+    Gif { encoder: GifEncoder<File>, pending: Option<(RgbaImage, DateTime<Local>)>, last_delay_ms: u32, count: u64 },
+    Ffmpeg { child: Child, stdin: ChildStdin, count: u64 },
+}
+
+impl ClipSink {
+    fn create(format: ClipFormat, output: &Path, fps: f64) -> io::Result<Self> {
+        Ok(match format {
+            ClipFormat::Mjpeg => ClipSink::Mjpeg { writer: MjpegWriter::create(output)?, count: 0 },
+            ClipFormat::Jpeg => {
+                fs::create_dir_all(output)?;
+                ClipSink::Jpeg { dir: output.to_path_buf(), count: 0 }
+            }
+            ClipFormat::Gif => {
+                let mut encoder = GifEncoder::new_with_speed(File::create(output)?, GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite).map_err(io::Error::other)?;
+                ClipSink::Gif { encoder, pending: None, last_delay_ms: (1000.0 / fps) as u32, count: 0 }
+            }
+            ClipFormat::Mp4 | ClipFormat::Avi | ClipFormat::Webp => {
+                let mut command = ffmpeg_command();
+                command
+                    .args(["-f", "image2pipe", "-codec:v", "mjpeg", "-framerate", &format!("{:.3}", fps), "-i", "-"])
+                    .args(output_args(format, true))
+                    .arg(output)
+                    .stdin(Stdio::piped());
+                let mut child = spawn_ffmpeg(command, format)?;
+                let stdin = child.stdin.take().ok_or_else(|| io::Error::other("Failed to get ffmpeg stdin"))?;
+                ClipSink::Ffmpeg { child, stdin, count: 0 }
+            }
+        })
+    }
+
+    fn write(&mut self, jpeg: &[u8], captured_at: DateTime<Local>) -> io::Result<()> {
+        match self {
+            ClipSink::Mjpeg { writer, count } => {
+                *count += 1;
+                writer.write_frame_at(jpeg, captured_at)
+            }
+            ClipSink::Jpeg { dir, count } => {
+                *count += 1;
+                fs::write(dir.join(format!("frame_{:06}.jpg", count)), jpeg)
+            }
+            ClipSink::Gif { encoder, pending, last_delay_ms, count } => {
+                let image = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .to_rgba8();
+                // GIFの表示時間は次のフレームの撮影時刻までとする
+                if let Some((previous, previous_at)) = pending.take() {
+                    *last_delay_ms = (captured_at - previous_at).num_milliseconds().clamp(10, 10_000) as u32;
+                    encoder.encode_frame(Frame::from_parts(previous, 0, 0, Delay::from_numer_denom_ms(*last_delay_ms, 1)))
+                        .map_err(io::Error::other)?;
+                }
+                *pending = Some((image, captured_at));
+                *count += 1;
+                Ok(())
+            }
+            ClipSink::Ffmpeg { stdin, count, .. } => {
+                *count += 1;
+                stdin.write_all(jpeg)
+            }
+        }
+    }
+
+    /// 書き出しを終えてフレーム数を返す
+    fn finish(self) -> io::Result<u64> {
+        match self {
+            ClipSink::Mjpeg { writer, count } => {
+                writer.finish()?;
+                Ok(count)
+            }
+            ClipSink::Jpeg { count, .. } => Ok(count),
+            ClipSink::Gif { mut encoder, pending, last_delay_ms, count } => {
+                if let Some((image, _)) = pending {
+                    encoder.encode_frame(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(last_delay_ms, 1)))
+                        .map_err(io::Error::other)?;
+                }
+                Ok(count)
+            }
+            ClipSink::Ffmpeg { child, stdin, count } => {
+                drop(stdin);
+                wait_ffmpeg(child)?;
+                Ok(count)
+            }
+        }
+    }
+}
+
+/// MP4録画をffmpegで切り出して書き出す
+fn export_from_mp4(
+    recording: &Path,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    format: ClipFormat,
+    output: &Path,
+    key: Option<&KeySource>,
+) -> io::Result<()> {
+    let recording_start = recording_start(recording).ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("start time of {:?} is unknown", recording),
+    ))?;
+    let offset = (start - recording_start).max(Duration::zero());
+    let duration = end - start.max(recording_start);
+    if duration <= Duration::zero() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "clip ends before the recording starts"));
+    }
+    let offset = format!("{:.3}", offset.num_milliseconds() as f64 / 1000.0);
+    let duration = format!("{:.3}", duration.num_milliseconds() as f64 / 1000.0);
+
+    let mut reader = crypto::open_recording(recording, key)?;
+    let encrypted = reader.is_encrypted();
+
+    let mut command = ffmpeg_command();
+    if encrypted {
+        // 復号したストリームをstdinから読ませる（シークできないので入力後に-ss）
+        command.args(["-i", "pipe:0", "-ss", &offset, "-t", &duration]).stdin(Stdio::piped());
+    } else {
+        command.args(["-ss", &offset, "-i"]).arg(recording).args(["-t", &duration]).stdin(Stdio::null());
+    }
+    command.args(output_args(format, false));
+    if format == ClipFormat::Jpeg {
+        fs::create_dir_all(output)?;
+        command.arg(output.join("frame_%06d.jpg"));
+    } else {
+        command.arg(output);
+    }
+
+    let mut child = spawn_ffmpeg(command, format)?;
+    let feeder = child.stdin.take().map(|mut stdin| {
+        thread::spawn(move || match io::copy(&mut reader, &mut stdin) {
+            // ffmpegは切り出し範囲を読み終えると入力を閉じる
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result.map(|_| ()),
+        })
+    });
+
+    let result = wait_ffmpeg(child);
+    if let Some(feeder) = feeder {
+        feeder.join().unwrap_or_else(|_| Err(io::Error::other("decryption thread panicked")))?;
+    }
+    result
+}
+
+fn ffmpeg_command() -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-nostats", "-y"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// 出力形式ごとのffmpeg引数（入力がMJPEGフレームの場合、AVIは再エンコードしない）
+fn output_args(format: ClipFormat, from_mjpeg: bool) -> &'static [&'static str] {
+    match format {
+        ClipFormat::Mp4 => &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-movflags", "+faststart"],
+        ClipFormat::Avi if from_mjpeg => &["-c:v", "copy"],
+        ClipFormat::Avi => &["-c:v", "mjpeg", "-q:v", "3"],
+        ClipFormat::Mjpeg => &["-c:v", "mjpeg", "-q:v", "3", "-f", "mjpeg"],
+        ClipFormat::Jpeg => &["-q:v", "3"],
+        ClipFormat::Gif => &["-f", "gif"],
+        ClipFormat::Webp => &["-c:v", "libwebp_anim", "-loop", "0"],
+    }
+}
+
+fn spawn_ffmpeg(mut command: Command, format: ClipFormat) -> io::Result<Child> {
+    command.spawn().map_err(|e| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("ffmpeg is required for {} output: {}", format, e),
+    ))
+}
+
+fn wait_ffmpeg(child: Child) -> io::Result<()> {
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "ffmpeg failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn jpeg(shade: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(16, 8, image::Rgb([shade; 3]))
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        data
+    }
+
+    fn t0() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 2, 2, 14, 0).unwrap()
+    }
+
+    /// 100msごとに5フレームの録画
+    fn write_recording(path: &Path, key: Option<&KeySource>) {
+        let mut writer = MjpegWriter::create_with_key(path, key).unwrap();
+        for i in 0..5 {
+            writer.write_frame_at(&jpeg(i as u8 * 40), t0() + Duration::milliseconds(i * 100)).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_parse_clip_time() {
+        let now = Local::now();
+        assert_eq!(parse_clip_time("+90s", Some(t0()), now).unwrap(), t0() + Duration::seconds(90));
+        assert_eq!(parse_clip_time("+1.5", Some(t0()), now).unwrap(), t0() + Duration::milliseconds(1500));
+        assert_eq!(parse_clip_time("02:15:30", Some(t0()), now).unwrap(), t0() + Duration::seconds(90));
+        // 録画開始より前の時刻は翌日
+        assert_eq!(parse_clip_time("01:00", Some(t0()), now).unwrap().date_naive(), t0().date_naive() + Duration::days(1));
+        assert_eq!(parse_clip_time("2026-01-02 02:14", None, now).unwrap(), t0());
+        assert!(parse_clip_time("+90s", None, now).is_err());
+        assert!(parse_clip_time("+5x", Some(t0()), now).is_err());
+    }
+
+    #[test]
+    fn test_format_from_output_path() {
+        assert_eq!(ClipFormat::from_output_path(Path::new("clip.MP4")), Some(ClipFormat::Mp4));
+        assert_eq!(ClipFormat::from_output_path(Path::new("clip.gif")), Some(ClipFormat::Gif));
+        assert_eq!(ClipFormat::from_output_path(Path::new("frames")), Some(ClipFormat::Jpeg));
+        assert_eq!(ClipFormat::from_output_path(Path::new("clip.txt")), None);
+        for format in ClipFormat::ALL {
+            assert_eq!(format.to_string().parse::<ClipFormat>(), Ok(format));
+        }
+    }
+
+    #[test]
+    fn test_export_mjpeg_clip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeySource::KeyFile([9; 32]);
+        let recording = dir.path().join("motion_20260102_021400.mjpeg");
+        write_recording(&recording, Some(&key));
+
+        let (start, end) = (t0() + Duration::milliseconds(150), t0() + Duration::milliseconds(350));
+        let output = dir.path().join("clip.mjpeg");
+        let summary = export_clip(&recording, start, end, ClipFormat::Mjpeg, &output, Some(&key)).unwrap();
+        assert_eq!(summary.frames, Some(2));
+
+        // 出力は平文で、元の撮影時刻のインデックス付き
+        let scan = mjpeg::scan_file(&output).unwrap();
+        assert_eq!(scan.frames.len(), 2);
+        let index = mjpeg::read_index(&mjpeg::index_path(&output)).unwrap();
+        assert_eq!(index[0].captured_at, t0() + Duration::milliseconds(200));
+
+        let frames_dir = dir.path().join("frames");
+        export_clip(&recording, start, end, ClipFormat::Jpeg, &frames_dir, Some(&key)).unwrap();
+        assert_eq!(fs::read(frames_dir.join("frame_000002.jpg")).unwrap(), jpeg(120));
+
+        assert!(export_clip(&recording, start, end, ClipFormat::Mjpeg, &output, None).is_err());
+        assert!(export_clip(&recording, end, start, ClipFormat::Mjpeg, &output, Some(&key)).is_err());
+    }
+
+    #[test]
+    fn test_export_without_index_and_gif() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = dir.path().join("manual_20260102_021400.mjpeg");
+        write_recording(&recording, None);
+        fs::remove_file(mjpeg::index_path(&recording)).unwrap();
+
+        // 11fpsとみなすので、0.1〜0.2秒は2フレーム目（約0.18秒）
+        let output = dir.path().join("clip.gif");
+        let summary = export_clip(
+            &recording,
+            t0() + Duration::milliseconds(100),
+            t0() + Duration::milliseconds(200),
+            ClipFormat::Gif,
+            &output,
+            None,
+        ).unwrap();
+        assert_eq!(summary.frames, Some(1));
+        assert!(fs::read(&output).unwrap().starts_with(b"GIF89a"));
+    }
+}
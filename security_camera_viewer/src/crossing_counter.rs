@@ -0,0 +1,483 @@
+//! 通過カウント（仮想ラインの横断・ゾーンへの進入）モジュール
+//!
+//! 動き検知のかたまり（`MotionBlob`）の重心をフレーム間で追跡し（最も近い重心どうしを対応づける単純な追跡）、
+//! 追跡中の物体について次を数える:
+//! - ラインの横断: 前フレームから今フレームへの重心の移動がラインと交わったら、向きごとに数える。
+//!   `forward`はラインを始点から終点へ見て右側へ渡った場合（画面座標、下向きがy）、`backward`はその逆
+//! - ゾーンへの進入: 重心がゾーンの外から内に入ったら数える（ゾーンの中で追跡が始まった物体は数えない）
+//!
+//! 座標はすべて画像の幅・高さに対する0.0-1.0。
+//! 時間帯ごとの集計は`HourlyCountLog`がCSV（`hour,counter,kind,count`）に追記する。
+
+use crate::frame_analyzer::{AnalyzerFrame, Detection, FrameAnalyzer};
+use crate::motion_detector::{polygon_contains, MotionResult};
+use chrono::{DateTime, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// 時間帯ごとの集計の保存先（メトリクスと同じディレクトリ）
+pub const COUNT_LOG_FILE_NAME: &str = "counts.csv";
+
+/// 通過カウントの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CountingConfig {
+    /// 通過カウントON/OFF
+    pub enabled: bool,
+    /// 横断を数える仮想ライン
+    pub lines: Vec<CountingLine>,
+    /// 進入を数えるゾーン
+    pub zones: Vec<CountingZone>,
+    /// 追跡するかたまりの最小面積（画面に対する%, デフォルト0.3%）
+    pub min_blob_area: f32,
+    /// 1フレームで同じ物体とみなす重心の移動の上限（画像の幅・高さに対する割合, デフォルト0.15）
+    pub max_distance: f32,
+    /// 見失ってから追跡をやめるまでのフレーム数（デフォルト5）
+    pub max_missed_frames: u32,
+}
+
+impl Default for CountingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lines: Vec::new(),
+            zones: Vec::new(),
+            min_blob_area: 0.3,
+            max_distance: 0.15,
+            max_missed_frames: 5,
+        }
+    }
+}
+
+/// 仮想ライン（始点・終点）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountingLine {
+    pub name: String,
+    pub points: [[f32; 2]; 2],
+}
+
+/// 進入を数えるゾーン（多角形）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountingZone {
+    pub name: String,
+    pub points: Vec<[f32; 2]>,
+}
+
+/// カウントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CountKind {
+    /// ラインを始点から終点へ見て右側へ横断
+    Forward,
+    /// ラインを始点から終点へ見て左側へ横断
+    Backward,
+    /// ゾーンへの進入
+    Entry,
+}
+
+impl fmt::Display for CountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CountKind::Forward => "forward",
+            CountKind::Backward => "backward",
+            CountKind::Entry => "entry",
+        })
+    }
+}
+
+/// 1回の横断・進入
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountEvent {
+    /// ライン・ゾーンの名前
+    pub counter: String,
+    pub kind: CountKind,
+    pub track_id: u64,
+}
+
+impl fmt::Display for CountEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (#{})", self.counter, self.kind, self.track_id)
+    }
+}
+
+/// ライン・ゾーンごとの累計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub forward: u64,
+    pub backward: u64,
+    pub entries: u64,
+}
+
+/// 追跡中の物体
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub id: u64,
+    /// 重心（0.0-1.0）
+    pub centroid: [f32; 2],
+    /// 見失っているフレーム数
+    missed: u32,
+    /// ゾーンごとに、重心が中にあるか
+    inside: Vec<bool>,
+}
+
+/// 点pが有向線分a→bのどちら側にあるか（正なら右側、画面座標）
+fn side(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// 重心の移動from→toがラインを横断した向き
+fn crossing(line: &CountingLine, from: [f32; 2], to: [f32; 2]) -> Option<CountKind> {
+    let [a, b] = line.points;
+    let (before, after) = (side(a, b, from), side(a, b, to));
+    let kind = if before < 0.0 && after >= 0.0 {
+        CountKind::Forward
+    } else if before >= 0.0 && after < 0.0 {
+        CountKind::Backward
+    } else {
+        return None;
+    };
+    // ラインの端点が移動の両側にあれば、ラインの範囲内で交わっている
+    let (start, end) = (side(from, to, a), side(from, to, b));
+    ((start < 0.0) != (end < 0.0)).then_some(kind)
+}
+
+/// 動きのかたまりの重心を追跡し、ラインの横断とゾーンへの進入を数える
+pub struct CrossingCounter {
+    config: CountingConfig,
+    tracks: Vec<Track>,
+    next_id: u64,
+    totals: BTreeMap<String, Counts>,
+}
+
+impl CrossingCounter {
+    pub fn new(config: CountingConfig) -> Self {
+        Self { config, tracks: Vec::new(), next_id: 1, totals: BTreeMap::new() }
+    }
+
+    pub fn config(&self) -> &CountingConfig {
+        &self.config
+    }
+
+    /// 設定を変更する（ゾーンが変わった場合は、追跡中の物体がどのゾーンの中にいるかを測り直す）
+    pub fn set_config(&mut self, config: CountingConfig) {
+        if config.zones != self.config.zones {
+            for track in &mut self.tracks {
+                track.inside = config.zones.iter().map(|zone| polygon_contains(&zone.points, track.centroid[0], track.centroid[1])).collect();
+            }
+        }
+        self.config = config;
+    }
+
+    /// 追跡中の物体
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// ライン・ゾーンの名前ごとの累計
+    pub fn totals(&self) -> &BTreeMap<String, Counts> {
+        &self.totals
+    }
+
+    /// 累計を0に戻す（追跡は続ける）
+    pub fn reset_totals(&mut self) {
+        self.totals.clear();
+    }
+
+    /// 1フレームの重心（0.0-1.0）を反映し、このフレームの横断・進入を返す
+    pub fn update(&mut self, centroids: &[[f32; 2]]) -> Vec<CountEvent> {
+        // 近い組から順に、追跡中の物体と重心を対応づける
+        let max_distance = self.config.max_distance;
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            for (c, centroid) in centroids.iter().enumerate() {
+                let distance = (track.centroid[0] - centroid[0]).hypot(track.centroid[1] - centroid[1]);
+                if distance <= max_distance {
+                    pairs.push((distance, t, c));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut matched_track = vec![None; self.tracks.len()];
+        let mut used = vec![false; centroids.len()];
+        for (_, t, c) in pairs {
+            if matched_track[t].is_none() && !used[c] {
+                matched_track[t] = Some(c);
+                used[c] = true;
+            }
+        }
+
+        let mut events = Vec::new();
+        for (track, matched) in self.tracks.iter_mut().zip(matched_track) {
+            let Some(c) = matched else {
+                track.missed += 1;
+                continue;
+            };
+            let (from, to) = (track.centroid, centroids[c]);
+            for line in &self.config.lines {
+                if let Some(kind) = crossing(line, from, to) {
+                    events.push(CountEvent { counter: line.name.clone(), kind, track_id: track.id });
+                }
+            }
+            for (zone, inside) in self.config.zones.iter().zip(&mut track.inside) {
+                let now_inside = polygon_contains(&zone.points, to[0], to[1]);
+                if now_inside && !*inside {
+                    events.push(CountEvent { counter: zone.name.clone(), kind: CountKind::Entry, track_id: track.id });
+                }
+                *inside = now_inside;
+            }
+            track.centroid = to;
+            track.missed = 0;
+        }
+        let max_missed = self.config.max_missed_frames;
+        self.tracks.retain(|track| track.missed <= max_missed);
+
+        // 対応のなかった重心から追跡を始める
+        for (centroid, _) in centroids.iter().zip(used).filter(|(_, used)| !used) {
+            let inside = self.config.zones.iter().map(|zone| polygon_contains(&zone.points, centroid[0], centroid[1])).collect();
+            self.tracks.push(Track { id: self.next_id, centroid: *centroid, missed: 0, inside });
+            self.next_id += 1;
+        }
+
+        for event in &events {
+            let counts = self.totals.entry(event.counter.clone()).or_default();
+            match event.kind {
+                CountKind::Forward => counts.forward += 1,
+                CountKind::Backward => counts.backward += 1,
+                CountKind::Entry => counts.entries += 1,
+            }
+        }
+        events
+    }
+
+    /// 動き検知の結果から、追跡するかたまりの重心（0.0-1.0）を取り出す
+    fn centroids(&self, result: &MotionResult) -> Vec<[f32; 2]> {
+        let (width, height) = result.mask.dimensions();
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let min_area = self.config.min_blob_area / 100.0 * (width * height) as f32;
+        result.blobs
+            .iter()
+            .filter(|blob| blob.area as f32 >= min_area)
+            .map(|blob| [(blob.centroid.0 + 0.5) / width as f32, (blob.centroid.1 + 0.5) / height as f32])
+            .collect()
+    }
+}
+
+impl FrameAnalyzer for CrossingCounter {
+    fn name(&self) -> &str {
+        "counting"
+    }
+
+    fn enabled(&self) -> bool {
+        self.config.enabled && !(self.config.lines.is_empty() && self.config.zones.is_empty())
+    }
+
+    /// 先に実行した動き検知のかたまりを使う（動き検知が実行されていなければ何もしない）
+    fn analyze(&mut self, _frame: &AnalyzerFrame, earlier: &[Detection]) -> Vec<Detection> {
+        let Some(result) = earlier.iter().find_map(|detection| match detection {
+            Detection::Motion(result) => Some(result),
+            _ => None,
+        }) else {
+            return Vec::new();
+        };
+        let centroids = self.centroids(result);
+        vec![Detection::Counts(self.update(&centroids))]
+    }
+}
+
+/// 時間帯ごとの集計をCSVに追記する
+///
+/// 1時間が終わるたびに、その時間帯のライン・ゾーン・種類ごとの件数を1行ずつ書く（0件の行は書かない）。
+/// 途中の時間帯はドロップ時に書くため、同じ時間帯の行が複数の実行に分かれることがある（合計すればよい）。
+pub struct HourlyCountLog {
+    path: PathBuf,
+    hour: Option<DateTime<Local>>,
+    counts: BTreeMap<(String, CountKind), u64>,
+}
+
+impl HourlyCountLog {
+    /// `dir`の`counts.csv`に追記する（なければヘッダーを書いて作る）
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(COUNT_LOG_FILE_NAME);
+        if !path.exists() {
+            let mut file = File::create(&path)?;
+            writeln!(file, "hour,counter,kind,count")?;
+        }
+        Ok(Self { path, hour: None, counts: BTreeMap::new() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 1フレーム分の横断・進入を加える（時間帯が変わっていれば、前の時間帯を書き出す）
+    pub fn record(&mut self, now: DateTime<Local>, events: &[CountEvent]) -> io::Result<()> {
+        let hour = now.with_minute(0).and_then(|t| t.with_second(0)).and_then(|t| t.with_nanosecond(0)).unwrap_or(now);
+        if self.hour != Some(hour) {
+            self.flush()?;
+            self.hour = Some(hour);
+        }
+        for event in events {
+            *self.counts.entry((event.counter.clone(), event.kind)).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    /// 今の時間帯の件数を書き出す
+    pub fn flush(&mut self) -> io::Result<()> {
+        let Some(hour) = self.hour else {
+            return Ok(());
+        };
+        if self.counts.is_empty() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        for ((counter, kind), count) in std::mem::take(&mut self.counts) {
+            writeln!(file, "{},{},{},{}", hour.format("%Y-%m-%d %H:00"), counter.replace(',', " "), kind, count)?;
+        }
+        file.flush()
+    }
+}
+
+impl Drop for HourlyCountLog {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Failed to write hourly counts to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion_detector::{BoundingBox, MotionBlob};
+    use chrono::TimeZone;
+    use image::GrayImage;
+
+    /// 画面の中央を左から右へ横切るライン（下へ渡るとforward）と、右下のゾーン
+    fn counter() -> CrossingCounter {
+        CrossingCounter::new(CountingConfig {
+            enabled: true,
+            lines: vec![CountingLine { name: "door".to_string(), points: [[0.1, 0.5], [0.9, 0.5]] }],
+            zones: vec![CountingZone { name: "lobby".to_string(), points: vec![[0.6, 0.6], [1.0, 0.6], [1.0, 1.0], [0.6, 1.0]] }],
+            ..CountingConfig::default()
+        })
+    }
+
+    /// 80x60のマスク上の動き: 重心(29.5, y)の40画素のかたまりと、4画素のノイズ（0.3%は14.4画素）
+    fn motion(y: f32) -> Detection {
+        let blob = |area: u32, y: f32| MotionBlob {
+            bbox: BoundingBox { x: 0, y: 0, width: 1, height: 1 },
+            centroid: (29.5, y),
+            area,
+        };
+        Detection::Motion(MotionResult {
+            motion_detected: true,
+            mask: GrayImage::new(80, 60),
+            blobs: vec![blob(40, y), blob(4, 5.0)],
+            ..MotionResult::default()
+        })
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 5, 6, hour, minute, 0).unwrap()
+    }
+
+    fn kinds(events: &[CountEvent]) -> Vec<(&str, CountKind)> {
+        events.iter().map(|event| (event.counter.as_str(), event.kind)).collect()
+    }
+
+    #[test]
+    fn test_line_crossing_both_directions() {
+        let mut counter = counter();
+        assert!(counter.update(&[[0.3, 0.3]]).is_empty());
+        assert!(counter.update(&[[0.3, 0.42]]).is_empty());
+        assert_eq!(kinds(&counter.update(&[[0.3, 0.55]])), [("door", CountKind::Forward)]);
+        assert_eq!(kinds(&counter.update(&[[0.32, 0.45]])), [("door", CountKind::Backward)]);
+        assert_eq!(counter.tracks().len(), 1);
+        assert_eq!(counter.totals()["door"], Counts { forward: 1, backward: 1, entries: 0 });
+    }
+
+    #[test]
+    fn test_crossing_outside_the_line_is_ignored() {
+        let mut counter = counter();
+        counter.update(&[[0.95, 0.45]]);
+        assert!(counter.update(&[[0.95, 0.55]]).is_empty());
+    }
+
+    #[test]
+    fn test_zone_entry() {
+        let mut counter = counter();
+        // ゾーンの中で現れた物体は進入に数えない
+        counter.update(&[[0.8, 0.8], [0.3, 0.3]]);
+        assert!(counter.update(&[[0.82, 0.8], [0.3, 0.3]]).is_empty());
+
+        // 外からゾーンに入り、出て、また入る
+        let mut counter = self::counter();
+        counter.update(&[[0.5, 0.7]]);
+        assert_eq!(kinds(&counter.update(&[[0.62, 0.7]])), [("lobby", CountKind::Entry)]);
+        assert!(counter.update(&[[0.55, 0.7]]).is_empty());
+        assert_eq!(kinds(&counter.update(&[[0.65, 0.7]])), [("lobby", CountKind::Entry)]);
+        assert_eq!(counter.totals()["lobby"].entries, 2);
+    }
+
+    #[test]
+    fn test_tracks_match_nearest_and_expire() {
+        let mut counter = counter();
+        counter.update(&[[0.2, 0.2], [0.7, 0.2]]);
+        let ids: Vec<u64> = counter.tracks().iter().map(|track| track.id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        // 逆の順で渡しても、それぞれ同じIDのまま
+        counter.update(&[[0.72, 0.22], [0.22, 0.22]]);
+        assert_eq!(counter.tracks()[0].centroid, [0.22, 0.22]);
+        assert_eq!(counter.tracks()[1].centroid, [0.72, 0.22]);
+
+        // 同じ物体とみなすには遠すぎるので新しい追跡になる
+        counter.update(&[[0.22, 0.9]]);
+        assert_eq!(counter.tracks().len(), 3);
+        for _ in 0..6 {
+            counter.update(&[]);
+        }
+        assert!(counter.tracks().is_empty());
+    }
+
+    #[test]
+    fn test_analyzer_follows_motion_blobs() {
+        let mut counter = counter();
+        let image = image::RgbaImage::new(80, 60);
+        let frame = AnalyzerFrame { jpeg: None, image: Some(&image), captured_at: Local::now(), sequence: 0 };
+
+        assert!(counter.analyze(&frame, &[]).is_empty());
+        counter.analyze(&frame, &[motion(27.5)]);
+        let found = counter.analyze(&frame, &[motion(32.5)]);
+        assert!(matches!(found.as_slice(), [Detection::Counts(events)] if kinds(events) == [("door", CountKind::Forward)]));
+        assert_eq!(counter.tracks().len(), 1);
+    }
+
+    #[test]
+    fn test_hourly_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let event = |counter: &str, kind: CountKind| CountEvent { counter: counter.to_string(), kind, track_id: 1 };
+
+        let mut log = HourlyCountLog::create(dir.path()).unwrap();
+        log.record(at(9, 10), &[event("door", CountKind::Forward), event("door", CountKind::Forward)]).unwrap();
+        log.record(at(9, 50), &[event("lobby", CountKind::Entry)]).unwrap();
+        log.record(at(10, 5), &[event("door", CountKind::Backward)]).unwrap();
+        let written = fs::read_to_string(log.path()).unwrap();
+        assert_eq!(written, "hour,counter,kind,count\n2026-05-06 09:00,door,forward,2\n2026-05-06 09:00,lobby,entry,1\n");
+
+        // 集計中の時間帯は破棄時に書き込まれ、新しいログは同じファイルに追記する
+        drop(log);
+        let mut log = HourlyCountLog::create(dir.path()).unwrap();
+        log.record(at(11, 0), &[]).unwrap();
+        drop(log);
+        let written = fs::read_to_string(dir.path().join(COUNT_LOG_FILE_NAME)).unwrap();
+        assert!(written.ends_with("2026-05-06 09:00,lobby,entry,1\n2026-05-06 10:00,door,backward,1\n"), "{}", written);
+    }
+}
@@ -0,0 +1,679 @@
+//! 録画ファイル暗号化モジュール
+//!
+//! XChaCha20-Poly1305による認証付き暗号で録画を保存する。
+//! 鍵はパスフレーズ（PBKDF2-HMAC-SHA256）またはキーファイル（32バイト）。
+//! 録画時はパスフレーズからの鍵導出をセッションごとに1回だけ行い（`KeySource::derive`）、
+//! そのセッションのファイルは同じソルトを使う（ファイルごとのノンス接頭辞はランダム）。
+//!
+//! ファイル形式:
+//! ```text
+//! ヘッダー（51バイト）: "SCAMENC1" | KDF(1) | 予約(3) | 反復回数(4, BE) | ソルト(16) | ノンス接頭辞(19)
+//! チャンク（繰り返し）: フラグ(1, 1=最終) | 暗号文長(4, BE) | 暗号文（平文 + 16バイトのタグ）
+//! ```
+//!
+//! ノンスは「接頭辞 | チャンク番号(4, BE) | フラグ(1)」、追加認証データはヘッダー全体。
+//! チャンクの並べ替え・削除・末尾の切り落としは認証エラーまたは最終チャンクの欠落として検出できる。
+//! 書き込み途中で落ちたファイルも、最後の完全なチャンクまでは復号できる。
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// ファイル先頭のマジックナンバー
+pub const MAGIC: &[u8; 8] = b"SCAMENC1";
+/// ヘッダー長
+pub const HEADER_LEN: usize = 51;
+/// 平文チャンクの最大長
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// パスフレーズからの鍵導出の反復回数
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = 19;
+const CHUNK_HEADER_LEN: u64 = 5;
+const FLAG_LAST: u8 = 1;
+
+const KDF_KEY_FILE: u8 = 0;
+const KDF_PBKDF2_SHA256: u8 = 1;
+
+/// 暗号鍵の取得元
+#[derive(Clone)]
+pub enum KeySource {
+    /// パスフレーズ（ファイルごとのソルトで鍵を導出）
+    Passphrase(String),
+    /// キーファイルの32バイト鍵
+    KeyFile([u8; 32]),
+    /// パスフレーズから導出済みの鍵（書き込むファイルはすべてこのソルトを使う）
+    Derived(DerivedKey),
+}
+
+/// セッションのソルトでパスフレーズから導出した鍵
+///
+/// 別のソルトのファイルを読む場合は、パスフレーズから改めて導出する。
+#[derive(Clone)]
+pub struct DerivedKey {
+    passphrase: String,
+    salt: [u8; SALT_LEN],
+    iterations: u32,
+    key: [u8; 32],
+}
+
+fn pbkdf2_key(passphrase: &str, salt: &[u8; SALT_LEN], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+impl KeySource {
+    /// キーファイルを読み込む（64文字の16進、または32バイトのバイナリ）
+    pub fn from_key_file(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let key = std::str::from_utf8(&data).ok()
+            .and_then(|text| hex::decode(text.trim()).ok())
+            .unwrap_or(data);
+        let key: [u8; 32] = key.try_into().map_err(|_| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("key file {:?} must contain 32 bytes (or 64 hex characters)", path),
+        ))?;
+        Ok(KeySource::KeyFile(key))
+    }
+
+    /// ランダムな鍵を生成してキーファイル（16進）に書き出す
+    pub fn generate_key_file(path: &Path) -> io::Result<Self> {
+        let mut key = [0u8; 32];
+        getrandom::getrandom(&mut key)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all((hex::encode(key) + "\n").as_bytes())?;
+
+        Ok(KeySource::KeyFile(key))
+    }
+
+    /// 録画セッション用の鍵にする（パスフレーズなら新しいソルトで1回だけ導出する）
+    ///
+    /// ファイルごとに鍵を導出すると、反復回数のぶん1ファイルあたり数百msかかるため、
+    /// 個別フレームの保存や録画の開始前に1回呼んでおく。
+    pub fn derive(&self) -> io::Result<KeySource> {
+        self.derive_with_iterations(PBKDF2_ITERATIONS)
+    }
+
+    fn derive_with_iterations(&self, iterations: u32) -> io::Result<KeySource> {
+        match self {
+            KeySource::Passphrase(passphrase) => {
+                let mut salt = [0u8; SALT_LEN];
+                getrandom::getrandom(&mut salt)?;
+                Ok(KeySource::Derived(DerivedKey {
+                    passphrase: passphrase.clone(),
+                    salt,
+                    iterations,
+                    key: pbkdf2_key(passphrase, &salt, iterations),
+                }))
+            }
+            other => Ok(other.clone()),
+        }
+    }
+}
+
+/// ファイルヘッダー
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    kdf: u8,
+    iterations: u32,
+    salt: [u8; SALT_LEN],
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl Header {
+    fn generate(source: &KeySource) -> io::Result<Self> {
+        let mut header = Header {
+            kdf: match source {
+                KeySource::Passphrase(_) | KeySource::Derived(_) => KDF_PBKDF2_SHA256,
+                KeySource::KeyFile(_) => KDF_KEY_FILE,
+            },
+            iterations: match source {
+                KeySource::Passphrase(_) => PBKDF2_ITERATIONS,
+                KeySource::Derived(derived) => derived.iterations,
+                KeySource::KeyFile(_) => 0,
+            },
+            salt: [0; SALT_LEN],
+            nonce_prefix: [0; NONCE_PREFIX_LEN],
+        };
+        match source {
+            KeySource::Derived(derived) => header.salt = derived.salt,
+            _ => getrandom::getrandom(&mut header.salt)?,
+        }
+        getrandom::getrandom(&mut header.nonce_prefix)?;
+        Ok(header)
+    }
+
+    fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8] = self.kdf;
+        bytes[12..16].copy_from_slice(&self.iterations.to_be_bytes());
+        bytes[16..32].copy_from_slice(&self.salt);
+        bytes[32..].copy_from_slice(&self.nonce_prefix);
+        bytes
+    }
+
+    fn parse(bytes: &[u8; HEADER_LEN]) -> io::Result<Self> {
+        if &bytes[..8] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an encrypted recording"));
+        }
+        Ok(Header {
+            kdf: bytes[8],
+            iterations: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            salt: bytes[16..32].try_into().unwrap(),
+            nonce_prefix: bytes[32..].try_into().unwrap(),
+        })
+    }
+
+    fn cipher(&self, source: &KeySource) -> io::Result<XChaCha20Poly1305> {
+        let key = match (self.kdf, source) {
+            (KDF_PBKDF2_SHA256, KeySource::Passphrase(passphrase)) => pbkdf2_key(passphrase, &self.salt, self.iterations),
+            (KDF_PBKDF2_SHA256, KeySource::Derived(derived)) if derived.salt == self.salt && derived.iterations == self.iterations => {
+                derived.key
+            }
+            (KDF_PBKDF2_SHA256, KeySource::Derived(derived)) => pbkdf2_key(&derived.passphrase, &self.salt, self.iterations),
+            (KDF_KEY_FILE, KeySource::KeyFile(key)) => *key,
+            (KDF_PBKDF2_SHA256, _) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is encrypted with a passphrase, not a key file"));
+            }
+            (KDF_KEY_FILE, _) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is encrypted with a key file, not a passphrase"));
+            }
+            (kdf, _) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown key derivation: {}", kdf)));
+            }
+        };
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    fn nonce(&self, counter: u32, flags: u8) -> XNonce {
+        let mut nonce = [0u8; 24];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[19..23].copy_from_slice(&counter.to_be_bytes());
+        nonce[23] = flags;
+        nonce.into()
+    }
+}
+
+/// 暗号化ライター
+///
+/// 書き込まれたデータをチャンク単位で暗号化する。`flush_chunk()`（`flush()`）で
+/// バッファ中のデータを短いチャンクとして書き出せるため、定期的な同期と組み合わせて
+/// 電源断時に失われる範囲を抑えられる。`finish()`で最終チャンクを書く。
+pub struct EncryptedWriter<W: Write> {
+    /// `finish()`後はNone
+    inner: Option<W>,
+    cipher: XChaCha20Poly1305,
+    header: Header,
+    header_bytes: [u8; HEADER_LEN],
+    counter: u32,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    /// ヘッダーを書き込んで暗号化ライターを作成
+    pub fn new(mut inner: W, source: &KeySource) -> io::Result<Self> {
+        let header = Header::generate(source)?;
+        let header_bytes = header.to_bytes();
+        let cipher = header.cipher(source)?;
+        inner.write_all(&header_bytes)?;
+
+        Ok(Self {
+            inner: Some(inner),
+            cipher,
+            header,
+            header_bytes,
+            counter: 0,
+            buffer: Vec::with_capacity(DEFAULT_CHUNK_SIZE),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// バッファ中のデータを（最終でない）チャンクとして書き出す
+    pub fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let data = std::mem::take(&mut self.buffer);
+        self.write_chunk(&data, 0)?;
+        self.buffer = data;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// 内部のライター
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("writer already finished")
+    }
+
+    /// 最終チャンクを書き込んで内部のライターを返す
+    pub fn finish(mut self) -> io::Result<W> {
+        let data = std::mem::take(&mut self.buffer);
+        self.write_chunk(&data, FLAG_LAST)?;
+        let mut inner = self.inner.take().expect("writer already finished");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn write_chunk(&mut self, data: &[u8], flags: u8) -> io::Result<()> {
+        let ciphertext = self.cipher
+            .encrypt(&self.header.nonce(self.counter, flags), Payload { msg: data, aad: &self.header_bytes })
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.counter = self.counter.checked_add(1)
+            .ok_or_else(|| io::Error::other("too many chunks"))?;
+
+        let inner = self.inner.as_mut().expect("writer already finished");
+        inner.write_all(&[flags])?;
+        inner.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+        inner.write_all(&ciphertext)
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= self.chunk_size {
+            let rest = self.buffer.split_off(self.chunk_size);
+            let chunk = std::mem::replace(&mut self.buffer, rest);
+            self.write_chunk(&chunk, 0)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_chunk()?;
+        self.inner.as_mut().expect("writer already finished").flush()
+    }
+}
+
+impl<W: Write> Drop for EncryptedWriter<W> {
+    fn drop(&mut self) {
+        // finish()されずに破棄された場合も、書き込み済みのデータは復号できるようにする
+        if self.inner.is_some() {
+            let _ = self.flush_chunk();
+        }
+    }
+}
+
+/// 復号リーダー
+///
+/// 最終チャンクの前にファイルが終わっている場合（書き込み途中で停止したファイル）は、
+/// 完全なチャンクまでを返して`is_truncated()`がtrueになる。
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    cipher: XChaCha20Poly1305,
+    header: Header,
+    header_bytes: [u8; HEADER_LEN],
+    counter: u32,
+    plain: Vec<u8>,
+    pos: usize,
+    finished: bool,
+    truncated: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// ヘッダーを読み込んで復号リーダーを作成
+    pub fn new(mut inner: R, source: &KeySource) -> io::Result<Self> {
+        let mut header_bytes = [0u8; HEADER_LEN];
+        inner.read_exact(&mut header_bytes)?;
+        let header = Header::parse(&header_bytes)?;
+        let cipher = header.cipher(source)?;
+
+        Ok(Self {
+            inner,
+            cipher,
+            header,
+            header_bytes,
+            counter: 0,
+            plain: Vec::new(),
+            pos: 0,
+            finished: false,
+            truncated: false,
+        })
+    }
+
+    /// 最終チャンクがなかった（書き込み途中で停止したファイル）
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// 次のチャンクを復号する。ファイル終端ならfalse
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        let mut chunk_header = [0u8; CHUNK_HEADER_LEN as usize];
+        if !read_full(&mut self.inner, &mut chunk_header)? {
+            self.truncated = true;
+            return Ok(false);
+        }
+        let flags = chunk_header[0];
+        let len = u32::from_be_bytes([chunk_header[1], chunk_header[2], chunk_header[3], chunk_header[4]]) as usize;
+        if !(TAG_LEN..=DEFAULT_CHUNK_SIZE * 16 + TAG_LEN).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid chunk #{} length {}", self.counter, len)));
+        }
+
+        let mut ciphertext = vec![0u8; len];
+        if !read_full(&mut self.inner, &mut ciphertext)? {
+            self.truncated = true;
+            return Ok(false);
+        }
+
+        self.plain = self.cipher
+            .decrypt(&self.header.nonce(self.counter, flags), Payload { msg: &ciphertext, aad: &self.header_bytes })
+            .map_err(|_| io::Error::new(
+                io::ErrorKind::InvalidData,
+                if self.counter == 0 {
+                    "decryption failed: wrong key or corrupted file".to_string()
+                } else {
+                    format!("decryption failed at chunk #{}: file was modified or chunks were reordered", self.counter)
+                },
+            ))?;
+        self.pos = 0;
+        self.counter += 1;
+
+        if flags & FLAG_LAST != 0 {
+            self.finished = true;
+            let mut extra = [0u8; 1];
+            if self.inner.read(&mut extra)? != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected data after the final chunk"));
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.finished || self.truncated || !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// bufを埋めるまで読む。途中で終端に達した場合はfalse（先頭で終端の場合もfalse）
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// 暗号化されたファイルか（先頭のマジックナンバーで判定）
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file = File::open(path)?;
+    Ok(read_full(&mut file, &mut magic)? && &magic == MAGIC)
+}
+
+/// 録画ファイルのリーダー（平文または復号）
+pub enum RecordingReader {
+    Plain(BufReader<File>),
+    Encrypted(DecryptingReader<BufReader<File>>),
+}
+
+impl RecordingReader {
+    /// 暗号化されたファイルか
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::Encrypted(_))
+    }
+
+    /// 最終チャンクがないまま終わったか（平文ファイルでは常にfalse）
+    pub fn is_truncated(&self) -> bool {
+        match self {
+            Self::Plain(_) => false,
+            Self::Encrypted(reader) => reader.is_truncated(),
+        }
+    }
+}
+
+impl Read for RecordingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(reader) => reader.read(buf),
+            Self::Encrypted(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// 録画ファイルを開く（暗号化されていれば復号しながら読む）
+///
+/// # Errors
+/// - 暗号化されたファイルで鍵が指定されていない場合
+/// - 鍵が違う場合
+pub fn open_recording(path: &Path, key: Option<&KeySource>) -> io::Result<RecordingReader> {
+    let file = BufReader::new(File::open(path)?);
+    if !is_encrypted(path)? {
+        return Ok(RecordingReader::Plain(file));
+    }
+
+    let key = key.ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{:?} is encrypted; a passphrase or key file is required", path),
+    ))?;
+    Ok(RecordingReader::Encrypted(DecryptingReader::new(file, key)?))
+}
+
+/// 小さなファイル（個別フレームやスナップショット）を書き込む（鍵が指定されていれば暗号化）
+pub fn write_with_key<W: Write>(mut writer: W, data: &[u8], key: Option<&KeySource>) -> io::Result<()> {
+    match key {
+        Some(key) => {
+            let mut writer = EncryptedWriter::new(writer, key)?;
+            writer.write_all(data)?;
+            writer.finish()?;
+        }
+        None => writer.write_all(data)?,
+    }
+    Ok(())
+}
+
+/// 暗号化ファイルの途切れた末尾チャンクを切り詰める（鍵は不要）
+///
+/// # Returns
+/// (完全なチャンク数, それらの平文の長さ, 削除したバイト数)
+pub fn truncate_torn_chunk(path: &Path) -> io::Result<(u64, u64, u64)> {
+    let mut file = File::open(path)?;
+    let total_len = file.seek(SeekFrom::End(0))?;
+    let mut offset = HEADER_LEN as u64;
+    let mut chunks = 0u64;
+    let mut plain_len = 0u64;
+
+    while offset + CHUNK_HEADER_LEN <= total_len {
+        file.seek(SeekFrom::Start(offset + 1))?;
+        let mut len = [0u8; 4];
+        file.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as u64;
+        let end = offset + CHUNK_HEADER_LEN + len;
+        if end > total_len {
+            break;
+        }
+        offset = end;
+        chunks += 1;
+        plain_len += len.saturating_sub(TAG_LEN as u64);
+    }
+
+    let valid_len = offset.min(total_len);
+    let removed = total_len - valid_len;
+    if removed > 0 {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid_len)?;
+        file.sync_all()?;
+    }
+
+    Ok((chunks, plain_len, removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn key() -> KeySource {
+        KeySource::KeyFile([7; 32])
+    }
+
+    fn encrypt(data: &[u8], chunk_size: usize, flush_every: Option<usize>) -> Vec<u8> {
+        let mut writer = EncryptedWriter::new(Vec::new(), &key()).unwrap();
+        writer.chunk_size = chunk_size;
+        for (i, part) in data.chunks(10).enumerate() {
+            writer.write_all(part).unwrap();
+            if flush_every.is_some_and(|n| i % n == n - 1) {
+                writer.flush_chunk().unwrap();
+            }
+        }
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(data: &[u8], key: &KeySource) -> io::Result<(Vec<u8>, bool)> {
+        let mut reader = DecryptingReader::new(Cursor::new(data), key)?;
+        let mut plain = Vec::new();
+        reader.read_to_end(&mut plain)?;
+        Ok((plain, reader.is_truncated()))
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        for (chunk_size, flush_every) in [(DEFAULT_CHUNK_SIZE, None), (64, None), (64, Some(3))] {
+            let encrypted = encrypt(&data, chunk_size, flush_every);
+            assert_eq!(&encrypted[..8], MAGIC);
+            assert_eq!(decrypt(&encrypted, &key()).unwrap(), (data.clone(), false));
+        }
+
+        // 空のファイル
+        assert_eq!(decrypt(&encrypt(&[], 64, None), &key()).unwrap(), (Vec::new(), false));
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let source = KeySource::Passphrase("correct horse".to_string());
+        let mut header = Header::generate(&source).unwrap();
+        header.iterations = 1000; // テストを速くする
+        let header_bytes = header.to_bytes();
+
+        let cipher = header.cipher(&source).unwrap();
+        let ciphertext = cipher.encrypt(&header.nonce(0, FLAG_LAST), Payload { msg: b"frame", aad: &header_bytes }).unwrap();
+        let mut data = header_bytes.to_vec();
+        data.push(FLAG_LAST);
+        data.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        data.extend_from_slice(&ciphertext);
+
+        assert_eq!(decrypt(&data, &source).unwrap().0, b"frame");
+        assert!(decrypt(&data, &KeySource::Passphrase("wrong".to_string())).is_err());
+        assert!(decrypt(&data, &key()).is_err());
+    }
+
+    #[test]
+    fn test_derived_key() {
+        let passphrase = KeySource::Passphrase("correct horse".to_string());
+        let derived = passphrase.derive_with_iterations(1000).unwrap();
+        let KeySource::Derived(session) = &derived else { panic!() };
+
+        // セッションのファイルは同じソルトで、ノンス接頭辞だけが異なる
+        let encrypt_with = |source: &KeySource, data: &[u8]| {
+            let mut writer = EncryptedWriter::new(Vec::new(), source).unwrap();
+            writer.write_all(data).unwrap();
+            writer.finish().unwrap()
+        };
+        let first = encrypt_with(&derived, b"frame 1");
+        let second = encrypt_with(&derived, b"frame 2");
+        assert_eq!(first[16..32], session.salt);
+        assert_eq!(first[16..32], second[16..32]);
+        assert_ne!(first[32..HEADER_LEN], second[32..HEADER_LEN]);
+
+        // パスフレーズだけでも復号でき、導出済みの鍵で別のソルトのファイルも読める
+        assert_eq!(decrypt(&first, &passphrase).unwrap().0, b"frame 1");
+        assert_eq!(decrypt(&second, &derived).unwrap().0, b"frame 2");
+        let other_session = passphrase.derive_with_iterations(1000).unwrap();
+        assert_eq!(decrypt(&first, &other_session).unwrap().0, b"frame 1");
+        assert!(decrypt(&first, &KeySource::Passphrase("wrong".to_string()).derive_with_iterations(1000).unwrap()).is_err());
+        assert!(decrypt(&first, &key()).is_err());
+
+        // キーファイルはそのまま
+        assert!(matches!(key().derive().unwrap(), KeySource::KeyFile(k) if k == [7; 32]));
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering() {
+        let data = vec![0x42u8; 300];
+        let encrypted = encrypt(&data, 64, None);
+
+        assert!(decrypt(&encrypted, &KeySource::KeyFile([8; 32])).is_err());
+
+        let mut modified = encrypted.clone();
+        let n = modified.len();
+        modified[n / 2] ^= 1;
+        assert!(decrypt(&modified, &key()).is_err());
+
+        // 最終チャンクを切り落とすと、それまでのデータは読めるが途切れたことがわかる
+        let chunk_len = CHUNK_HEADER_LEN as usize + 64 + TAG_LEN;
+        let cut = &encrypted[..HEADER_LEN + chunk_len * 2];
+        assert_eq!(decrypt(cut, &key()).unwrap(), (data[..128].to_vec(), true));
+    }
+
+    #[test]
+    fn test_truncate_torn_chunk_and_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("motion.mjpeg");
+        let data = vec![0x11u8; 200];
+
+        let mut writer = EncryptedWriter::new(File::create(&path).unwrap(), &key()).unwrap();
+        writer.chunk_size = 64;
+        writer.write_all(&data).unwrap();
+        drop(writer); // finish()せずに終了（クラッシュ相当）
+
+        // 書きかけのチャンクを追加
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0, 0, 0, 1, 0, 0xAA]).unwrap();
+        drop(file);
+
+        assert!(is_encrypted(&path).unwrap());
+        assert_eq!(truncate_torn_chunk(&path).unwrap(), (4, 200, 6));
+
+        let mut plain = Vec::new();
+        open_recording(&path, Some(&key())).unwrap().read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, data);
+
+        assert!(open_recording(&path, None).is_err());
+    }
+
+    #[test]
+    fn test_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording.key");
+
+        let KeySource::KeyFile(generated) = KeySource::generate_key_file(&path).unwrap() else { panic!() };
+        let KeySource::KeyFile(loaded) = KeySource::from_key_file(&path).unwrap() else { panic!() };
+        assert_eq!(generated, loaded);
+
+        // 既存のキーファイルは上書きしない
+        assert!(KeySource::generate_key_file(&path).is_err());
+
+        fs::write(&path, "too short").unwrap();
+        assert!(KeySource::from_key_file(&path).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! 静止シーンのフレーム間引き
+//!
+//! 最後に残したフレームとほとんど変わらないフレームを録画から捨てて容量を節約する。
+//! 比較は縮小したグレースケール画像に`MotionDetector`と同じ差分判定をかけて行う
+//! （縮小でセンサーノイズが平均化され、細かいノイズでは「変化あり」にならない）。
+//! 静止が続いても`keepalive_secs`ごとに1フレームは残す。
+//!
+//! 捨てたフレームの扱いは録画形式ごとに異なる（`RecordingWriter::skip_frame`）:
+//! - MJPEG: インデックスに残したフレームの撮影時刻だけが記録され、捨てた区間は時刻の空白になる
+//! - MP4: 固定フレームレートの時間軸を保つため、直前に残したフレームを繰り返す
+//!   （同一フレームはエンコーダーがほぼ0バイトに圧縮する）
+
+use crate::motion_detector::MotionDetector;
+use chrono::{DateTime, Duration, Local};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, ImageFormat};
+use log::warn;
+
+/// 比較用に縮小する画像サイズ
+const COMPARE_WIDTH: u32 = 64;
+const COMPARE_HEIGHT: u32 = 48;
+/// 変化ありとみなす1ピクセルの輝度差
+const PIXEL_THRESHOLD: u8 = 12;
+
+/// 間引き設定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DedupConfig {
+    /// これ以下の変化（%、縮小画像で変化したピクセルの割合）なら同じフレームとみなす
+    pub max_change_percent: f32,
+    /// 静止中でもこの間隔（秒）で1フレームは残す
+    pub keepalive_secs: u32,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            max_change_percent: 0.5,
+            keepalive_secs: 5,
+        }
+    }
+}
+
+/// 間引きの統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// 残したフレーム数
+    pub kept: u64,
+    /// 捨てたフレーム数
+    pub dropped: u64,
+}
+
+/// 比較用の縮小グレースケール画像を作る
+///
+/// デコード済みの画像がある場合（GUIのキャプチャスレッド）はこれで作って`should_keep_thumbnail`に渡せば、
+/// 判定のためにJPEGをもう一度デコードせずに済む。
+pub fn thumbnail(image: &DynamicImage) -> GrayImage {
+    imageops::resize(&image.to_luma8(), COMPARE_WIDTH, COMPARE_HEIGHT, FilterType::Triangle)
+}
+
+/// 最後に残したフレームと比較してフレームを間引く
+pub struct FrameDeduplicator {
+    config: DedupConfig,
+    /// 最後に残したフレーム（縮小グレースケール）
+    last_kept: Option<GrayImage>,
+    /// 最後に残したフレームの撮影時刻
+    last_kept_at: Option<DateTime<Local>>,
+    stats: DedupStats,
+}
+
+impl FrameDeduplicator {
+    pub fn new(config: DedupConfig) -> Self {
+        Self { config, last_kept: None, last_kept_at: None, stats: DedupStats::default() }
+    }
+
+    /// 設定を更新（比較対象のフレームはそのまま）
+    pub fn set_config(&mut self, config: DedupConfig) {
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        self.stats
+    }
+
+    /// JPEGフレームを残すか判定する（残す場合は比較対象をこのフレームに更新する）
+    ///
+    /// デコードできないフレームは比較できないので残す。
+    pub fn should_keep(&mut self, jpeg_data: &[u8], captured_at: DateTime<Local>) -> bool {
+        let thumbnail = match image::load_from_memory_with_format(jpeg_data, ImageFormat::Jpeg) {
+            Ok(image) => Some(thumbnail(&image)),
+            Err(e) => {
+                warn!("Frame not checked for duplicates: {}", e);
+                None
+            }
+        };
+        self.should_keep_thumbnail(thumbnail, captured_at)
+    }
+
+    /// `thumbnail`で縮小済みの画像で判定する（Noneはデコードできず比較できないフレーム）
+    pub fn should_keep_thumbnail(&mut self, thumbnail: Option<GrayImage>, captured_at: DateTime<Local>) -> bool {
+        let keepalive = Duration::seconds(self.config.keepalive_secs.max(1) as i64);
+        let duplicate = match (&self.last_kept, &thumbnail, self.last_kept_at) {
+            (Some(last), Some(current), Some(last_at)) => {
+                captured_at - last_at < keepalive
+                    && MotionDetector::changed_ratio(last, current, PIXEL_THRESHOLD) <= self.config.max_change_percent
+            }
+            _ => false,
+        };
+
+        if duplicate {
+            self.stats.dropped += 1;
+            return false;
+        }
+
+        if thumbnail.is_some() {
+            self.last_kept = thumbnail;
+        }
+        self.last_kept_at = Some(captured_at);
+        self.stats.kept += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mjpeg;
+    use crate::mp4_recorder::Mp4EncoderConfig;
+    use crate::recording::{RecordingFormat, RecordingWriter};
+    use chrono::TimeZone;
+    use image::{Luma, Rgb, RgbImage};
+
+    fn at(ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 2, 12, 0, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    fn gray(value: u8) -> Option<GrayImage> {
+        Some(GrayImage::from_pixel(COMPARE_WIDTH, COMPARE_HEIGHT, Luma([value])))
+    }
+
+    fn jpeg(value: u8) -> Vec<u8> {
+        let image = RgbImage::from_pixel(160, 120, Rgb([value, value, value]));
+        let mut jpeg = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg).unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn test_dedup_drops_static_frames_with_keepalive() {
+        let mut dedup = FrameDeduplicator::new(DedupConfig { keepalive_secs: 2, ..DedupConfig::default() });
+
+        // 静止シーン（11fps、3秒）: 最初のフレームと2秒ごとのフレームだけ残る
+        let kept: Vec<i64> = (0..33)
+            .map(|i| i * 1000 / 11)
+            .filter(|&ms| dedup.should_keep_thumbnail(gray(100), at(ms)))
+            .collect();
+        assert_eq!(kept, vec![0, 2000]);
+        assert_eq!(dedup.stats(), DedupStats { kept: 2, dropped: 31 });
+
+        // 変化したフレームは残り、以降はそのフレームと比較する
+        assert!(dedup.should_keep_thumbnail(gray(160), at(3100)));
+        assert!(!dedup.should_keep_thumbnail(gray(165), at(3200)));
+        // 比較できないフレームは残す
+        assert!(dedup.should_keep_thumbnail(None, at(3300)));
+        assert!(!dedup.should_keep_thumbnail(gray(160), at(3400)));
+    }
+
+    #[test]
+    fn test_dedup_small_change_is_duplicate() {
+        let mut dedup = FrameDeduplicator::new(DedupConfig::default());
+        assert!(dedup.should_keep(&jpeg(80), at(0)));
+        assert!(!dedup.should_keep(&jpeg(82), at(100)));
+
+        // 画面の一部だけ動いたフレームは残す
+        let mut moved = GrayImage::from_pixel(COMPARE_WIDTH, COMPARE_HEIGHT, Luma([80]));
+        for x in 0..8 {
+            for y in 0..8 {
+                moved.put_pixel(x, y, Luma([200]));
+            }
+        }
+        assert!(dedup.should_keep_thumbnail(Some(moved), at(200)));
+    }
+
+    #[test]
+    fn test_thumbnail_matches_jpeg_check() {
+        // デコード済みの画像から作った縮小画像でも、JPEGを渡した場合と同じ判定になる
+        let decoded = |value| image::load_from_memory(&jpeg(value)).unwrap();
+        let mut from_jpeg = FrameDeduplicator::new(DedupConfig::default());
+        let mut from_thumbnail = FrameDeduplicator::new(DedupConfig::default());
+        for (i, value) in [80, 82, 140, 141, 80].into_iter().enumerate() {
+            let captured_at = at(i as i64 * 100);
+            assert_eq!(
+                from_jpeg.should_keep(&jpeg(value), captured_at),
+                from_thumbnail.should_keep_thumbnail(Some(thumbnail(&decoded(value))), captured_at),
+            );
+        }
+        assert_eq!(from_jpeg.stats(), from_thumbnail.stats());
+        assert_eq!(from_jpeg.stats(), DedupStats { kept: 3, dropped: 2 });
+    }
+
+    #[test]
+    fn test_mjpeg_index_keeps_capture_times() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.mjpeg");
+        let mut writer = RecordingWriter::create(&path, RecordingFormat::Mjpeg, 11, &Mp4EncoderConfig::default(), None).unwrap();
+        let mut dedup = FrameDeduplicator::new(DedupConfig { keepalive_secs: 60, ..DedupConfig::default() });
+
+        let frames = [(0, jpeg(50)), (100, jpeg(50)), (200, jpeg(50)), (300, jpeg(200)), (400, jpeg(200))];
+        for (ms, frame) in &frames {
+            if dedup.should_keep(frame, at(*ms)) {
+                writer.write_frame_at(frame, at(*ms)).unwrap();
+            } else {
+                writer.skip_frame().unwrap();
+            }
+        }
+        writer.finish().unwrap();
+
+        // 捨てた区間は時刻の空白として残る
+        let index = mjpeg::read_index(&mjpeg::index_path(&path)).unwrap();
+        let times: Vec<_> = index.iter().map(|entry| entry.captured_at).collect();
+        assert_eq!(times, vec![at(0), at(300)]);
+        assert_eq!(mjpeg::scan_file(&path).unwrap().frames.len(), 2);
+    }
+}
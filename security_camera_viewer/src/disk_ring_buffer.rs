@@ -0,0 +1,450 @@
+//! ディスク上のリングバッファ（長いプリバッファ用）
+//!
+//! 固定サイズのファイルにフレームを循環して書き込み、メモリにはフレームの位置・長さ・
+//! 受信時刻だけを持つ。数分のプリバッファでもメモリ使用量はフレーム数に比例する分だけで済む。
+//! ファイル末尾に収まらないフレームは先頭に戻って書き、末尾の余りは使わない。
+//!
+//! カメラの映像がディスクに残らないように、フレームはバッファごとにメモリ上で生成した鍵で
+//! XChaCha20-Poly1305暗号化する（鍵はファイルに書かないため、異常終了で残ったファイルも読めない）。
+//! ファイルはバッファを破棄した時点で削除する。
+//!
+//! ファイルは作成時に実際にディスク領域を確保する（疎なファイルにしない）ため、録画中にディスクが埋まっても書き込みは失敗しない。
+//! 暗号化と書き込みはバッファごとの書き込みスレッドで行い、`push`（GUIではUIスレッド）はディスクを待たない。
+//! 書き込みが追いつかずに待ちが`WRITE_QUEUE`フレームを超えた場合、新しいフレームは捨てる。
+
+use crate::ring_buffer::{EvictionPolicy, FrameBuffer, JpegFrame};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use log::{error, warn};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// 認証タグ長
+const TAG_LEN: usize = 16;
+/// 書き込みスレッドが書き終えていないフレームの上限
+const WRITE_QUEUE: usize = 16;
+/// 領域を確保するときに一度に書く0のバイト数
+const ZERO_CHUNK: usize = 1024 * 1024;
+
+/// ファイル内のフレームの位置
+struct Slot {
+    offset: u64,
+    /// 暗号文の長さ（平文 + タグ）
+    len: u64,
+    /// ノンスに使う通し番号
+    sequence: u64,
+    timestamp: Instant,
+}
+
+impl Slot {
+    fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+
+    fn plain_len(&self) -> usize {
+        self.len as usize - TAG_LEN
+    }
+}
+
+/// 書き込みスレッドに渡すフレーム
+struct WriteJob {
+    offset: u64,
+    sequence: u64,
+    jpeg_data: Vec<u8>,
+}
+
+/// 書き込みスレッドの進み具合
+#[derive(Default)]
+struct WriteProgress {
+    /// 処理し終えたフレーム数（書き込みに失敗したものも含む）
+    done: u64,
+    /// 書き込みに失敗したフレームの通し番号（読み出すときに飛ばす）
+    failed: HashSet<u64>,
+}
+
+type SharedProgress = Arc<(Mutex<WriteProgress>, Condvar)>;
+
+/// 書き込みスレッド: フレームを受け取った順に暗号化して書き込む（バッファが破棄されたら終わる）
+fn write_thread(jobs: Receiver<WriteJob>, mut file: File, cipher: XChaCha20Poly1305, path: PathBuf, progress: SharedProgress) {
+    for job in jobs {
+        let written = cipher
+            .encrypt(&DiskRingBuffer::nonce(job.sequence), job.jpeg_data.as_slice())
+            .map_err(|_| io::Error::other("encryption failed"))
+            .and_then(|ciphertext| {
+                file.seek(SeekFrom::Start(job.offset))?;
+                file.write_all(&ciphertext)
+            });
+
+        let (lock, written_cond) = &*progress;
+        let mut progress = lock.lock().unwrap();
+        if let Err(e) = written {
+            error!("Failed to write pre-buffer frame to {:?}: {}", path, e);
+            progress.failed.insert(job.sequence);
+        }
+        progress.done += 1;
+        written_cond.notify_all();
+    }
+}
+
+/// `file`の先頭から`len`バイトのディスク領域を確保する（疎なファイルにしない）
+#[cfg(target_os = "linux")]
+fn reserve(file: &mut File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = libc::off_t::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "pre-buffer file too large"))?;
+    // SAFETY: `file`が開いている間の有効なファイル記述子を渡す
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len) } {
+        0 => Ok(()),
+        // ファイルシステムが対応していない場合は0を書いて確保する
+        libc::EOPNOTSUPP | libc::EINVAL => write_zeros(file, len as u64),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// `file`の先頭から`len`バイトのディスク領域を確保する（NTFSは疎でないファイルの長さ分のクラスタを割り当てる）
+#[cfg(windows)]
+fn reserve(file: &mut File, len: u64) -> io::Result<()> {
+    file.set_len(len)
+}
+
+/// `file`の先頭から`len`バイトのディスク領域を確保する
+#[cfg(not(any(target_os = "linux", windows)))]
+fn reserve(file: &mut File, len: u64) -> io::Result<()> {
+    write_zeros(file, len)
+}
+
+/// `file`の先頭から`len`バイトに0を書く
+#[cfg_attr(windows, allow(dead_code))]
+fn write_zeros(file: &mut File, len: u64) -> io::Result<()> {
+    let zeros = vec![0u8; ZERO_CHUNK];
+    file.seek(SeekFrom::Start(0))?;
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(ZERO_CHUNK as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.flush()
+}
+
+/// ディスク上のリングバッファ
+pub struct DiskRingBuffer {
+    file: File,
+    path: PathBuf,
+    /// ファイルサイズ（バイト）
+    file_size: u64,
+    /// 削除ポリシー（ファイルに収まらないフレームはポリシーとは別に削除する）
+    policy: EvictionPolicy,
+    /// フレームの位置（古い順）
+    slots: VecDeque<Slot>,
+    /// 次に書き込む位置
+    write_pos: u64,
+    /// 現在のバッファ内総バイト数（平文）
+    total_bytes: usize,
+    cipher: XChaCha20Poly1305,
+    next_sequence: u64,
+    /// 書き込みスレッドへのフレーム（破棄時にNoneにしてスレッドを終わらせる）
+    jobs: Option<SyncSender<WriteJob>>,
+    writer: Option<JoinHandle<()>>,
+    progress: SharedProgress,
+}
+
+impl DiskRingBuffer {
+    /// `path`に`file_size`バイトのバッファファイルを作成する
+    ///
+    /// 既存のファイル（異常終了で残ったバッファなど）は上書きする。
+    /// `policy`を超えるか、ファイルに収まらなくなると古いフレームから削除する。
+    ///
+    /// # Errors
+    /// - ファイルを作成・確保できない場合
+    pub fn create(path: &Path, policy: EvictionPolicy, file_size: u64) -> io::Result<Self> {
+        let mut key = [0u8; 32];
+        getrandom::getrandom(&mut key)?;
+
+        let mut file = File::options().read(true).write(true).create(true).truncate(true).open(path)?;
+        if let Err(e) = reserve(&mut file, file_size) {
+            drop(file);
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        let progress = SharedProgress::default();
+        let (jobs, job_rx) = mpsc::sync_channel(WRITE_QUEUE);
+        let writer = {
+            let (file, cipher, path, progress) = (file.try_clone()?, cipher.clone(), path.to_path_buf(), progress.clone());
+            thread::Builder::new()
+                .name("prebuffer-writer".to_string())
+                .spawn(move || write_thread(job_rx, file, cipher, path, progress))?
+        };
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            file_size,
+            policy,
+            slots: VecDeque::new(),
+            write_pos: 0,
+            total_bytes: 0,
+            cipher,
+            next_sequence: 0,
+            jobs: Some(jobs),
+            writer: Some(writer),
+            progress,
+        })
+    }
+
+    /// 書き込みスレッドが送ったフレームをすべて書き終えるまで待ち、書き込みに失敗したフレームの通し番号を返す
+    fn wait_for_writes(&self) -> HashSet<u64> {
+        let (lock, written) = &*self.progress;
+        let progress = written
+            .wait_while(lock.lock().unwrap(), |progress| progress.done < self.next_sequence)
+            .unwrap();
+        progress.failed.clone()
+    }
+
+    fn nonce(sequence: u64) -> XNonce {
+        let mut nonce = XNonce::default();
+        nonce[..8].copy_from_slice(&sequence.to_be_bytes());
+        nonce
+    }
+
+    /// 書き込み位置を決め、上書きされるフレームを削除する
+    fn make_room(&mut self, len: u64) -> u64 {
+        if self.write_pos + len > self.file_size {
+            // 書き込み位置より後ろのフレームは前回の周回のもの（最も古い）なので、先頭に戻る前に捨てる
+            while self.slots.front().is_some_and(|slot| slot.offset >= self.write_pos) {
+                self.pop_oldest();
+            }
+            self.write_pos = 0;
+        }
+
+        let (start, end) = (self.write_pos, self.write_pos + len);
+        while self.slots.front().is_some_and(|slot| slot.offset < end && start < slot.end()) {
+            self.pop_oldest();
+        }
+        start
+    }
+
+    fn read_slot(&self, slot: &Slot) -> io::Result<Vec<u8>> {
+        let mut ciphertext = vec![0u8; slot.len as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(slot.offset))?;
+        file.read_exact(&mut ciphertext)?;
+        self.cipher
+            .decrypt(&Self::nonce(slot.sequence), ciphertext.as_slice())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("pre-buffer frame at offset {} is corrupted", slot.offset)))
+    }
+}
+
+impl FrameBuffer for DiskRingBuffer {
+    /// 新しいフレームを追加
+    ///
+    /// 暗号化と書き込みは書き込みスレッドに任せて待たない。
+    /// 書き込みが追いつかないときや書き込みに失敗したフレームはログに残して捨てる（プリバッファは録画を止めない）。
+    fn push(&mut self, frame: JpegFrame) {
+        let len = (frame.jpeg_data.len() + TAG_LEN) as u64;
+        if len > self.file_size {
+            warn!("Pre-buffer frame of {} bytes does not fit in {} byte buffer file, dropped", frame.jpeg_data.len(), self.file_size);
+            return;
+        }
+
+        let sequence = self.next_sequence;
+        let offset = self.make_room(len);
+        let plain_len = frame.jpeg_data.len();
+        let Some(jobs) = &self.jobs else { return };
+        match jobs.try_send(WriteJob { offset, sequence, jpeg_data: frame.jpeg_data }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!("Pre-buffer writes to {:?} are falling behind, frame dropped", self.path);
+                return;
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("Pre-buffer writer for {:?} has stopped, frame dropped", self.path);
+                return;
+            }
+        }
+
+        self.next_sequence += 1;
+        self.write_pos = offset + len;
+        self.total_bytes += plain_len;
+        self.slots.push_back(Slot { offset, len, sequence, timestamp: frame.timestamp });
+        self.enforce_policy();
+    }
+
+    /// 書き込み待ちのフレームを書き終えてから読み出す（書き込みに失敗したフレームは飛ばす）
+    fn for_each_frame(&self, f: &mut dyn FnMut(&JpegFrame) -> io::Result<()>) -> io::Result<()> {
+        let failed = self.wait_for_writes();
+        for slot in self.slots.iter().filter(|slot| !failed.contains(&slot.sequence)) {
+            f(&JpegFrame { jpeg_data: self.read_slot(slot)?, timestamp: slot.timestamp })?;
+        }
+        Ok(())
+    }
+
+    fn pop_oldest(&mut self) -> bool {
+        match self.slots.pop_front() {
+            Some(slot) => {
+                self.total_bytes -= slot.plain_len();
+                let mut progress = self.progress.0.lock().unwrap();
+                if !progress.failed.is_empty() {
+                    progress.failed.remove(&slot.sequence);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.write_pos = 0;
+        self.total_bytes = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    fn set_policy(&mut self, policy: EvictionPolicy) {
+        self.policy = policy;
+        self.enforce_policy();
+    }
+
+    fn oldest_timestamp(&self) -> Option<Instant> {
+        self.slots.front().map(|slot| slot.timestamp)
+    }
+
+    fn newest_timestamp(&self) -> Option<Instant> {
+        self.slots.back().map(|slot| slot.timestamp)
+    }
+}
+
+impl Drop for DiskRingBuffer {
+    fn drop(&mut self) {
+        // 書き込み待ち（最大WRITE_QUEUEフレーム）を終えてからファイルを削除する
+        self.jobs = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove pre-buffer file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8, len: usize) -> JpegFrame {
+        JpegFrame { jpeg_data: vec![value; len], timestamp: Instant::now() }
+    }
+
+    fn frames(max_frames: usize) -> EvictionPolicy {
+        EvictionPolicy { max_frames: Some(max_frames), ..EvictionPolicy::default() }
+    }
+
+    fn contents(buffer: &DiskRingBuffer) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        buffer.for_each_frame(&mut |frame| {
+            frames.push(frame.jpeg_data.clone());
+            Ok(())
+        }).unwrap();
+        frames
+    }
+
+    #[test]
+    fn test_disk_ring_buffer_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = DiskRingBuffer::create(&dir.path().join("prebuffer.bin"), frames(3), 4096).unwrap();
+        assert!(buffer.is_empty());
+
+        for i in 0..5 {
+            buffer.push(frame(i, 10 + i as usize));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.total_bytes(), 12 + 13 + 14);
+        assert_eq!(contents(&buffer), vec![vec![2; 12], vec![3; 13], vec![4; 14]]);
+
+        let mut file = Vec::new();
+        assert_eq!(buffer.flush_to_file(&mut file).unwrap(), (3, 39));
+        assert_eq!(file.len(), 39);
+    }
+
+    #[test]
+    fn test_disk_ring_buffer_wraps() {
+        let dir = tempfile::tempdir().unwrap();
+        // 1フレーム = 100 + 16バイト、ファイルには4フレームまで入る
+        let path = dir.path().join("prebuffer.bin");
+        let mut buffer = DiskRingBuffer::create(&path, EvictionPolicy::seconds(60), 500).unwrap();
+
+        for i in 0..10u8 {
+            buffer.push(frame(i, 100));
+            let frames = contents(&buffer);
+            assert_eq!(frames.last().unwrap(), &vec![i; 100]);
+            // 古い順に連続している
+            for pair in frames.windows(2) {
+                assert_eq!(pair[0][0] + 1, pair[1][0]);
+            }
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 500);
+
+        // 大きいフレームは複数の古いフレームを押し出す
+        buffer.push(frame(10, 300));
+        assert_eq!(contents(&buffer), vec![vec![10; 300]]);
+        buffer.push(frame(11, 100));
+        assert_eq!(contents(&buffer), vec![vec![10; 300], vec![11; 100]]);
+
+        // ファイルに収まらないフレームは捨てる
+        buffer.push(frame(12, 600));
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_disk_ring_buffer_reserves_space() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prebuffer.bin");
+        let _buffer = DiskRingBuffer::create(&path, frames(10), 1024 * 1024).unwrap();
+
+        // 疎なファイルではなく、長さ分のブロックが割り当てられている
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len(), 1024 * 1024);
+        assert!(metadata.blocks() * 512 >= metadata.len(), "{} blocks allocated", metadata.blocks());
+    }
+
+    #[test]
+    fn test_disk_ring_buffer_encrypted_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prebuffer.bin");
+        let mut buffer = DiskRingBuffer::create(&path, frames(10), 1024).unwrap();
+        buffer.push(frame(0xAB, 64));
+        assert!(buffer.wait_for_writes().is_empty());
+
+        // ファイルに平文は残らない
+        let raw = fs::read(&path).unwrap();
+        assert!(!raw.windows(16).any(|w| w.iter().all(|&b| b == 0xAB)));
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        drop(buffer);
+        assert!(!path.exists());
+    }
+}
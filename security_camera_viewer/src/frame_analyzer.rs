@@ -0,0 +1,447 @@
+//! フレーム解析器の共通インターフェースと登録簿
+//!
+//! 動き検知・いたずら検知などの解析器は`FrameAnalyzer`を実装し、`AnalyzerRegistry`に登録する。
+//! 登録簿は1フレームごとに有効な解析器を設定の順に実行し、検知結果（`Detection`）をまとめて返す。
+//! 後の解析器は同じフレームで先に実行した解析器の結果を受け取る（動きのあったフレームだけを物体検知するなど）。
+//! チームごとの独自の解析器も同じように登録でき、結果は`Detection::Custom`で返す。
+//!
+//! 実行順と有効/無効は設定の`analyzers`（`AnalyzerSetting`の並び）で決める。
+//! 設定にない解析器は、設定にあるものの後に登録順で実行する。
+//! 解析器ごとの処理時間と検知数は`AnalyzerStats`に記録し、メトリクスに出す。
+//! 別スレッドで処理して後のフレームで結果を返す解析器（`FrameAnalyzer::deferred`）は、結果の処理時間を記録する。
+
+use crate::crossing_counter::CountEvent;
+use crate::motion_detector::{self, MotionDetector, MotionResult};
+use crate::object_detector::{ObjectDetection, ObjectFrame};
+use crate::tamper_detector::{TamperDetector, TamperNotification};
+use chrono::{DateTime, Local};
+use image::{GrayImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt;
+use std::time::Instant;
+
+/// 処理時間の移動平均で、1フレームごとに新しい値を取り込む割合
+const TIMING_SMOOTHING: f32 = 0.1;
+
+/// 解析器の実行順と有効/無効（設定ファイルの`analyzers`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyzerSetting {
+    /// 解析器の名前（`FrameAnalyzer::name`）
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl Default for AnalyzerSetting {
+    fn default() -> Self {
+        Self { name: String::new(), enabled: true }
+    }
+}
+
+/// 解析するフレームとメタデータ
+///
+/// GUIは表示用にデコード済みのRGBAを、CLIは受信したJPEGだけを渡す。
+pub struct AnalyzerFrame<'a> {
+    /// 受信したJPEG
+    pub jpeg: Option<&'a [u8]>,
+    /// デコード済みのフレーム
+    pub image: Option<&'a RgbaImage>,
+    /// 受信時刻
+    pub captured_at: DateTime<Local>,
+    /// 受信したフレームの通し番号
+    pub sequence: u64,
+}
+
+impl AnalyzerFrame<'_> {
+    /// 縦横を`scale`分の1に縮小した輝度（デコード済みならRGBAから、なければJPEGの縮小デコード）
+    pub fn luma(&self, scale: u32) -> Option<GrayImage> {
+        match (self.image, self.jpeg) {
+            (Some(image), _) => Some(motion_detector::luma_from_rgba(image, scale, Vec::new())),
+            (None, Some(jpeg)) => motion_detector::luma_from_jpeg(jpeg, scale, Vec::new()).ok(),
+            (None, None) => None,
+        }
+    }
+}
+
+/// 解析器の検知結果
+#[derive(Debug, Clone)]
+pub enum Detection {
+    /// 動き検知の1フレームの結果（動きがなくても毎フレーム返す）
+    Motion(MotionResult),
+    /// いたずらイベントの開始・終了
+    Tamper(TamperNotification),
+    /// 物体検知の1フレームの結果（推論したフレームだけ、後のフレームで返る）
+    Objects(ObjectFrame),
+    /// 通過カウントの1フレームの横断・進入（追跡したフレームだけ、なければ空）
+    Counts(Vec<CountEvent>),
+    /// 独自の解析器の結果
+    Custom { label: String, message: String },
+}
+
+impl Detection {
+    /// 別スレッドで処理した結果の処理時間（ms）
+    pub fn processing_time_ms(&self) -> Option<f32> {
+        match self {
+            Detection::Objects(frame) => Some(frame.inference_ms),
+            _ => None,
+        }
+    }
+
+    /// 検知数に数える結果か（動きあり・いたずらの開始・物体あり・横断/進入あり・独自の結果）
+    pub fn is_positive(&self) -> bool {
+        match self {
+            Detection::Motion(result) => result.motion_detected,
+            Detection::Tamper(notification) => matches!(notification, TamperNotification::Started(_)),
+            Detection::Objects(frame) => !frame.objects.is_empty(),
+            Detection::Counts(events) => !events.is_empty(),
+            Detection::Custom { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Detection::Motion(result) => write!(f, "motion {:.1}% ({} blobs)", result.motion_ratio, result.blobs.len()),
+            Detection::Tamper(TamperNotification::Started(event)) => write!(f, "tampering started: {}", event.kind),
+            Detection::Tamper(TamperNotification::Ended(event)) => write!(f, "tampering ended: {}", event.kind),
+            Detection::Objects(frame) if frame.objects.is_empty() => f.write_str("no objects"),
+            Detection::Objects(frame) => {
+                let objects: Vec<String> = frame.objects.iter().map(ObjectDetection::to_string).collect();
+                f.write_str(&objects.join(", "))
+            }
+            Detection::Counts(events) if events.is_empty() => f.write_str("no crossings"),
+            Detection::Counts(events) => {
+                let events: Vec<String> = events.iter().map(CountEvent::to_string).collect();
+                f.write_str(&events.join(", "))
+            }
+            Detection::Custom { label, message } => write!(f, "{}: {}", label, message),
+        }
+    }
+}
+
+/// フレーム解析器
+///
+/// GUIは登録簿ごと解析用のスレッドで実行するので`Send`であること。
+pub trait FrameAnalyzer: Any + Send {
+    /// 設定・メトリクスで使う名前（登録簿の中で一意）
+    fn name(&self) -> &str;
+
+    /// 解析器自身の設定で有効か（無効なら登録簿は実行せず、時間も数えない）
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// 別スレッドで処理して後のフレームで結果を返すか
+    ///
+    /// 統計の処理時間には`analyze`の時間ではなく、返した結果の処理時間（`Detection::processing_time_ms`）を数える。
+    fn deferred(&self) -> bool {
+        false
+    }
+
+    /// 登録簿の設定で無効になった（別スレッドの処理待ちの結果を捨てるなど）
+    fn disabled(&mut self) {}
+
+    /// 1フレームを解析する（結果がなければ空）
+    ///
+    /// `earlier`は同じフレームで先に実行した解析器の結果。
+    fn analyze(&mut self, frame: &AnalyzerFrame, earlier: &[Detection]) -> Vec<Detection>;
+}
+
+impl FrameAnalyzer for MotionDetector {
+    fn name(&self) -> &str {
+        "motion"
+    }
+
+    fn enabled(&self) -> bool {
+        self.config().enabled
+    }
+
+    fn analyze(&mut self, frame: &AnalyzerFrame, _earlier: &[Detection]) -> Vec<Detection> {
+        let result = match (frame.image, frame.jpeg) {
+            (Some(image), _) => self.detect(image),
+            (None, Some(jpeg)) => match self.detect_jpeg(jpeg) {
+                Ok(result) => result,
+                Err(_) => return Vec::new(),
+            },
+            (None, None) => return Vec::new(),
+        };
+        vec![Detection::Motion(result)]
+    }
+}
+
+impl FrameAnalyzer for TamperDetector {
+    fn name(&self) -> &str {
+        "tamper"
+    }
+
+    fn enabled(&self) -> bool {
+        self.config().enabled
+    }
+
+    fn analyze(&mut self, frame: &AnalyzerFrame, _earlier: &[Detection]) -> Vec<Detection> {
+        frame.luma(self.config().downscale)
+            .and_then(|luma| self.update(&luma, frame.captured_at))
+            .map(Detection::Tamper)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// 解析器ごとの統計
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnalyzerStats {
+    pub name: String,
+    /// 設定で有効か（解析器自身の設定で無効なものも含む）
+    pub enabled: bool,
+    /// 解析したフレーム数
+    pub frames: u64,
+    /// 処理時間を数えたフレーム数（`deferred`の解析器は結果を返した数）
+    pub timed_frames: u64,
+    /// 検知数（`Detection::is_positive`）
+    pub detections: u64,
+    /// 直近フレームの処理時間（ms、`deferred`の解析器は別スレッドでの処理時間）
+    pub last_time_ms: f32,
+    /// 処理時間の移動平均（ms）
+    pub avg_time_ms: f32,
+    /// 最大の処理時間（ms）
+    pub max_time_ms: f32,
+    /// 直近の検知
+    pub last_detection: Option<String>,
+}
+
+impl AnalyzerStats {
+    fn record_time(&mut self, elapsed_ms: f32) {
+        self.avg_time_ms = if self.timed_frames == 0 {
+            elapsed_ms
+        } else {
+            self.avg_time_ms + (elapsed_ms - self.avg_time_ms) * TIMING_SMOOTHING
+        };
+        self.timed_frames += 1;
+        self.last_time_ms = elapsed_ms;
+        self.max_time_ms = self.max_time_ms.max(elapsed_ms);
+    }
+
+    fn record(&mut self, detections: &[Detection]) {
+        self.frames += 1;
+        for detection in detections.iter().filter(|detection| detection.is_positive()) {
+            self.detections += 1;
+            self.last_detection = Some(detection.to_string());
+        }
+    }
+}
+
+struct RegisteredAnalyzer {
+    analyzer: Box<dyn FrameAnalyzer>,
+    /// 登録順（設定にない解析器の並び）
+    index: usize,
+    stats: AnalyzerStats,
+}
+
+/// フレーム解析器の登録簿
+#[derive(Default)]
+pub struct AnalyzerRegistry {
+    analyzers: Vec<RegisteredAnalyzer>,
+    next_index: usize,
+}
+
+impl AnalyzerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 解析器を登録する（同じ名前の解析器は置き換え、統計はリセットする）
+    pub fn register<A: FrameAnalyzer>(&mut self, analyzer: A) {
+        let stats = AnalyzerStats { name: analyzer.name().to_string(), enabled: true, ..AnalyzerStats::default() };
+        let registered = RegisteredAnalyzer { analyzer: Box::new(analyzer), index: self.next_index, stats };
+        self.next_index += 1;
+        match self.analyzers.iter_mut().find(|entry| entry.stats.name == registered.stats.name) {
+            Some(entry) => *entry = RegisteredAnalyzer { index: entry.index, ..registered },
+            None => self.analyzers.push(registered),
+        }
+    }
+
+    /// 設定に従って実行順と有効/無効を決める（知らない名前は無視する）
+    pub fn configure(&mut self, settings: &[AnalyzerSetting]) {
+        let position = |name: &str| settings.iter().position(|setting| setting.name == name);
+        for entry in &mut self.analyzers {
+            let enabled = position(&entry.stats.name).is_none_or(|i| settings[i].enabled);
+            if entry.stats.enabled && !enabled {
+                entry.analyzer.disabled();
+            }
+            entry.stats.enabled = enabled;
+        }
+        self.analyzers.sort_by_key(|entry| (position(&entry.stats.name).unwrap_or(usize::MAX), entry.index));
+    }
+
+    /// 有効な解析器を順に実行し、検知結果をまとめて返す
+    pub fn run(&mut self, frame: &AnalyzerFrame) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        for entry in self.analyzers.iter_mut().filter(|entry| entry.stats.enabled && entry.analyzer.enabled()) {
+            let start = Instant::now();
+            let found = entry.analyzer.analyze(frame, &detections);
+            let elapsed_ms = start.elapsed().as_secs_f32() * 1000.0;
+            if entry.analyzer.deferred() {
+                found.iter().filter_map(Detection::processing_time_ms).for_each(|time| entry.stats.record_time(time));
+            } else {
+                entry.stats.record_time(elapsed_ms);
+            }
+            entry.stats.record(&found);
+            detections.extend(found);
+        }
+        detections
+    }
+
+    /// 解析器ごとの統計（実行順）
+    pub fn stats(&self) -> impl Iterator<Item = &AnalyzerStats> {
+        self.analyzers.iter().map(|entry| &entry.stats)
+    }
+
+    /// 型で解析器を探す
+    pub fn get<T: FrameAnalyzer>(&self) -> Option<&T> {
+        self.analyzers.iter().find_map(|entry| (entry.analyzer.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    /// 型で解析器を探す（設定の変更など）
+    pub fn get_mut<T: FrameAnalyzer>(&mut self) -> Option<&mut T> {
+        self.analyzers.iter_mut().find_map(|entry| (entry.analyzer.as_mut() as &mut dyn Any).downcast_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion_detector::MotionDetectionConfig;
+    use crate::tamper_detector::TamperConfig;
+    use image::Rgba;
+
+    /// 平均輝度が閾値を超えたら報告する独自の解析器
+    struct BrightnessAlarm {
+        threshold: u8,
+    }
+
+    impl FrameAnalyzer for BrightnessAlarm {
+        fn name(&self) -> &str {
+            "brightness"
+        }
+
+        fn analyze(&mut self, frame: &AnalyzerFrame, _earlier: &[Detection]) -> Vec<Detection> {
+            let luma = frame.luma(8).unwrap();
+            let mean = luma.pixels().map(|p| p[0] as u32).sum::<u32>() / (luma.width() * luma.height());
+            if mean > self.threshold as u32 {
+                vec![Detection::Custom { label: "brightness".to_string(), message: format!("mean {}", mean) }]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    /// 一様な灰色の64x48のフレーム
+    fn gray(value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(64, 48, Rgba([value, value, value, 255]))
+    }
+
+    fn frame(image: &RgbaImage) -> AnalyzerFrame<'_> {
+        AnalyzerFrame { jpeg: None, image: Some(image), captured_at: Local::now(), sequence: 0 }
+    }
+
+    fn registry() -> AnalyzerRegistry {
+        let mut registry = AnalyzerRegistry::new();
+        registry.register(MotionDetector::new(MotionDetectionConfig { enabled: true, ..MotionDetectionConfig::default() }));
+        registry.register(TamperDetector::new(TamperConfig { enabled: true, ..TamperConfig::default() }));
+        registry.register(BrightnessAlarm { threshold: 100 });
+        registry
+    }
+
+    fn names(registry: &AnalyzerRegistry) -> Vec<&str> {
+        registry.stats().map(|stats| stats.name.as_str()).collect()
+    }
+
+    fn setting(name: &str, enabled: bool) -> AnalyzerSetting {
+        AnalyzerSetting { name: name.to_string(), enabled }
+    }
+
+    #[test]
+    fn test_configure_orders_and_disables() {
+        let mut registry = registry();
+        assert_eq!(names(&registry), ["motion", "tamper", "brightness"]);
+
+        registry.configure(&[setting("brightness", true), setting("unknown", true), setting("motion", false)]);
+        assert_eq!(names(&registry), ["brightness", "motion", "tamper"]);
+        let enabled: Vec<bool> = registry.stats().map(|stats| stats.enabled).collect();
+        assert_eq!(enabled, [true, false, true]);
+
+        // 設定が空なら登録順に戻り、すべて有効
+        registry.configure(&[]);
+        assert_eq!(names(&registry), ["motion", "tamper", "brightness"]);
+        assert!(registry.stats().all(|stats| stats.enabled));
+    }
+
+    #[test]
+    fn test_run_collects_detections_and_stats() {
+        let mut registry = registry();
+        registry.configure(&[setting("tamper", false)]);
+        let dark = gray(20);
+        let bright = gray(220);
+
+        let first = registry.run(&frame(&dark));
+        assert!(matches!(first.as_slice(), [Detection::Motion(result)] if !result.motion_detected));
+
+        let second = registry.run(&frame(&bright));
+        assert_eq!(second.len(), 2);
+        assert!(matches!(&second[0], Detection::Motion(_)));
+        assert_eq!(second[1].to_string(), "brightness: mean 220");
+
+        let stats: Vec<&AnalyzerStats> = registry.stats().collect();
+        assert_eq!(names(&registry), ["tamper", "motion", "brightness"]);
+        assert_eq!((stats[0].frames, stats[1].frames, stats[2].frames), (0, 2, 2));
+        assert_eq!(stats[2].detections, 1);
+        assert_eq!(stats[2].last_detection.as_deref(), Some("brightness: mean 220"));
+
+        // さらに2フレーム: 解析器ごとに実行したフレーム数と検知数が積み上がり、無効な解析器は0のまま
+        // （画面全体が一様に明るさを変えるのは照明の変化なので、動き検知の検知数には数えない）
+        registry.run(&frame(&dark));
+        registry.run(&frame(&bright));
+        let counts: Vec<(&str, u64, u64)> = registry.stats().map(|stats| (stats.name.as_str(), stats.frames, stats.detections)).collect();
+        assert_eq!(counts, [("tamper", 0, 0), ("motion", 4, 0), ("brightness", 4, 2)]);
+        for stats in registry.stats().filter(|stats| stats.frames > 0) {
+            assert!(stats.max_time_ms >= stats.last_time_ms && stats.max_time_ms >= stats.avg_time_ms, "{}", stats.name);
+        }
+        let tamper = registry.stats().next().unwrap();
+        assert_eq!((tamper.max_time_ms, tamper.last_detection.as_deref()), (0.0, None));
+    }
+
+    #[test]
+    fn test_analyzer_disabled_by_its_own_config_is_skipped() {
+        let mut registry = registry();
+        registry.get_mut::<MotionDetector>().unwrap().update_config(MotionDetectionConfig::default());
+        let image = gray(20);
+
+        assert!(registry.run(&frame(&image)).iter().all(|detection| !matches!(detection, Detection::Motion(_))));
+        assert_eq!(registry.stats().next().unwrap().frames, 0);
+        assert!(!registry.get::<MotionDetector>().unwrap().config().enabled);
+    }
+
+    #[test]
+    fn test_register_replaces_same_name() {
+        let mut registry = registry();
+        registry.register(BrightnessAlarm { threshold: 250 });
+        assert_eq!(names(&registry), ["motion", "tamper", "brightness"]);
+        assert_eq!(registry.get::<BrightnessAlarm>().unwrap().threshold, 250);
+    }
+
+    #[test]
+    fn test_analyze_jpeg_frame() {
+        let image = gray(120);
+        let rgb = image::DynamicImage::ImageRgba8(image).to_rgb8();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode_image(&rgb).unwrap();
+
+        let frame = AnalyzerFrame { jpeg: Some(&jpeg), image: None, captured_at: Local::now(), sequence: 1 };
+        assert_eq!(frame.luma(8).unwrap().dimensions(), (8, 6));
+        let mut registry = registry();
+        let detections = registry.run(&frame);
+        assert!(matches!(detections.as_slice(), [Detection::Motion(_), Detection::Custom { .. }]));
+    }
+}
@@ -1,48 +1,67 @@
-mod protocol;
-mod serial;
 mod metrics;
 mod ring_buffer;
-mod motion_detector;
-mod mp4_recorder;
+mod disk_ring_buffer;
 
+use security_camera_viewer::{
+    archive, catalog, clip_export, crossing_counter, crypto, dedup, frame_analyzer, manifest,
+    metadata, motion_detector, motion_event, mp4_recorder, object_detector, overlay,
+    protocol, recording, recovery, schedule, serial, snapshot, tamper_detector, timelapse,
+};
 use eframe::egui;
-use log::{error, info, warn};
-use serial::SerialConnection;
+use log::{debug, error, info, warn};
+use serial::{DeviceProfile, SerialConnection};
 use protocol::Packet;
 use metrics::{MetricsLogger, PerformanceMetrics, SpresenseFpsCalculator, SpresenseCameraFpsCalculator};
-use ring_buffer::{RingBuffer, JpegFrame};
-use motion_detector::{MotionDetector, MotionDetectionConfig};
-use mp4_recorder::Mp4Recorder;
-use std::sync::mpsc::{self, Receiver, Sender};
+use ring_buffer::{EvictionPolicy, FrameBuffer, RingBuffer, JpegFrame};
+use disk_ring_buffer::DiskRingBuffer;
+use motion_detector::{ExclusionMask, MotionAlgorithm, MotionDetector, MotionDetectionConfig, MotionDetectorStats, MotionResult, MotionZone, SceneChange, ZoneMotion};
+use mp4_recorder::Mp4EncoderConfig;
+use recording::{RecordingFormat, RecordingWriter};
+use schedule::{DailyWindow, Schedule, ScheduleMode, Scheduler};
+use timelapse::{TimelapseConfig, TimelapseRecorder};
+use dedup::{DedupConfig, FrameDeduplicator};
+use archive::ArchiveConfig;
+use motion_event::{EventNotification, MotionEventTracker};
+use tamper_detector::{TamperDetector, TamperEvent, TamperMeasurements, TamperNotification};
+use object_detector::{ObjectDetection, ObjectDetectionConfig, ObjectDetector, ObjectFrame};
+use crossing_counter::{CountEvent, CountingConfig, CountingLine, CountingZone, Counts, CrossingCounter, HourlyCountLog, Track};
+use frame_analyzer::{AnalyzerFrame, AnalyzerRegistry, AnalyzerSetting, AnalyzerStats, Detection};
+use catalog::{Catalog, RecordingEntry, RecordingTrigger};
+use clip_export::ClipFormat;
+use crypto::KeySource;
+use manifest::{ManifestSigner, SigningQueue};
+use metadata::{FirmwareMetrics, RecordingSidecar};
+use overlay::{OverlayConfig, OverlayPosition, OverlaySettings};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Instant;
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use chrono;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{self, DateTime, Local};
 
 // Phase 3: Recording functionality constants
 const MAX_RECORDING_SIZE: u64 = 1_000_000_000;  // 1 GB
 const RECORDING_DIR: &str = "./recordings";
+// Number of recent recordings listed in the side panel
+const RECENT_RECORDINGS_SHOWN: usize = 20;
+// Wait before retrying a scheduled recording that failed to start
+const SCHEDULE_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+// Pre-record limits: RAM holds every frame, the disk buffer only an index per frame
+const MAX_RAM_PRE_RECORD_SECONDS: u32 = 30;
+const MAX_DISK_PRE_RECORD_SECONDS: u32 = 600;
+// Disk pre-buffer file, kept next to the recordings (removed when the app closes)
+const PREBUFFER_FILE_NAME: &str = ".prebuffer";
+// How often the idle analysis thread checks for settings changes while no frames arrive
+const ANALYZER_COMMAND_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+// Example shown when the schedule is first opened
+const DEFAULT_SCHEDULE: &str = "mon-fri 09:00-17:00 off\nmon-fri 22:00-06:00 motion\nsat,sun continuous\ndefault off";
 
 // Phase 3/5: Recording state management
-/// 録画フォーマット (Phase 6)
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum RecordingFormat {
-    /// MJPEG形式（Phase 3-5）
-    Mjpeg,
-    /// MP4形式（Phase 6）
-    Mp4,
-}
-
-impl Default for RecordingFormat {
-    fn default() -> Self {
-        RecordingFormat::Mp4  // Phase 6以降はMP4をデフォルトに
-    }
-}
-
 enum RecordingState {
     Idle,
     /// 手動録画 (Phase 3)
@@ -59,17 +78,61 @@ enum RecordingState {
         start_time: Instant,
         frame_count: u32,
         total_bytes: u64,
-        motion_active: bool,           // 現在動き検知中か
-        countdown_frames: u32,         // ポスト録画残りフレーム数
+        countdown_frames: u32,         // 動きイベント終了後のポスト録画残りフレーム数
         format: RecordingFormat,  // Phase 6: 録画フォーマット
     },
 }
 
+/// Polygon or line being drawn on the live view
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ZoneDrawing {
+    Zone,
+    Mask,
+    /// Counting line (two points, forward = crossing to its right)
+    Line,
+    /// Zone whose entries are counted
+    CountZone,
+}
+
+impl ZoneDrawing {
+    /// Drawn from the counting panel rather than the zones list
+    fn is_counting(self) -> bool {
+        matches!(self, ZoneDrawing::Line | ZoneDrawing::CountZone)
+    }
+
+    /// Points needed before the drawing can be finished
+    fn min_points(self) -> usize {
+        if self == ZoneDrawing::Line { 2 } else { 3 }
+    }
+}
+
+/// Clip export settings for one recording (opened from the recordings list)
+struct ClipDialog {
+    recording: PathBuf,
+    start: String,
+    end: String,
+    format: ClipFormat,
+}
+
+impl ClipDialog {
+    fn new(entry: &RecordingEntry) -> Self {
+        Self {
+            recording: entry.path.clone(),
+            start: entry.started_at.format("%H:%M:%S").to_string(),
+            end: entry.ended_at
+                .map(|end| end.format("%H:%M:%S").to_string())
+                .unwrap_or_else(|| "+60s".to_string()),
+            format: ClipFormat::Mp4,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum AppMessage {
     NewFrame(Vec<u8>),  // Legacy - will be replaced by DecodedFrame
     DecodedFrame { width: u32, height: u32, pixels: Vec<u8> },  // RGBA8 decoded image
     ConnectionStatus(String),
+    DeviceConnected(DeviceProfile),  // Port and USB details, written to recording sidecars
     Stats {
         fps: f32,
         spresense_fps: f32,  // Spresense-side FPS (from sequence numbers)
@@ -89,7 +152,67 @@ enum AppMessage {
         avg_packet_size: u32,
         errors: u32,
     },
-    JpegFrame(Vec<u8>),  // Phase 3: JPEG frame data for recording
+    Detections { captured_at: DateTime<Local>, detections: Vec<Detection> },  // Results of the analysis thread for one live frame
+    AnalyzerState(Box<AnalyzerState>),  // Analyzer state after a frame or a command, for the UI
+    JpegFrame {  // Phase 3: JPEG frame data for recording
+        jpeg_data: Vec<u8>,
+        received_at: Instant,  // Pre-buffer frames age from here, not from when the GUI gets to them
+        thumbnail: Option<image::GrayImage>,  // Dedup comparison image, made from the capture thread's decode
+    },
+}
+
+/// Latest received frame (original JPEG bytes and receive time), kept for snapshots
+type LatestJpeg = Arc<Mutex<Option<(Vec<u8>, DateTime<Local>)>>>;
+
+/// Recording state the capture thread reads to decide what to send for each frame
+struct CaptureFlags {
+    is_recording: Arc<AtomicBool>,
+    is_deduplicating: Arc<AtomicBool>,
+}
+
+/// Decoded live frame handed to the analysis thread
+struct AnalysisFrame {
+    image: image::RgbaImage,
+    captured_at: DateTime<Local>,
+    sequence: u64,
+}
+
+/// Settings changes and actions for the frame analyzers, applied by the analysis thread between frames
+enum AnalyzerCommand {
+    Configure(Box<MotionDetectionConfig>),
+    RegisterObjects(Box<ObjectDetector>),
+    RelearnTamperReference,
+    ResetCounts,
+}
+
+/// What the UI shows of the frame analyzers, sent by the analysis thread so the UI never waits for a frame
+#[derive(Debug, Clone)]
+struct AnalyzerState {
+    stats: Vec<AnalyzerStats>,
+    motion: MotionDetectorStats,
+    zone_results: Vec<ZoneMotion>,
+    tamper: Option<TamperEvent>,
+    tamper_measurements: Option<TamperMeasurements>,
+    count_totals: BTreeMap<String, Counts>,
+    tracks: Vec<Track>,
+}
+
+impl AnalyzerState {
+    // The motion, tamper and counting analyzers are registered in CameraApp::new() and never removed
+    fn of(analyzers: &AnalyzerRegistry) -> Self {
+        let motion = analyzers.get::<MotionDetector>().expect("motion analyzer is registered");
+        let tamper = analyzers.get::<TamperDetector>().expect("tamper analyzer is registered");
+        let counter = analyzers.get::<CrossingCounter>().expect("counting analyzer is registered");
+        Self {
+            stats: analyzers.stats().cloned().collect(),
+            motion: motion.stats(),
+            zone_results: motion.zone_results().to_vec(),
+            tamper: tamper.current().cloned(),
+            tamper_measurements: tamper.measurements().copied(),
+            count_totals: counter.totals().clone(),
+            tracks: counter.tracks().to_vec(),
+        }
+    }
 }
 
 struct CameraApp {
@@ -102,6 +225,7 @@ struct CameraApp {
     connection_status: String,
     is_running: Arc<Mutex<bool>>,
     is_recording: Arc<AtomicBool>,  // Phase 3: Recording state shared with capture thread
+    is_deduplicating: Arc<AtomicBool>,  // The capture thread only makes dedup thumbnails while set
 
     // Statistics
     fps: f32,
@@ -118,21 +242,116 @@ struct CameraApp {
     spresense_camera_fps: Option<f32>,
     spresense_action_q_depth: Option<u32>,
     spresense_errors: Option<u32>,
+    device_profile: Option<DeviceProfile>,
 
     // Phase 3: Recording functionality
     recording_state: RecordingState,
-    recording_file: Option<Arc<Mutex<File>>>,
+    recorder: Option<RecordingWriter>,
+    // Metadata sidecar of the current recording (<recording>.meta.json)
+    sidecar: Option<RecordingSidecar>,
     recording_dir: PathBuf,
+    catalog: Catalog,
+    // Hashing and signing run on their own thread, in the order recordings close
+    manifest_queue: Option<SigningQueue>,
+    manifest_status: Arc<Mutex<String>>,
 
     // Phase 5: Motion detection recording
     motion_config: MotionDetectionConfig,
-    motion_detector: MotionDetector,
-    ring_buffer: RingBuffer,
+    // Motion, tamper and any other frame analyzers live on the analysis thread and run in the configured order;
+    // the UI changes them through commands and shows the state the thread sends back
+    analyzer_commands: Sender<AnalyzerCommand>,
+    analyzers: AnalyzerState,
+    // Detection config last sent to the analysis thread
+    analyzer_config: Option<MotionDetectionConfig>,
+    // Live frames for the analysis thread, dropped while it is still busy with the previous one
+    analysis_tx: SyncSender<AnalysisFrame>,
+    // Per-analyzer timings, also written to the metrics CSV by the capture thread
+    analyzer_metrics: Arc<Mutex<Vec<AnalyzerStats>>>,
+    // Result of the latest live frame (blobs drawn on the live view, boxes stored with motion events)
+    motion_result: MotionResult,
+    // Turns per-frame results into motion events; the recorder follows them through its subscription
+    motion_events: MotionEventTracker,
+    motion_event_rx: Receiver<EventNotification>,
+    // Covered lens, defocus and repositioning from the tamper analyzer
+    tamper_rx: Receiver<TamperNotification>,
+    // Person/vehicle detection on motion frames, registered once a model is loaded
+    object_model_text: String,
+    object_triggers_text: String,
+    object_status: String,
+    // Objects found during the open motion event (drawn on the live view)
+    object_detections: Vec<ObjectDetection>,
+    // Set once a trigger object was seen during the open motion event
+    object_triggered: bool,
+    // Hourly line crossing/zone entry counts, next to the metrics logs (created on the first counted frame)
+    count_log: Option<HourlyCountLog>,
+    count_status: String,
+    ring_buffer: Box<dyn FrameBuffer>,
+    // Spill the pre-record buffer to a fixed-size file for windows of several minutes
+    disk_prebuffer: bool,
+    disk_prebuffer_mb: u32,
+    // Memory budget for the in-RAM pre-record buffer
+    prebuffer_ram_mb: u32,
+    // Disk buffer size the current ring buffer was built with (None = in memory)
+    ring_buffer_disk_mb: Option<u32>,
     last_motion_time: Option<Instant>,
+    // Zone/mask polygon being drawn by clicking the live view (normalized points)
+    zone_drawing: Option<ZoneDrawing>,
+    zone_draft: Vec<[f32; 2]>,
 
     // Phase 6: MP4 recording
     recording_format: RecordingFormat,
-    mp4_recorder: Option<Mp4Recorder>,
+    mp4_config: Mp4EncoderConfig,
+    mp4_extra_args: String,
+    ffmpeg_status: String,
+
+    // Skip frames of a static scene in manual/motion recordings (applied to the next recording)
+    dedup_enabled: bool,
+    dedup_config: DedupConfig,
+    dedup: Option<FrameDeduplicator>,
+
+    // Date/time and camera name overlay
+    overlay: OverlaySettings,
+    live_frame_count: u64,
+
+    // Weekly schedule (switches motion detection and continuous recording)
+    schedule_enabled: bool,
+    schedule_text: String,
+    scheduler: Option<Scheduler>,
+    schedule_error: String,
+    schedule_retry_at: Option<Instant>,
+
+    // Timelapse (runs alongside manual/motion recording)
+    timelapse_config: TimelapseConfig,
+    timelapse_window_enabled: bool,
+    timelapse_window: String,
+    timelapse: Option<TimelapseRecorder>,
+    timelapse_motion: Option<MotionDetector>,
+    timelapse_sidecar: Option<RecordingSidecar>,
+    timelapse_status: String,
+
+    // Snapshots (📸 button or F12)
+    latest_jpeg: LatestJpeg,
+    snapshot_png: bool,
+    snapshot_status: String,
+
+    // Encryption at rest (applied to the next recording)
+    encryption_enabled: bool,
+    encryption_use_key_file: bool,
+    encryption_passphrase: String,
+    encryption_key_file: String,
+    // Key stretched from the passphrase once per session (and again only if the passphrase changes)
+    session_key: RefCell<Option<(String, KeySource)>>,
+
+    // Recordings list and clip export
+    recordings: Vec<RecordingEntry>,
+    clip_dialog: Option<ClipDialog>,
+    clip_status: Arc<Mutex<String>>,
+
+    // Background conversion of old MJPEG recordings to a compact format
+    archive_config: ArchiveConfig,
+    archive_running: Arc<AtomicBool>,
+    archive_cancel: Arc<AtomicBool>,
+    archive_status: Arc<Mutex<String>>,
 
     // Settings
     port_path: String,
@@ -142,6 +361,61 @@ struct CameraApp {
 impl CameraApp {
     fn new(_cc: &eframe::CreationContext<'_>) -> Self {
         let (tx, rx) = mpsc::channel();
+        let mut motion_events = MotionEventTracker::new(MotionDetectionConfig::default().events);
+        let motion_event_rx = motion_events.subscribe();
+        // The tamper analyzer checks frames against a long-term reference of the scene
+        let mut tamper_detector = TamperDetector::new(MotionDetectionConfig::default().tamper);
+        let tamper_rx = tamper_detector.subscribe();
+        let mut analyzers = AnalyzerRegistry::new();
+        analyzers.register(MotionDetector::default());
+        analyzers.register(tamper_detector);
+        // Counts line crossings and zone entries of the motion blobs, so it runs after motion detection
+        analyzers.register(CrossingCounter::new(MotionDetectionConfig::default().counting));
+        let analyzer_state = AnalyzerState::of(&analyzers);
+        let analyzer_metrics = Arc::new(Mutex::new(Vec::new()));
+        let (analysis_tx, analysis_rx) = mpsc::sync_channel(1);
+        let (analyzer_commands, command_rx) = mpsc::channel();
+        {
+            let analyzer_metrics = analyzer_metrics.clone();
+            let tx = tx.clone();
+            thread::spawn(move || analysis_thread(analysis_rx, command_rx, analyzers, analyzer_metrics, tx));
+        }
+
+        // Finalize recordings left open by a crash or power loss
+        let catalog = Catalog::new(&PathBuf::from(RECORDING_DIR));
+        let recovered = recovery::recover_orphaned_recordings(&catalog).unwrap_or_else(|e| {
+            error!("Recording recovery failed: {}", e);
+            Vec::new()
+        });
+        if !recovered.is_empty() {
+            warn!("Recovered {} recording(s) that were not closed properly", recovered.len());
+        }
+
+        // Recordings are signed with a locally generated key when they close
+        let manifest_status = Arc::new(Mutex::new(String::new()));
+        let status = manifest_status.clone();
+        let manifest_queue = ManifestSigner::load_or_generate(&PathBuf::from(RECORDING_DIR).join(manifest::KEY_DIR_NAME))
+            .and_then(|signer| SigningQueue::start(signer, move |path, result| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                *status.lock().unwrap() = match result {
+                    Ok(_) => format!("🔏 Signed {}", name),
+                    Err(e) => {
+                        error!("Failed to write manifest for {:?}: {}", path, e);
+                        format!("❌ Manifest for {} failed: {}", name, e)
+                    }
+                };
+            }))
+            .map_err(|e| error!("Manifest signing disabled, failed to load key: {}", e))
+            .ok();
+        if let Some(queue) = &manifest_queue {
+            for recording in recovered.iter().filter(|r| r.path.exists()) {
+                queue.sign(&recording.path, &recording.format);
+            }
+        }
+
+        let mp4_config = Mp4EncoderConfig::default();
+        let (ffmpeg_available, ffmpeg_status) = check_ffmpeg(&mp4_config.codec);
+        let recordings = catalog.recordings().unwrap_or_default();
 
         Self {
             rx,
@@ -150,6 +424,7 @@ impl CameraApp {
             connection_status: "Not connected".to_string(),
             is_running: Arc::new(Mutex::new(false)),
             is_recording: Arc::new(AtomicBool::new(false)),
+            is_deduplicating: Arc::new(AtomicBool::new(false)),
             fps: 0.0,
             spresense_fps: 0.0,
             frame_count: 0,
@@ -162,15 +437,76 @@ impl CameraApp {
             spresense_camera_fps: None,
             spresense_action_q_depth: None,
             spresense_errors: None,
+            device_profile: None,
             recording_state: RecordingState::Idle,
-            recording_file: None,
+            recorder: None,
+            sidecar: None,
             recording_dir: PathBuf::from(RECORDING_DIR),
+            catalog,
+            manifest_queue,
+            manifest_status,
             motion_config: MotionDetectionConfig::default(),
-            motion_detector: MotionDetector::default(),
-            ring_buffer: RingBuffer::from_seconds(10, 11),  // 10秒@11fps
+            analyzer_commands,
+            analyzers: analyzer_state,
+            analyzer_config: None,
+            analysis_tx,
+            analyzer_metrics,
+            motion_result: MotionResult::default(),
+            motion_events,
+            motion_event_rx,
+            tamper_rx,
+            object_model_text: ObjectDetectionConfig::default().model_path.display().to_string(),
+            object_triggers_text: String::new(),
+            object_status: if cfg!(feature = "object-detection") { "No model loaded".to_string() } else { "Not built with object-detection".to_string() },
+            object_detections: Vec::new(),
+            object_triggered: false,
+            count_log: None,
+            count_status: String::new(),
+            ring_buffer: Box::new(RingBuffer::with_policy(EvictionPolicy::seconds(10))),  // 直近10秒
+            disk_prebuffer: false,
+            disk_prebuffer_mb: 256,
+            prebuffer_ram_mb: 64,
+            ring_buffer_disk_mb: None,
             last_motion_time: None,
-            recording_format: RecordingFormat::default(),
-            mp4_recorder: None,
+            zone_drawing: None,
+            zone_draft: Vec::new(),
+            // Fall back to MJPEG when ffmpeg cannot encode MP4
+            recording_format: if ffmpeg_available { RecordingFormat::default() } else { RecordingFormat::Mjpeg },
+            mp4_config,
+            mp4_extra_args: String::new(),
+            ffmpeg_status,
+            dedup_enabled: false,
+            dedup_config: DedupConfig::default(),
+            dedup: None,
+            overlay: OverlaySettings::default(),
+            live_frame_count: 0,
+            schedule_enabled: false,
+            schedule_text: DEFAULT_SCHEDULE.to_string(),
+            scheduler: None,
+            schedule_error: String::new(),
+            schedule_retry_at: None,
+            timelapse_config: TimelapseConfig::default(),
+            timelapse_window_enabled: false,
+            timelapse_window: "07:00-19:00".to_string(),
+            timelapse: None,
+            timelapse_motion: None,
+            timelapse_sidecar: None,
+            timelapse_status: String::new(),
+            latest_jpeg: Arc::new(Mutex::new(None)),
+            snapshot_png: false,
+            snapshot_status: String::new(),
+            encryption_enabled: false,
+            encryption_use_key_file: false,
+            encryption_passphrase: String::new(),
+            encryption_key_file: String::new(),
+            session_key: RefCell::new(None),
+            recordings,
+            clip_dialog: None,
+            clip_status: Arc::new(Mutex::new(String::new())),
+            archive_config: ArchiveConfig::default(),
+            archive_running: Arc::new(AtomicBool::new(false)),
+            archive_cancel: Arc::new(AtomicBool::new(false)),
+            archive_status: Arc::new(Mutex::new(String::new())),
             port_path: "/dev/ttyACM0".to_string(),
             auto_detect: true,
         }
@@ -186,12 +522,14 @@ impl CameraApp {
 
         let tx = self.tx.clone();
         let is_running = self.is_running.clone();
-        let is_recording = self.is_recording.clone();
+        let flags = CaptureFlags { is_recording: self.is_recording.clone(), is_deduplicating: self.is_deduplicating.clone() };
+        let latest_jpeg = self.latest_jpeg.clone();
         let port_path = self.port_path.clone();
         let auto_detect = self.auto_detect;
+        let analyzer_metrics = self.analyzer_metrics.clone();
 
         thread::spawn(move || {
-            capture_thread(tx, is_running, is_recording, port_path, auto_detect);
+            capture_thread(tx, is_running, flags, latest_jpeg, analyzer_metrics, port_path, auto_detect);
         });
     }
 
@@ -205,10 +543,11 @@ impl CameraApp {
                 error!("Failed to auto-stop recording: {}", e);
             }
         }
+        self.stop_timelapse();
     }
 
     // Phase 3/5: Recording methods
-    fn start_manual_recording(&mut self) -> io::Result<()> {
+    fn start_manual_recording(&mut self, trigger: RecordingTrigger) -> io::Result<()> {
         // Check if already recording
         if matches!(self.recording_state, RecordingState::ManualRecording { .. } | RecordingState::MotionRecording { .. }) {
             warn!("Recording already in progress");
@@ -220,25 +559,19 @@ impl CameraApp {
 
         // Generate filename with timestamp (Phase 6: dynamic extension)
         let now = chrono::Local::now();
-        let extension = match self.recording_format {
-            RecordingFormat::Mjpeg => "mjpeg",
-            RecordingFormat::Mp4 => "mp4",
-        };
-        let filename = format!("manual_{}.{}", now.format("%Y%m%d_%H%M%S"), extension);
+        let extension = self.recording_format.extension();
+        let filename = format!("{}_{}.{}", trigger, now.format("%Y%m%d_%H%M%S"), extension);
         let filepath = self.recording_dir.join(&filename);
+        let key = self.recording_key()?;
 
-        // Phase 6: Create recorder based on format
-        match self.recording_format {
-            RecordingFormat::Mjpeg => {
-                let file = File::create(&filepath)?;
-                info!("Started manual MJPEG recording to: {:?}", filepath);
-                self.recording_file = Some(Arc::new(Mutex::new(file)));
-            }
-            RecordingFormat::Mp4 => {
-                let recorder = Mp4Recorder::new(&filepath, 11)?;  // 11 fps
-                info!("Started manual MP4 recording to: {:?}", filepath);
-                self.mp4_recorder = Some(recorder);
-            }
+        // Phase 6: Create recorder based on format (11 fps)
+        self.recorder = Some(RecordingWriter::create(&filepath, self.recording_format, 11, &self.mp4_config, key)?);
+        self.start_dedup();
+        self.sidecar = Some(self.start_sidecar(&filepath, extension, trigger, None));
+        info!("Started {} {} recording to: {:?}", trigger, extension.to_uppercase(), filepath);
+
+        if let Err(e) = self.catalog.recording_started(&filepath, trigger, extension) {
+            error!("Failed to update recording catalog: {}", e);
         }
 
         // Update state
@@ -267,50 +600,37 @@ impl CameraApp {
 
         // Generate filename with timestamp (Phase 6: dynamic extension)
         let now = chrono::Local::now();
-        let extension = match self.recording_format {
-            RecordingFormat::Mjpeg => "mjpeg",
-            RecordingFormat::Mp4 => "mp4",
-        };
+        let extension = self.recording_format.extension();
         let filename = format!("motion_{}.{}", now.format("%Y%m%d_%H%M%S"), extension);
         let filepath = self.recording_dir.join(&filename);
-
-        // Phase 6: Create recorder and write pre-buffer based on format
-        let (pre_frames, pre_bytes) = match self.recording_format {
-            RecordingFormat::Mjpeg => {
-                let mut file = File::create(&filepath)?;
-                let (frames, bytes) = self.ring_buffer.flush_to_file(&mut file)?;
-                info!("Started motion MJPEG recording to: {:?}", filepath);
-                info!("  Pre-buffer: {} frames, {:.2} MB", frames, bytes as f32 / 1_000_000.0);
-                self.recording_file = Some(Arc::new(Mutex::new(file)));
-                (frames, bytes)
-            }
-            RecordingFormat::Mp4 => {
-                let mut recorder = Mp4Recorder::new(&filepath, 11)?;
-
-                // Write pre-buffer frames to MP4
-                let mut pre_frame_count = 0;
-                let mut pre_byte_count = 0;
-
-                // Ring bufferから各フレームを取得してMP4に書き込む
-                // Note: ring_bufferはflush_to_fileでしか一括取得できないため、
-                // 一時的にMJPEGファイルを経由する必要がある
-                // TODO: より効率的な実装（ring_bufferにイテレータを追加）
-                let temp_file_path = std::env::temp_dir().join(format!("prebuffer_{}.mjpeg", now.format("%Y%m%d_%H%M%S")));
-                let mut temp_file = File::create(&temp_file_path)?;
-                let (frames, bytes) = self.ring_buffer.flush_to_file(&mut temp_file)?;
-                drop(temp_file);
-
-                // TODO: MJPEGファイルを読み込んで個別フレームとしてMP4に書き込む処理
-                // 現在の実装では、プリバッファはスキップ（MP4の場合）
-                warn!("MP4 motion recording: pre-buffer not yet implemented, starting from current frame");
-
-                std::fs::remove_file(temp_file_path)?;
-
-                info!("Started motion MP4 recording to: {:?}", filepath);
-                self.mp4_recorder = Some(recorder);
-                (0, 0)  // プリバッファは未実装
-            }
-        };
+        let key = self.recording_key()?;
+
+        // Frames stop arriving while motion detection is off, drop the ones that aged out since
+        self.ring_buffer.expire(Instant::now());
+
+        // Phase 6: Create recorder and write the pre-buffer ahead of the live frames
+        let mut writer = RecordingWriter::create(&filepath, self.recording_format, 11, &self.mp4_config, key)?;
+        let mut pre_frames = 0;
+        let mut pre_bytes = 0;
+        self.ring_buffer.for_each_frame(&mut |frame| {
+            // Stamp pre-buffer frames with the time they were captured
+            let age = chrono::Duration::from_std(frame.timestamp.elapsed()).unwrap_or_default();
+            let captured_at = Local::now() - age;
+            pre_frames += 1;
+            let jpeg_data = self.burn_recording_overlay(&frame.jpeg_data, captured_at, pre_frames as u64);
+            writer.write_frame_at(&jpeg_data, captured_at)?;
+            pre_bytes += jpeg_data.len();
+            Ok(())
+        })?;
+        self.recorder = Some(writer);
+        info!("Started motion {} recording to: {:?}", extension.to_uppercase(), filepath);
+        info!("  Pre-buffer: {} frames, {:.2} MB", pre_frames, pre_bytes as f32 / 1_000_000.0);
+
+        if let Err(e) = self.catalog.recording_started(&filepath, RecordingTrigger::Motion, extension) {
+            error!("Failed to update recording catalog: {}", e);
+        }
+        self.start_dedup();
+        self.sidecar = Some(self.start_sidecar(&filepath, extension, RecordingTrigger::Motion, Some(self.motion_config.clone())));
 
         // Update state
         self.recording_state = RecordingState::MotionRecording {
@@ -318,7 +638,6 @@ impl CameraApp {
             start_time: Instant::now(),
             frame_count: pre_frames as u32,
             total_bytes: pre_bytes as u64,
-            motion_active: true,
             countdown_frames: self.motion_config.post_record_seconds * 11,  // 11 fps
             format: self.recording_format,
         };
@@ -330,6 +649,15 @@ impl CameraApp {
     }
 
     fn stop_recording(&mut self) -> io::Result<()> {
+        // Close the motion event still open when the recording ends
+        if let RecordingState::MotionRecording { filepath, .. } = &self.recording_state {
+            if let Some(EventNotification::EventEnded(event)) = self.motion_events.end(Local::now()) {
+                if let Err(e) = self.catalog.motion_event(&catalog::recording_id(filepath), &event) {
+                    error!("Failed to update recording catalog: {}", e);
+                }
+            }
+        }
+
         // Check if recording (manual or motion)
         match &self.recording_state {
             RecordingState::ManualRecording { filepath, start_time, frame_count, total_bytes, format } |
@@ -341,22 +669,23 @@ impl CameraApp {
                 info!("  Duration: {:.1}s", duration.as_secs_f32());
                 info!("  Frames: {}", frame_count);
                 info!("  Size: {:.2} MB", *total_bytes as f32 / 1_000_000.0);
-
-                // Phase 6: Close recorder based on format
-                match format {
-                    RecordingFormat::Mjpeg => {
-                        self.recording_file = None;
-                    }
-                    RecordingFormat::Mp4 => {
-                        if let Some(recorder) = self.mp4_recorder.take() {
-                            recorder.finish()?;
-                        }
-                    }
+                if let Some(dedup) = self.dedup.take() {
+                    info!("  Duplicate frames skipped: {}", dedup.stats().dropped);
                 }
+                self.is_deduplicating.store(false, Ordering::Relaxed);
+
+                // Phase 6: Close recorder (segments are added when ffmpeg had to be restarted)
+                let segments = match self.recorder.take() {
+                    Some(recorder) => recorder.finish()?,
+                    None => Vec::new(),
+                };
+                let sidecar = self.sidecar.take();
+                self.finalize_recording(filepath, *format, *frame_count as u64, *total_bytes, &segments, sidecar);
 
                 // Update state
                 self.recording_state = RecordingState::Idle;
                 self.is_recording.store(false, Ordering::Relaxed);
+                self.refresh_recordings();
             }
             RecordingState::Idle => {
                 warn!("No recording in progress");
@@ -366,11 +695,779 @@ impl CameraApp {
         Ok(())
     }
 
-    fn write_frame(&mut self, jpeg_data: &[u8]) -> io::Result<()> {
+    /// Parse the schedule text; the schedule is followed only while it is valid
+    fn reload_schedule(&mut self) {
+        if !self.schedule_enabled {
+            self.scheduler = None;
+            self.schedule_error.clear();
+            return;
+        }
+        match self.schedule_text.parse::<Schedule>() {
+            Ok(schedule) => {
+                info!("Schedule enabled:\n{}", schedule);
+                self.scheduler = Some(Scheduler::new(schedule));
+                self.schedule_error.clear();
+            }
+            Err(e) => {
+                self.scheduler = None;
+                self.schedule_error = format!("❌ {}", e);
+            }
+        }
+    }
+
+    /// Switch motion detection and continuous recording to the mode the schedule gives for now
+    fn apply_schedule(&mut self) {
+        let Some(scheduler) = &mut self.scheduler else {
+            return;
+        };
+        let now = Local::now();
+        if let Some(mode) = scheduler.update(&now) {
+            info!("Schedule: {}", scheduler.describe(&now));
+            self.motion_config.enabled = mode == ScheduleMode::Motion;
+
+            // A motion recording in progress may finish on its own; anything else belongs to the previous mode
+            let keep_recording = match self.recording_state {
+                RecordingState::Idle => true,
+                RecordingState::MotionRecording { .. } => mode == ScheduleMode::Motion,
+                RecordingState::ManualRecording { .. } => false,
+            };
+            if !keep_recording {
+                if let Err(e) = self.stop_recording() {
+                    error!("Failed to stop recording for schedule change: {}", e);
+                }
+            }
+        }
+
+        // Continuous recording is restarted after the size limit or when capture restarts
+        let continuous = self.scheduler.as_ref().and_then(Scheduler::current) == Some(ScheduleMode::Continuous);
+        let retry_due = self.schedule_retry_at.is_none_or(|at| Instant::now() >= at);
+        if continuous && retry_due && matches!(self.recording_state, RecordingState::Idle) && *self.is_running.lock().unwrap() {
+            match self.start_manual_recording(RecordingTrigger::Scheduled) {
+                Ok(()) => {
+                    self.schedule_retry_at = None;
+                    self.schedule_error.clear();
+                }
+                Err(e) => {
+                    error!("Failed to start scheduled recording: {}", e);
+                    self.schedule_retry_at = Some(Instant::now() + SCHEDULE_RETRY_INTERVAL);
+                    self.schedule_error = format!("❌ Scheduled recording failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Start a timelapse in the recording directory with the current settings
+    fn start_timelapse(&mut self) -> Result<(), String> {
+        let mut config = self.timelapse_config.clone();
+        config.window = if self.timelapse_window_enabled {
+            Some(self.timelapse_window.parse::<DailyWindow>()?)
+        } else {
+            None
+        };
+        let key = self.recording_key().map_err(|e| e.to_string())?;
+        let recorder = TimelapseRecorder::start(&self.recording_dir, config.clone(), &self.mp4_config, key)
+            .map_err(|e| e.to_string())?;
+        info!("Started timelapse to: {:?} (every {}s)", recorder.path(), config.interval_secs);
+
+        if let Err(e) = self.catalog.recording_started(recorder.path(), RecordingTrigger::Timelapse, config.format.extension()) {
+            error!("Failed to update recording catalog: {}", e);
+        }
+        let motion = config.motion_only.then(|| self.motion_config.clone());
+        self.timelapse_sidecar = Some(self.start_sidecar(recorder.path(), config.format.extension(), RecordingTrigger::Timelapse, motion));
+
+        // Motion for the timelapse is detected separately, so motion recording can stay off
+        self.timelapse_motion = config.motion_only.then(|| MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            ..self.motion_config.clone()
+        }));
+        self.timelapse = Some(recorder);
+        self.timelapse_status.clear();
+        Ok(())
+    }
+
+    fn stop_timelapse(&mut self) {
+        let Some(recorder) = self.timelapse.take() else {
+            return;
+        };
+        self.timelapse_motion = None;
+
+        let path = recorder.path().to_path_buf();
+        let format = recorder.config().format;
+        let frame_count = recorder.frame_count();
+        match recorder.finish() {
+            Ok(segments) => {
+                info!("Stopped timelapse: {:?} ({} frames)", path, frame_count);
+                let sidecar = self.timelapse_sidecar.take();
+                self.finalize_recording(&path, format, frame_count, 0, &segments, sidecar);
+                self.timelapse_status = format!("✅ {} frames → {}", frame_count,
+                                                path.file_name().unwrap_or_default().to_string_lossy());
+            }
+            Err(e) => {
+                error!("Failed to finish timelapse {:?}: {}", path, e);
+                self.timelapse_status = format!("❌ Timelapse failed: {}", e);
+            }
+        }
+        self.refresh_recordings();
+    }
+
+    /// Add the latest frame to the timelapse when its interval is due
+    fn feed_timelapse(&mut self, motion: bool) {
+        let Some(timelapse) = &mut self.timelapse else {
+            return;
+        };
+        let Some(captured_at) = self.latest_jpeg.lock().unwrap().as_ref().map(|(_, at)| *at) else {
+            return;
+        };
+        if !timelapse.sample(captured_at, motion) {
+            return;
+        }
+        let sequence = timelapse.frame_count() + 1;
+
+        let Some((jpeg_data, captured_at)) = self.latest_jpeg.lock().unwrap().clone() else {
+            return;
+        };
+        let burned = self.burn_recording_overlay(&jpeg_data, captured_at, sequence).into_owned();
+        if let Some(timelapse) = &mut self.timelapse {
+            if let Err(e) = timelapse.write_frame(&burned, captured_at) {
+                error!("Failed to write timelapse frame: {}", e);
+                self.stop_timelapse();
+            }
+        }
+    }
+
+    /// Metadata sidecar for a recording that is starting
+    fn start_sidecar(&self, filepath: &Path, format: &str, trigger: RecordingTrigger, motion: Option<MotionDetectionConfig>) -> RecordingSidecar {
+        RecordingSidecar::start(filepath, format, trigger, &self.overlay.camera_name, self.device_profile.clone(), motion)
+    }
+
+    /// Record a closed recording in the catalog and metadata sidecar, and queue its signed manifests
+    fn finalize_recording(
+        &self,
+        filepath: &Path,
+        format: RecordingFormat,
+        frame_count: u64,
+        total_bytes: u64,
+        segments: &[PathBuf],
+        sidecar: Option<RecordingSidecar>,
+    ) {
+        // MP4 size is only known once ffmpeg has finished
+        let size_bytes = std::iter::once(filepath).chain(segments.iter().map(PathBuf::as_path))
+            .map(|path| std::fs::metadata(path).map(|m| m.len()))
+            .sum::<io::Result<u64>>()
+            .unwrap_or(total_bytes);
+        if let Err(e) = self.catalog.recording_finished(&catalog::recording_id(filepath), frame_count, size_bytes, segments) {
+            error!("Failed to update recording catalog: {}", e);
+        }
+        if let Some(sidecar) = sidecar {
+            if let Err(e) = sidecar.finish(filepath, frame_count, size_bytes, segments) {
+                error!("Failed to write metadata sidecar for {:?}: {}", filepath, e);
+            }
+        }
+
+        // Write the signed manifest for each file of the recording (in the background)
+        if let Some(queue) = &self.manifest_queue {
+            *self.manifest_status.lock().unwrap() = format!("Signing {}...", filepath.file_name().unwrap_or_default().to_string_lossy());
+            for path in std::iter::once(filepath).chain(segments.iter().map(PathBuf::as_path)) {
+                queue.sign(path, format.extension());
+            }
+        }
+    }
+
+    /// Save the latest frame as a snapshot (original JPEG, plus PNG if enabled) and add it to the catalog
+    fn take_snapshot(&mut self) -> io::Result<PathBuf> {
+        let Some((jpeg_data, captured_at)) = self.latest_jpeg.lock().unwrap().clone() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no frame received yet"));
+        };
+        let key = self.recording_key()?;
+        let png = snapshot::PngOptions {
+            overlay: &self.overlay.snapshot,
+            camera_name: &self.overlay.camera_name,
+            sequence: self.live_frame_count,
+        };
+
+        let dir = self.recording_dir.join(snapshot::SNAPSHOT_DIR_NAME);
+        let snapshot = snapshot::save_snapshot(&dir, &jpeg_data, captured_at, self.snapshot_png.then_some(&png), key.as_ref())?;
+        self.catalog.snapshot_taken(&snapshot.jpeg_path, snapshot.png_path.as_deref(), snapshot.captured_at)?;
+        Ok(snapshot.jpeg_path)
+    }
+
+    /// Take a snapshot and report the result in the side panel
+    fn snapshot_clicked(&mut self) {
+        self.snapshot_status = match self.take_snapshot() {
+            Ok(path) => {
+                info!("Snapshot saved: {:?}", path);
+                format!("✅ {}", path.file_name().unwrap_or_default().to_string_lossy())
+            }
+            Err(e) => {
+                error!("Snapshot failed: {}", e);
+                format!("❌ Snapshot failed: {}", e)
+            }
+        };
+    }
+
+    /// Reload the recordings list from the catalog
+    fn refresh_recordings(&mut self) {
+        match self.catalog.recordings() {
+            Ok(recordings) => self.recordings = recordings,
+            Err(e) => error!("Failed to read recording catalog: {}", e),
+        }
+    }
+
+    /// Export a clip in the background; progress and errors go to the clip status line
+    fn start_clip_export(&self, dialog: &ClipDialog) -> Result<(), String> {
+        let recording_start = clip_export::recording_start(&dialog.recording);
+        let now = Local::now();
+        let start = clip_export::parse_clip_time(&dialog.start, recording_start, now)?;
+        let end = clip_export::parse_clip_time(&dialog.end, recording_start, now)?;
+        // Encrypted recordings are read with the key configured for recording
+        let key = self.recording_key().map_err(|e| e.to_string())?;
+
+        let stem = dialog.recording.file_stem().unwrap_or_default().to_string_lossy();
+        let mut name = format!("{}_{}-{}", stem, start.format("%H%M%S"), end.format("%H%M%S"));
+        if let Some(extension) = dialog.format.extension() {
+            name = format!("{}.{}", name, extension);
+        }
+        let output = self.recording_dir.join("clips").join(name);
+        std::fs::create_dir_all(self.recording_dir.join("clips")).map_err(|e| e.to_string())?;
+
+        let recording = dialog.recording.clone();
+        let format = dialog.format;
+        let status = self.clip_status.clone();
+        *status.lock().unwrap() = format!("Exporting {}...", output.display());
+        thread::spawn(move || {
+            let message = match clip_export::export_clip(&recording, start, end, format, &output, key.as_ref()) {
+                Ok(summary) => {
+                    info!("Exported clip {:?}", summary.output);
+                    format!("✅ Exported {}", summary.output.display())
+                }
+                Err(e) => {
+                    error!("Clip export from {:?} failed: {}", recording, e);
+                    format!("❌ Export failed: {}", e)
+                }
+            };
+            *status.lock().unwrap() = message;
+        });
+        Ok(())
+    }
+
+    /// Archive old recordings in the background (throttled to leave CPU for live capture)
+    fn start_archive(&self) -> io::Result<()> {
+        // Encrypted recordings are converted with the key configured for recording
+        let key = self.recording_key()?;
+        let config = ArchiveConfig { mp4_config: self.mp4_config.clone(), ..self.archive_config.clone() };
+        let catalog = Catalog::new(&self.recording_dir);
+        let key_dir = self.recording_dir.join(manifest::KEY_DIR_NAME);
+        let running = self.archive_running.clone();
+        let cancel = self.archive_cancel.clone();
+        let status = self.archive_status.clone();
+
+        running.store(true, Ordering::Relaxed);
+        cancel.store(false, Ordering::Relaxed);
+        *status.lock().unwrap() = format!("Archiving recordings older than {} days...", config.older_than_days);
+        thread::spawn(move || {
+            let message = match archive::archive_old_recordings(&catalog, &config, key.as_ref(), &cancel) {
+                Ok(archived) => {
+                    // Replaced recordings lost their manifest, sign the archived copies
+                    match ManifestSigner::load_or_generate(&key_dir) {
+                        Ok(signer) => {
+                            for recording in archived.iter().filter(|r| r.replaced) {
+                                if let Err(e) = signer.sign_recording(&recording.path, recording.format.extension()) {
+                                    error!("Failed to sign archived recording {:?}: {}", recording.path, e);
+                                }
+                            }
+                        }
+                        Err(e) => error!("Archived recordings not signed, failed to load key: {}", e),
+                    }
+                    let saved: u64 = archived.iter().map(|r| r.original_bytes - r.size_bytes).sum();
+                    let cancelled = if cancel.load(Ordering::Relaxed) { " (cancelled)" } else { "" };
+                    format!("✅ Archived {} recording(s), {:.1} MB saved{}", archived.len(), saved as f64 / 1_048_576.0, cancelled)
+                }
+                Err(e) => {
+                    error!("Archiving failed: {}", e);
+                    format!("❌ Archiving failed: {}", e)
+                }
+            };
+            *status.lock().unwrap() = message;
+            running.store(false, Ordering::Relaxed);
+        });
+        Ok(())
+    }
+
+    /// Zone and exclusion mask lists, and the buttons for drawing new ones on the live view
+    fn zones_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing(format!("🗺 Zones ({}) / Masks ({})", self.motion_config.zones.len(), self.motion_config.exclusions.len()), |ui| {
+            let mut remove_zone = None;
+            let zone_results = &self.analyzers.zone_results;
+            for (i, zone) in self.motion_config.zones.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut zone.name).desired_width(100.0));
+                    if let Some(result) = zone_results.get(i) {
+                        ui.label(format!("{} {:.1}%", if result.motion_detected { "🔴" } else { "⚪" }, result.motion_ratio));
+                    }
+                    if ui.small_button("🗑").clicked() {
+                        remove_zone = Some(i);
+                    }
+                });
+                ui.add(egui::Slider::new(&mut zone.sensitivity, 0.0..=1.0).text("sens."));
+                ui.add(egui::Slider::new(&mut zone.min_motion_area, 0.1..=10.0).text("%"));
+            }
+            if let Some(i) = remove_zone {
+                self.motion_config.zones.remove(i);
+            }
+
+            let mut remove_mask = None;
+            for (i, mask) in self.motion_config.exclusions.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("⬛");
+                    ui.add(egui::TextEdit::singleline(&mut mask.name).desired_width(100.0));
+                    if ui.small_button("🗑").clicked() {
+                        remove_mask = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove_mask {
+                self.motion_config.exclusions.remove(i);
+            }
+
+            match self.zone_drawing {
+                None => {
+                    ui.horizontal(|ui| {
+                        if ui.button("✏ Draw zone").clicked() {
+                            self.zone_drawing = Some(ZoneDrawing::Zone);
+                            self.zone_draft.clear();
+                        }
+                        if ui.button("✏ Draw mask").clicked() {
+                            self.zone_drawing = Some(ZoneDrawing::Mask);
+                            self.zone_draft.clear();
+                        }
+                    });
+                    if self.motion_config.zones.is_empty() {
+                        ui.label("No zones: the whole frame is watched");
+                    }
+                }
+                Some(drawing) if !drawing.is_counting() => self.drawing_ui(ui, drawing),
+                Some(_) => {
+                    ui.label("Finish the counting line/zone first");
+                }
+            }
+        });
+    }
+
+    /// Finish/cancel buttons for the polygon or line being drawn on the live view
+    fn drawing_ui(&mut self, ui: &mut egui::Ui, drawing: ZoneDrawing) {
+        ui.label(format!("Click the live view to add points ({} so far)", self.zone_draft.len()));
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.zone_draft.len() >= drawing.min_points(), egui::Button::new("✔ Finish")).clicked() {
+                let points = std::mem::take(&mut self.zone_draft);
+                match drawing {
+                    ZoneDrawing::Zone => self.motion_config.zones.push(MotionZone {
+                        name: format!("zone{}", self.motion_config.zones.len() + 1),
+                        points,
+                        sensitivity: self.motion_config.sensitivity,
+                        min_motion_area: self.motion_config.min_motion_area,
+                    }),
+                    ZoneDrawing::Mask => self.motion_config.exclusions.push(ExclusionMask {
+                        name: format!("mask{}", self.motion_config.exclusions.len() + 1),
+                        points,
+                    }),
+                    ZoneDrawing::Line => self.motion_config.counting.lines.push(CountingLine {
+                        name: format!("line{}", self.motion_config.counting.lines.len() + 1),
+                        points: [points[0], points[1]],
+                    }),
+                    ZoneDrawing::CountZone => self.motion_config.counting.zones.push(CountingZone {
+                        name: format!("area{}", self.motion_config.counting.zones.len() + 1),
+                        points,
+                    }),
+                }
+                self.zone_drawing = None;
+            }
+            if ui.button("✖ Cancel").clicked() {
+                self.zone_draft.clear();
+                self.zone_drawing = None;
+            }
+        });
+    }
+
+    /// Counting lines and zones with their totals, and the buttons for drawing new ones on the live view
+    fn counting_ui(&mut self, ui: &mut egui::Ui) {
+        let counting = &mut self.motion_config.counting;
+        ui.checkbox(&mut counting.enabled, "Count line crossings and zone entries");
+        if !counting.enabled {
+            return;
+        }
+        if !self.motion_config.enabled {
+            ui.colored_label(egui::Color32::YELLOW, "⚠ Needs motion detection");
+        }
+
+        let totals = &self.analyzers.count_totals;
+        let counting = &mut self.motion_config.counting;
+        let mut remove_line = None;
+        for (i, line) in counting.lines.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label("➖");
+                ui.add(egui::TextEdit::singleline(&mut line.name).desired_width(90.0));
+                let counts = totals.get(&line.name).copied().unwrap_or_default();
+                ui.label(format!("➡ {}  ⬅ {}", counts.forward, counts.backward))
+                    .on_hover_text("➡ crossed to the right of the line (seen from its first point), ⬅ to the left");
+                if ui.small_button("🗑").clicked() {
+                    remove_line = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove_line {
+            counting.lines.remove(i);
+        }
+        let mut remove_zone = None;
+        for (i, zone) in counting.zones.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label("⬡");
+                ui.add(egui::TextEdit::singleline(&mut zone.name).desired_width(90.0));
+                let counts = totals.get(&zone.name).copied().unwrap_or_default();
+                ui.label(format!("🚪 {}", counts.entries));
+                if ui.small_button("🗑").clicked() {
+                    remove_zone = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove_zone {
+            counting.zones.remove(i);
+        }
+
+        match self.zone_drawing {
+            None => {
+                ui.horizontal(|ui| {
+                    if ui.button("✏ Draw line").clicked() {
+                        self.zone_drawing = Some(ZoneDrawing::Line);
+                        self.zone_draft.clear();
+                    }
+                    if ui.button("✏ Draw zone").clicked() {
+                        self.zone_drawing = Some(ZoneDrawing::CountZone);
+                        self.zone_draft.clear();
+                    }
+                    if ui.button("↺ Reset counts").clicked() {
+                        self.send_analyzer_command(AnalyzerCommand::ResetCounts);
+                    }
+                });
+            }
+            Some(drawing) if drawing.is_counting() => self.drawing_ui(ui, drawing),
+            Some(_) => {
+                ui.label("Finish the motion zone/mask first");
+            }
+        }
+
+        let counting = &mut self.motion_config.counting;
+        ui.add(egui::Slider::new(&mut counting.min_blob_area, 0.05..=5.0).text("min object %"))
+            .on_hover_text("Smaller motion blobs are not tracked");
+        ui.add(egui::Slider::new(&mut counting.max_distance, 0.02..=0.5).text("max step"))
+            .on_hover_text("Largest move between frames (fraction of the frame) still treated as the same object");
+        ui.add(egui::Slider::new(&mut counting.max_missed_frames, 1..=30).text("keep frames"))
+            .on_hover_text("Frames an object may go unseen before its track is dropped");
+        if !self.count_status.is_empty() {
+            ui.label(&self.count_status);
+        }
+    }
+
+    /// Log the crossings and entries of a frame and add them to the hourly counts
+    fn handle_counts(&mut self, events: Vec<CountEvent>) {
+        for event in &events {
+            info!("Counted: {}", event);
+        }
+        if self.count_log.is_none() && self.count_status.is_empty() {
+            let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join("metrics");
+            match HourlyCountLog::create(&dir) {
+                Ok(log) => {
+                    info!("Hourly counts: {:?}", log.path());
+                    self.count_status = format!("Hourly counts: {}", log.path().display());
+                    self.count_log = Some(log);
+                }
+                Err(e) => {
+                    error!("Failed to create the hourly count log in {:?}: {}", dir, e);
+                    self.count_status = format!("❌ Hourly counts not saved: {}", e);
+                }
+            }
+        }
+        if let Some(log) = &mut self.count_log {
+            if let Err(e) = log.record(Local::now(), &events) {
+                error!("Failed to write {:?}: {}", log.path(), e);
+            }
+        }
+    }
+
+    fn send_analyzer_command(&self, command: AnalyzerCommand) {
+        if self.analyzer_commands.send(command).is_err() {
+            error!("Analysis thread has stopped");
+        }
+    }
+
+    /// Send the detection config to the analysis thread when it changed since the last frame
+    fn sync_analyzer_config(&mut self) {
+        if self.analyzer_config.as_ref() != Some(&self.motion_config) {
+            self.send_analyzer_command(AnalyzerCommand::Configure(Box::new(self.motion_config.clone())));
+            self.analyzer_config = Some(self.motion_config.clone());
+        }
+    }
+
+    /// Enable and order the frame analyzers; changes are written back to the detection config
+    fn analyzers_ui(&mut self, ui: &mut egui::Ui) {
+        let all_stats = &self.analyzers.stats;
+        let mut settings: Vec<AnalyzerSetting> = all_stats.iter()
+            .map(|stats| AnalyzerSetting { name: stats.name.clone(), enabled: stats.enabled })
+            .collect();
+        let mut changed = false;
+        let mut move_up = None;
+        let count = settings.len();
+        for (i, stats) in all_stats.iter().enumerate() {
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut settings[i].enabled, &stats.name)
+                    .on_hover_text("Unchecked analyzers are skipped even when their own detection is enabled")
+                    .changed();
+                if ui.add_enabled(i > 0, egui::Button::new("⬆")).clicked() {
+                    move_up = Some(i);
+                }
+                if ui.add_enabled(i + 1 < count, egui::Button::new("⬇")).clicked() {
+                    move_up = Some(i + 1);
+                }
+            });
+            if stats.frames > 0 {
+                ui.label(format!("   {:.2} ms avg, {:.2} ms max, {} detections", stats.avg_time_ms, stats.max_time_ms, stats.detections))
+                    .on_hover_text(stats.last_detection.as_deref().unwrap_or("No detections yet"));
+            }
+        }
+        if let Some(i) = move_up {
+            settings.swap(i - 1, i);
+            changed = true;
+        }
+        if changed {
+            self.motion_config.analyzers = settings;
+        }
+    }
+
+    /// Let the event tracker decide when motion events start and end, then run the post-record countdown
+    fn handle_motion_result(&mut self, result: MotionResult, captured_at: DateTime<Local>) {
+        self.motion_result = result;
+        self.motion_events.update(&self.motion_result, captured_at);
+        while let Ok(notification) = self.motion_event_rx.try_recv() {
+            self.handle_motion_event(notification);
+        }
+
+        // Post-record countdown once the motion event has ended
+        let event_active = self.motion_events.current().is_some();
+        if let RecordingState::MotionRecording { countdown_frames, .. } = &mut self.recording_state {
+            if event_active {
+                // Motion event still open
+            } else if *countdown_frames > 0 {
+                *countdown_frames -= 1;
+            } else if let Err(e) = self.stop_recording() {
+                // Countdown finished - stop recording
+                error!("Failed to stop motion recording: {}", e);
+            }
+        }
+    }
+
+    /// Start, extend or close the motion recording as motion events come and go
+    fn handle_motion_event(&mut self, notification: EventNotification) {
+        let post_record_frames = self.motion_config.post_record_seconds * 11;
+        debug!("Motion event #{}: {:?}", notification.event().id, notification);
+        match notification {
+            EventNotification::EventStarted(_) | EventNotification::EventUpdated(_) => {
+                self.last_motion_time = Some(Instant::now());
+                let waiting = self.waits_for_objects() && !self.object_triggered;
+                match &mut self.recording_state {
+                    RecordingState::Idle if waiting => {
+                        // Started by handle_objects once a trigger object shows up
+                    }
+                    RecordingState::Idle => {
+                        if let Err(e) = self.start_motion_recording() {
+                            error!("Failed to start motion recording: {}", e);
+                        }
+                    }
+                    RecordingState::MotionRecording { countdown_frames, .. } => {
+                        *countdown_frames = post_record_frames;
+                    }
+                    RecordingState::ManualRecording { .. } => {
+                        // Manual recording in progress - don't interfere
+                    }
+                }
+            }
+            EventNotification::EventEnded(event) => {
+                info!("Motion event #{} ended after {:.1}s", event.id, event.duration().num_milliseconds() as f32 / 1000.0);
+                self.object_triggered = false;
+                self.object_detections.clear();
+                if let RecordingState::MotionRecording { filepath, .. } = &self.recording_state {
+                    if let Err(e) = self.catalog.motion_event(&catalog::recording_id(filepath), &event) {
+                        error!("Failed to update recording catalog: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Motion recording waits for a trigger object (e.g. a person) when trigger classes are set
+    fn waits_for_objects(&self) -> bool {
+        let objects = &self.motion_config.objects;
+        objects.enabled
+            && !objects.trigger_classes.is_empty()
+            && self.analyzers.stats.iter().any(|stats| stats.name == "objects" && stats.enabled)
+    }
+
+    /// Keep the objects found on a motion frame and start the motion recording on a trigger object
+    ///
+    /// Inference finishes on a later frame, so only objects seen since the current event started confirm it.
+    fn handle_objects(&mut self, found: ObjectFrame) {
+        if self.motion_events.current().is_none_or(|event| event.started_at > found.captured_at) {
+            return;
+        }
+        if !self.object_triggered {
+            if let Some(object) = self.motion_config.objects.trigger(&found.objects) {
+                info!("Motion event confirmed by {}", object);
+                self.object_triggered = true;
+                if matches!(self.recording_state, RecordingState::Idle) {
+                    if let Err(e) = self.start_motion_recording() {
+                        error!("Failed to start motion recording: {}", e);
+                    }
+                }
+            }
+        }
+        self.object_detections = found.objects;
+    }
+
+    /// Load the ONNX model; the detector replaces the previously loaded one
+    fn load_object_model(&mut self) {
+        let config = ObjectDetectionConfig {
+            model_path: PathBuf::from(self.object_model_text.trim()),
+            ..self.motion_config.objects.clone()
+        };
+        match ObjectDetector::load(config.clone()) {
+            Ok(detector) => {
+                info!("Object detection model loaded: {:?}", config.model_path);
+                self.object_status = format!("✅ Loaded {}", config.model_path.display());
+                self.motion_config.objects = config;
+                self.send_analyzer_command(AnalyzerCommand::RegisterObjects(Box::new(detector)));
+            }
+            Err(e) => {
+                error!("Failed to load object detection model {:?}: {}", config.model_path, e);
+                self.object_status = format!("❌ {}", e);
+            }
+        }
+    }
+
+    /// Log a tamper event and record its start and end in the catalog
+    fn handle_tamper(&mut self, notification: TamperNotification) {
+        let event = notification.event();
+        match &notification {
+            TamperNotification::Started(_) => warn!("Camera tampering: {} since {} (sharpness {:.0}/{:.0}, entropy {:.1}, shift {:.0}%/{:.0}%)",
+                event.kind, event.started_at.format("%H:%M:%S"), event.measurements.sharpness, event.measurements.reference_sharpness,
+                event.measurements.entropy, event.measurements.shift.0, event.measurements.shift.1),
+            TamperNotification::Ended(_) => info!("Camera tampering ended: {}", event.kind),
+        }
+        if let Err(e) = self.catalog.tamper_event(event) {
+            error!("Failed to update recording catalog: {}", e);
+        }
+    }
+
+    /// Pre-record buffer limits: the last pre_record_seconds by capture time, capped in RAM by size
+    fn prebuffer_policy(&self) -> EvictionPolicy {
+        let policy = EvictionPolicy::seconds(self.motion_config.pre_record_seconds);
+        if self.disk_prebuffer {
+            policy  // Bounded by the file size
+        } else {
+            policy.with_max_bytes(self.prebuffer_ram_mb as usize * 1024 * 1024)
+        }
+    }
+
+    /// Recreate the pre-record buffer in RAM or on disk (buffered frames are dropped)
+    fn rebuild_ring_buffer(&mut self) {
+        // Drop the old buffer first, it removes its file on drop and the path is reused
+        self.ring_buffer = Box::new(RingBuffer::new(0));
+        self.ring_buffer_disk_mb = None;
+
+        if self.disk_prebuffer {
+            let path = self.recording_dir.join(PREBUFFER_FILE_NAME);
+            let file_size = self.disk_prebuffer_mb as u64 * 1024 * 1024;
+            match std::fs::create_dir_all(&self.recording_dir).and_then(|_| DiskRingBuffer::create(&path, self.prebuffer_policy(), file_size)) {
+                Ok(buffer) => {
+                    info!("Pre-record buffer on disk: {:?} ({} MB, {} s)", path, self.disk_prebuffer_mb, self.motion_config.pre_record_seconds);
+                    self.ring_buffer = Box::new(buffer);
+                    self.ring_buffer_disk_mb = Some(self.disk_prebuffer_mb);
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to create disk pre-record buffer {:?}, using RAM: {}", path, e);
+                    self.disk_prebuffer = false;
+                    self.motion_config.pre_record_seconds = self.motion_config.pre_record_seconds.min(MAX_RAM_PRE_RECORD_SECONDS);
+                }
+            }
+        }
+        self.ring_buffer = Box::new(RingBuffer::with_policy(self.prebuffer_policy()));
+    }
+
+    /// Key for the next recording, or None when encryption is off
+    fn recording_key(&self) -> io::Result<Option<KeySource>> {
+        if !self.encryption_enabled {
+            return Ok(None);
+        }
+        if self.encryption_use_key_file {
+            return KeySource::from_key_file(&PathBuf::from(self.encryption_key_file.trim())).map(Some);
+        }
+        if self.encryption_passphrase.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "encryption is enabled but no passphrase is set"));
+        }
+        let mut session_key = self.session_key.borrow_mut();
+        if let Some((_, key)) = session_key.as_ref().filter(|(passphrase, _)| *passphrase == self.encryption_passphrase) {
+            return Ok(Some(key.clone()));
+        }
+        let key = KeySource::Passphrase(self.encryption_passphrase.clone()).derive()?;
+        *session_key = Some((self.encryption_passphrase.clone(), key.clone()));
+        Ok(Some(key))
+    }
+
+    /// Burn the recording overlay into a frame, keeping the original frame if that fails
+    fn burn_recording_overlay<'a>(&self, jpeg_data: &'a [u8], captured_at: DateTime<Local>, sequence: u64) -> Cow<'a, [u8]> {
+        let config = &self.overlay.recording;
+        if !config.enabled {
+            return Cow::Borrowed(jpeg_data);
+        }
+
+        let text = config.text(&self.overlay.camera_name, captured_at, sequence);
+        match overlay::burn_into_jpeg(jpeg_data, config, &text) {
+            Ok(burned) => Cow::Owned(burned),
+            Err(e) => {
+                warn!("Recording overlay failed, saving frame without it: {}", e);
+                Cow::Borrowed(jpeg_data)
+            }
+        }
+    }
+
+    /// Skip unchanged frames in the new recording when enabled (the capture thread then makes thumbnails)
+    fn start_dedup(&mut self) {
+        self.dedup = self.dedup_enabled.then(|| FrameDeduplicator::new(self.dedup_config));
+        self.is_deduplicating.store(self.dedup.is_some(), Ordering::Relaxed);
+    }
+
+    fn write_frame(&mut self, jpeg_data: &[u8], thumbnail: Option<image::GrayImage>) -> io::Result<()> {
+        let sequence = match &self.recording_state {
+            RecordingState::ManualRecording { frame_count, .. } |
+            RecordingState::MotionRecording { frame_count, .. } => *frame_count as u64 + 1,
+            RecordingState::Idle => return Ok(()),
+        };
+
+        // Static scene: advance the recording's timeline without saving the frame
+        if let Some(dedup) = &mut self.dedup {
+            if !dedup.should_keep_thumbnail(thumbnail, Local::now()) {
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.skip_frame()?;
+                }
+                return Ok(());
+            }
+        }
+
+        let burned = self.burn_recording_overlay(jpeg_data, Local::now(), sequence);
+        let jpeg_data: &[u8] = &burned;
+
         // Check if recording (manual or motion)
         match &mut self.recording_state {
-            RecordingState::ManualRecording { total_bytes, frame_count, format, .. } |
-            RecordingState::MotionRecording { total_bytes, frame_count, format, .. } => {
+            RecordingState::ManualRecording { total_bytes, frame_count, .. } |
+            RecordingState::MotionRecording { total_bytes, frame_count, .. } => {
                 // Check size limit
                 if *total_bytes + jpeg_data.len() as u64 > MAX_RECORDING_SIZE {
                     warn!("Recording size limit reached ({} MB), stopping", MAX_RECORDING_SIZE / 1_000_000);
@@ -378,23 +1475,11 @@ impl CameraApp {
                     return Ok(());
                 }
 
-                // Phase 6: Write to appropriate recorder based on format
-                match format {
-                    RecordingFormat::Mjpeg => {
-                        // Write JPEG data to MJPEG file
-                        if let Some(ref file) = self.recording_file {
-                            let mut file_guard = file.lock().unwrap();
-                            file_guard.write_all(jpeg_data)?;
-                            // Note: flush() removed to reduce GUI thread blocking
-                            // File will be flushed automatically on close or periodically by OS
-                        }
-                    }
-                    RecordingFormat::Mp4 => {
-                        // Write JPEG frame to MP4 encoder
-                        if let Some(ref mut recorder) = self.mp4_recorder {
-                            recorder.write_frame(jpeg_data)?;
-                        }
-                    }
+                // Phase 6: Write to the MJPEG file or MP4 encoder
+                // Note: no per-frame flush() to reduce GUI thread blocking;
+                // the MJPEG writer syncs to disk periodically instead
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.write_frame(jpeg_data)?;
                 }
 
                 // Update counters
@@ -437,9 +1522,19 @@ impl CameraApp {
                 AppMessage::DecodedFrame { width, height, pixels } => {
                     // Fast path - receive pre-decoded RGBA data
                     let size = [width as usize, height as usize];
+                    self.live_frame_count += 1;
+                    // Draw on a copy so the overlay text is not seen by motion detection
+                    let display = self.overlay.live.enabled
+                        .then(|| image::RgbaImage::from_raw(width, height, pixels.clone()))
+                        .flatten()
+                        .map(|mut display| {
+                            let text = self.overlay.live.text(&self.overlay.camera_name, Local::now(), self.live_frame_count);
+                            overlay::draw_text(&mut display, &self.overlay.live, &text);
+                            display.into_raw()
+                        });
                     let color_image = egui::ColorImage::from_rgba_unmultiplied(
                         size,
-                        &pixels,
+                        display.as_deref().unwrap_or(&pixels),
                     );
 
                     if let Some(texture) = &mut self.current_frame {
@@ -452,51 +1547,53 @@ impl CameraApp {
                         ));
                     }
 
-                    // Phase 5: Motion detection
-                    if self.motion_config.enabled {
-                        use image::RgbaImage;
-
-                        // Convert pixels Vec<u8> to RgbaImage
-                        if let Some(rgba_img) = RgbaImage::from_raw(width, height, pixels) {
-                            // Detect motion
-                            let motion_detected = self.motion_detector.detect(&rgba_img);
-
-                            // Handle motion detection states
-                            match &mut self.recording_state {
-                                RecordingState::Idle => {
-                                    if motion_detected {
-                                        // Start motion recording
-                                        if let Err(e) = self.start_motion_recording() {
-                                            error!("Failed to start motion recording: {}", e);
-                                        }
-                                    }
-                                }
-                                RecordingState::MotionRecording { motion_active, countdown_frames, .. } => {
-                                    if motion_detected {
-                                        // Motion continues - reset countdown
-                                        *motion_active = true;
-                                        *countdown_frames = self.motion_config.post_record_seconds * 11;
-                                        self.last_motion_time = Some(Instant::now());
-                                    } else {
-                                        // No motion - countdown
-                                        *motion_active = false;
-                                        if *countdown_frames > 0 {
-                                            *countdown_frames -= 1;
-                                        } else {
-                                            // Countdown finished - stop recording
-                                            if let Err(e) = self.stop_recording() {
-                                                error!("Failed to stop motion recording: {}", e);
-                                            }
-                                        }
-                                    }
-                                }
-                                RecordingState::ManualRecording { .. } => {
-                                    // Manual recording in progress - don't interfere
-                                }
-                            }
+                    // Timelapse: keep one frame per interval
+                    if self.timelapse.is_some() {
+                        let motion = match &mut self.timelapse_motion {
+                            Some(detector) => image::RgbaImage::from_raw(width, height, pixels.clone())
+                                .is_some_and(|img| detector.detect(&img).motion_detected),
+                            None => false,
+                        };
+                        self.feed_timelapse(motion);
+                    }
+
+                    // JpegFrame messages only arrive while recording, so fill the pre-buffer here
+                    if self.motion_config.enabled && !self.is_recording.load(Ordering::Relaxed) {
+                        if let Some((jpeg_data, received_at)) = self.latest_jpeg.lock().unwrap().clone() {
+                            // Stamp with the receive time, decoded frames can queue up behind a busy UI
+                            let age = (Local::now() - received_at).to_std().unwrap_or_default();
+                            let timestamp = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+                            self.ring_buffer.push(JpegFrame { jpeg_data, timestamp });
+                        }
+                    }
+
+                    // Phase 5: Motion detection, tamper detection and any other analyzers run on the analysis thread;
+                    // a frame that arrives while it is still busy is not analyzed
+                    if let Some(image) = image::RgbaImage::from_raw(width, height, pixels) {
+                        let frame = AnalysisFrame { image, captured_at: Local::now(), sequence: self.live_frame_count };
+                        if let Err(TrySendError::Full(frame)) = self.analysis_tx.try_send(frame) {
+                            debug!("Analysis busy, frame #{} not analyzed", frame.sequence);
+                        }
+                    }
+                }
+                AppMessage::Detections { captured_at, detections } => {
+                    for detection in detections {
+                        match detection {
+                            Detection::Motion(result) => self.handle_motion_result(result, captured_at),
+                            Detection::Objects(found) => self.handle_objects(found),
+                            Detection::Counts(events) => self.handle_counts(events),
+                            // Delivered through tamper_rx, together with manual resets
+                            Detection::Tamper(_) => {}
+                            custom @ Detection::Custom { .. } => info!("Analyzer: {}", custom),
                         }
                     }
                 }
+                AppMessage::AnalyzerState(state) => {
+                    self.analyzers = *state;
+                    while let Ok(notification) = self.tamper_rx.try_recv() {
+                        self.handle_tamper(notification);
+                    }
+                }
                 AppMessage::ConnectionStatus(status) => {
                     self.connection_status = status;
                 }
@@ -510,24 +1607,41 @@ impl CameraApp {
                     self.texture_upload_time_ms = texture_upload_time_ms;
                     self.jpeg_size_kb = jpeg_size_kb;
                 }
-                AppMessage::SpresenseMetrics { timestamp_ms: _, camera_frames, camera_fps, usb_packets: _, action_q_depth, avg_packet_size: _, errors } => {
+                AppMessage::SpresenseMetrics { timestamp_ms, camera_frames, camera_fps, usb_packets, action_q_depth, avg_packet_size, errors } => {
                     // Phase 4.1: Update Spresense-side metrics
                     self.spresense_camera_frames = Some(camera_frames);
                     self.spresense_camera_fps = Some(camera_fps);
                     self.spresense_action_q_depth = Some(action_q_depth);
                     self.spresense_errors = Some(errors);
+
+                    // Keep the first and last report of each recording in its sidecar
+                    let metrics = FirmwareMetrics {
+                        received_at: Local::now(),
+                        uptime_ms: timestamp_ms,
+                        camera_frames,
+                        usb_packets,
+                        action_q_depth,
+                        avg_packet_size,
+                        errors,
+                    };
+                    for sidecar in [&mut self.sidecar, &mut self.timelapse_sidecar].into_iter().flatten() {
+                        sidecar.record_metrics(metrics.clone());
+                    }
                 }
-                AppMessage::JpegFrame(jpeg_data) => {
+                AppMessage::DeviceConnected(profile) => {
+                    self.device_profile = Some(profile);
+                }
+                AppMessage::JpegFrame { jpeg_data, received_at, thumbnail } => {
                     // Phase 5: Add to ring buffer (if motion detection enabled)
                     if self.motion_config.enabled {
                         self.ring_buffer.push(JpegFrame {
                             jpeg_data: jpeg_data.clone(),
-                            timestamp: Instant::now(),
+                            timestamp: received_at,
                         });
                     }
 
                     // Phase 3/5: Write JPEG frame to recording file
-                    if let Err(e) = self.write_frame(&jpeg_data) {
+                    if let Err(e) = self.write_frame(&jpeg_data, thumbnail) {
                         error!("Failed to write recording frame: {}", e);
                     }
                 }
@@ -538,13 +1652,21 @@ impl CameraApp {
 
 impl eframe::App for CameraApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Follow the weekly schedule before motion detection reads its config
+        self.apply_schedule();
+
         // Phase 5: Sync motion detector config
-        self.motion_detector.update_config(self.motion_config.clone());
+        self.sync_analyzer_config();
+        self.motion_events.set_config(self.motion_config.events);
 
-        // Update ring buffer capacity if pre_record_seconds changed
-        let expected_capacity = (self.motion_config.pre_record_seconds * 11) as usize;
-        if self.ring_buffer.capacity() != expected_capacity {
-            self.ring_buffer = RingBuffer::from_seconds(self.motion_config.pre_record_seconds, 11);
+        // Rebuild the ring buffer when it moves between RAM and disk, otherwise resize it in place
+        let expected_disk_mb = self.disk_prebuffer.then_some(self.disk_prebuffer_mb);
+        if self.ring_buffer_disk_mb != expected_disk_mb {
+            self.rebuild_ring_buffer();
+        }
+        let policy = self.prebuffer_policy();
+        if self.ring_buffer.policy() != policy {
+            self.ring_buffer.set_policy(policy);
         }
 
         // Process incoming messages
@@ -553,6 +1675,10 @@ impl eframe::App for CameraApp {
         // Request continuous repaint for smooth video
         ctx.request_repaint();
 
+        if ctx.input(|i| i.key_pressed(egui::Key::F12)) {
+            self.snapshot_clicked();
+        }
+
         // Top panel - Controls
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -574,6 +1700,16 @@ impl eframe::App for CameraApp {
 
                     ui.separator();
 
+                    let has_frame = self.latest_jpeg.lock().unwrap().is_some();
+                    if ui.add_enabled(has_frame, egui::Button::new("📸 Snapshot"))
+                        .on_hover_text("Save the latest frame (F12)")
+                        .clicked()
+                    {
+                        self.snapshot_clicked();
+                    }
+
+                    ui.separator();
+
                     // Phase 3/5: Recording controls
                     let is_recording = matches!(self.recording_state,
                         RecordingState::ManualRecording { .. } | RecordingState::MotionRecording { .. });
@@ -593,10 +1729,10 @@ impl eframe::App for CameraApp {
                                 ui.label(format!("🔴 MANUAL {}:{:02} | {:.1}MB | {} frames",
                                                duration / 60, duration % 60, size_mb, frame_count));
                             }
-                            RecordingState::MotionRecording { start_time, frame_count, total_bytes, motion_active, countdown_frames, .. } => {
+                            RecordingState::MotionRecording { start_time, frame_count, total_bytes, countdown_frames, .. } => {
                                 let duration = start_time.elapsed().as_secs();
                                 let size_mb = *total_bytes as f32 / 1_000_000.0;
-                                let motion_indicator = if *motion_active { "🔴 MOTION" } else { "⏱️  POST" };
+                                let motion_indicator = if self.motion_events.current().is_some() { "🔴 MOTION" } else { "⏱️  POST" };
                                 ui.label(format!("{} {}:{:02} | {:.1}MB | {} frames | {}f left",
                                                motion_indicator, duration / 60, duration % 60, size_mb, frame_count, countdown_frames));
                             }
@@ -609,7 +1745,7 @@ impl eframe::App for CameraApp {
                         ui.radio_value(&mut self.recording_format, RecordingFormat::Mjpeg, "MJPEG");
 
                         if ui.button("⏺ Start Rec").clicked() {
-                            if let Err(e) = self.start_manual_recording() {
+                            if let Err(e) = self.start_manual_recording(RecordingTrigger::Manual) {
                                 error!("Failed to start recording: {}", e);
                             }
                         }
@@ -639,6 +1775,10 @@ impl eframe::App for CameraApp {
                 ui.separator();
                 ui.label(format!("🖼 Texture: {:.1}ms", self.texture_upload_time_ms));
                 ui.separator();
+                for stats in self.analyzers.stats.iter().filter(|stats| stats.enabled && stats.frames > 0) {
+                    ui.label(format!("🧩 {}: {:.1}ms", stats.name, stats.avg_time_ms));
+                    ui.separator();
+                }
                 ui.label(format!("📦 JPEG: {:.1}KB", self.jpeg_size_kb));
                 ui.separator();
 
@@ -691,7 +1831,8 @@ impl eframe::App for CameraApp {
             ui.heading("🔍 Motion Detection");
             ui.separator();
 
-            ui.checkbox(&mut self.motion_config.enabled, "Enable Motion Detection");
+            ui.add_enabled(self.scheduler.is_none(), egui::Checkbox::new(&mut self.motion_config.enabled, "Enable Motion Detection"))
+                .on_disabled_hover_text("Controlled by the schedule");
 
             if self.motion_config.enabled {
                 ui.add_space(5.0);
@@ -711,10 +1852,77 @@ impl eframe::App for CameraApp {
 
                 ui.add_space(5.0);
 
+                // Compare against the previous frame or a learned background
+                egui::ComboBox::from_label("Algorithm")
+                    .selected_text(self.motion_config.algorithm.to_string())
+                    .show_ui(ui, |ui| {
+                        for algorithm in [MotionAlgorithm::FrameDiff, MotionAlgorithm::Background] {
+                            ui.selectable_value(&mut self.motion_config.algorithm, algorithm, algorithm.to_string());
+                        }
+                    });
+                if self.motion_config.algorithm == MotionAlgorithm::Background {
+                    ui.add(egui::Slider::new(&mut self.motion_config.background_learning_rate, 0.001..=0.2)
+                        .logarithmic(true)
+                        .text("learning"))
+                        .on_hover_text("How fast stopped objects and lighting changes become background");
+                }
+                // Analysis resolution (smaller is faster, blobs get coarser)
+                egui::ComboBox::from_label("Resolution")
+                    .selected_text(format!("1/{}", self.motion_config.downscale))
+                    .show_ui(ui, |ui| {
+                        for downscale in [1, 2, 4, 8] {
+                            ui.selectable_value(&mut self.motion_config.downscale, downscale, format!("1/{}", downscale));
+                        }
+                    });
+                ui.checkbox(&mut self.motion_config.suppress_illumination, "Ignore lighting changes")
+                    .on_hover_text("Compensate frame-wide brightness shifts (lights, clouds, auto exposure) instead of treating them as motion");
+
+                // When detections become an event and when the event ends
+                egui::CollapsingHeader::new("Event Timing").show(ui, |ui| {
+                    let events = &mut self.motion_config.events;
+                    ui.horizontal(|ui| {
+                        ui.label("Trigger:");
+                        ui.add(egui::DragValue::new(&mut events.trigger_frames).clamp_range(1..=events.window_frames));
+                        ui.label("of");
+                        ui.add(egui::DragValue::new(&mut events.window_frames).clamp_range(1..=30));
+                        ui.label("frames");
+                    });
+                    events.trigger_frames = events.trigger_frames.min(events.window_frames);
+                    ui.add(egui::Slider::new(&mut events.quiet_seconds, 0.0..=10.0).text("quiet s"))
+                        .on_hover_text("Seconds without motion before the event ends");
+                    ui.add(egui::Slider::new(&mut events.min_event_seconds, 0.0..=30.0).text("min s"))
+                        .on_hover_text("Events are never shorter than this");
+                    ui.add(egui::Slider::new(&mut events.cooldown_seconds, 0.0..=30.0).text("cooldown s"))
+                        .on_hover_text("Seconds after an event before a new one can start");
+                });
+                if let Some(event) = self.motion_events.current() {
+                    ui.colored_label(egui::Color32::RED, format!("● Event #{} ({:.0}s, peak {:.1}%)",
+                        event.id, event.duration().num_milliseconds() as f32 / 1000.0, event.peak_motion_ratio));
+                }
+
+                ui.add_space(5.0);
+
                 // Pre-record seconds
                 ui.label("Pre-record (sec):");
-                ui.add(egui::Slider::new(&mut self.motion_config.pre_record_seconds, 5..=30)
+                let max_pre_record = if self.disk_prebuffer { MAX_DISK_PRE_RECORD_SECONDS } else { MAX_RAM_PRE_RECORD_SECONDS };
+                ui.add(egui::Slider::new(&mut self.motion_config.pre_record_seconds, 5..=max_pre_record)
                     .text("s"));
+                if ui.checkbox(&mut self.disk_prebuffer, "Buffer on disk")
+                    .on_hover_text("Keep the pre-record frames in a fixed-size encrypted file instead of RAM")
+                    .changed()
+                    && !self.disk_prebuffer
+                {
+                    self.motion_config.pre_record_seconds = self.motion_config.pre_record_seconds.min(MAX_RAM_PRE_RECORD_SECONDS);
+                }
+                ui.horizontal(|ui| {
+                    if self.disk_prebuffer {
+                        ui.label("File size:");
+                        ui.add(egui::DragValue::new(&mut self.disk_prebuffer_mb).clamp_range(16..=4096).suffix(" MB"));
+                    } else {
+                        ui.label("Max memory:");
+                        ui.add(egui::DragValue::new(&mut self.prebuffer_ram_mb).clamp_range(8..=1024).suffix(" MB"));
+                    }
+                });
 
                 ui.add_space(5.0);
 
@@ -725,21 +1933,352 @@ impl eframe::App for CameraApp {
 
                 ui.add_space(5.0);
 
+                self.zones_ui(ui);
+
+                ui.add_space(5.0);
+
                 // Motion detector stats
-                let stats = self.motion_detector.stats();
+                let stats = &self.analyzers.motion;
                 if stats.total_frames > 0 {
                     ui.label(format!("📊 Detection: {:.1}%", stats.detection_rate));
                 }
+                if stats.illumination_count > 0 {
+                    ui.label(format!("💡 Lighting changes ignored: {}", stats.illumination_count));
+                }
+                if self.motion_result.change == SceneChange::Illumination {
+                    ui.colored_label(egui::Color32::YELLOW, format!("💡 Lighting change (brightness x{:.2})",
+                        self.motion_result.brightness_gain.unwrap_or(1.0)));
+                }
 
                 // Ring buffer status
-                ui.label(format!("💾 Buffer: {}/{} frames ({:.1}%)",
-                    self.ring_buffer.len(),
-                    self.ring_buffer.capacity(),
+                let buffer_stats = self.ring_buffer.stats();
+                ui.label(format!("💾 Buffer: {:.1}/{}s, {} frames @ {:.1} fps ({:.1}%)",
+                    buffer_stats.duration.as_secs_f32(),
+                    self.motion_config.pre_record_seconds,
+                    buffer_stats.frames,
+                    buffer_stats.fps,
                     self.ring_buffer.usage_ratio() * 100.0));
 
                 if let Some(age) = self.ring_buffer.oldest_frame_age_secs() {
                     ui.label(format!("⏱️  Oldest: {:.1}s ago", age));
                 }
+                let max_mb = self.ring_buffer_disk_mb.unwrap_or(self.prebuffer_ram_mb);
+                ui.label(format!("{} {:.1}/{} MB",
+                    if self.ring_buffer_disk_mb.is_some() { "🗄 On disk:" } else { "🧠 In memory:" },
+                    buffer_stats.bytes as f32 / 1_048_576.0, max_mb));
+            }
+
+            ui.separator();
+
+            // Covered lens, defocus and repositioning
+            ui.heading("🛡 Tamper Detection");
+            ui.separator();
+
+            ui.checkbox(&mut self.motion_config.tamper.enabled, "Enable Tamper Detection");
+            if self.motion_config.tamper.enabled {
+                let tamper = &mut self.motion_config.tamper;
+                ui.add(egui::Slider::new(&mut tamper.confirm_seconds, 1.0..=30.0).text("confirm s"))
+                    .on_hover_text("How long the camera must stay tampered with (or back to normal) before an alert starts (ends)");
+                ui.add(egui::Slider::new(&mut tamper.defocus_ratio, 0.05..=0.9).text("defocus"))
+                    .on_hover_text("Alert when sharpness drops below this fraction of the reference");
+                ui.add(egui::Slider::new(&mut tamper.shift_percent, 1.0..=20.0).text("shift %"))
+                    .on_hover_text("Alert when the whole view moves by this much");
+
+                match &self.analyzers.tamper {
+                    Some(event) => {
+                        ui.colored_label(egui::Color32::RED, format!("⚠ Tampering: {} since {}", event.kind, event.started_at.format("%H:%M:%S")));
+                    }
+                    None => {
+                        ui.label("✅ Camera view OK");
+                    }
+                }
+                if let Some(m) = &self.analyzers.tamper_measurements {
+                    ui.label(format!("Sharpness {:.0}/{:.0}, entropy {:.1} bits", m.sharpness, m.reference_sharpness, m.entropy))
+                        .on_hover_text(format!("Brightness {:.0}, shift {:.1}%/{:.1}%, differs from reference {:.0}%",
+                            m.brightness, m.shift.0, m.shift.1, m.changed_ratio));
+                }
+                if ui.button("↺ Relearn reference").on_hover_text("Use the current view as the reference (after moving the camera on purpose)").clicked() {
+                    self.send_analyzer_command(AnalyzerCommand::RelearnTamperReference);
+                }
+            }
+
+            ui.separator();
+
+            // Person/vehicle detection with an ONNX model, on frames with motion
+            ui.heading("🧍 Object Detection");
+            ui.separator();
+
+            ui.checkbox(&mut self.motion_config.objects.enabled, "Detect objects on motion frames");
+            if self.motion_config.objects.enabled {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.object_model_text).desired_width(160.0))
+                        .on_hover_text("YOLOv5/YOLOv8 ONNX model (input size and labels are set in the motion config)");
+                    if ui.button("📂 Load").clicked() {
+                        self.load_object_model();
+                    }
+                });
+                ui.label(&self.object_status);
+                ui.add(egui::Slider::new(&mut self.motion_config.objects.min_confidence, 0.1..=0.95).text("confidence"));
+                ui.horizontal(|ui| {
+                    ui.label("Record only if:");
+                    if ui.add(egui::TextEdit::singleline(&mut self.object_triggers_text).desired_width(120.0).hint_text("person, car"))
+                        .on_hover_text("Motion recording starts once one of these objects is seen (empty: any motion)")
+                        .changed()
+                    {
+                        self.motion_config.objects.trigger_classes = self.object_triggers_text
+                            .split(',')
+                            .map(|class| class.trim().to_string())
+                            .filter(|class| !class.is_empty())
+                            .collect();
+                    }
+                });
+                if !self.object_detections.is_empty() {
+                    let objects: Vec<String> = self.object_detections.iter().map(ObjectDetection::to_string).collect();
+                    ui.label(format!("👀 {}", objects.join(", ")));
+                }
+            }
+
+            ui.separator();
+
+            // Line crossings and zone entries of the tracked motion blobs (e.g. at an entrance)
+            ui.heading("🚶 Counting");
+            ui.separator();
+            self.counting_ui(ui);
+
+            ui.separator();
+
+            // Motion, tamper and any other frame analyzers
+            ui.heading("🧩 Analyzers");
+            ui.separator();
+            self.analyzers_ui(ui);
+
+            ui.separator();
+
+            // Weekly schedule: one rule per line, e.g. "mon-fri 22:00-06:00 motion"
+            ui.heading("🗓 Schedule");
+            ui.separator();
+
+            let mut schedule_changed = ui.checkbox(&mut self.schedule_enabled, "Follow weekly schedule").changed();
+            if self.schedule_enabled {
+                schedule_changed |= ui.add(egui::TextEdit::multiline(&mut self.schedule_text)
+                    .desired_rows(4)
+                    .hint_text("[days] [HH:MM-HH:MM] off|motion|continuous"))
+                    .changed();
+            }
+            if schedule_changed {
+                self.reload_schedule();
+            }
+            if let Some(scheduler) = &self.scheduler {
+                ui.label(format!("Now: {}", scheduler.describe(&Local::now())));
+            }
+            if !self.schedule_error.is_empty() {
+                ui.label(&self.schedule_error);
+            }
+
+            ui.separator();
+
+            // Date/time and camera name overlay, configured per output
+            ui.heading("🕒 Overlay");
+            ui.separator();
+
+            ui.label("Camera name:");
+            ui.text_edit_singleline(&mut self.overlay.camera_name);
+
+            overlay_config_ui(ui, "Live view", &mut self.overlay.live);
+            overlay_config_ui(ui, "Recording (burned in)", &mut self.overlay.recording);
+            if self.overlay.recording.enabled {
+                ui.label("⚠ Re-encodes every recorded frame (JPEG quality 90) on the UI thread, \
+                          which costs CPU and makes frames larger.");
+            }
+            overlay_config_ui(ui, "Snapshot", &mut self.overlay.snapshot);
+
+            ui.separator();
+
+            // Snapshots of the latest frame (📸 button or F12)
+            ui.heading("📸 Snapshots");
+            ui.separator();
+
+            ui.label("Saves the original JPEG of the latest frame.");
+            ui.checkbox(&mut self.snapshot_png, "Also save PNG (with snapshot overlay)");
+            if !self.snapshot_status.is_empty() {
+                ui.label(&self.snapshot_status);
+            }
+
+            ui.separator();
+
+            // Timelapse: one frame every N seconds, saved with the recording overlay
+            ui.heading("⏱ Timelapse");
+            ui.separator();
+
+            if let Some(timelapse) = &self.timelapse {
+                ui.label(format!("🔴 {} frames → {}", timelapse.frame_count(),
+                                 timelapse.path().file_name().unwrap_or_default().to_string_lossy()));
+                if ui.button("⏹ Stop Timelapse").clicked() {
+                    self.stop_timelapse();
+                }
+            } else {
+                ui.horizontal(|ui| {
+                    ui.label("Every:");
+                    ui.add(egui::DragValue::new(&mut self.timelapse_config.interval_secs).clamp_range(1..=86_400).suffix(" s"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Playback:");
+                    ui.add(egui::DragValue::new(&mut self.timelapse_config.playback_fps).clamp_range(1..=60).suffix(" fps"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    ui.radio_value(&mut self.timelapse_config.format, RecordingFormat::Mp4, "MP4");
+                    ui.radio_value(&mut self.timelapse_config.format, RecordingFormat::Mjpeg, "MJPEG");
+                });
+                ui.checkbox(&mut self.timelapse_config.motion_only, "Only frames with motion");
+                ui.checkbox(&mut self.timelapse_window_enabled, "Daily window (HH:MM-HH:MM)");
+                if self.timelapse_window_enabled {
+                    ui.text_edit_singleline(&mut self.timelapse_window);
+                }
+                if ui.button("⏺ Start Timelapse").clicked() {
+                    if let Err(e) = self.start_timelapse() {
+                        error!("Failed to start timelapse: {}", e);
+                        self.timelapse_status = format!("❌ {}", e);
+                    }
+                }
+            }
+            if !self.timelapse_status.is_empty() {
+                ui.label(&self.timelapse_status);
+            }
+
+            ui.separator();
+
+            // Drop frames nearly identical to the last saved one (manual and motion recordings)
+            ui.heading("🧊 Static Scenes");
+            ui.separator();
+
+            ui.checkbox(&mut self.dedup_enabled, "Skip unchanged frames (next recording)");
+            ui.horizontal(|ui| {
+                ui.label("Max change:");
+                ui.add(egui::DragValue::new(&mut self.dedup_config.max_change_percent).clamp_range(0.0..=10.0).speed(0.05).suffix(" %"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Keep-alive:");
+                ui.add(egui::DragValue::new(&mut self.dedup_config.keepalive_secs).clamp_range(1..=600).suffix(" s"));
+            });
+            if let Some(dedup) = &mut self.dedup {
+                dedup.set_config(self.dedup_config);
+                let stats = dedup.stats();
+                ui.label(format!("Kept {} / skipped {} frames", stats.kept, stats.dropped));
+            }
+
+            ui.separator();
+
+            // MP4 encoder settings (applied to the next recording)
+            ui.heading("🎞 MP4 Encoder");
+            ui.separator();
+
+            ui.label(&self.ffmpeg_status);
+
+            ui.label("Codec:");
+            ui.text_edit_singleline(&mut self.mp4_config.codec);
+
+            ui.label("Preset:");
+            ui.text_edit_singleline(&mut self.mp4_config.preset);
+
+            let mut use_crf = self.mp4_config.crf.is_some();
+            ui.checkbox(&mut use_crf, "CRF");
+            if use_crf {
+                let mut crf = self.mp4_config.crf.unwrap_or(23);
+                ui.add(egui::Slider::new(&mut crf, 0..=51));
+                self.mp4_config.crf = Some(crf);
+            } else {
+                self.mp4_config.crf = None;
+            }
+
+            ui.label("Extra ffmpeg args:");
+            if ui.text_edit_singleline(&mut self.mp4_extra_args).changed() {
+                self.mp4_config.extra_args = Mp4EncoderConfig::parse_extra_args(&self.mp4_extra_args);
+            }
+
+            if ui.button("🔄 Re-check ffmpeg").clicked() {
+                self.ffmpeg_status = check_ffmpeg(&self.mp4_config.codec).1;
+            }
+
+            ui.separator();
+
+            // Encryption at rest (applied to the next recording)
+            ui.heading("🔒 Encryption");
+            ui.separator();
+
+            ui.checkbox(&mut self.encryption_enabled, "Encrypt recordings");
+            if self.encryption_enabled {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.encryption_use_key_file, false, "Passphrase");
+                    ui.radio_value(&mut self.encryption_use_key_file, true, "Key file");
+                });
+                if self.encryption_use_key_file {
+                    ui.text_edit_singleline(&mut self.encryption_key_file);
+                } else {
+                    ui.add(egui::TextEdit::singleline(&mut self.encryption_passphrase).password(true));
+                }
+                ui.label("Without the key, recordings cannot be played back.");
+            }
+
+            ui.separator();
+
+            // Recent recordings; right-click one to export a clip
+            ui.heading("📼 Recordings");
+            ui.separator();
+
+            if ui.button("🔄 Refresh").clicked() {
+                self.refresh_recordings();
+            }
+            let mut open_dialog = None;
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                for entry in self.recordings.iter().rev().take(RECENT_RECORDINGS_SHOWN) {
+                    let label = format!("{} {} ({})", entry.started_at.format("%m-%d %H:%M:%S"), entry.trigger, entry.format);
+                    let response = ui.add(egui::Label::new(label).sense(egui::Sense::click()))
+                        .on_hover_text(entry.path.display().to_string());
+                    response.context_menu(|ui| {
+                        if ui.button("✂ Export clip...").clicked() {
+                            open_dialog = Some(ClipDialog::new(entry));
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+            if open_dialog.is_some() {
+                self.clip_dialog = open_dialog;
+            }
+            let manifest_status = self.manifest_status.lock().unwrap().clone();
+            if !manifest_status.is_empty() {
+                ui.label(manifest_status);
+            }
+            let clip_status = self.clip_status.lock().unwrap().clone();
+            if !clip_status.is_empty() {
+                ui.label(clip_status);
+            }
+
+            // Convert old MJPEG recordings (each copy is verified before it replaces the original)
+            ui.horizontal(|ui| {
+                ui.label("Archive older than:");
+                ui.add(egui::DragValue::new(&mut self.archive_config.older_than_days).clamp_range(0..=3650).suffix(" days"));
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.archive_config.format, RecordingFormat::Mjpeg, "MJPEG");
+                ui.radio_value(&mut self.archive_config.format, RecordingFormat::Mp4, "MP4");
+                if self.archive_config.format == RecordingFormat::Mjpeg {
+                    ui.add(egui::DragValue::new(&mut self.archive_config.jpeg_quality).clamp_range(10..=95).prefix("quality "));
+                }
+            });
+            if self.archive_running.load(Ordering::Relaxed) {
+                if ui.button("⏹ Cancel archiving").clicked() {
+                    self.archive_cancel.store(true, Ordering::Relaxed);
+                }
+            } else if ui.button("🗄 Archive old recordings").clicked() {
+                if let Err(e) = self.start_archive() {
+                    *self.archive_status.lock().unwrap() = format!("❌ {}", e);
+                }
+            }
+            let archive_status = self.archive_status.lock().unwrap().clone();
+            if !archive_status.is_empty() {
+                ui.label(archive_status);
             }
 
             ui.separator();
@@ -759,7 +2298,43 @@ impl eframe::App for CameraApp {
                     let scale = (available_size.x / img_size.x).min(available_size.y / img_size.y);
                     let display_size = img_size * scale * 0.95; // 95% to leave some margin
 
-                    ui.add(egui::Image::new(texture).fit_to_exact_size(display_size));
+                    let response = ui.add(egui::Image::new(texture)
+                        .fit_to_exact_size(display_size)
+                        .sense(egui::Sense::click()));
+
+                    // Clicks add points to the zone/mask/line being drawn (a line takes two)
+                    let drawing_open = self.zone_drawing.is_some_and(|drawing| drawing != ZoneDrawing::Line || self.zone_draft.len() < 2);
+                    if drawing_open && response.clicked() {
+                        if let Some(pos) = response.interact_pointer_pos() {
+                            let point = (pos - response.rect.min) / response.rect.size();
+                            self.zone_draft.push([point.x.clamp(0.0, 1.0), point.y.clamp(0.0, 1.0)]);
+                        }
+                    }
+                    if let Some(event) = &self.analyzers.tamper {
+                        let painter = ui.painter_at(response.rect);
+                        painter.rect_stroke(response.rect.shrink(2.0), 0.0, egui::Stroke::new(4.0, egui::Color32::RED));
+                        painter.text(response.rect.center_top() + egui::vec2(0.0, 12.0), egui::Align2::CENTER_TOP,
+                            format!("⚠ CAMERA TAMPERING: {}", event.kind.to_string().to_uppercase()),
+                            egui::FontId::proportional(20.0), egui::Color32::RED);
+                    }
+                    if self.motion_config.enabled {
+                        paint_motion_blobs(&ui.painter_at(response.rect), response.rect, &self.motion_result);
+                    }
+                    if self.motion_config.objects.enabled {
+                        paint_objects(&ui.painter_at(response.rect), response.rect, &self.object_detections);
+                    }
+                    if self.motion_config.counting.enabled {
+                        paint_counting(&ui.painter_at(response.rect), response.rect, &self.motion_config.counting, &self.analyzers);
+                    }
+                    if self.motion_config.enabled || self.zone_drawing.is_some() {
+                        paint_zones(
+                            &ui.painter_at(response.rect),
+                            response.rect,
+                            &self.motion_config,
+                            &self.analyzers.zone_results,
+                            &self.zone_draft,
+                        );
+                    }
                 } else {
                     ui.vertical_centered(|ui| {
                         ui.add_space(100.0);
@@ -771,13 +2346,260 @@ impl eframe::App for CameraApp {
                 }
             });
         });
+
+        // Clip export dialog
+        let mut export_requested = false;
+        let mut dialog_open = self.clip_dialog.is_some();
+        if let Some(dialog) = &mut self.clip_dialog {
+            egui::Window::new("✂ Export clip").open(&mut dialog_open).show(ctx, |ui| {
+                ui.label(dialog.recording.display().to_string());
+                ui.label("Start / end (+90s, 02:14:30 or date/time):");
+                ui.text_edit_singleline(&mut dialog.start);
+                ui.text_edit_singleline(&mut dialog.end);
+
+                egui::ComboBox::from_label("Format")
+                    .selected_text(dialog.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in ClipFormat::ALL {
+                            ui.selectable_value(&mut dialog.format, format, format.to_string());
+                        }
+                    });
+
+                export_requested = ui.button("💾 Export").clicked();
+            });
+        }
+        if export_requested {
+            if let Some(dialog) = self.clip_dialog.take() {
+                match self.start_clip_export(&dialog) {
+                    Ok(()) => dialog_open = false,
+                    Err(e) => {
+                        *self.clip_status.lock().unwrap() = format!("❌ {}", e);
+                        self.clip_dialog = Some(dialog);
+                    }
+                }
+            }
+        }
+        if !dialog_open {
+            self.clip_dialog = None;
+        }
+    }
+}
+
+/// Draw motion zones (red while motion is detected in them), exclusion masks and the polygon being drawn
+fn paint_zones(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    config: &MotionDetectionConfig,
+    results: &[motion_detector::ZoneMotion],
+    draft: &[[f32; 2]],
+) {
+    let to_screen = |points: &[[f32; 2]]| -> Vec<egui::Pos2> {
+        points.iter().map(|p| rect.min + egui::vec2(p[0] * rect.width(), p[1] * rect.height())).collect()
+    };
+    let label = |points: &[egui::Pos2], name: &str, color: egui::Color32| {
+        if let Some(&first) = points.first() {
+            painter.text(first, egui::Align2::LEFT_BOTTOM, name, egui::FontId::proportional(14.0), color);
+        }
+    };
+
+    for mask in &config.exclusions {
+        let points = to_screen(&mask.points);
+        painter.add(egui::Shape::closed_line(points.clone(), egui::Stroke::new(2.0, egui::Color32::GRAY)));
+        label(&points, &mask.name, egui::Color32::GRAY);
+    }
+    for (i, zone) in config.zones.iter().enumerate() {
+        let active = results.get(i).is_some_and(|result| result.motion_detected);
+        let color = if active { egui::Color32::RED } else { egui::Color32::GREEN };
+        let points = to_screen(&zone.points);
+        painter.add(egui::Shape::closed_line(points.clone(), egui::Stroke::new(2.0, color)));
+        label(&points, &zone.name, color);
+    }
+
+    let points = to_screen(draft);
+    for &point in &points {
+        painter.circle_filled(point, 3.0, egui::Color32::YELLOW);
+    }
+    painter.add(egui::Shape::line(points, egui::Stroke::new(2.0, egui::Color32::YELLOW)));
+}
+
+/// Draw the bounding boxes of the latest motion blobs
+fn paint_motion_blobs(painter: &egui::Painter, rect: egui::Rect, result: &MotionResult) {
+    let (width, height) = result.mask.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let scale = egui::vec2(rect.width() / width as f32, rect.height() / height as f32);
+    for blob in &result.blobs {
+        let min = rect.min + egui::vec2(blob.bbox.x as f32 * scale.x, blob.bbox.y as f32 * scale.y);
+        let size = egui::vec2(blob.bbox.width as f32 * scale.x, blob.bbox.height as f32 * scale.y);
+        painter.rect_stroke(egui::Rect::from_min_size(min, size), 0.0, egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 160, 0)));
+    }
+}
+
+/// Draw the objects found during the open motion event with their label and confidence
+fn paint_objects(painter: &egui::Painter, rect: egui::Rect, objects: &[ObjectDetection]) {
+    let color = egui::Color32::from_rgb(0, 200, 255);
+    for object in objects {
+        let [x, y, width, height] = object.bbox;
+        let min = rect.min + egui::vec2(x * rect.width(), y * rect.height());
+        let size = egui::vec2(width * rect.width(), height * rect.height());
+        painter.rect_stroke(egui::Rect::from_min_size(min, size), 0.0, egui::Stroke::new(2.0, color));
+        painter.text(min, egui::Align2::LEFT_BOTTOM, object.to_string(), egui::FontId::proportional(14.0), color);
+    }
+}
+
+/// Draw the counting lines (arrow on the forward side) and zones with their totals, and the tracked objects
+fn paint_counting(painter: &egui::Painter, rect: egui::Rect, config: &CountingConfig, analyzers: &AnalyzerState) {
+    let to_screen = |p: [f32; 2]| rect.min + egui::vec2(p[0] * rect.width(), p[1] * rect.height());
+    let color = egui::Color32::from_rgb(255, 0, 200);
+    let font = egui::FontId::proportional(14.0);
+    let totals = &analyzers.count_totals;
+
+    for line in &config.lines {
+        let [a, b] = line.points.map(to_screen);
+        painter.line_segment([a, b], egui::Stroke::new(3.0, color));
+        let middle = a + (b - a) * 0.5;
+        let normal = egui::vec2(-(b - a).y, (b - a).x).normalized();
+        painter.arrow(middle, normal * 20.0, egui::Stroke::new(2.0, color));
+        let counts = totals.get(&line.name).copied().unwrap_or_default();
+        painter.text(a, egui::Align2::LEFT_BOTTOM, format!("{} ➡{} ⬅{}", line.name, counts.forward, counts.backward), font.clone(), color);
+    }
+    for zone in &config.zones {
+        let points: Vec<egui::Pos2> = zone.points.iter().copied().map(to_screen).collect();
+        painter.add(egui::Shape::closed_line(points.clone(), egui::Stroke::new(2.0, color)));
+        if let Some(&first) = points.first() {
+            let counts = totals.get(&zone.name).copied().unwrap_or_default();
+            painter.text(first, egui::Align2::LEFT_BOTTOM, format!("{} 🚪{}", zone.name, counts.entries), font.clone(), color);
+        }
+    }
+    for track in &analyzers.tracks {
+        let center = to_screen(track.centroid);
+        painter.circle_filled(center, 4.0, color);
+        painter.text(center + egui::vec2(6.0, 0.0), egui::Align2::LEFT_CENTER, format!("#{}", track.id), egui::FontId::proportional(12.0), color);
+    }
+}
+
+/// Settings for one overlay output
+fn overlay_config_ui(ui: &mut egui::Ui, label: &str, config: &mut OverlayConfig) {
+    ui.collapsing(label, |ui| {
+        ui.checkbox(&mut config.enabled, "Enabled");
+        ui.checkbox(&mut config.show_time, "Date/time");
+        ui.checkbox(&mut config.show_camera_name, "Camera name");
+        ui.checkbox(&mut config.show_sequence, "Frame number");
+        ui.checkbox(&mut config.background, "Background box");
+
+        egui::ComboBox::from_id_source(label)
+            .selected_text(config.position.to_string())
+            .show_ui(ui, |ui| {
+                for position in OverlayPosition::ALL {
+                    ui.selectable_value(&mut config.position, position, position.to_string());
+                }
+            });
+
+        ui.add(egui::Slider::new(&mut config.scale, 1..=6).text("Font size"));
+    });
+}
+
+/// Probe ffmpeg for MP4 recording, returning (usable, status label)
+fn check_ffmpeg(codec: &str) -> (bool, String) {
+    match mp4_recorder::probe_ffmpeg(codec) {
+        Ok(probe) if probe.encoder_available => {
+            info!("{} ({} available)", probe.version, codec);
+            (true, format!("✅ {}", probe.version))
+        }
+        Ok(probe) => {
+            warn!("{}: encoder {} not available", probe.version, codec);
+            (false, format!("⚠ Encoder '{}' not available", codec))
+        }
+        Err(e) => {
+            warn!("ffmpeg not usable, MP4 recording disabled: {}", e);
+            (false, "❌ ffmpeg not found (MJPEG only)".to_string())
+        }
+    }
+}
+
+/// Run the frame analyzers on each live frame off the UI thread and send their results back
+///
+/// The thread owns the analyzers: settings changes arrive as commands and are applied between frames,
+/// and the state the UI shows is sent back after every frame and command.
+fn analysis_thread(
+    frames: Receiver<AnalysisFrame>,
+    commands: Receiver<AnalyzerCommand>,
+    mut analyzers: AnalyzerRegistry,
+    analyzer_metrics: Arc<Mutex<Vec<AnalyzerStats>>>,
+    tx: Sender<AppMessage>,
+) {
+    let mut config = MotionDetectionConfig::default();
+    loop {
+        let live = match frames.recv_timeout(ANALYZER_COMMAND_POLL) {
+            Ok(live) => Some(live),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let mut changed = false;
+        for command in commands.try_iter() {
+            apply_analyzer_command(&mut analyzers, &mut config, command);
+            changed = true;
+        }
+
+        if let Some(live) = &live {
+            let frame = AnalyzerFrame { jpeg: None, image: Some(&live.image), captured_at: live.captured_at, sequence: live.sequence };
+            let detections = analyzers.run(&frame);
+            *analyzer_metrics.lock().unwrap() = analyzers.stats().cloned().collect();
+            if tx.send(AppMessage::Detections { captured_at: live.captured_at, detections }).is_err() {
+                break;
+            }
+        }
+        if (changed || live.is_some()) && tx.send(AppMessage::AnalyzerState(Box::new(AnalyzerState::of(&analyzers)))).is_err() {
+            break;
+        }
+    }
+}
+
+/// Apply one UI command to the analyzers; `config` is the detection config last sent by the UI
+fn apply_analyzer_command(analyzers: &mut AnalyzerRegistry, config: &mut MotionDetectionConfig, command: AnalyzerCommand) {
+    match command {
+        AnalyzerCommand::Configure(new_config) => *config = *new_config,
+        // Configured below like the other analyzers
+        AnalyzerCommand::RegisterObjects(detector) => analyzers.register(*detector),
+        AnalyzerCommand::RelearnTamperReference => {
+            if let Some(detector) = analyzers.get_mut::<TamperDetector>() {
+                detector.reset(Local::now());
+            }
+            return;
+        }
+        AnalyzerCommand::ResetCounts => {
+            if let Some(counter) = analyzers.get_mut::<CrossingCounter>() {
+                counter.reset_totals();
+            }
+            return;
+        }
+    }
+
+    analyzers.configure(&config.analyzers);
+    if let Some(detector) = analyzers.get_mut::<TamperDetector>() {
+        detector.set_config(config.tamper);
+        if !config.tamper.enabled && detector.current().is_some() {
+            detector.reset(Local::now());
+        }
+    }
+    if let Some(detector) = analyzers.get_mut::<ObjectDetector>() {
+        detector.set_config(config.objects.clone());
+    }
+    if let Some(counter) = analyzers.get_mut::<CrossingCounter>().filter(|counter| counter.config() != &config.counting) {
+        counter.set_config(config.counting.clone());
+    }
+    if let Some(detector) = analyzers.get_mut::<MotionDetector>() {
+        detector.update_config(config.clone());
     }
 }
 
 fn capture_thread(
     tx: Sender<AppMessage>,
     is_running: Arc<Mutex<bool>>,
-    is_recording: Arc<AtomicBool>,
+    flags: CaptureFlags,
+    latest_jpeg: LatestJpeg,
+    analyzer_metrics: Arc<Mutex<Vec<AnalyzerStats>>>,
     port_path: String,
     auto_detect: bool,
 ) {
@@ -812,6 +2634,8 @@ fn capture_thread(
         }
     };
 
+    tx.send(AppMessage::DeviceConnected(serial.profile().clone())).ok();
+
     // Flush buffer
     if let Err(e) = serial.flush() {
         error!("Failed to flush: {}", e);
@@ -898,20 +2722,32 @@ fn capture_thread(
                 let jpeg_size_bytes = packet.jpeg_data.len();
                 total_jpeg_size_bytes += jpeg_size_bytes as u64;
 
+                // Keep the undecoded frame for snapshots
+                let received_at = Instant::now();
+                *latest_jpeg.lock().unwrap() = Some((packet.jpeg_data.clone(), Local::now()));
+
+                // Option A: Decode JPEG in capture thread (not GUI thread)
+                let decode_start = Instant::now();
+                let decoded = image::load_from_memory(&packet.jpeg_data);
+                let decode_time_ms = decode_start.elapsed().as_secs_f32() * 1000.0;
+
                 // Phase 3: Send JPEG data for recording ONLY when recording is active
                 // This prevents message queue congestion and Metrics packet delay
-                if is_recording.load(Ordering::Relaxed) {
-                    tx.send(AppMessage::JpegFrame(packet.jpeg_data.clone())).ok();
+                // The dedup thumbnail reuses the decode above so the GUI thread never decodes recorded frames,
+                // and is only made while the recording skips unchanged frames
+                if flags.is_recording.load(Ordering::Relaxed) {
+                    let thumbnail = match &decoded {
+                        Ok(img) if flags.is_deduplicating.load(Ordering::Relaxed) => Some(dedup::thumbnail(img)),
+                        _ => None,
+                    };
+                    tx.send(AppMessage::JpegFrame { jpeg_data: packet.jpeg_data.clone(), received_at, thumbnail }).ok();
                 }
 
-                // Option A: Decode JPEG in capture thread (not GUI thread)
-                let decode_start = Instant::now();
-                match image::load_from_memory(&packet.jpeg_data) {
+                match decoded {
                     Ok(img) => {
                         // Phase 4.1.1: Reset consecutive JPEG errors on success
                         consecutive_jpeg_errors = 0;
 
-                        let decode_time_ms = decode_start.elapsed().as_secs_f32() * 1000.0;
                         total_decode_time_ms += decode_time_ms;
 
                         // Convert to RGBA8
@@ -995,6 +2831,7 @@ fn capture_thread(
                             spresense_usb_packets,
                             action_q_depth: spresense_action_q_depth,
                             spresense_errors,
+                            analyzers: analyzer_metrics.lock().unwrap().clone(),
                         };
 
                         if let Err(e) = logger.log(&metrics) {
@@ -0,0 +1,25 @@
+//! Modules shared by the CLI (`security_camera_viewer`) and GUI (`security_camera_gui`) binaries
+
+pub mod archive;
+pub mod catalog;
+pub mod clip_export;
+pub mod crossing_counter;
+pub mod crypto;
+pub mod dedup;
+pub mod frame_analyzer;
+pub mod manifest;
+pub mod metadata;
+pub mod mjpeg;
+pub mod motion_detector;
+pub mod motion_event;
+pub mod mp4_recorder;
+pub mod object_detector;
+pub mod overlay;
+pub mod protocol;
+pub mod recording;
+pub mod recovery;
+pub mod schedule;
+pub mod serial;
+pub mod snapshot;
+pub mod tamper_detector;
+pub mod timelapse;
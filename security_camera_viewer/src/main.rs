@@ -1,18 +1,45 @@
-mod protocol;
-mod serial;
-
-use clap::Parser;
+use security_camera_viewer::{
+    archive, catalog, clip_export, crossing_counter, crypto, dedup, frame_analyzer, manifest,
+    metadata, mjpeg, motion_detector, motion_event, mp4_recorder, object_detector, overlay,
+    protocol, recording, recovery, schedule, serial, snapshot, tamper_detector, timelapse,
+};
+use clap::{Parser, Subcommand};
 use log::{debug, info, warn, error};
+use std::borrow::Cow;
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use anyhow::{Result, Context};
+use archive::ArchiveConfig;
 use serial::SerialConnection;
 use protocol::Packet;
+use catalog::{Catalog, CatalogEvent, EventQuery, EventType, RecordingTrigger};
+use clip_export::ClipFormat;
+use crypto::KeySource;
+use dedup::{DedupConfig, FrameDeduplicator};
+use frame_analyzer::{AnalyzerFrame, AnalyzerRegistry, Detection};
+use mjpeg::MjpegWriter;
+use manifest::ManifestSigner;
+use metadata::{FirmwareMetrics, FrameMetadata, RecordingSidecar};
+use motion_detector::{MotionAlgorithm, MotionDetectionConfig, MotionDetector, SceneChange};
+use motion_event::{EventNotification, MotionEventTracker};
+use tamper_detector::{TamperConfig, TamperDetector, TamperNotification};
+use object_detector::{ObjectDetectionConfig, ObjectDetector};
+use crossing_counter::{CrossingCounter, HourlyCountLog};
+use mp4_recorder::Mp4EncoderConfig;
+use overlay::{OverlayConfig, OverlayPosition};
+use recording::RecordingFormat;
+use schedule::{DailyWindow, Schedule, ScheduleMode, Scheduler};
+use timelapse::{TimelapseConfig, TimelapseRecorder};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Serial port path (e.g., /dev/ttyACM0)
     /// If not specified, auto-detection will be attempted
     #[arg(short, long)]
@@ -41,6 +68,265 @@ struct Args {
     /// Maximum number of consecutive errors before exit
     #[arg(long, default_value = "10")]
     max_errors: u32,
+
+    /// Burn a date/time and camera name overlay into saved frames
+    #[arg(long)]
+    overlay: bool,
+
+    /// Camera name shown in the overlay
+    #[arg(long, default_value = "Spresense")]
+    camera_name: String,
+
+    /// Overlay position: top-left, top-right, bottom-left or bottom-right
+    #[arg(long, default_value = "top-left")]
+    overlay_position: OverlayPosition,
+
+    /// Overlay font size (pixels per font dot; text height = 7 x scale)
+    #[arg(long, default_value = "2")]
+    overlay_scale: u32,
+
+    /// Also show the frame sequence number in the overlay
+    #[arg(long)]
+    overlay_sequence: bool,
+
+    /// Draw the overlay text without a background box
+    #[arg(long)]
+    overlay_no_background: bool,
+
+    /// Recording directory for snapshots (saved to <dir>/snapshots and listed in <dir>/catalog.jsonl).
+    /// Type `s` and Enter while receiving to take a snapshot of the latest frame
+    #[arg(long, default_value = "./recordings")]
+    snapshot_dir: PathBuf,
+
+    /// Also save snapshots as PNG (decoded, with the overlay when --overlay is set)
+    #[arg(long)]
+    snapshot_png: bool,
+
+    /// Timelapse mode: keep one frame every SECS seconds instead of every frame
+    #[arg(long, value_name = "SECS", conflicts_with = "individual_files")]
+    timelapse: Option<u32>,
+
+    /// Timelapse playback frame rate
+    #[arg(long, default_value = "30")]
+    timelapse_fps: u32,
+
+    /// Timelapse format: mjpeg or mp4 (mp4 needs ffmpeg)
+    #[arg(long, default_value = "mjpeg")]
+    timelapse_format: RecordingFormat,
+
+    /// Only keep timelapse frames with motion
+    #[arg(long)]
+    timelapse_motion_only: bool,
+
+    /// Only keep timelapse frames within this daily window (e.g. 07:00-19:00, or 22:00-06:00 overnight)
+    #[arg(long, value_name = "HH:MM-HH:MM")]
+    timelapse_window: Option<DailyWindow>,
+
+    /// Motion detection settings as JSON (sensitivity, min_motion_area, named polygon zones and
+    /// exclusion masks with 0.0-1.0 coordinates); used by --timelapse-motion-only and scheduled motion
+    #[arg(long, value_name = "FILE")]
+    motion_config: Option<PathBuf>,
+
+    /// Alert when the camera is covered, knocked out of focus or turned away.
+    /// Tampering is logged and listed in the snapshot directory's catalog.jsonl (tunable under "tamper" in --motion-config)
+    #[arg(long)]
+    tamper: bool,
+
+    /// Run an ONNX object detection model (YOLOv5/YOLOv8 layout) on frames with motion, on the CPU.
+    /// Needs a build with --features object-detection (tunable under "objects" in --motion-config)
+    #[arg(long, value_name = "FILE")]
+    object_model: Option<PathBuf>,
+
+    /// In scheduled motion mode, only save frames once one of these objects was seen during the motion event
+    /// (e.g. person or person,car; implies object detection)
+    #[arg(long, value_name = "CLASS", value_delimiter = ',')]
+    object_trigger: Vec<String>,
+
+    /// Weekly schedule file: save frames only when it says motion (frames with motion) or continuous
+    #[arg(long, value_name = "FILE")]
+    schedule: Option<PathBuf>,
+
+    /// Skip frames that are nearly identical to the last saved frame (static scene).
+    /// The MJPEG index keeps the capture time of every saved frame
+    #[arg(long, conflicts_with = "timelapse")]
+    dedup: bool,
+
+    /// With --dedup, largest change (% of pixels) still treated as the same frame
+    #[arg(long, value_name = "PERCENT", default_value = "0.5")]
+    dedup_threshold: f32,
+
+    /// With --dedup, still save at least one frame every SECS seconds
+    #[arg(long, value_name = "SECS", default_value = "5")]
+    dedup_keepalive: u32,
+
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// Encryption at rest: a key file or a passphrase taken from an environment variable
+#[derive(clap::Args, Debug, Clone, Default)]
+struct KeyArgs {
+    /// Key file for encrypting/decrypting recordings (create one with `gen-key`)
+    #[arg(long, conflicts_with = "passphrase_env")]
+    key_file: Option<PathBuf>,
+
+    /// Environment variable holding the encryption passphrase
+    #[arg(long, value_name = "VAR")]
+    passphrase_env: Option<String>,
+}
+
+impl KeyArgs {
+    fn load(&self) -> Result<Option<KeySource>> {
+        if let Some(path) = &self.key_file {
+            let key = KeySource::from_key_file(path)
+                .context(format!("Failed to read key file {:?}", path))?;
+            return Ok(Some(key));
+        }
+        if let Some(var) = &self.passphrase_env {
+            let passphrase = std::env::var(var)
+                .context(format!("Environment variable {} is not set", var))?;
+            if passphrase.is_empty() {
+                anyhow::bail!("Environment variable {} is empty", var);
+            }
+            return Ok(Some(KeySource::Passphrase(passphrase)));
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Search the recording catalog for recordings and motion events
+    Events {
+        /// Only events ending after this time (e.g. 2h, 7d, today, "2026-01-02 02:14")
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only events starting before this time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Event type to list: recording, motion, snapshot or tamper (default: all)
+        #[arg(long = "type")]
+        event_type: Option<EventType>,
+
+        /// Recording directory containing catalog.jsonl
+        #[arg(long, default_value = "./recordings")]
+        dir: PathBuf,
+    },
+
+    /// Finalize recordings left open by a crash or power loss (the GUI also runs this at startup)
+    Recover {
+        /// Recording directory containing catalog.jsonl
+        #[arg(long, default_value = "./recordings")]
+        dir: PathBuf,
+    },
+
+    /// Check a recording against its signed manifest (<recording>.manifest.json)
+    Verify {
+        /// Recording file (.mjpeg or .mp4)
+        recording: PathBuf,
+
+        /// Trusted public key file (default: keys/manifest_signing.pub next to the recording)
+        #[arg(long)]
+        public_key: Option<PathBuf>,
+    },
+
+    /// Create a random key file for encrypting recordings
+    GenKey {
+        /// Key file to create (never overwritten)
+        path: PathBuf,
+    },
+
+    /// Show the format, encryption state and frame count of a recording
+    Inspect {
+        /// Recording file (.mjpeg or .mp4, plain or encrypted)
+        recording: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+
+    /// Split an MJPEG recording into individual JPEG files
+    Split {
+        /// Recording file (.mjpeg, plain or encrypted)
+        recording: PathBuf,
+
+        /// Output directory for the JPEG files
+        #[arg(long, default_value = "frames")]
+        output_dir: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+
+    /// Export part of a recording as MJPEG, MP4, AVI, a JPEG sequence, GIF or WebP
+    Export {
+        /// Recording file (.mjpeg or .mp4, plain or encrypted)
+        recording: PathBuf,
+
+        /// Clip start: offset into the recording (+90s), time of day (02:14:30) or date/time
+        #[arg(long)]
+        start: String,
+
+        /// Clip end (same formats as --start)
+        #[arg(long)]
+        end: String,
+
+        /// Output file, or a directory for a JPEG sequence
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Output format: mjpeg, mp4, avi, jpeg, gif or webp (default: from the output extension)
+        #[arg(long)]
+        format: Option<ClipFormat>,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+
+    /// Convert finished MJPEG recordings older than N days to a more compact format.
+    /// Each copy is verified before it replaces the original, and the catalog is updated
+    Archive {
+        /// Recording directory containing catalog.jsonl
+        #[arg(long, default_value = "./recordings")]
+        dir: PathBuf,
+
+        /// Only recordings that ended more than DAYS days ago
+        #[arg(long, value_name = "DAYS", default_value = "30")]
+        older_than: u32,
+
+        /// Archive format: mjpeg (frames re-encoded at --quality) or mp4 (needs ffmpeg)
+        #[arg(long, default_value = "mjpeg")]
+        format: RecordingFormat,
+
+        /// JPEG quality of re-encoded MJPEG frames (1-100)
+        #[arg(long, default_value = "60")]
+        quality: u8,
+
+        /// Convert at most this many frames per second, leaving CPU for live capture (0 = no limit)
+        #[arg(long, default_value = "30")]
+        max_fps: u32,
+
+        /// Only list the recordings that would be converted
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+
+    /// Write a decrypted copy of an encrypted recording (for ffmpeg and other tools)
+    Decrypt {
+        /// Encrypted recording file
+        input: PathBuf,
+
+        /// Plaintext output file (must not exist)
+        output: PathBuf,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
 }
 
 fn main() -> Result<()> {
@@ -57,6 +343,10 @@ fn main() -> Result<()> {
             .init();
     }
 
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
+
     info!("Security Camera Viewer (MJPEG) v{}", env!("CARGO_PKG_VERSION"));
     info!("==========================================");
 
@@ -79,9 +369,150 @@ fn main() -> Result<()> {
 
     info!("Connected successfully");
 
+    // A passphrase is stretched once here, not for every file written
+    let key = args.key.load()?.map(|key| key.derive()).transpose()
+        .context("Failed to derive the encryption key")?;
+    if key.is_some() {
+        info!("Encryption at rest: enabled");
+    }
+
     // Prepare output
     let output_path = PathBuf::from(&args.output);
-    let mut stream_file = if args.individual_files {
+    let mut timelapse = match args.timelapse {
+        Some(interval_secs) => {
+            let config = TimelapseConfig {
+                interval_secs,
+                playback_fps: args.timelapse_fps,
+                format: args.timelapse_format,
+                motion_only: args.timelapse_motion_only,
+                window: args.timelapse_window,
+            };
+            let path = output_path.with_extension(config.format.extension());
+            let recorder = TimelapseRecorder::create(&path, config, &Mp4EncoderConfig::default(), key.clone())
+                .context(format!("Failed to create timelapse file: {:?}", path))?;
+            info!("Output file: {:?}", path);
+            info!("Mode: Timelapse (1 frame every {}s, played at {} fps{}{})",
+                  interval_secs,
+                  args.timelapse_fps,
+                  if args.timelapse_motion_only { ", motion only" } else { "" },
+                  args.timelapse_window.map(|w| format!(", {}", w)).unwrap_or_default());
+            Some(recorder)
+        }
+        None => None,
+    };
+    let mut scheduler = match &args.schedule {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .context(format!("Failed to read schedule {:?}", path))?;
+            let schedule: Schedule = text.parse().map_err(anyhow::Error::msg)
+                .context(format!("Invalid schedule {:?}", path))?;
+            info!("Schedule ({:?}):", path);
+            for line in schedule.to_string().lines() {
+                info!("  {}", line);
+            }
+            Some(Scheduler::new(schedule))
+        }
+        None => None,
+    };
+
+    // Motion is checked on every frame so that frame differences stay meaningful
+    let motion_config = match &args.motion_config {
+        Some(path) => {
+            let json = fs::read_to_string(path)
+                .context(format!("Failed to read motion config {:?}", path))?;
+            serde_json::from_str::<MotionDetectionConfig>(&json)
+                .context(format!("Invalid motion config {:?}", path))?
+        }
+        None => MotionDetectionConfig::default(),
+    };
+    let motion_config = MotionDetectionConfig { enabled: true, ..motion_config };
+    if motion_config.algorithm == MotionAlgorithm::Background {
+        info!("Motion detection against a background model (learning rate {})", motion_config.background_learning_rate);
+    }
+    for zone in &motion_config.zones {
+        info!("Motion zone '{}': sensitivity {}, min area {}%", zone.name, zone.sensitivity, zone.min_motion_area);
+    }
+    for mask in &motion_config.exclusions {
+        info!("Motion exclusion mask '{}'", mask.name);
+    }
+    let object_detection = args.object_model.is_some() || !args.object_trigger.is_empty() || motion_config.objects.enabled;
+    let counting = motion_config.counting.enabled;
+    let needs_motion = args.timelapse_motion_only
+        || scheduler.as_ref().is_some_and(|s| s.schedule().uses(ScheduleMode::Motion))
+        || object_detection
+        || counting;
+    // Scheduled motion mode saves frames while a motion event is open (N of M frames to start, quiet period to end)
+    let mut motion_events = MotionEventTracker::new(motion_config.events);
+    let motion_event_log = motion_events.subscribe();
+    // In scheduled motion mode, frames keep being saved for a while after the motion event ends (11 fps)
+    let post_record_frames = motion_config.post_record_seconds * 11;
+    let mut post_record_left = 0u32;
+    // Set once a trigger object was seen during the open motion event
+    let mut object_triggered = false;
+
+    // Frame analyzers run on every frame in the order given by "analyzers" in --motion-config
+    let mut analyzers = AnalyzerRegistry::new();
+    if needs_motion {
+        analyzers.register(MotionDetector::new(motion_config.clone()));
+    }
+    // Tamper detection compares every frame against a slowly learned reference of the scene
+    if args.tamper || motion_config.tamper.enabled {
+        info!("Tamper detection: covered lens, defocus and repositioning (confirmed after {}s)", motion_config.tamper.confirm_seconds);
+        analyzers.register(TamperDetector::new(TamperConfig { enabled: true, ..motion_config.tamper }));
+    }
+    // Object detection runs after motion detection, on frames with motion only
+    if object_detection {
+        let mut config = ObjectDetectionConfig { enabled: true, ..motion_config.objects.clone() };
+        if let Some(path) = &args.object_model {
+            config.model_path = path.clone();
+        }
+        if !args.object_trigger.is_empty() {
+            config.trigger_classes = args.object_trigger.clone();
+        }
+        let detector = ObjectDetector::load(config)
+            .context("Failed to load the object detection model")?;
+        info!("Object detection: {:?} (confidence >= {:.0}%, at most every {} ms)", detector.config().model_path,
+              detector.config().min_confidence * 100.0, detector.config().min_interval_ms);
+        if !detector.config().trigger_classes.is_empty() {
+            info!("Scheduled motion recording waits for: {}", detector.config().trigger_classes.join(", "));
+        }
+        analyzers.register(detector);
+    }
+    // Line crossings and zone entries are counted on the motion blobs tracked across frames
+    let mut count_log = None;
+    if counting {
+        let config = &motion_config.counting;
+        for line in &config.lines {
+            info!("Counting line '{}': {:?} -> {:?} (forward = crossing to its right)", line.name, line.points[0], line.points[1]);
+        }
+        for zone in &config.zones {
+            info!("Counting zone '{}'", zone.name);
+        }
+        let dir = std::env::current_dir()?.join("metrics");
+        let log = HourlyCountLog::create(&dir)
+            .context(format!("Failed to create the hourly count log in {:?}", dir))?;
+        info!("Hourly counts: {:?}", log.path());
+        count_log = Some(log);
+        analyzers.register(CrossingCounter::new(config.clone()));
+    }
+    analyzers.configure(&motion_config.analyzers);
+    for stats in analyzers.stats().filter(|stats| !stats.enabled) {
+        info!("Analyzer '{}' disabled in the configuration", stats.name);
+    }
+    let tamper_log = analyzers.get_mut::<TamperDetector>().map(TamperDetector::subscribe);
+    let waits_for_objects = analyzers.stats().any(|stats| stats.name == "objects" && stats.enabled)
+        && analyzers.get::<ObjectDetector>().is_some_and(|detector| !detector.config().trigger_classes.is_empty());
+
+    let mut dedup = args.dedup.then(|| {
+        let config = DedupConfig { max_change_percent: args.dedup_threshold, keepalive_secs: args.dedup_keepalive };
+        info!("Dedup: skipping frames with <= {}% change (keep-alive every {}s)",
+              config.max_change_percent, config.keepalive_secs);
+        FrameDeduplicator::new(config)
+    });
+
+    let mut stream_file = if timelapse.is_some() {
+        None
+    } else if args.individual_files {
         // Create output directory for individual JPEG files
         fs::create_dir_all(&output_path)
             .context(format!("Failed to create output directory: {}", args.output))?;
@@ -91,11 +522,39 @@ fn main() -> Result<()> {
     } else {
         // Create single MJPEG stream file
         let stream_path = output_path.with_extension("mjpeg");
-        let file = File::create(&stream_path)
+        let writer = MjpegWriter::create_with_key(&stream_path, key.as_ref())
             .context(format!("Failed to create output file: {:?}", stream_path))?;
         info!("Output file: {:?}", stream_path);
         info!("Mode: MJPEG stream");
-        Some(file)
+        Some(writer)
+    };
+
+    // Sidecar with the device, firmware metrics and settings of the recording
+    let recording_path = match (&timelapse, &stream_file) {
+        (Some(timelapse), _) => Some((timelapse.path().to_path_buf(), timelapse.config().format.extension())),
+        (None, Some(_)) => Some((output_path.with_extension("mjpeg"), "mjpeg")),
+        (None, None) => None,
+    };
+    let trigger = if timelapse.is_some() {
+        RecordingTrigger::Timelapse
+    } else if scheduler.is_some() {
+        RecordingTrigger::Scheduled
+    } else {
+        RecordingTrigger::Manual
+    };
+    let mut sidecar = recording_path.as_ref().map(|(path, format)| {
+        RecordingSidecar::start(path, format, trigger, &args.camera_name, Some(serial.profile().clone()),
+                                needs_motion.then(|| motion_config.clone()))
+    });
+    // The recording is listed in the catalog.jsonl next to it, like the GUI's recordings
+    let recording_catalog = match &recording_path {
+        Some((path, format)) => {
+            let catalog = Catalog::new(path.parent().unwrap_or(Path::new(".")));
+            catalog.recording_started(path, trigger, format)
+                .context(format!("Failed to update catalog {:?}", catalog.path()))?;
+            Some(catalog)
+        }
+        None => None,
     };
 
     // Flush any existing data in the buffer
@@ -109,11 +568,29 @@ fn main() -> Result<()> {
     info!("==========================================");
 
     let mut frame_count = 0u64;
+    let mut saved_frames = 0u64;
     let mut packet_count = 0u64;
     let mut error_count = 0u32;
     let mut total_bytes = 0u64;
     let mut jpeg_errors = 0u32;
 
+    let overlay_config = OverlayConfig {
+        enabled: args.overlay,
+        show_sequence: args.overlay_sequence,
+        position: args.overlay_position,
+        scale: args.overlay_scale,
+        background: !args.overlay_no_background,
+        ..OverlayConfig::default()
+    };
+    if overlay_config.enabled {
+        info!("Overlay: {} ({})", overlay_config.position, args.camera_name);
+    }
+
+    let snapshot_requested = spawn_snapshot_listener();
+    let snapshot_catalog = Catalog::new(&args.snapshot_dir);
+    info!("Type `s` and Enter to save a snapshot to {:?}",
+          args.snapshot_dir.join(snapshot::SNAPSHOT_DIR_NAME));
+
     loop {
         // Check max frames limit
         if args.max_frames > 0 && frame_count >= args.max_frames {
@@ -181,13 +658,159 @@ fn main() -> Result<()> {
                 }
                 total_bytes += jpeg_size as u64;
 
+                if snapshot_requested.swap(false, Ordering::Relaxed) {
+                    let png = snapshot::PngOptions {
+                        overlay: &overlay_config,
+                        camera_name: &args.camera_name,
+                        sequence: frame_count,
+                    };
+                    match take_snapshot(&snapshot_catalog, &packet.jpeg_data,
+                                        args.snapshot_png.then_some(&png), key.as_ref()) {
+                        Ok(path) => info!("Snapshot saved: {:?}", path),
+                        Err(e) => error!("Snapshot failed: {:#}", e),
+                    }
+                }
+
+                let now = chrono::Local::now();
+                let frame = AnalyzerFrame { jpeg: Some(&packet.jpeg_data), image: None, captured_at: now, sequence: frame_count };
+                let mut motion = false;
+                for detection in analyzers.run(&frame) {
+                    match detection {
+                        Detection::Motion(result) => {
+                            motion_events.update(&result, now);
+                            if result.change == SceneChange::Illumination {
+                                debug!("Frame #{}: lighting change ignored (brightness x{:.2})",
+                                       frame_count, result.brightness_gain.unwrap_or(1.0));
+                            }
+                            if let Some(detector) = analyzers.get::<MotionDetector>().filter(|_| result.motion_detected) {
+                                for zone in detector.zone_results().iter().filter(|zone| zone.motion_detected) {
+                                    debug!("Frame #{}: motion in zone '{}' ({:.1}%)", frame_count, zone.name, zone.motion_ratio);
+                                }
+                                for blob in &result.blobs {
+                                    debug!("Frame #{}: motion at {}x{}+{}+{} ({} px at 1/{} scale)",
+                                           frame_count, blob.bbox.width, blob.bbox.height, blob.bbox.x, blob.bbox.y, blob.area,
+                                           motion_detector::effective_downscale(detector.config().downscale));
+                                }
+                            }
+                            motion = result.motion_detected;
+                        }
+                        // Logged and catalogued through the subscription below
+                        Detection::Tamper(_) => {}
+                        // Inference finishes on a later frame; the result keeps the frame it ran on
+                        Detection::Objects(found) => {
+                            for object in &found.objects {
+                                debug!("Frame #{}: {} at {:.2?}", found.sequence, object, object.bbox);
+                            }
+                            let in_event = motion_events.current().is_some_and(|event| event.started_at <= found.captured_at);
+                            let trigger = analyzers.get::<ObjectDetector>().and_then(|detector| detector.config().trigger(&found.objects));
+                            if let Some(object) = trigger.filter(|_| in_event && !object_triggered) {
+                                info!("Motion event confirmed by {}", object);
+                                object_triggered = true;
+                            }
+                        }
+                        Detection::Counts(events) => {
+                            for event in &events {
+                                info!("Frame #{}: {}", frame_count, event);
+                            }
+                            if let Some(log) = &mut count_log {
+                                if let Err(e) = log.record(now, &events) {
+                                    error!("Failed to write {:?}: {}", log.path(), e);
+                                }
+                            }
+                        }
+                        custom @ Detection::Custom { .. } => info!("Frame #{}: {}", frame_count, custom),
+                    }
+                }
+                for notification in motion_event_log.try_iter() {
+                    match notification {
+                        EventNotification::EventStarted(event) => info!("Motion event #{} started", event.id),
+                        EventNotification::EventUpdated(_) => {}
+                        EventNotification::EventEnded(event) => {
+                            info!("Motion event #{} ended ({:.1}s, {} motion frames, peak {:.1}%)",
+                                  event.id, event.duration().num_milliseconds() as f64 / 1000.0, event.frame_count, event.peak_motion_ratio);
+                            object_triggered = false;
+                        }
+                    }
+                }
+
+                for notification in tamper_log.iter().flat_map(|log| log.try_iter()) {
+                    let event = notification.event();
+                    match &notification {
+                        TamperNotification::Started(_) => warn!("Camera tampering: {} since {} (sharpness {:.0}/{:.0}, entropy {:.1}, shift {:.0}%/{:.0}%)",
+                            event.kind, event.started_at.format("%H:%M:%S"), event.measurements.sharpness, event.measurements.reference_sharpness,
+                            event.measurements.entropy, event.measurements.shift.0, event.measurements.shift.1),
+                        TamperNotification::Ended(_) => info!("Camera tampering ended: {}", event.kind),
+                    }
+                    if let Err(e) = snapshot_catalog.tamper_event(event) {
+                        error!("Failed to update catalog {:?}: {}", snapshot_catalog.path(), e);
+                    }
+                }
+
+                // The schedule decides whether frames are saved at all
+                let scheduled = match &mut scheduler {
+                    Some(scheduler) => {
+                        if scheduler.update(&now).is_some() {
+                            info!("Schedule: {}", scheduler.describe(&now));
+                            post_record_left = 0;
+                        }
+                        match scheduler.current().unwrap_or_default() {
+                            ScheduleMode::Off => false,
+                            ScheduleMode::Continuous => true,
+                            ScheduleMode::Motion if motion_events.current().is_some() && (object_triggered || !waits_for_objects) => {
+                                post_record_left = post_record_frames;
+                                true
+                            }
+                            ScheduleMode::Motion => {
+                                post_record_left = post_record_left.saturating_sub(1);
+                                post_record_left > 0
+                            }
+                        }
+                    }
+                    None => true,
+                };
+
+                // Timelapse mode keeps only the frames that are due, dedup only frames that changed
+                let keep = scheduled && match &mut timelapse {
+                    Some(timelapse) => timelapse.sample(now, motion),
+                    None => dedup.as_mut().is_none_or(|dedup| dedup.should_keep(&packet.jpeg_data, now)),
+                };
+
+                // Burn the overlay into the saved frame (falls back to the original frame)
+                let jpeg_data: Cow<[u8]> = if overlay_config.enabled && keep {
+                    let text = overlay_config.text(&args.camera_name, now, frame_count);
+                    match overlay::burn_into_jpeg(&packet.jpeg_data, &overlay_config, &text) {
+                        Ok(burned) => Cow::Owned(burned),
+                        Err(e) => {
+                            warn!("Frame #{}: overlay failed: {}", frame_count, e);
+                            Cow::Borrowed(&packet.jpeg_data)
+                        }
+                    }
+                } else {
+                    Cow::Borrowed(&packet.jpeg_data)
+                };
+
                 // Save JPEG data
-                if args.individual_files {
-                    // Save as individual file
-                    let filename = output_path.join(format!("frame_{:06}.jpg", frame_count));
+                saved_frames += keep as u64;
+                if !keep {
+                    debug!("Frame #{} not saved (schedule, timelapse or duplicate)", frame_count);
+                } else if let Some(timelapse) = &mut timelapse {
+                    timelapse.write_frame(&jpeg_data, now)
+                        .context("Failed to write timelapse frame")?;
+                    debug!("Timelapse frame #{} (received frame #{})", timelapse.frame_count(), frame_count);
+                } else if args.individual_files {
+                    // Save as individual file, tagged with the capture time, camera name and frame number
+                    let exif = FrameMetadata { captured_at: now, camera_name: &args.camera_name, sequence: frame_count };
+                    let jpeg_data = metadata::inject_exif(&jpeg_data, &exif)
+                        .map(Cow::Owned)
+                        .unwrap_or_else(|e| {
+                            warn!("Frame #{}: EXIF not added: {}", frame_count, e);
+                            jpeg_data
+                        });
+                    let extension = if key.is_some() { "jpg.enc" } else { "jpg" };
+                    let filename = output_path.join(format!("frame_{:06}.{}", frame_count, extension));
                     match File::create(&filename) {
-                        Ok(mut file) => {
-                            file.write_all(&packet.jpeg_data)
+                        Ok(file) => {
+                            crypto::write_with_key(file, &jpeg_data, key.as_ref())
                                 .context(format!("Failed to write JPEG file: {:?}", filename))?;
                             debug!("Saved: {:?}", filename);
                         }
@@ -198,8 +821,8 @@ fn main() -> Result<()> {
                     }
                 } else {
                     // Append to stream file
-                    if let Some(ref mut file) = stream_file {
-                        file.write_all(&packet.jpeg_data)
+                    if let Some(ref mut writer) = stream_file {
+                        writer.write_frame_at(&jpeg_data, now)
                             .context("Failed to write to MJPEG stream")?;
                     }
                 }
@@ -218,6 +841,9 @@ fn main() -> Result<()> {
                 // Phase 4.1: Metrics packets - just log and continue (CLI viewer doesn't display them)
                 error_count = 0; // Reset error count on success
                 packet_count += 1;
+                if let Some(sidecar) = &mut sidecar {
+                    sidecar.record_metrics(FirmwareMetrics::new(&metrics, chrono::Local::now()));
+                }
                 debug!("Metrics packet: seq={}, cam_frames={}, usb_pkts={}, q_depth={}, errors={}",
                        metrics.sequence,
                        metrics.camera_frames,
@@ -244,6 +870,51 @@ fn main() -> Result<()> {
         }
     }
 
+    let mut segments = Vec::new();
+    let timelapse_summary = match timelapse {
+        Some(timelapse) => {
+            let path = timelapse.path().to_path_buf();
+            let format = timelapse.config().format;
+            let frames = timelapse.frame_count();
+            segments = timelapse.finish().context("Failed to finalize timelapse")?;
+
+            let signer = ManifestSigner::load_or_generate(&manifest::default_key_dir(&path))
+                .context("Failed to load manifest signing key")?;
+            for file in std::iter::once(&path).chain(&segments) {
+                signer.sign_recording(file, format.extension())
+                    .context(format!("Failed to write manifest for {:?}", file))?;
+            }
+            Some((path, frames))
+        }
+        None => None,
+    };
+
+    if let Some(writer) = stream_file {
+        writer.finish().context("Failed to finalize MJPEG stream")?;
+
+        let stream_path = output_path.with_extension("mjpeg");
+        ManifestSigner::load_or_generate(&manifest::default_key_dir(&stream_path))
+            .and_then(|signer| signer.sign_recording(&stream_path, "mjpeg"))
+            .context(format!("Failed to write manifest for {:?}", stream_path))?;
+    }
+
+    if let Some((path, _)) = &recording_path {
+        let size_bytes = std::iter::once(path).chain(&segments)
+            .filter_map(|file| fs::metadata(file).ok())
+            .map(|m| m.len())
+            .sum();
+        if let Some(catalog) = &recording_catalog {
+            catalog.recording_finished(&catalog::recording_id(path), saved_frames, size_bytes, &segments)
+                .context(format!("Failed to update catalog {:?}", catalog.path()))?;
+            info!("Catalog: {:?}", catalog.path());
+        }
+        if let Some(sidecar) = sidecar {
+            let sidecar_path = sidecar.finish(path, saved_frames, size_bytes, &segments)
+                .context(format!("Failed to write metadata sidecar for {:?}", path))?;
+            info!("Metadata: {:?}", sidecar_path);
+        }
+    }
+
     // Final statistics
     info!("==========================================");
     info!("Reception Summary:");
@@ -251,15 +922,38 @@ fn main() -> Result<()> {
     info!("  Total packets: {}", packet_count);
     info!("  Total data: {:.2} MB", total_bytes as f64 / 1_048_576.0);
     info!("  JPEG errors: {}", jpeg_errors);
+    if let Some(dedup) = &dedup {
+        let stats = dedup.stats();
+        info!("  Duplicate frames skipped: {} (kept {})", stats.dropped, stats.kept);
+    }
+    if let Some(counter) = analyzers.get::<CrossingCounter>() {
+        for (name, counts) in counter.totals() {
+            info!("  Counted '{}': {} forward, {} backward, {} entries", name, counts.forward, counts.backward, counts.entries);
+        }
+    }
+    for stats in analyzers.stats().filter(|stats| stats.frames > 0) {
+        info!("  Analyzer '{}': {:.2} ms/frame (max {:.2} ms), {} detections in {} frames",
+              stats.name, stats.avg_time_ms, stats.max_time_ms, stats.detections, stats.frames);
+    }
     if frame_count > 0 {
         info!("  Average frame size: {:.2} KB",
               (total_bytes as f64 / frame_count as f64) / 1024.0);
     }
     info!("==========================================");
 
-    if args.individual_files {
+    if let Some((path, frames)) = timelapse_summary {
+        info!("Timelapse saved to: {:?} ({} frames, {:.1}s at {} fps)",
+              path, frames, frames as f64 / args.timelapse_fps.max(1) as f64, args.timelapse_fps);
+        if args.timelapse_format == RecordingFormat::Mjpeg {
+            info!("Play with: ffplay -framerate {} {:?}", args.timelapse_fps, path);
+        }
+    } else if args.individual_files {
         info!("JPEG files saved to: {}", args.output);
         info!("View with: feh {} or eog {}", args.output, args.output);
+    } else if key.is_some() {
+        let stream_path = PathBuf::from(&args.output).with_extension("mjpeg");
+        info!("Encrypted MJPEG stream saved to: {:?}", stream_path);
+        info!("Extract frames with: security_camera_viewer split {:?} --key-file <KEY>", stream_path);
     } else {
         let stream_path = PathBuf::from(&args.output).with_extension("mjpeg");
         info!("MJPEG stream saved to: {:?}", stream_path);
@@ -275,3 +969,335 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Events { since, until, event_type, dir } => {
+            let now = chrono::Local::now();
+            let query = EventQuery {
+                since: since.map(|s| catalog::parse_time_arg(&s, now)).transpose()
+                    .map_err(anyhow::Error::msg).context("Invalid --since")?,
+                until: until.map(|s| catalog::parse_time_arg(&s, now)).transpose()
+                    .map_err(anyhow::Error::msg).context("Invalid --until")?,
+                event_type,
+            };
+
+            let catalog = Catalog::new(&dir);
+            let events = catalog.query(&query)
+                .context(format!("Failed to read catalog: {:?}", catalog.path()))?;
+
+            for event in &events {
+                print_event(event);
+            }
+            info!("{} event(s) in {:?}", events.len(), catalog.path());
+            Ok(())
+        }
+
+        Command::Recover { dir } => {
+            let catalog = Catalog::new(&dir);
+            let recovered = recovery::recover_orphaned_recordings(&catalog)
+                .context(format!("Failed to recover recordings in {:?}", dir))?;
+
+            for recording in &recovered {
+                println!("{}  {} frames  {:.2} MB  ({} torn bytes removed)",
+                         recording.path.display(),
+                         recording.frame_count,
+                         recording.size_bytes as f64 / 1_048_576.0,
+                         recording.removed_bytes);
+            }
+            info!("{} recording(s) recovered", recovered.len());
+
+            if !recovered.is_empty() {
+                let signer = ManifestSigner::load_or_generate(&dir.join(manifest::KEY_DIR_NAME))
+                    .context("Failed to load manifest signing key")?;
+                for recording in recovered.iter().filter(|r| r.path.exists()) {
+                    signer.sign_recording(&recording.path, &recording.format)
+                        .context(format!("Failed to sign {:?}", recording.path))?;
+                }
+            }
+            Ok(())
+        }
+        Command::Verify { recording, public_key } => {
+            let trusted_key = match public_key {
+                Some(path) => Some(fs::read_to_string(&path)
+                    .context(format!("Failed to read public key {:?}", path))?),
+                None => manifest::load_public_key(&manifest::default_key_dir(&recording)),
+            };
+
+            let report = manifest::verify_recording(&recording, trusted_key.as_deref())
+                .context(format!("Failed to verify {:?}", recording))?;
+
+            println!("{}: {} {}s in manifest, {} in file",
+                     recording.display(),
+                     report.manifest.entries.len(),
+                     report.manifest.unit,
+                     report.units_checked);
+            for warning in &report.warnings {
+                println!("  warning: {}", warning);
+            }
+            for problem in &report.problems {
+                println!("  TAMPERED: {}", problem);
+            }
+
+            if !report.is_ok() {
+                anyhow::bail!("{:?} failed verification ({} problem(s))", recording, report.problems.len());
+            }
+            println!("OK: recording matches its signed manifest");
+            Ok(())
+        }
+
+        Command::GenKey { path } => {
+            KeySource::generate_key_file(&path)
+                .context(format!("Failed to create key file {:?}", path))?;
+            println!("Created key file {}", path.display());
+            println!("Keep a backup: recordings encrypted with this key cannot be read without it.");
+            Ok(())
+        }
+
+        Command::Inspect { recording, key } => inspect_recording(&recording, key.load()?.as_ref()),
+
+        Command::Split { recording, output_dir, key } => {
+            let mut reader = crypto::open_recording(&recording, key.load()?.as_ref())
+                .context(format!("Failed to open {:?}", recording))?;
+            fs::create_dir_all(&output_dir)
+                .context(format!("Failed to create output directory {:?}", output_dir))?;
+
+            let mut count = 0u64;
+            let scan = mjpeg::for_each_frame(&mut reader, |_, frame| {
+                count += 1;
+                fs::write(output_dir.join(format!("frame_{:06}.jpg", count)), frame)
+            }).context(format!("Failed to split {:?}", recording))?;
+
+            println!("{} frame(s) written to {}", count, output_dir.display());
+            if scan.total_len > scan.valid_len {
+                println!("  ignored {} bytes of torn data at the end", scan.total_len - scan.valid_len);
+            }
+            if reader.is_truncated() {
+                println!("  warning: encrypted recording was not closed cleanly (final chunk missing)");
+            }
+            Ok(())
+        }
+
+        Command::Export { recording, start, end, output, format, key } => {
+            let format = match format {
+                Some(format) => format,
+                None => ClipFormat::from_output_path(&output)
+                    .context(format!("Cannot tell the clip format from {:?}; use --format", output))?,
+            };
+            let recording_start = clip_export::recording_start(&recording);
+            let now = chrono::Local::now();
+            let start = clip_export::parse_clip_time(&start, recording_start, now)
+                .map_err(anyhow::Error::msg).context("Invalid --start")?;
+            let end = clip_export::parse_clip_time(&end, recording_start, now)
+                .map_err(anyhow::Error::msg).context("Invalid --end")?;
+
+            let summary = clip_export::export_clip(&recording, start, end, format, &output, key.load()?.as_ref())
+                .context(format!("Failed to export clip from {:?}", recording))?;
+
+            match summary.frames {
+                Some(frames) => println!("Exported {} frame(s) ({} - {}) to {}",
+                                         frames, start.format("%H:%M:%S%.3f"), end.format("%H:%M:%S%.3f"), summary.output.display()),
+                None => println!("Exported {} - {} to {}",
+                                 start.format("%H:%M:%S%.3f"), end.format("%H:%M:%S%.3f"), summary.output.display()),
+            }
+            if summary.frames == Some(0) {
+                warn!("No frames fall between {} and {}", start, end);
+            }
+            Ok(())
+        }
+
+        Command::Archive { dir, older_than, format, quality, max_fps, dry_run, key } => {
+            let catalog = Catalog::new(&dir);
+            if dry_run {
+                let candidates = archive::archive_candidates(&catalog, older_than, chrono::Local::now())
+                    .context(format!("Failed to read catalog: {:?}", catalog.path()))?;
+                for entry in &candidates {
+                    println!("{}  {}  {:.2} MB",
+                             entry.started_at.format("%Y-%m-%d %H:%M:%S"),
+                             entry.path.display(),
+                             entry.size_bytes as f64 / 1_048_576.0);
+                }
+                info!("{} recording(s) older than {} days would be archived", candidates.len(), older_than);
+                return Ok(());
+            }
+
+            let config = ArchiveConfig {
+                older_than_days: older_than,
+                format,
+                jpeg_quality: quality,
+                max_fps,
+                ..ArchiveConfig::default()
+            };
+            let key = key.load()?.map(|key| key.derive()).transpose()
+                .context("Failed to derive the encryption key")?;
+            let archived = archive::archive_old_recordings(&catalog, &config, key.as_ref(), &AtomicBool::new(false))
+                .context(format!("Failed to archive recordings in {:?}", dir))?;
+
+            let signer = ManifestSigner::load_or_generate(&dir.join(manifest::KEY_DIR_NAME))
+                .context("Failed to load manifest signing key")?;
+            for recording in &archived {
+                println!("{}  {} frames  {:.2} MB -> {:.2} MB{}",
+                         recording.path.display(),
+                         recording.frame_count,
+                         recording.original_bytes as f64 / 1_048_576.0,
+                         recording.size_bytes as f64 / 1_048_576.0,
+                         if recording.replaced { "" } else { "  (not smaller, original kept)" });
+                if recording.replaced {
+                    signer.sign_recording(&recording.path, recording.format.extension())
+                        .context(format!("Failed to sign {:?}", recording.path))?;
+                }
+            }
+            let saved: u64 = archived.iter().map(|r| r.original_bytes - r.size_bytes).sum();
+            info!("{} recording(s) archived, {:.2} MB saved", archived.len(), saved as f64 / 1_048_576.0);
+            Ok(())
+        }
+
+        Command::Decrypt { input, output, key } => {
+            let key = key.load()?
+                .context("--key-file or --passphrase-env is required to decrypt")?;
+            let mut reader = crypto::open_recording(&input, Some(&key))
+                .context(format!("Failed to open {:?}", input))?;
+            if !reader.is_encrypted() {
+                anyhow::bail!("{:?} is not encrypted", input);
+            }
+
+            let mut file = File::options().write(true).create_new(true).open(&output)
+                .context(format!("Failed to create {:?}", output))?;
+            let bytes = io::copy(&mut reader, &mut file)
+                .context(format!("Failed to decrypt {:?}", input))?;
+            file.sync_all()?;
+
+            println!("Decrypted {} bytes to {}", bytes, output.display());
+            if reader.is_truncated() {
+                println!("  warning: recording was not closed cleanly (final chunk missing)");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn inspect_recording(path: &Path, key: Option<&KeySource>) -> Result<()> {
+    let file_size = fs::metadata(path)
+        .context(format!("Failed to read {:?}", path))?
+        .len();
+    let encrypted = crypto::is_encrypted(path)?;
+    let is_mp4 = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
+
+    println!("{}", path.display());
+    println!("  format:     {}{}", if is_mp4 { "mp4" } else { "mjpeg" }, if encrypted { " (encrypted)" } else { "" });
+    println!("  file size:  {:.2} MB", file_size as f64 / 1_048_576.0);
+
+    if encrypted && key.is_none() {
+        println!("  contents:   unavailable without --key-file or --passphrase-env");
+        return Ok(());
+    }
+
+    let mut reader = crypto::open_recording(path, key)
+        .context(format!("Failed to open {:?}", path))?;
+    if is_mp4 {
+        let (valid_len, samples, total_len) = recovery::scan_fragmented_mp4(&mut reader)
+            .context(format!("Failed to read {:?}", path))?;
+        println!("  frames:     {}", samples);
+        println!("  torn tail:  {} bytes", total_len - valid_len);
+    } else {
+        let scan = mjpeg::scan_frames(&mut reader)
+            .context(format!("Failed to read {:?}", path))?;
+        println!("  frames:     {}", scan.frames.len());
+        if let (Some(min), Some(max)) = (scan.frames.iter().map(|f| f.len).min(), scan.frames.iter().map(|f| f.len).max()) {
+            println!("  frame size: {:.1} - {:.1} KB (avg {:.1} KB)",
+                     min as f64 / 1024.0,
+                     max as f64 / 1024.0,
+                     scan.valid_len as f64 / scan.frames.len() as f64 / 1024.0);
+        }
+        println!("  torn tail:  {} bytes", scan.total_len - scan.valid_len);
+    }
+    if reader.is_truncated() {
+        println!("  warning:    not closed cleanly (final encrypted chunk missing)");
+    }
+    Ok(())
+}
+
+/// Watch stdin for snapshot commands (`s` or `snapshot`)
+fn spawn_snapshot_listener() -> Arc<AtomicBool> {
+    let requested = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&requested);
+    std::thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else { break };
+            match line.trim() {
+                "s" | "snapshot" => flag.store(true, Ordering::Relaxed),
+                "" => {}
+                other => warn!("Unknown command {:?} (type `s` to take a snapshot)", other),
+            }
+        }
+    });
+    requested
+}
+
+/// Save the original JPEG of the current frame and record it in the catalog
+fn take_snapshot(
+    catalog: &Catalog,
+    jpeg_data: &[u8],
+    png: Option<&snapshot::PngOptions>,
+    key: Option<&KeySource>,
+) -> Result<PathBuf> {
+    let dir = catalog.path().with_file_name(snapshot::SNAPSHOT_DIR_NAME);
+    let snapshot = snapshot::save_snapshot(&dir, jpeg_data, chrono::Local::now(), png, key)
+        .context(format!("Failed to save snapshot to {:?}", dir))?;
+    catalog.snapshot_taken(&snapshot.jpeg_path, snapshot.png_path.as_deref(), snapshot.captured_at)
+        .context(format!("Failed to update catalog {:?}", catalog.path()))?;
+    Ok(snapshot.jpeg_path)
+}
+
+fn print_event(event: &CatalogEvent) {
+    match event {
+        CatalogEvent::Recording(entry) => {
+            let duration = entry.duration()
+                .map(|d| format!("{:.1}s", d.num_milliseconds() as f64 / 1000.0))
+                .unwrap_or_else(|| "unfinished".to_string());
+            println!("{}  recording  {:<10} {:>6} frames  {:>8.2} MB  {:<5} {:<6}  {}",
+                     entry.started_at.format("%Y-%m-%d %H:%M:%S"),
+                     duration,
+                     entry.frame_count,
+                     entry.size_bytes as f64 / 1_048_576.0,
+                     entry.format,
+                     entry.trigger,
+                     entry.path.display());
+            if entry.recovered {
+                println!("{:21}(recovered after unclean shutdown)", "");
+            }
+            for segment in &entry.segments {
+                println!("{:21}+ segment {}", "", segment.display());
+            }
+        }
+        CatalogEvent::Motion { event, path } => {
+            println!("{}  motion     {:<10} {:>6} frames  peak {:>5.1}%              {}",
+                     event.started_at.format("%Y-%m-%d %H:%M:%S"),
+                     format!("{:.1}s", event.duration().num_milliseconds() as f64 / 1000.0),
+                     event.frame_count,
+                     event.peak_motion_ratio,
+                     path.display());
+            if let Some(extent) = &event.extent {
+                println!("{:21}+ {} box(es), area x {:.0}-{:.0}% y {:.0}-{:.0}%", "",
+                         event.boxes.len(),
+                         extent.x * 100.0, (extent.x + extent.width) * 100.0,
+                         extent.y * 100.0, (extent.y + extent.height) * 100.0);
+            }
+        }
+        CatalogEvent::Tamper(entry) => {
+            let duration = entry.ended_at
+                .map(|end| format!("{:.1}s", (end - entry.started_at).num_milliseconds() as f64 / 1000.0))
+                .unwrap_or_else(|| "ongoing".to_string());
+            println!("{}  tamper     {:<10} {}", entry.started_at.format("%Y-%m-%d %H:%M:%S"), duration, entry.kind);
+        }
+        CatalogEvent::Snapshot(entry) => {
+            println!("{}  snapshot   {:53}{}",
+                     entry.captured_at.format("%Y-%m-%d %H:%M:%S"),
+                     "",
+                     entry.path.display());
+            if let Some(png) = &entry.png {
+                println!("{:21}+ png {}", "", png.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,647 @@
+//! 録画マニフェスト（改ざん検知）モジュール
+//!
+//! 録画終了時に、フレーム単位（MJPEG）またはチャンク単位（MP4・暗号化ファイル）のSHA-256ハッシュを
+//! ハッシュチェーンでつないだマニフェスト（`<録画ファイル>.manifest.json`）を書き出し、
+//! ローカルで生成したEd25519鍵で署名する。
+//!
+//! チェーンの初期値は直前に署名した録画の最終ハッシュで、録画同士もつながる。
+//! 検証時はファイルからハッシュを再計算し、変更・削除・追加・並べ替えられた
+//! フレームを検出する。
+
+use crate::crypto;
+use crate::mjpeg;
+use chrono::{DateTime, Local};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+/// マニフェスト形式のバージョン
+pub const MANIFEST_VERSION: u32 = 1;
+/// 署名鍵を置くディレクトリ名（録画ディレクトリ直下）
+pub const KEY_DIR_NAME: &str = "keys";
+/// MP4のハッシュ単位
+pub const MP4_CHUNK_SIZE: u64 = 1024 * 1024;
+
+const SIGNING_KEY_FILE: &str = "manifest_signing.key";
+const PUBLIC_KEY_FILE: &str = "manifest_signing.pub";
+const CHAIN_HEAD_FILE: &str = "manifest_chain_head.json";
+
+/// ハッシュの単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashUnit {
+    /// JPEGフレーム（MJPEG）
+    Frame,
+    /// 固定長チャンク（MP4）
+    Chunk,
+}
+
+impl fmt::Display for HashUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashUnit::Frame => "frame",
+            HashUnit::Chunk => "chunk",
+        })
+    }
+}
+
+/// ハッシュ対象の1単位
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub offset: u64,
+    pub len: u64,
+    /// データのSHA-256（16進）
+    pub sha256: String,
+}
+
+/// 直前の録画へのリンク
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainLink {
+    /// 直前の録画のファイル名
+    pub recording: String,
+    /// 直前の録画の最終チェーンハッシュ（16進）
+    pub final_hash: String,
+}
+
+/// 録画マニフェスト
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// 録画ファイル名
+    pub recording: String,
+    /// ファイル形式（"mjpeg", "mp4"）
+    pub format: String,
+    pub unit: HashUnit,
+    pub created_at: DateTime<Local>,
+    pub file_size: u64,
+    pub previous: Option<ChainLink>,
+    pub entries: Vec<ManifestEntry>,
+    /// 最終チェーンハッシュ（16進）
+    pub final_hash: String,
+    /// 署名鍵の公開鍵（16進）
+    pub public_key: String,
+    /// Ed25519署名（16進）
+    pub signature: String,
+}
+
+impl Manifest {
+    /// 署名対象のバイト列
+    ///
+    /// エントリのハッシュは`final_hash`を通じて順序込みで署名に含まれる。
+    fn signing_payload(&self) -> Vec<u8> {
+        let previous = self.previous.as_ref()
+            .map(|p| format!("{} {}", p.recording, p.final_hash))
+            .unwrap_or_else(|| "-".to_string());
+        format!(
+            "security-camera-manifest/v{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n",
+            self.version,
+            self.recording,
+            self.format,
+            self.unit,
+            self.created_at.to_rfc3339(),
+            self.file_size,
+            previous,
+            self.entries.len(),
+            self.final_hash,
+        ).into_bytes()
+    }
+
+    /// チェーンの初期値
+    fn chain_seed(&self) -> [u8; 32] {
+        self.previous.as_ref()
+            .and_then(|p| decode_hash(&p.final_hash))
+            .unwrap_or([0; 32])
+    }
+}
+
+/// マニフェストのパス（`<録画ファイル>.manifest.json`）
+pub fn manifest_path(recording: &Path) -> PathBuf {
+    let mut name = recording.file_name().unwrap_or_default().to_os_string();
+    name.push(".manifest.json");
+    recording.with_file_name(name)
+}
+
+/// 録画ファイルの署名鍵ディレクトリ（録画ディレクトリ直下の`keys/`）
+pub fn default_key_dir(recording: &Path) -> PathBuf {
+    recording.parent().unwrap_or(Path::new(".")).join(KEY_DIR_NAME)
+}
+
+/// 鍵ディレクトリの公開鍵（16進）を読み込む
+pub fn load_public_key(key_dir: &Path) -> Option<String> {
+    fs::read_to_string(key_dir.join(PUBLIC_KEY_FILE)).ok().map(|key| key.trim().to_string())
+}
+
+/// 直前に署名した録画（チェーンの先頭）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChainHead {
+    recording: String,
+    final_hash: String,
+}
+
+/// マニフェスト署名器
+pub struct ManifestSigner {
+    key: SigningKey,
+    key_dir: PathBuf,
+}
+
+impl ManifestSigner {
+    /// 署名鍵を読み込む（なければ生成して保存する）
+    pub fn load_or_generate(key_dir: &Path) -> io::Result<Self> {
+        let key_path = key_dir.join(SIGNING_KEY_FILE);
+
+        let key = match fs::read_to_string(&key_path) {
+            Ok(text) => {
+                let bytes = decode_hash(text.trim()).ok_or_else(|| io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid signing key file: {:?}", key_path),
+                ))?;
+                SigningKey::from_bytes(&bytes)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut secret = [0u8; 32];
+                getrandom::getrandom(&mut secret)?;
+                let key = SigningKey::from_bytes(&secret);
+
+                fs::create_dir_all(key_dir)?;
+                write_private(&key_path, &hex::encode(secret))?;
+                fs::write(key_dir.join(PUBLIC_KEY_FILE), hex::encode(key.verifying_key().as_bytes()) + "\n")?;
+                info!("Generated manifest signing key in {:?}", key_dir);
+                key
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self { key, key_dir: key_dir.to_path_buf() })
+    }
+
+    /// 公開鍵（16進）
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    /// 録画ファイルのマニフェストを作成・署名して書き出す
+    ///
+    /// 直前に署名した録画の最終ハッシュにチェーンし、チェーンの先頭を更新する。
+    pub fn sign_recording(&self, recording: &Path, format: &str) -> io::Result<Manifest> {
+        let (unit, entries) = hash_recording(recording, format)?;
+        let previous = self.chain_head().map(|head| ChainLink {
+            recording: head.recording,
+            final_hash: head.final_hash,
+        });
+
+        let mut manifest = Manifest {
+            version: MANIFEST_VERSION,
+            recording: file_name(recording),
+            format: format.to_string(),
+            unit,
+            created_at: Local::now(),
+            file_size: fs::metadata(recording)?.len(),
+            previous,
+            entries,
+            final_hash: String::new(),
+            public_key: self.public_key_hex(),
+            signature: String::new(),
+        };
+        manifest.final_hash = hex::encode(chain_hash(manifest.chain_seed(), &manifest.entries));
+        manifest.signature = hex::encode(self.key.sign(&manifest.signing_payload()).to_bytes());
+
+        let json = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+        fs::write(manifest_path(recording), json + "\n")?;
+
+        let head = ChainHead { recording: manifest.recording.clone(), final_hash: manifest.final_hash.clone() };
+        fs::write(self.key_dir.join(CHAIN_HEAD_FILE), serde_json::to_string(&head).map_err(io::Error::other)?)?;
+
+        info!("Signed manifest for {:?}: {} {}s", recording, manifest.entries.len(), manifest.unit);
+        Ok(manifest)
+    }
+
+    fn chain_head(&self) -> Option<ChainHead> {
+        let text = fs::read_to_string(self.key_dir.join(CHAIN_HEAD_FILE)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+}
+
+/// 秘密鍵ファイルを所有者のみ読み書きできる権限で書き出す
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// 録画ファイルを単位ごとにハッシュする
+fn hash_recording(path: &Path, format: &str) -> io::Result<(HashUnit, Vec<ManifestEntry>)> {
+    let mut file = File::open(path)?;
+
+    let (unit, spans) = match format {
+        // 暗号化ファイルは鍵なしで検証できるよう、暗号文をチャンク単位でハッシュする
+        _ if crypto::is_encrypted(path)? => (HashUnit::Chunk, fixed_chunks(file.metadata()?.len())),
+        "mjpeg" => {
+            let scan = mjpeg::scan_frames(&mut file)?;
+            (HashUnit::Frame, scan.frames.iter().map(|f| (f.offset, f.len)).collect::<Vec<_>>())
+        }
+        "mp4" => (HashUnit::Chunk, fixed_chunks(file.metadata()?.len())),
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown recording format: {}", other),
+            ));
+        }
+    };
+
+    let mut entries = Vec::with_capacity(spans.len());
+    let mut buf = Vec::new();
+    for (offset, len) in spans {
+        buf.resize(len as usize, 0);
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+        entries.push(ManifestEntry { offset, len, sha256: hex::encode(Sha256::digest(&buf)) });
+    }
+
+    Ok((unit, entries))
+}
+
+/// ファイル全体を`MP4_CHUNK_SIZE`ごとに区切る
+fn fixed_chunks(size: u64) -> Vec<(u64, u64)> {
+    (0..size).step_by(MP4_CHUNK_SIZE as usize)
+        .map(|offset| (offset, MP4_CHUNK_SIZE.min(size - offset)))
+        .collect()
+}
+
+/// chain_i = SHA-256(chain_{i-1} || hash_i)
+fn chain_hash(seed: [u8; 32], entries: &[ManifestEntry]) -> [u8; 32] {
+    entries.iter().fold(seed, |chain, entry| {
+        let mut hasher = Sha256::new();
+        hasher.update(chain);
+        hasher.update(decode_hash(&entry.sha256).unwrap_or_default());
+        hasher.finalize().into()
+    })
+}
+
+fn decode_hash(text: &str) -> Option<[u8; 32]> {
+    hex::decode(text).ok()?.try_into().ok()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// 録画の署名をバックグラウンドで順に行うキュー
+///
+/// ハッシュ計算は録画のサイズに比例して時間がかかるため、呼び出し側（GUIスレッド）を止めない。
+/// 1つのスレッドで追加した順に署名するので、ハッシュチェーンも追加した順につながる。
+/// ドロップ時は残りの署名が終わるまで待つ。
+pub struct SigningQueue {
+    sender: Option<Sender<(PathBuf, String)>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SigningQueue {
+    /// 署名スレッドを起動する（`on_signed`は録画ごとに署名スレッドで呼ばれる）
+    pub fn start<F>(signer: ManifestSigner, mut on_signed: F) -> io::Result<Self>
+    where
+        F: FnMut(&Path, io::Result<Manifest>) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel::<(PathBuf, String)>();
+        let worker = thread::Builder::new().name("manifest-signer".to_string()).spawn(move || {
+            for (recording, format) in receiver {
+                let result = signer.sign_recording(&recording, &format);
+                on_signed(&recording, result);
+            }
+        })?;
+        Ok(Self { sender: Some(sender), worker: Some(worker) })
+    }
+
+    /// 録画ファイルの署名を予約する
+    pub fn sign(&self, recording: &Path, format: &str) {
+        if let Some(sender) = &self.sender {
+            // 署名スレッドが止まっている場合だけ失敗する（結果はon_signedで通知済み）
+            let _ = sender.send((recording.to_path_buf(), format.to_string()));
+        }
+    }
+}
+
+impl Drop for SigningQueue {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// 検証で見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// 内容が変更されたフレーム/チャンク（マニフェスト上の番号）
+    Modified(usize),
+    /// 削除されたフレーム/チャンク
+    Removed(usize),
+    /// マニフェストにないフレーム/チャンク（ファイル上の番号）
+    Inserted(usize),
+    /// 順序が入れ替わったフレーム/チャンク
+    Reordered { index: usize, found_at: usize },
+    /// ファイルサイズが署名時と異なる
+    SizeChanged { expected: u64, actual: u64 },
+    /// マニフェスト内のハッシュチェーンが一致しない
+    ChainMismatch,
+    /// 署名が不正
+    BadSignature,
+    /// 信頼する公開鍵と異なる鍵で署名されている
+    UntrustedKey,
+    /// 直前の録画のマニフェストとチェーンがつながらない
+    BrokenLink,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Modified(i) => write!(f, "unit #{} was modified", i),
+            Problem::Removed(i) => write!(f, "unit #{} was removed", i),
+            Problem::Inserted(i) => write!(f, "unit at position {} is not in the manifest", i),
+            Problem::Reordered { index, found_at } => write!(f, "unit #{} was moved to position {}", index, found_at),
+            Problem::SizeChanged { expected, actual } => write!(f, "file size changed: {} -> {} bytes", expected, actual),
+            Problem::ChainMismatch => write!(f, "hash chain in the manifest does not match its entries"),
+            Problem::BadSignature => write!(f, "manifest signature is invalid"),
+            Problem::UntrustedKey => write!(f, "manifest was signed with an untrusted key"),
+            Problem::BrokenLink => write!(f, "hash chain does not link to the previous recording's manifest"),
+        }
+    }
+}
+
+/// 検証結果
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub manifest: Manifest,
+    /// ファイルから再計算した単位数
+    pub units_checked: usize,
+    pub problems: Vec<Problem>,
+    /// 改ざんとは断定できない注意事項
+    pub warnings: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// 録画ファイルをマニフェストと照合する
+///
+/// `trusted_key`を指定した場合、その公開鍵で署名されていることも確認する。
+pub fn verify_recording(recording: &Path, trusted_key: Option<&str>) -> io::Result<VerifyReport> {
+    let text = fs::read_to_string(manifest_path(recording))?;
+    let manifest: Manifest = serde_json::from_str(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+
+    // 署名
+    let signature_ok = decode_hash(&manifest.public_key)
+        .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        .zip(hex::decode(&manifest.signature).ok().and_then(|s| Signature::from_slice(&s).ok()))
+        .is_some_and(|(key, signature)| key.verify(&manifest.signing_payload(), &signature).is_ok());
+    if !signature_ok {
+        problems.push(Problem::BadSignature);
+    }
+    match trusted_key {
+        Some(key) if !key.trim().eq_ignore_ascii_case(&manifest.public_key) => problems.push(Problem::UntrustedKey),
+        Some(_) => {}
+        None => warnings.push("no trusted public key available; signature checked against the key in the manifest".to_string()),
+    }
+
+    // マニフェスト自体の整合性
+    if hex::encode(chain_hash(manifest.chain_seed(), &manifest.entries)) != manifest.final_hash {
+        problems.push(Problem::ChainMismatch);
+    }
+
+    // 直前の録画とのリンク
+    if let Some(previous) = &manifest.previous {
+        match fs::read_to_string(manifest_path(&recording.with_file_name(&previous.recording))) {
+            Ok(text) => match serde_json::from_str::<Manifest>(&text) {
+                Ok(prev) if prev.final_hash == previous.final_hash => {}
+                _ => problems.push(Problem::BrokenLink),
+            },
+            Err(_) => warnings.push(format!("previous recording's manifest ({}) not found", previous.recording)),
+        }
+    }
+
+    // ファイル内容
+    let actual_size = fs::metadata(recording)?.len();
+    if actual_size != manifest.file_size {
+        problems.push(Problem::SizeChanged { expected: manifest.file_size, actual: actual_size });
+    }
+    let (_, entries) = hash_recording(recording, &manifest.format)?;
+    let expected: Vec<&str> = manifest.entries.iter().map(|e| e.sha256.as_str()).collect();
+    let actual: Vec<&str> = entries.iter().map(|e| e.sha256.as_str()).collect();
+    problems.extend(compare_units(&expected, &actual));
+
+    Ok(VerifyReport { manifest, units_checked: entries.len(), problems, warnings })
+}
+
+/// マニフェストのハッシュ列とファイルのハッシュ列を比較する
+///
+/// 同じ位置で別の内容になっているものを変更、片方にしかないものを削除/追加、
+/// 両方にあるが相対順序が崩れているものを並べ替えとして報告する。
+fn compare_units(expected: &[&str], actual: &[&str]) -> Vec<Problem> {
+    if expected == actual {
+        return Vec::new();
+    }
+
+    let expected_set: HashSet<&str> = expected.iter().copied().collect();
+    let actual_set: HashSet<&str> = actual.iter().copied().collect();
+
+    let missing: Vec<usize> = (0..expected.len()).filter(|&i| !actual_set.contains(expected[i])).collect();
+    let mut extra: Vec<usize> = (0..actual.len()).filter(|&j| !expected_set.contains(actual[j])).collect();
+
+    let mut problems = Vec::new();
+
+    // 同じ位置で内容が変わったもの
+    let extra_at: HashSet<usize> = extra.iter().copied().collect();
+    let (modified, missing): (Vec<usize>, Vec<usize>) = missing.into_iter().partition(|i| extra_at.contains(i));
+    let modified_at: HashSet<usize> = modified.iter().copied().collect();
+    extra.retain(|j| !modified_at.contains(j));
+    problems.extend(modified.into_iter().map(Problem::Modified));
+    problems.extend(missing.into_iter().map(Problem::Removed));
+    problems.extend(extra.into_iter().map(Problem::Inserted));
+
+    // 共通のフレームのマニフェスト上の番号をファイル順に並べ、
+    // 最長増加部分列に入らないものを並べ替えとする（重複フレームは先頭から対応付ける）
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, hash) in expected.iter().enumerate().rev() {
+        positions.entry(hash).or_default().push(i);
+    }
+    let common: Vec<(usize, usize)> = actual.iter().enumerate()
+        .filter_map(|(found_at, hash)| Some((positions.get_mut(hash)?.pop()?, found_at)))
+        .collect();
+    let in_order = longest_increasing(&common.iter().map(|&(index, _)| index).collect::<Vec<_>>());
+    problems.extend(common.iter().enumerate()
+        .filter(|(k, _)| !in_order.contains(k))
+        .map(|(_, &(index, found_at))| Problem::Reordered { index, found_at }));
+
+    problems
+}
+
+/// 最長増加部分列に含まれる要素の位置
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // tails[k]: 長さk+1の増加列の末尾要素の位置
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+
+    for (i, &value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < value);
+        previous[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut result = HashSet::new();
+    let mut current = tails.last().copied();
+    while let Some(i) = current {
+        result.insert(i);
+        current = previous[i];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(n: u8) -> Vec<u8> {
+        vec![0xFF, 0xD8, n, n, 0xFF, 0xD9]
+    }
+
+    fn write_frames(path: &Path, frames: &[u8]) {
+        let data: Vec<u8> = frames.iter().flat_map(|&n| frame(n)).collect();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_signing_queue() {
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ManifestSigner::load_or_generate(&dir.path().join(KEY_DIR_NAME)).unwrap();
+        let first = dir.path().join("manual_1.mjpeg");
+        let second = dir.path().join("manual_2.mjpeg");
+        write_frames(&first, &[1, 2]);
+        write_frames(&second, &[3]);
+
+        let signed = Arc::new(Mutex::new(Vec::new()));
+        let results = signed.clone();
+        let queue = SigningQueue::start(signer, move |path, manifest| {
+            results.lock().unwrap().push((path.to_path_buf(), manifest.map(|m| m.final_hash)));
+        }).unwrap();
+        queue.sign(&first, "mjpeg");
+        queue.sign(&second, "mjpeg");
+        queue.sign(&dir.path().join("missing.mjpeg"), "mjpeg");
+        // ドロップで残りの署名を待つ
+        drop(queue);
+
+        let signed = signed.lock().unwrap();
+        assert_eq!(signed.len(), 3);
+        assert_eq!(signed[0].0, first);
+        assert!(signed[2].1.is_err());
+        let manifest: Manifest = serde_json::from_str(&fs::read_to_string(manifest_path(&second)).unwrap()).unwrap();
+        assert_eq!(manifest.previous.unwrap().final_hash, *signed[0].1.as_ref().unwrap());
+        assert!(verify_recording(&second, None).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ManifestSigner::load_or_generate(&dir.path().join(KEY_DIR_NAME)).unwrap();
+
+        let first = dir.path().join("manual_1.mjpeg");
+        write_frames(&first, &[1, 2, 3]);
+        let manifest = signer.sign_recording(&first, "mjpeg").unwrap();
+        assert_eq!(manifest.entries.len(), 3);
+        assert!(manifest.previous.is_none());
+
+        // 2本目は1本目にチェーンする
+        let second = dir.path().join("manual_2.mp4");
+        fs::write(&second, vec![7u8; MP4_CHUNK_SIZE as usize + 10]).unwrap();
+        let manifest2 = signer.sign_recording(&second, "mp4").unwrap();
+        assert_eq!(manifest2.unit, HashUnit::Chunk);
+        assert_eq!(manifest2.entries.len(), 2);
+        assert_eq!(manifest2.previous.as_ref().unwrap().final_hash, manifest.final_hash);
+
+        let key = signer.public_key_hex();
+        for path in [&first, &second] {
+            let report = verify_recording(path, Some(&key)).unwrap();
+            assert!(report.is_ok(), "{:?}", report.problems);
+        }
+
+        // 別の鍵を信頼している場合
+        let report = verify_recording(&first, Some(&"00".repeat(32))).unwrap();
+        assert_eq!(report.problems, [Problem::UntrustedKey]);
+
+        // 鍵は再利用される
+        let reloaded = ManifestSigner::load_or_generate(&dir.path().join(KEY_DIR_NAME)).unwrap();
+        assert_eq!(reloaded.public_key_hex(), key);
+    }
+
+    #[test]
+    fn test_detect_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ManifestSigner::load_or_generate(&dir.path().join(KEY_DIR_NAME)).unwrap();
+        let path = dir.path().join("motion_1.mjpeg");
+        write_frames(&path, &[1, 2, 3, 4]);
+        signer.sign_recording(&path, "mjpeg").unwrap();
+        let key = signer.public_key_hex();
+
+        write_frames(&path, &[1, 9, 3, 4]);
+        assert_eq!(verify_recording(&path, Some(&key)).unwrap().problems, [Problem::Modified(1)]);
+
+        write_frames(&path, &[1, 3, 4]);
+        let problems = verify_recording(&path, Some(&key)).unwrap().problems;
+        assert!(problems.contains(&Problem::Removed(1)), "{:?}", problems);
+
+        write_frames(&path, &[1, 3, 2, 4]);
+        assert_eq!(verify_recording(&path, Some(&key)).unwrap().problems, [Problem::Reordered { index: 2, found_at: 1 }]);
+    }
+
+    #[test]
+    fn test_detect_manifest_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = ManifestSigner::load_or_generate(&dir.path().join(KEY_DIR_NAME)).unwrap();
+        let path = dir.path().join("motion_1.mjpeg");
+        write_frames(&path, &[1, 2]);
+        signer.sign_recording(&path, "mjpeg").unwrap();
+
+        // 改ざんしたフレームに合わせてマニフェストのハッシュを書き換えても署名で検出できる
+        write_frames(&path, &[1, 9]);
+        let mut manifest: Manifest = serde_json::from_str(&fs::read_to_string(manifest_path(&path)).unwrap()).unwrap();
+        manifest.entries[1].sha256 = hex::encode(Sha256::digest(frame(9)));
+        manifest.final_hash = hex::encode(chain_hash(manifest.chain_seed(), &manifest.entries));
+        fs::write(manifest_path(&path), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let report = verify_recording(&path, Some(&signer.public_key_hex())).unwrap();
+        assert_eq!(report.problems, [Problem::BadSignature]);
+    }
+
+    #[test]
+    fn test_compare_units() {
+        assert!(compare_units(&["a", "b"], &["a", "b"]).is_empty());
+        assert_eq!(compare_units(&["a", "b", "c"], &["a", "c"]), [Problem::Removed(1)]);
+        assert_eq!(compare_units(&["a", "c"], &["a", "x", "c"]), [Problem::Inserted(1)]);
+        assert_eq!(compare_units(&["a", "b", "c"], &["c", "a", "b"]), [Problem::Reordered { index: 2, found_at: 0 }]);
+        assert_eq!(compare_units(&["a", "b", "c"], &["a", "x", "b"]), [Problem::Removed(2), Problem::Inserted(1)]);
+    }
+}
@@ -0,0 +1,411 @@
+//! 撮影メタデータモジュール
+//!
+//! 保存するJPEGに撮影日時・カメラ名・フレーム番号のEXIF（APP1）を埋め込む。
+//! 画像データはそのままコピーするため再エンコードによる劣化はない。
+//! 録画ごとに、デバイス情報・カメラから届いたメトリクス・開始/終了時刻・フレーム数・
+//! 録画の理由・動き検知設定をJSONのサイドカー（`<録画ファイル>.meta.json`）に書き出す。
+//!
+//! EXIFのタグ:
+//! - IFD0: ImageDescription（カメラ名）、Software
+//! - Exif IFD: ExifVersion、DateTimeOriginal、OffsetTimeOriginal、ImageNumber（フレーム番号）、SubSecTimeOriginal
+
+use crate::catalog::RecordingTrigger;
+use crate::motion_detector::MotionDetectionConfig;
+use crate::protocol::MetricsPacket;
+use crate::serial::DeviceProfile;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// サイドカー形式のバージョン
+pub const SIDECAR_VERSION: u32 = 1;
+
+const SOI: [u8; 2] = [0xFF, 0xD8];
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const EXIF_HEADER: &[u8; 6] = b"Exif\0\0";
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_IMAGE_NUMBER: u16 = 0x9211;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+
+/// JPEGに埋め込む撮影情報
+#[derive(Debug, Clone)]
+pub struct FrameMetadata<'a> {
+    pub captured_at: DateTime<Local>,
+    pub camera_name: &'a str,
+    /// フレーム番号
+    pub sequence: u64,
+}
+
+/// EXIFのIFDエントリの値
+enum Value {
+    Ascii(String),
+    Long(u32),
+    Undefined(Vec<u8>),
+}
+
+impl Value {
+    /// 型・個数・値のバイト列（リトルエンディアン）
+    fn encode(&self) -> (u16, u32, Vec<u8>) {
+        match self {
+            Value::Ascii(text) => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                (TYPE_ASCII, bytes.len() as u32, bytes)
+            }
+            Value::Long(value) => (TYPE_LONG, 1, value.to_le_bytes().to_vec()),
+            Value::Undefined(bytes) => (TYPE_UNDEFINED, bytes.len() as u32, bytes.clone()),
+        }
+    }
+}
+
+/// IFDの長さ（4バイトを超える値の領域を含む）
+fn ifd_len(entries: &[(u16, Value)]) -> usize {
+    let values: usize = entries.iter()
+        .map(|(_, value)| value.encode().2.len())
+        .filter(|&len| len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    2 + entries.len() * 12 + 4 + values
+}
+
+/// `tiff`の末尾にIFDを書き込む（エントリはタグ順）
+fn write_ifd(tiff: &mut Vec<u8>, entries: &[(u16, Value)]) {
+    let start = tiff.len();
+    let mut value_offset = start + 2 + entries.len() * 12 + 4;
+    let mut values = Vec::new();
+
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, value) in entries {
+        let (value_type, count, mut bytes) = value.encode();
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&value_type.to_le_bytes());
+        tiff.extend_from_slice(&count.to_le_bytes());
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            tiff.extend_from_slice(&bytes);
+        } else {
+            tiff.extend_from_slice(&(value_offset as u32).to_le_bytes());
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            value_offset += bytes.len();
+            values.extend_from_slice(&bytes);
+        }
+    }
+    tiff.extend_from_slice(&0u32.to_le_bytes());  // 次のIFDなし
+    tiff.extend_from_slice(&values);
+}
+
+/// APP1セグメント（マーカーと長さを含む）を作る
+fn exif_segment(metadata: &FrameMetadata) -> io::Result<Vec<u8>> {
+    let mut ifd0 = vec![
+        (TAG_IMAGE_DESCRIPTION, Value::Ascii(metadata.camera_name.to_string())),
+        (TAG_SOFTWARE, Value::Ascii(format!("security_camera_viewer {}", env!("CARGO_PKG_VERSION")))),
+        (TAG_EXIF_IFD, Value::Long(0)),
+    ];
+    let exif_ifd = [
+        (TAG_EXIF_VERSION, Value::Undefined(b"0232".to_vec())),
+        (TAG_DATE_TIME_ORIGINAL, Value::Ascii(metadata.captured_at.format("%Y:%m:%d %H:%M:%S").to_string())),
+        (TAG_OFFSET_TIME_ORIGINAL, Value::Ascii(metadata.captured_at.format("%:z").to_string())),
+        (TAG_IMAGE_NUMBER, Value::Long(metadata.sequence.min(u32::MAX as u64) as u32)),
+        (TAG_SUB_SEC_TIME_ORIGINAL, Value::Ascii(metadata.captured_at.format("%3f").to_string())),
+    ];
+
+    // TIFFヘッダー（リトルエンディアン、IFD0は8バイト目から）の後にIFD0、Exif IFDの順
+    let exif_offset = 8 + ifd_len(&ifd0);
+    ifd0[2].1 = Value::Long(exif_offset as u32);
+
+    let mut tiff = b"II\x2A\x00\x08\x00\x00\x00".to_vec();
+    write_ifd(&mut tiff, &ifd0);
+    write_ifd(&mut tiff, &exif_ifd);
+
+    let len = 2 + EXIF_HEADER.len() + tiff.len();
+    if len > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXIF data too large (camera name too long)"));
+    }
+    let mut segment = vec![0xFF, APP1];
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    segment.extend_from_slice(EXIF_HEADER);
+    segment.extend_from_slice(&tiff);
+    Ok(segment)
+}
+
+/// JPEGにEXIFを埋め込む（画像データは変更しない）
+///
+/// 既存のEXIFは置き換える。JFIF（APP0）がある場合はその直後、ない場合はSOIの直後に入れる。
+///
+/// # Errors
+/// - JPEGでない場合、またはマーカーが壊れている場合
+pub fn inject_exif(jpeg_data: &[u8], metadata: &FrameMetadata) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if !jpeg_data.starts_with(&SOI) {
+        return Err(invalid("not a JPEG (missing SOI marker)"));
+    }
+
+    // 先頭のAPPnセグメントを読み、既存のEXIFだけ取り除く
+    let mut segments = Vec::new();
+    let mut pos = SOI.len();
+    while jpeg_data.len() >= pos + 4 && jpeg_data[pos] == 0xFF && (APP0..=0xEF).contains(&jpeg_data[pos + 1]) {
+        let len = u16::from_be_bytes([jpeg_data[pos + 2], jpeg_data[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > jpeg_data.len() {
+            return Err(invalid("truncated JPEG segment"));
+        }
+        let segment = &jpeg_data[pos..end];
+        if !(segment[1] == APP1 && segment[4..].starts_with(EXIF_HEADER)) {
+            segments.push(segment);
+        }
+        pos = end;
+    }
+
+    let exif = exif_segment(metadata)?;
+    let mut output = Vec::with_capacity(jpeg_data.len() + exif.len());
+    output.extend_from_slice(&SOI);
+    let jfif = segments.first().is_some_and(|segment| segment[1] == APP0);
+    if jfif {
+        output.extend_from_slice(segments[0]);
+    }
+    output.extend_from_slice(&exif);
+    for segment in &segments[jfif as usize..] {
+        output.extend_from_slice(segment);
+    }
+    output.extend_from_slice(&jpeg_data[pos..]);
+    Ok(output)
+}
+
+/// カメラから届いたメトリクス
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareMetrics {
+    pub received_at: DateTime<Local>,
+    /// カメラの起動からの時間（ミリ秒）
+    pub uptime_ms: u32,
+    pub camera_frames: u32,
+    pub usb_packets: u32,
+    pub action_q_depth: u32,
+    pub avg_packet_size: u32,
+    pub errors: u32,
+}
+
+impl FirmwareMetrics {
+    pub fn new(packet: &MetricsPacket, received_at: DateTime<Local>) -> Self {
+        Self {
+            received_at,
+            uptime_ms: packet.timestamp_ms,
+            camera_frames: packet.camera_frames,
+            usb_packets: packet.usb_packets,
+            action_q_depth: packet.action_q_depth,
+            avg_packet_size: packet.avg_packet_size,
+            errors: packet.errors,
+        }
+    }
+}
+
+/// 録画のサイドカーのパス（`<録画ファイル>.meta.json`）
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    let mut name = recording.file_name().unwrap_or_default().to_os_string();
+    name.push(".meta.json");
+    recording.with_file_name(name)
+}
+
+/// 録画のサイドカー
+///
+/// 録画の開始時に`start`で作り、録画中に届いたメトリクスを`record_metrics`で記録して、
+/// 録画を閉じたら`finish`で書き出す。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingSidecar {
+    pub version: u32,
+    /// 録画ファイル名
+    pub recording: String,
+    pub format: String,
+    pub trigger: RecordingTrigger,
+    pub camera_name: String,
+    /// 接続していたデバイス（不明ならNone）
+    pub device: Option<DeviceProfile>,
+    pub started_at: DateTime<Local>,
+    pub ended_at: Option<DateTime<Local>>,
+    pub frame_count: u64,
+    pub size_bytes: u64,
+    /// ffmpegの再起動で追加されたセグメントのファイル名
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<String>,
+    /// 録画中に最初/最後に届いたメトリクス
+    pub firmware_metrics_first: Option<FirmwareMetrics>,
+    pub firmware_metrics_last: Option<FirmwareMetrics>,
+    /// 動き検知を使った録画の設定
+    pub motion: Option<MotionDetectionConfig>,
+}
+
+impl RecordingSidecar {
+    pub fn start(
+        recording: &Path,
+        format: &str,
+        trigger: RecordingTrigger,
+        camera_name: &str,
+        device: Option<DeviceProfile>,
+        motion: Option<MotionDetectionConfig>,
+    ) -> Self {
+        Self {
+            version: SIDECAR_VERSION,
+            recording: file_name(recording),
+            format: format.to_string(),
+            trigger,
+            camera_name: camera_name.to_string(),
+            device,
+            started_at: Local::now(),
+            ended_at: None,
+            frame_count: 0,
+            size_bytes: 0,
+            segments: Vec::new(),
+            firmware_metrics_first: None,
+            firmware_metrics_last: None,
+            motion,
+        }
+    }
+
+    /// 録画中に届いたメトリクスを記録する
+    pub fn record_metrics(&mut self, metrics: FirmwareMetrics) {
+        if self.firmware_metrics_first.is_none() {
+            self.firmware_metrics_first = Some(metrics.clone());
+        }
+        self.firmware_metrics_last = Some(metrics);
+    }
+
+    /// 終了時の情報を記録して`<録画ファイル>.meta.json`に書き出す
+    pub fn finish(
+        mut self,
+        recording: &Path,
+        frame_count: u64,
+        size_bytes: u64,
+        segments: &[PathBuf],
+    ) -> io::Result<PathBuf> {
+        self.ended_at = Some(Local::now());
+        self.frame_count = frame_count;
+        self.size_bytes = size_bytes;
+        self.segments = segments.iter().map(|path| file_name(path)).collect();
+
+        let path = sidecar_path(recording);
+        let json = serde_json::to_string_pretty(&self).map_err(io::Error::other)?;
+        fs::write(&path, json + "\n")?;
+        Ok(path)
+    }
+
+    /// サイドカーを読み込む
+    pub fn read(path: &Path) -> io::Result<Self> {
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use exif::{In, Reader, Tag};
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+
+    fn jpeg() -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(16, 8, Rgb([90, 120, 150]))
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        data
+    }
+
+    fn metadata() -> FrameMetadata<'static> {
+        FrameMetadata {
+            captured_at: Local.with_ymd_and_hms(2026, 1, 2, 2, 14, 0).unwrap() + chrono::Duration::milliseconds(250),
+            camera_name: "Front door",
+            sequence: 1234,
+        }
+    }
+
+    fn ascii(exif: &exif::Exif, tag: Tag) -> String {
+        exif.get_field(tag, In::PRIMARY).unwrap().display_value().to_string()
+    }
+
+    #[test]
+    fn test_inject_exif() {
+        let original = jpeg();
+        let tagged = inject_exif(&original, &metadata()).unwrap();
+
+        let exif = Reader::new().read_from_container(&mut Cursor::new(&tagged)).unwrap();
+        assert_eq!(ascii(&exif, Tag::DateTimeOriginal), "2026-01-02 02:14:00");
+        assert_eq!(ascii(&exif, Tag::SubSecTimeOriginal), "\"250\"");
+        assert_eq!(ascii(&exif, Tag::ImageDescription), "\"Front door\"");
+        let number = exif.get_field(Tag(exif::Context::Exif, TAG_IMAGE_NUMBER), In::PRIMARY).unwrap();
+        assert_eq!(number.value.get_uint(0), Some(1234));
+
+        // JFIF（APP0）の直後にEXIFのセグメントが入るだけで、他のバイトはそのまま
+        let app0_end = 4 + u16::from_be_bytes([original[4], original[5]]) as usize;
+        let exif_len = tagged.len() - original.len();
+        assert_eq!(&original[2..4], &[0xFF, APP0]);
+        assert_eq!(&tagged[..app0_end], &original[..app0_end]);
+        assert_eq!(&tagged[app0_end..app0_end + 2], &[0xFF, APP1]);
+        assert_eq!(&tagged[app0_end + exif_len..], &original[app0_end..]);
+        assert_eq!(image::load_from_memory(&tagged).unwrap().to_rgb8(), image::load_from_memory(&original).unwrap().to_rgb8());
+    }
+
+    #[test]
+    fn test_inject_exif_replaces_existing() {
+        let tagged = inject_exif(&jpeg(), &metadata()).unwrap();
+        let retagged = inject_exif(&tagged, &FrameMetadata { sequence: 7, ..metadata() }).unwrap();
+        assert_eq!(retagged.len(), tagged.len());
+        assert_eq!(retagged.windows(EXIF_HEADER.len()).filter(|w| w == EXIF_HEADER).count(), 1);
+
+        // JFIFのない（APP0なしの）JPEGにはSOIの直後に入れる
+        let bare = [0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x02, 0xFF, 0xD9];
+        let tagged = inject_exif(&bare, &metadata()).unwrap();
+        assert_eq!(&tagged[2..4], &[0xFF, APP1]);
+        assert!(tagged.ends_with(&bare[2..]));
+
+        assert!(inject_exif(b"not a jpeg", &metadata()).is_err());
+        assert!(inject_exif(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x40], &metadata()).is_err());
+    }
+
+    #[test]
+    fn test_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = dir.path().join("motion_20260102_021400.mjpeg");
+        let device = DeviceProfile { port: "/dev/ttyACM0".to_string(), baud_rate: 115200, usb_vid: Some(0x054C), ..Default::default() };
+        let mut sidecar = RecordingSidecar::start(&recording, "mjpeg", RecordingTrigger::Motion, "Front door",
+                                                  Some(device.clone()), Some(MotionDetectionConfig::default()));
+
+        let packet = MetricsPacket {
+            sequence: 1, timestamp_ms: 5000, camera_frames: 100, usb_packets: 100,
+            action_q_depth: 1, avg_packet_size: 40_000, errors: 0,
+        };
+        sidecar.record_metrics(FirmwareMetrics::new(&packet, Local::now()));
+        sidecar.record_metrics(FirmwareMetrics::new(&MetricsPacket { camera_frames: 200, ..packet }, Local::now()));
+
+        let path = sidecar.finish(&recording, 42, 1000, &[]).unwrap();
+        assert_eq!(path, dir.path().join("motion_20260102_021400.mjpeg.meta.json"));
+
+        let read = RecordingSidecar::read(&path).unwrap();
+        assert_eq!(read.recording, "motion_20260102_021400.mjpeg");
+        assert_eq!(read.trigger, RecordingTrigger::Motion);
+        assert_eq!(read.device, Some(device));
+        assert_eq!(read.frame_count, 42);
+        assert_eq!(read.firmware_metrics_first.unwrap().camera_frames, 100);
+        assert_eq!(read.firmware_metrics_last.unwrap().camera_frames, 200);
+        assert!(read.ended_at.unwrap() >= read.started_at);
+        assert_eq!(read.motion, Some(MotionDetectionConfig::default()));
+    }
+}
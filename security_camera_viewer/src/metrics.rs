@@ -1,3 +1,4 @@
+use security_camera_viewer::frame_analyzer::AnalyzerStats;
 use std::fs::File;
 use std::io::{self, Write};
 use std::path::PathBuf;
@@ -22,6 +23,7 @@ pub struct PerformanceMetrics {
     pub spresense_usb_packets: u32,    // Spresense USB packets sent
     pub action_q_depth: u32,           // Pipeline queue depth (0-3)
     pub spresense_errors: u32,         // Spresense error count
+    pub analyzers: Vec<AnalyzerStats>, // Frame analyzer timings (motion, tamper, ...)
 }
 
 impl PerformanceMetrics {
@@ -42,6 +44,7 @@ impl PerformanceMetrics {
             spresense_usb_packets: 0,
             action_q_depth: 0,
             spresense_errors: 0,
+            analyzers: Vec::new(),
         }
     }
 
@@ -82,7 +85,8 @@ impl MetricsLogger {
             file,
             "timestamp,pc_fps,spresense_fps,frame_count,error_count,\
              decode_time_ms,serial_read_time_ms,texture_upload_time_ms,jpeg_size_kb,\
-             spresense_camera_frames,spresense_camera_fps,spresense_usb_packets,action_q_depth,spresense_errors"
+             spresense_camera_frames,spresense_camera_fps,spresense_usb_packets,action_q_depth,spresense_errors,\
+             analyzers"
         )?;
 
         Ok(Self {
@@ -97,7 +101,7 @@ impl MetricsLogger {
 
         writeln!(
             file,
-            "{:.3},{:.2},{:.2},{},{},{:.2},{:.2},{:.2},{:.2},{},{:.2},{},{},{}",
+            "{:.3},{:.2},{:.2},{},{},{:.2},{:.2},{:.2},{:.2},{},{:.2},{},{},{},{}",
             metrics.timestamp,
             metrics.pc_fps,
             metrics.spresense_fps,
@@ -112,6 +116,7 @@ impl MetricsLogger {
             metrics.spresense_usb_packets,
             metrics.action_q_depth,
             metrics.spresense_errors,
+            format_analyzers(&metrics.analyzers),
         )?;
 
         file.flush()?;
@@ -124,6 +129,18 @@ impl MetricsLogger {
     }
 }
 
+/// Format analyzer timings for the single `analyzers` CSV column
+///
+/// One `name=avg_ms/max_ms/detections` entry per analyzer, separated by `;`
+fn format_analyzers(analyzers: &[AnalyzerStats]) -> String {
+    analyzers
+        .iter()
+        .filter(|stats| stats.enabled)
+        .map(|stats| format!("{}={:.2}/{:.2}/{}", stats.name, stats.avg_time_ms, stats.max_time_ms, stats.detections))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
 /// Spresense FPS calculator
 ///
 /// Calculates Spresense-side send rate from packet sequence numbers
@@ -288,4 +305,23 @@ mod tests {
         let fps = calc.current_fps();
         assert!(fps > 0.0, "FPS should be calculated even near wraparound");
     }
+
+    #[test]
+    fn test_format_analyzers() {
+        let stats = |name: &str, enabled: bool| AnalyzerStats {
+            name: name.to_string(),
+            enabled,
+            frames: 10,
+            detections: 3,
+            avg_time_ms: 1.234,
+            max_time_ms: 4.5,
+            ..AnalyzerStats::default()
+        };
+
+        assert_eq!(format_analyzers(&[]), "");
+        assert_eq!(
+            format_analyzers(&[stats("motion", true), stats("tamper", false), stats("custom", true)]),
+            "motion=1.23/4.50/3;custom=1.23/4.50/3"
+        );
+    }
 }
@@ -0,0 +1,437 @@
+//! MJPEGストリームファイルモジュール
+//!
+//! JPEGフレームを連結しただけのMJPEGファイルの書き込みと解析を行う。
+//! 書き込み側は一定間隔でディスクに同期し、電源断時に失われる範囲を抑える。
+//! 鍵を指定した場合は暗号化して書き込む（`crypto`モジュール）。
+//! 解析側はSOI/EOIマーカーでフレーム境界を求め、途中で途切れた末尾フレームを検出する。
+//!
+//! 書き込み時はフレームごとの撮影時刻をインデックス（`<ファイル>.idx`）にも記録する。
+//! 1行1フレームで「撮影時刻(UNIXミリ秒) オフセット 長さ」のテキスト形式。
+//! オフセットは（暗号化ファイルでも）平文ストリーム上の位置。
+
+use crate::crypto::{EncryptedWriter, KeySource};
+use chrono::{DateTime, Local, TimeZone};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// デフォルトのディスク同期間隔
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(2);
+
+/// MJPEGファイルライター
+///
+/// フレームごとの`flush()`はGUIスレッドをブロックするため行わず、
+/// `sync_interval`ごとに`sync_data()`でまとめてディスクに書き出す。
+pub struct MjpegWriter {
+    /// 出力ファイル
+    output: Output,
+    /// フレームインデックス
+    index: BufWriter<File>,
+    /// 平文ストリーム上の書き込み位置
+    position: u64,
+    /// ディスク同期間隔
+    sync_interval: Duration,
+    /// 最後に同期した時刻
+    last_sync: Instant,
+    /// 前回同期以降に書き込まれたバイト数
+    unsynced_bytes: u64,
+}
+
+/// 出力先（平文または暗号化）
+enum Output {
+    Plain(File),
+    Encrypted(EncryptedWriter<File>),
+}
+
+impl MjpegWriter {
+    /// 新しいMJPEGファイルとインデックスを作成
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::create_with_key(path, None)
+    }
+
+    /// 新しいMJPEGファイルとインデックスを作成（鍵が指定されていれば暗号化）
+    pub fn create_with_key(path: &Path, key: Option<&KeySource>) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let output = match key {
+            Some(key) => Output::Encrypted(EncryptedWriter::new(file, key)?),
+            None => Output::Plain(file),
+        };
+        let index = BufWriter::new(File::create(index_path(path))?);
+
+        Ok(Self {
+            output,
+            index,
+            position: 0,
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+            last_sync: Instant::now(),
+            unsynced_bytes: 0,
+        })
+    }
+
+    /// 現在時刻を撮影時刻としてJPEGフレームを書き込む
+    pub fn write_frame(&mut self, jpeg_data: &[u8]) -> io::Result<()> {
+        self.write_frame_at(jpeg_data, Local::now())
+    }
+
+    /// 撮影時刻を指定してJPEGフレームを書き込む（プリバッファのフレーム用）
+    ///
+    /// 前回の同期から`sync_interval`以上経過していればディスクに同期する。
+    pub fn write_frame_at(&mut self, jpeg_data: &[u8], captured_at: DateTime<Local>) -> io::Result<()> {
+        match &mut self.output {
+            Output::Plain(file) => file.write_all(jpeg_data)?,
+            Output::Encrypted(writer) => writer.write_all(jpeg_data)?,
+        }
+        writeln!(self.index, "{} {} {}", captured_at.timestamp_millis(), self.position, jpeg_data.len())?;
+        self.position += jpeg_data.len() as u64;
+        self.unsynced_bytes += jpeg_data.len() as u64;
+
+        if self.last_sync.elapsed() >= self.sync_interval {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// 書き込み済みデータをディスクに同期
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced_bytes > 0 {
+            match &mut self.output {
+                Output::Plain(file) => file.sync_data()?,
+                Output::Encrypted(writer) => {
+                    // バッファ中のデータも短いチャンクとして書き出す
+                    writer.flush_chunk()?;
+                    writer.get_ref().sync_data()?;
+                }
+            }
+            self.index.flush()?;
+            self.index.get_ref().sync_data()?;
+            self.unsynced_bytes = 0;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// 録画を終了（残りのデータをディスクに同期）
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self.output {
+            Output::Plain(file) => file,
+            Output::Encrypted(writer) => writer.finish()?,
+        };
+        file.flush()?;
+        file.sync_all()?;
+
+        let index = self.index.into_inner().map_err(|e| e.into_error())?;
+        index.sync_all()
+    }
+}
+
+/// フレームインデックスの1行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// 撮影時刻
+    pub captured_at: DateTime<Local>,
+    /// 平文ストリーム上のフレーム位置
+    pub span: FrameSpan,
+}
+
+/// 録画ファイルのインデックスのパス（`<ファイル>.idx`）
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".idx");
+    path.with_file_name(name)
+}
+
+/// インデックスを読み込む
+///
+/// 書き込み途中で途切れた行など、解釈できない行は無視する。
+pub fn read_index(path: &Path) -> io::Result<Vec<IndexEntry>> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [millis, offset, len] = fields[..] else { continue };
+        let (Ok(millis), Ok(offset), Ok(len)) = (millis.parse::<i64>(), offset.parse(), len.parse()) else {
+            continue;
+        };
+        if let Some(captured_at) = Local.timestamp_millis_opt(millis).single() {
+            entries.push(IndexEntry { captured_at, span: FrameSpan { offset, len } });
+        }
+    }
+    Ok(entries)
+}
+
+/// ファイル内の1フレームの位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSpan {
+    /// 先頭（SOI）のオフセット
+    pub offset: u64,
+    /// フレーム長（SOI〜EOIを含む）
+    pub len: u64,
+}
+
+/// フレーム境界のスキャン結果
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    /// 完全なフレームの一覧
+    pub frames: Vec<FrameSpan>,
+    /// 最後の完全なフレームの終端（これ以降は途切れたデータ）
+    pub valid_len: u64,
+    /// スキャンしたデータの総バイト数
+    pub total_len: u64,
+}
+
+/// MJPEGデータのフレーム境界をスキャン
+///
+/// SOI (FF D8) から次のEOI (FF D9) までを1フレームとする。
+/// エントロピー符号化データ中の0xFFはバイトスタッフィングされるため、
+/// フレーム途中でEOIが現れることはない。
+pub fn scan_frames<R: Read>(reader: R) -> io::Result<ScanResult> {
+    let mut frames = Vec::new();
+    let mut result = for_each_frame(reader, |span, _| {
+        frames.push(span);
+        Ok(())
+    })?;
+    result.frames = frames;
+    Ok(result)
+}
+
+/// MJPEGデータの完全なフレームを順に`f`に渡す（復号リーダーなどシーク不可の入力用）
+///
+/// 戻り値の`frames`は空で、`valid_len`と`total_len`のみ設定される。
+pub fn for_each_frame<R, F>(reader: R, mut f: F) -> io::Result<ScanResult>
+where
+    R: Read,
+    F: FnMut(FrameSpan, &[u8]) -> io::Result<()>,
+{
+    let mut reader = BufReader::with_capacity(64 * 1024, reader);
+    let mut buf = [0u8; 64 * 1024];
+
+    let mut result = ScanResult::default();
+    let mut pos = 0u64;
+    let mut prev_ff = false;
+    let mut frame_start: Option<u64> = None;
+    let mut frame = Vec::new();
+
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        for &byte in &buf[..n] {
+            if frame_start.is_some() {
+                frame.push(byte);
+            }
+            if prev_ff {
+                match (frame_start, byte) {
+                    (None, 0xD8) => {
+                        frame_start = Some(pos - 1);
+                        frame.clear();
+                        frame.extend_from_slice(&[0xFF, 0xD8]);
+                    }
+                    (Some(start), 0xD9) => {
+                        let end = pos + 1;
+                        f(FrameSpan { offset: start, len: end - start }, &frame)?;
+                        result.valid_len = end;
+                        frame_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            prev_ff = byte == 0xFF;
+            pos += 1;
+        }
+    }
+
+    result.total_len = pos;
+    Ok(result)
+}
+
+/// MJPEGファイルのフレーム境界をスキャン
+pub fn scan_file(path: &Path) -> io::Result<ScanResult> {
+    scan_frames(File::open(path)?)
+}
+
+/// 途切れた末尾フレームを切り詰める
+///
+/// # Returns
+/// 切り詰め後のスキャン結果と、削除したバイト数
+pub fn truncate_torn_frames(path: &Path) -> io::Result<(ScanResult, u64)> {
+    let mut scan = scan_file(path)?;
+    let removed = scan.total_len - scan.valid_len;
+
+    if removed > 0 {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(scan.valid_len)?;
+        file.sync_all()?;
+        scan.total_len = scan.valid_len;
+    }
+
+    Ok((scan, removed))
+}
+
+/// 録画の平文ストリームの長さに収まらないインデックスの行を削除する（切り詰め後の録画用）
+///
+/// 解釈できない行（書き込み途中で途切れた行）も取り除く。
+///
+/// # Returns
+/// 残したフレーム数（インデックスがなければ`None`）
+pub fn truncate_index(recording: &Path, valid_len: u64) -> io::Result<Option<u64>> {
+    let path = index_path(recording);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let entries: Vec<IndexEntry> = read_index(&path)?
+        .into_iter()
+        .filter(|entry| entry.span.offset + entry.span.len <= valid_len)
+        .collect();
+
+    let mut index = BufWriter::new(File::create(&path)?);
+    for entry in &entries {
+        writeln!(index, "{} {} {}", entry.captured_at.timestamp_millis(), entry.span.offset, entry.span.len)?;
+    }
+    let index = index.into_inner().map_err(|e| e.into_error())?;
+    index.sync_all()?;
+    Ok(Some(entries.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_A: &[u8] = &[0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x01, 0xFF, 0xD9];
+    const FRAME_B: &[u8] = &[0xFF, 0xD8, 0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD9];
+
+    #[test]
+    fn test_scan_frames() {
+        let mut data = Vec::new();
+        data.extend_from_slice(FRAME_A);
+        data.extend_from_slice(FRAME_B);
+
+        let scan = scan_frames(&data[..]).unwrap();
+
+        assert_eq!(scan.frames.len(), 2);
+        assert_eq!(scan.frames[0], FrameSpan { offset: 0, len: 8 });
+        assert_eq!(scan.frames[1], FrameSpan { offset: 8, len: 8 });
+        assert_eq!(scan.valid_len, 16);
+        assert_eq!(scan.total_len, 16);
+    }
+
+    #[test]
+    fn test_scan_torn_tail() {
+        let mut data = Vec::new();
+        data.extend_from_slice(FRAME_A);
+        data.extend_from_slice(&FRAME_B[..5]); // 書き込み途中で停止
+
+        let scan = scan_frames(&data[..]).unwrap();
+
+        assert_eq!(scan.frames.len(), 1);
+        assert_eq!(scan.valid_len, 8);
+        assert_eq!(scan.total_len, 13);
+    }
+
+    #[test]
+    fn test_for_each_frame() {
+        let mut data = vec![0x00, 0x01]; // フレーム前のゴミ
+        data.extend_from_slice(FRAME_A);
+        data.extend_from_slice(FRAME_B);
+        data.extend_from_slice(&FRAME_A[..4]);
+
+        let mut frames = Vec::new();
+        let scan = for_each_frame(&data[..], |span, frame| {
+            frames.push((span, frame.to_vec()));
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], (FrameSpan { offset: 2, len: 8 }, FRAME_A.to_vec()));
+        assert_eq!(frames[1].1, FRAME_B);
+        assert_eq!((scan.valid_len, scan.total_len), (18, 22));
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mjpeg");
+        let t0 = Local.timestamp_millis_opt(1_767_000_000_000).unwrap();
+
+        let mut writer = MjpegWriter::create(&path).unwrap();
+        writer.write_frame_at(FRAME_A, t0).unwrap();
+        writer.write_frame_at(FRAME_B, t0 + chrono::Duration::milliseconds(91)).unwrap();
+        writer.finish().unwrap();
+
+        // 途切れた行は無視する
+        OpenOptions::new().append(true).open(index_path(&path)).unwrap().write_all(b"1767000000200 16").unwrap();
+
+        let index = read_index(&index_path(&path)).unwrap();
+        assert_eq!(index_path(&path), dir.path().join("test.mjpeg.idx"));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[0], IndexEntry { captured_at: t0, span: FrameSpan { offset: 0, len: 8 } });
+        assert_eq!(index[1].span, FrameSpan { offset: 8, len: 8 });
+        assert_eq!((index[1].captured_at - t0).num_milliseconds(), 91);
+    }
+
+    #[test]
+    fn test_encrypted_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mjpeg");
+        let key = KeySource::KeyFile([3; 32]);
+
+        let mut writer = MjpegWriter::create_with_key(&path, Some(&key)).unwrap();
+        writer.write_frame(FRAME_A).unwrap();
+        writer.sync().unwrap();
+        writer.write_frame(FRAME_B).unwrap();
+        drop(writer); // finish()せずに終了（クラッシュ相当）
+
+        let mut reader = crate::crypto::open_recording(&path, Some(&key)).unwrap();
+        let scan = scan_frames(&mut reader).unwrap();
+        assert_eq!(scan.frames.len(), 2);
+        assert!(reader.is_truncated());
+    }
+
+    #[test]
+    fn test_writer_and_truncate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mjpeg");
+
+        let mut writer = MjpegWriter::create(&path).unwrap();
+        writer.write_frame(FRAME_A).unwrap();
+        writer.sync().unwrap();
+        writer.write_frame(FRAME_B).unwrap();
+        writer.write_frame(&FRAME_A[..3]).unwrap();
+        drop(writer); // finish()せずに終了（クラッシュ相当）
+
+        let (scan, removed) = truncate_torn_frames(&path).unwrap();
+
+        assert_eq!(removed, 3);
+        assert_eq!(scan.frames.len(), 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 16);
+
+        // 2回目は何もしない
+        let (_, removed) = truncate_torn_frames(&path).unwrap();
+        assert_eq!(removed, 0);
+    }
+
+    #[test]
+    fn test_truncate_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mjpeg");
+        assert_eq!(truncate_index(&path, 0).unwrap(), None);
+
+        let mut writer = MjpegWriter::create(&path).unwrap();
+        writer.write_frame(FRAME_A).unwrap();
+        writer.write_frame(FRAME_B).unwrap();
+        writer.write_frame(&FRAME_A[..3]).unwrap();
+        writer.finish().unwrap();
+        OpenOptions::new().append(true).open(index_path(&path)).unwrap().write_all(b"1767000000200 19").unwrap();
+
+        // 途切れたフレームと途切れた行の分を削除
+        let (scan, _) = truncate_torn_frames(&path).unwrap();
+        assert_eq!(truncate_index(&path, scan.valid_len).unwrap(), Some(2));
+        let spans: Vec<FrameSpan> = read_index(&index_path(&path)).unwrap().iter().map(|entry| entry.span).collect();
+        assert_eq!(spans, scan.frames);
+        let text = std::fs::read_to_string(index_path(&path)).unwrap();
+        assert_eq!(text.lines().count(), 2);
+    }
+}
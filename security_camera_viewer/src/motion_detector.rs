@@ -1,13 +1,45 @@
-/// 動き検知モジュール
-///
-/// フレーム間差分法により、映像内の動きを検出する。
-/// グレースケール変換後、前フレームとの差分を計算し、
-/// 閾値を超えたピクセル数で動きを判定する。
-
-use image::{GrayImage, Luma, RgbaImage};
+//! 動き検知モジュール
+//!
+//! 縦横を`downscale`分の1に縮小した輝度で比較対象との差分を計算し、
+//! 閾値を超えたピクセル数で動きを判定する。比較対象は`MotionAlgorithm`で選ぶ:
+//! - フレーム間差分: 前フレームと比較する。ゆっくり動く物体は1フレームごとの差が小さく見逃しやすい
+//! - 背景差分: 画素ごとの輝度の移動平均（背景モデル）と比較する。
+//!   ゆっくりした動きも背景との差として積み上がり、センサーノイズやちらつきは平均で均される。
+//!   止まった物体や照明の変化は学習率に応じて数秒〜数十秒で背景に取り込まれる
+//!
+//! 除外マスク（揺れる木やテレビなど）の中の変化は数えない。
+//! ゾーンを設定した場合は、ゾーンごとの感度・最小動き領域で判定し、
+//! いずれかのゾーンで動きがあれば動きありとする（ゾーン外の変化は数えない）。
+//! ゾーンとマスクは画像の幅・高さに対する0.0-1.0の座標の多角形で指定する。
+//!
+//! 検知結果（`MotionResult`）には、変化したピクセルのマスクと、
+//! マスクを3x3のオープニング（収縮→膨張）で孤立ノイズを除いてから
+//! 8近傍で連結した動きのかたまり（外接矩形・重心・面積）が入る。
+//!
+//! 照明の点灯・消灯や雲、自動露出で画面の大部分が一様に明るさを変えた場合は、
+//! 比較対象との明るさの比（画素ごとの比の中央値）で補正してから判定し直す。
+//! 補正で動きが消えれば「照明の変化」（`SceneChange::Illumination`）として動きには数えない。
+//!
+//! 縮小した輝度は、デコード済みのRGBAからは整数演算のブロック平均で、
+//! JPEGからはDCTの縮小デコード（1/8ではDC成分のみ）で直接作る。
+//! 縮小率はDCT縮小に合わせて1, 2, 4, 8のいずれかに切り下げ、どちらの経路でも同じ大きさになる。
+//! 動き割合・ゾーン・マスクは画像に対する割合なので縮小しても意味はほぼ変わらないが、
+//! ノイズ除去（3x3のオープニング）は縮小後の画素で行うため、縮小すると小さなかたまりも残りやすい。
+//! 既定は縮小しない（1）。検知結果のマスクとかたまりの座標は縮小後の画素で表す。
+//! 輝度・差分のバッファはフレームごとに使い回す。
+
+use crate::frame_analyzer::AnalyzerSetting;
+use crate::motion_event::MotionEventConfig;
+use crate::crossing_counter::CountingConfig;
+use crate::object_detector::ObjectDetectionConfig;
+use crate::tamper_detector::TamperConfig;
+use image::{GrayImage, ImageBuffer, Luma, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
 
 /// 動き検知設定
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MotionDetectionConfig {
     /// 動き検知ON/OFF
     pub enabled: bool,
@@ -21,6 +53,50 @@ pub struct MotionDetectionConfig {
     pub pre_record_seconds: u32,
     /// ポスト録画秒数（30秒）
     pub post_record_seconds: u32,
+    /// 動き検知ゾーン（空なら画面全体）
+    pub zones: Vec<MotionZone>,
+    /// 除外マスク
+    pub exclusions: Vec<ExclusionMask>,
+    /// 比較対象（フレーム間差分 / 背景差分）
+    pub algorithm: MotionAlgorithm,
+    /// 背景差分の学習率 (0.0-1.0, デフォルト0.02)
+    /// 1フレームごとに背景を現在のフレームへ近づける割合（11fpsで約5秒かけて馴染む）
+    pub background_learning_rate: f32,
+    /// 画面全体の明るさの変化を補正し、照明の変化を動きとみなさない
+    pub suppress_illumination: bool,
+    /// 動きイベントの開始・終了条件（`MotionEventTracker`）
+    pub events: MotionEventConfig,
+    /// 解析解像度の縮小率（1, 2, 4, 8, デフォルト2）
+    /// 縦横をこの分の1にした輝度で判定する（1で元の解像度）。ほかの値は`effective_downscale`で切り下げる
+    pub downscale: u32,
+    /// いたずら検知（覆い隠し・ピンぼけ・向きの変更）の設定（`TamperDetector`）
+    pub tamper: TamperConfig,
+    /// 動きのあったフレームの物体検知（人・車など）の設定（`ObjectDetector`）
+    pub objects: ObjectDetectionConfig,
+    /// 仮想ラインの横断・ゾーンへの進入のカウントの設定（`CrossingCounter`）
+    pub counting: CountingConfig,
+    /// 解析器（動き検知・いたずら検知など）の実行順と有効/無効（空なら登録順にすべて実行）
+    pub analyzers: Vec<AnalyzerSetting>,
+}
+
+/// 動き検知のアルゴリズム
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionAlgorithm {
+    /// 前フレームとの差分
+    #[default]
+    FrameDiff,
+    /// 背景モデル（輝度の移動平均）との差分
+    Background,
+}
+
+impl std::fmt::Display for MotionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MotionAlgorithm::FrameDiff => "frame diff",
+            MotionAlgorithm::Background => "background",
+        })
+    }
 }
 
 impl Default for MotionDetectionConfig {
@@ -31,20 +107,437 @@ impl Default for MotionDetectionConfig {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            zones: Vec::new(),
+            exclusions: Vec::new(),
+            algorithm: MotionAlgorithm::FrameDiff,
+            background_learning_rate: 0.02,
+            suppress_illumination: true,
+            events: MotionEventConfig::default(),
+            downscale: 2,
+            tamper: TamperConfig::default(),
+            objects: ObjectDetectionConfig::default(),
+            counting: CountingConfig::default(),
+            analyzers: Vec::new(),
+        }
+    }
+}
+
+/// 動き検知ゾーン
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotionZone {
+    /// ゾーン名（検知結果・ログに表示）
+    pub name: String,
+    /// 多角形の頂点（画像の幅・高さに対する0.0-1.0の座標）
+    pub points: Vec<[f32; 2]>,
+    /// 感度 (0.0-1.0、`MotionDetectionConfig::sensitivity`と同じ)
+    pub sensitivity: f32,
+    /// 最小動き領域 (%、ゾーンの面積に対する割合)
+    pub min_motion_area: f32,
+}
+
+/// 除外マスク（この中の変化は数えない）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExclusionMask {
+    pub name: String,
+    /// 多角形の頂点（画像の幅・高さに対する0.0-1.0の座標）
+    pub points: Vec<[f32; 2]>,
+}
+
+/// ゾーンごとの検知結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneMotion {
+    pub name: String,
+    /// ゾーン内の動き割合（%）
+    pub motion_ratio: f32,
+    pub motion_detected: bool,
+}
+
+/// 外接矩形（画素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 動きのかたまり（マスクの連結成分）
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionBlob {
+    pub bbox: BoundingBox,
+    /// 重心（画素）
+    pub centroid: (f32, f32),
+    /// 面積（画素数）
+    pub area: u32,
+}
+
+/// 画面のこの割合（%）以上が変化したら、照明の変化を疑って明るさを補正する
+const GLOBAL_CHANGE_PERCENT: f32 = 60.0;
+
+/// フレームの変化の分類
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SceneChange {
+    /// 変化なし（比較対象がないフレームを含む）
+    #[default]
+    None,
+    /// 動き
+    Motion,
+    /// 画面全体の明るさの変化（照明・雲・自動露出）で、補正すると動きが残らない
+    Illumination,
+}
+
+/// 1フレームの検知結果
+#[derive(Debug, Clone, Default)]
+pub struct MotionResult {
+    pub motion_detected: bool,
+    /// 変化の分類（照明の変化は`motion_detected`にならない）
+    pub change: SceneChange,
+    /// 明るさを補正した場合の、比較対象に対する明るさの比
+    pub brightness_gain: Option<f32>,
+    /// 動き割合（%、マスク外の画面全体に対する変化ピクセルの割合、ノイズ除去前）
+    pub motion_ratio: f32,
+    /// 変化したピクセルのマスク（255=変化、ノイズ除去後）
+    /// 比較対象がないフレームや検知が無効のときは空（0x0）
+    pub mask: GrayImage,
+    /// 動きのかたまり（面積の大きい順）
+    pub blobs: Vec<MotionBlob>,
+}
+
+/// 点(x, y)が多角形の内側にあるか（偶奇規則、3頂点未満は常にfalse）
+pub fn polygon_contains(points: &[[f32; 2]], x: f32, y: f32) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let ([xi, yi], [xj, yj]) = (points[i], points[j]);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// ゾーンとマスクを画素に展開したもの（画像サイズごとに作り直す）
+struct ZoneRaster {
+    width: u32,
+    height: u32,
+    /// マスク外の画素のインデックス
+    active: Vec<u32>,
+    /// ゾーンごとの、ゾーン内かつマスク外の画素のインデックス
+    zones: Vec<Vec<u32>>,
+}
+
+impl ZoneRaster {
+    fn build(config: &MotionDetectionConfig, width: u32, height: u32) -> Self {
+        let mut active = Vec::new();
+        let mut zones = vec![Vec::new(); config.zones.len()];
+        for y in 0..height {
+            for x in 0..width {
+                // 画素の中心で判定する
+                let (px, py) = ((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+                if config.exclusions.iter().any(|mask| polygon_contains(&mask.points, px, py)) {
+                    continue;
+                }
+                let index = y * width + x;
+                active.push(index);
+                for (zone, pixels) in config.zones.iter().zip(&mut zones) {
+                    if polygon_contains(&zone.points, px, py) {
+                        pixels.push(index);
+                    }
+                }
+            }
+        }
+        Self { width, height, active, zones }
+    }
+}
+
+/// フレームごとに使い回す作業用バッファ
+#[derive(Default)]
+struct Scratch {
+    /// 明るさを補正した差分
+    compensated: Vec<u8>,
+    /// オープニングの収縮後のマスク
+    eroded: Vec<u8>,
+    /// 連結成分の探索済みの画素
+    visited: Vec<bool>,
+    /// 連結成分の探索待ちの画素
+    stack: Vec<usize>,
+    /// 画素ごとの明るさの比（固定小数点）
+    ratios: Vec<u32>,
+}
+
+/// 幅`width`のマスクの3x3の収縮（`erode`）または膨張を`output`に書く（画像外の画素は数えない）
+fn morphology(mask: &[u8], width: usize, erode: bool, output: &mut Vec<u8>) {
+    let height = mask.len() / width.max(1);
+    output.clear();
+    output.resize(mask.len(), 0);
+    for y in 0..height {
+        let rows = y.saturating_sub(1)..(y + 2).min(height);
+        for x in 0..width {
+            let columns = x.saturating_sub(1)..(x + 2).min(width);
+            let mut neighbors = rows.clone().flat_map(|ny| &mask[ny * width + columns.start..ny * width + columns.end]);
+            let on = if erode { neighbors.all(|&value| value > 0) } else { neighbors.any(|&value| value > 0) };
+            output[y * width + x] = if on { 255 } else { 0 };
+        }
+    }
+}
+
+/// マスクの8近傍の連結成分を面積の大きい順に返す
+fn find_blobs(mask: &GrayImage, scratch: &mut Scratch) -> Vec<MotionBlob> {
+    let (width, height) = mask.dimensions();
+    let raw = mask.as_raw();
+    let Scratch { visited, stack, .. } = scratch;
+    visited.clear();
+    visited.resize(raw.len(), false);
+    stack.clear();
+    let mut blobs = Vec::new();
+
+    for start in 0..raw.len() {
+        if raw[start] == 0 || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+        let (mut sum_x, mut sum_y, mut area) = (0u64, 0u64, 0u32);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            sum_x += x as u64;
+            sum_y += y as u64;
+            area += 1;
+
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let j = (ny * width + nx) as usize;
+                    if raw[j] > 0 && !visited[j] {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+
+        blobs.push(MotionBlob {
+            bbox: BoundingBox { x: min_x, y: min_y, width: max_x - min_x + 1, height: max_y - min_y + 1 },
+            centroid: (sum_x as f32 / area as f32, sum_y as f32 / area as f32),
+            area,
+        });
+    }
+
+    blobs.sort_by_key(|blob| std::cmp::Reverse(blob.area));
+    blobs
+}
+
+/// 背景モデル（画素ごとの輝度の指数移動平均）
+struct BackgroundModel {
+    width: u32,
+    height: u32,
+    mean: Vec<f32>,
+}
+
+impl BackgroundModel {
+    /// 最初のフレームを背景として作成
+    fn new(gray: &GrayImage) -> Self {
+        Self {
+            width: gray.width(),
+            height: gray.height(),
+            mean: gray.as_raw().iter().map(|&v| v as f32).collect(),
+        }
+    }
+
+    /// 背景を`learning_rate`だけ現在のフレームへ近づける
+    fn update(&mut self, gray: &GrayImage, learning_rate: f32) {
+        let rate = learning_rate.clamp(0.0, 1.0);
+        for (mean, &value) in self.mean.iter_mut().zip(gray.as_raw()) {
+            *mean += (value as f32 - *mean) * rate;
+        }
+    }
+}
+
+/// 比較対象の輝度
+#[derive(Clone, Copy)]
+enum Reference<'a> {
+    /// 前フレーム
+    Frame(&'a [u8]),
+    /// 背景モデル
+    Background(&'a [f32]),
+}
+
+impl Reference<'_> {
+    fn value(&self, i: usize) -> f32 {
+        match self {
+            Reference::Frame(frame) => frame[i] as f32,
+            Reference::Background(mean) => mean[i],
+        }
+    }
+
+    /// 整数の輝度（背景モデルは四捨五入）
+    fn level(&self, i: usize) -> u32 {
+        match self {
+            Reference::Frame(frame) => frame[i] as u32,
+            Reference::Background(mean) => mean[i].round() as u32,
         }
     }
 }
 
-/// フレーム差分による動き検知器
+/// 明るさの比の固定小数点の小数部のビット数
+const GAIN_BITS: u32 = 16;
+
+/// 比較対象の輝度を`gain`倍した値と現在のフレームとの差の絶対値（`buffer`を使い回す）
+///
+/// 明るさの比は0付近の割り算を避けるため輝度+1で扱う（`gain`=1.0なら単純な差）。
+fn difference_from(reference: Reference, current: &GrayImage, gain: f32, mut buffer: Vec<u8>) -> GrayImage {
+    let raw = current.as_raw();
+    buffer.clear();
+    match reference {
+        // 前フレームとの単純な差は整数で求める
+        Reference::Frame(frame) if gain == 1.0 => {
+            buffer.extend(frame.iter().zip(raw).map(|(&previous, &value)| previous.abs_diff(value)));
+        }
+        _ => buffer.extend(raw.iter().enumerate().map(|(i, &value)| {
+            let expected = (reference.value(i) + 1.0) * gain - 1.0;
+            (value as f32 - expected).abs().round().min(255.0) as u8
+        })),
+    }
+    GrayImage::from_raw(current.width(), current.height(), buffer).expect("reference matches the frame size")
+}
+
+/// 比較対象に対する現在のフレームの明るさの比（画素ごとの比の中央値）
+///
+/// `pixels`を指定した場合はその画素（マスク外）だけで求める。
+/// 比は`GAIN_BITS`ビットの固定小数点の整数で`ratios`に並べて中央値を選ぶ。
+fn brightness_gain(reference: Reference, current: &GrayImage, pixels: Option<&[u32]>, ratios: &mut Vec<u32>) -> f32 {
+    let raw = current.as_raw();
+    let ratio = |i: usize| ((raw[i] as u32 + 1) << GAIN_BITS) / (reference.level(i) + 1);
+    ratios.clear();
+    match pixels {
+        Some(pixels) => ratios.extend(pixels.iter().map(|&i| ratio(i as usize))),
+        None => ratios.extend((0..raw.len()).map(ratio)),
+    }
+    if ratios.is_empty() {
+        return 1.0;
+    }
+    let middle = ratios.len() / 2;
+    *ratios.select_nth_unstable(middle).1 as f32 / (1u32 << GAIN_BITS) as f32
+}
+
+/// 画素の一覧のうち、差分が閾値を超えた画素の割合（%）
+fn changed_ratio_in(diff: &GrayImage, pixels: &[u32], threshold: u8) -> f32 {
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    let raw = diff.as_raw();
+    let changed = pixels.iter().filter(|&&i| raw[i as usize] > threshold).count();
+    (changed as f32) / (pixels.len() as f32) * 100.0
+}
+
+/// ITU-R BT.601の輝度（係数の合計が65536の整数演算）
+fn luma(r: u8, g: u8, b: u8) -> u32 {
+    (19595 * r as u32 + 38470 * g as u32 + 7471 * b as u32) >> 16
+}
+
+/// 実際に使う縮小率（JPEGのDCT縮小でできる1, 2, 4, 8のうち、`scale`以下で最大のもの）
+pub fn effective_downscale(scale: u32) -> u32 {
+    1 << scale.clamp(1, 8).ilog2()
+}
+
+/// RGBAを縦横`scale`分の1に縮小した輝度（`scale`x`scale`画素の平均、端の半端なブロックはある画素だけ）
+///
+/// `scale`は`effective_downscale`で切り下げるので、大きさは`luma_from_jpeg`と同じになる。
+pub fn luma_from_rgba<C: Deref<Target = [u8]>>(rgba: &ImageBuffer<Rgba<u8>, C>, scale: u32, mut buffer: Vec<u8>) -> GrayImage {
+    let (width, height) = rgba.dimensions();
+    let scale = effective_downscale(scale);
+    let (out_width, out_height) = (width.div_ceil(scale), height.div_ceil(scale));
+    let raw = rgba.as_raw();
+    buffer.clear();
+
+    if scale == 1 {
+        buffer.extend(raw.chunks_exact(4).map(|pixel| luma(pixel[0], pixel[1], pixel[2]) as u8));
+    } else {
+        let mut sums = vec![0u32; out_width as usize];
+        for block_y in 0..out_height {
+            sums.fill(0);
+            let rows = block_y * scale..((block_y + 1) * scale).min(height);
+            let block_height = rows.len() as u32;
+            for y in rows {
+                let row = &raw[(y * width * 4) as usize..((y + 1) * width * 4) as usize];
+                for (x, pixel) in row.chunks_exact(4).enumerate() {
+                    sums[x / scale as usize] += luma(pixel[0], pixel[1], pixel[2]);
+                }
+            }
+            buffer.extend(sums.iter().enumerate().map(|(block_x, &sum)| {
+                let block_width = scale.min(width - block_x as u32 * scale);
+                (sum / (block_width * block_height)) as u8
+            }));
+        }
+    }
+    GrayImage::from_raw(out_width, out_height, buffer).expect("buffer matches the scaled size")
+}
+
+/// JPEGを縦横`scale`分の1（`effective_downscale`で切り下げた縮小率）でデコードした輝度
+///
+/// 縮小はDCTの段階で行うため、元の解像度へのデコードと色変換を省ける。
+pub fn luma_from_jpeg(jpeg: &[u8], scale: u32, mut buffer: Vec<u8>) -> Result<GrayImage, jpeg_decoder::Error> {
+    let mut decoder = jpeg_decoder::Decoder::new(jpeg);
+    decoder.read_info()?;
+    let info = decoder.info().expect("header was read");
+    let scale = effective_downscale(scale) as u16;
+    let (width, height) = decoder.scale(info.width.div_ceil(scale), info.height.div_ceil(scale))?;
+    let pixels = decoder.decode()?;
+
+    buffer.clear();
+    match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => buffer.extend_from_slice(&pixels),
+        jpeg_decoder::PixelFormat::RGB24 => {
+            buffer.extend(pixels.chunks_exact(3).map(|pixel| luma(pixel[0], pixel[1], pixel[2]) as u8));
+        }
+        format => return Err(jpeg_decoder::Error::Format(format!("unsupported pixel format {:?}", format))),
+    }
+    GrayImage::from_raw(width as u32, height as u32, buffer)
+        .ok_or_else(|| jpeg_decoder::Error::Format("decoded size mismatch".to_string()))
+}
+
+/// フレーム差分・背景差分による動き検知器
 pub struct MotionDetector {
     /// 前フレーム（グレースケール）
     previous_frame: Option<GrayImage>,
+    /// 背景モデル（背景差分のときだけ使う）
+    background: Option<BackgroundModel>,
     /// 設定
     config: MotionDetectionConfig,
     /// 統計: 総フレーム数
     total_frames: u64,
     /// 統計: 動き検知回数
     motion_detected_count: u64,
+    /// 統計: 照明の変化と判定した回数
+    illumination_count: u64,
+    /// 直近フレームの動き割合（%）
+    last_motion_ratio: f32,
+    /// 直近フレームのゾーンごとの結果
+    zone_results: Vec<ZoneMotion>,
+    /// ゾーン・マスクの画素（ゾーンもマスクもない場合は使わない）
+    zone_raster: Option<ZoneRaster>,
+    /// 次のフレームの輝度に使い回すバッファ（2つ前のフレーム）
+    spare_frame: Vec<u8>,
+    /// 差分に使い回すバッファ
+    diff_buffer: Vec<u8>,
+    /// 補正・ノイズ除去・連結成分に使い回すバッファ
+    scratch: Scratch,
+}
+
+impl Default for MotionDetector {
+    /// デフォルト設定で作成
+    fn default() -> Self {
+        Self::new(MotionDetectionConfig::default())
+    }
 }
 
 impl MotionDetector {
@@ -52,64 +545,170 @@ impl MotionDetector {
     pub fn new(config: MotionDetectionConfig) -> Self {
         Self {
             previous_frame: None,
+            background: None,
             config,
             total_frames: 0,
             motion_detected_count: 0,
+            illumination_count: 0,
+            last_motion_ratio: 0.0,
+            zone_results: Vec::new(),
+            zone_raster: None,
+            spare_frame: Vec::new(),
+            diff_buffer: Vec::new(),
+            scratch: Scratch::default(),
         }
     }
 
-    /// デフォルト設定で作成
-    pub fn default() -> Self {
-        Self::new(MotionDetectionConfig::default())
-    }
-
     /// 動き検知を実行
     ///
     /// # Arguments
     /// * `current_frame` - 現在のフレーム（RGBA）
     ///
     /// # Returns
-    /// 動きが検知された場合true
-    pub fn detect(&mut self, current_frame: &RgbaImage) -> bool {
-        self.total_frames += 1;
+    /// 検知結果（動きがあれば`motion_detected`がtrue）
+    pub fn detect(&mut self, current_frame: &RgbaImage) -> MotionResult {
+        if !self.config.enabled {
+            self.total_frames += 1;
+            return MotionResult::default();
+        }
+
+        // 1. 縮小した輝度に変換
+        let gray = luma_from_rgba(current_frame, self.config.downscale, std::mem::take(&mut self.spare_frame));
+        self.detect_luma(gray)
+    }
 
+    /// JPEGのまま動き検知を実行（縮小デコードで輝度だけを取り出す）
+    ///
+    /// デコードできないフレームはフレーム数に数えない。
+    pub fn detect_jpeg(&mut self, jpeg: &[u8]) -> Result<MotionResult, jpeg_decoder::Error> {
         if !self.config.enabled {
-            return false;
+            self.total_frames += 1;
+            return Ok(MotionResult::default());
         }
 
-        // 1. グレースケール変換
-        let gray = Self::rgba_to_gray(current_frame);
+        // 1. 縮小デコードした輝度
+        let gray = luma_from_jpeg(jpeg, self.config.downscale, std::mem::take(&mut self.spare_frame))?;
+        Ok(self.detect_luma(gray))
+    }
 
-        if let Some(prev) = &self.previous_frame {
-            // 2. フレーム差分計算
-            let diff = Self::compute_difference(prev, &gray);
+    /// 縮小済みの輝度で動き検知
+    fn detect_luma(&mut self, gray: GrayImage) -> MotionResult {
+        self.total_frames += 1;
 
-            // 3. 閾値処理
-            let threshold = self.compute_threshold();
-            let changed_pixels = Self::count_changed_pixels(&diff, threshold);
+        // 2. 比較対象（前フレームまたは背景）。判定中は取り出しておく（なければ作るだけ）
+        let previous = self.previous_frame.take().filter(|prev| prev.dimensions() == gray.dimensions());
+        let background = match self.config.algorithm {
+            MotionAlgorithm::FrameDiff => {
+                self.background = None;
+                None
+            }
+            MotionAlgorithm::Background => match self.background.take() {
+                Some(model) if (model.width, model.height) == gray.dimensions() => Some(model),
+                _ => {
+                    self.background = Some(BackgroundModel::new(&gray));
+                    self.store_frame(gray, previous);
+                    return MotionResult::default();
+                }
+            },
+        };
+        let reference = match (&background, &previous) {
+            (Some(model), _) => Reference::Background(&model.mean),
+            (None, Some(prev)) => Reference::Frame(prev.as_raw()),
+            // 初回フレーム（比較対象なし）
+            (None, None) => {
+                self.store_frame(gray, previous);
+                return MotionResult::default();
+            }
+        };
+
+        // 3-4. 差分計算、閾値処理、動き判定
+        let threshold = self.compute_threshold();
+        let diff = difference_from(reference, &gray, 1.0, std::mem::take(&mut self.diff_buffer));
+        let (mut motion_ratio, mut motion_detected, mut mask) = self.threshold_difference(&diff, threshold);
+
+        // 画面の大部分が変化したら、明るさの比を補正して判定し直す
+        let mut change = if motion_detected { SceneChange::Motion } else { SceneChange::None };
+        let mut gain = None;
+        if self.config.suppress_illumination && motion_ratio >= GLOBAL_CHANGE_PERCENT {
+            let active = self.zone_raster.as_ref().map(|raster| raster.active.as_slice());
+            let brightness = brightness_gain(reference, &gray, active, &mut self.scratch.ratios);
+            let compensated = difference_from(reference, &gray, brightness, std::mem::take(&mut self.scratch.compensated));
+            let (ratio, detected, compensated_mask) = self.threshold_difference(&compensated, threshold);
+            if ratio < motion_ratio {
+                if motion_detected && !detected {
+                    change = SceneChange::Illumination;
+                    self.illumination_count += 1;
+                }
+                (motion_ratio, motion_detected, mask) = (ratio, detected, compensated_mask);
+                gain = Some(brightness);
+            } else {
+                // 補正しても減らない変化は照明ではない（ゾーンの結果を元に戻す）
+                self.threshold_difference(&diff, threshold);
+            }
+            self.scratch.compensated = compensated.into_raw();
+        }
+        self.diff_buffer = diff.into_raw();
 
-            // 4. 動き判定
-            let total_pixels = gray.width() * gray.height();
-            let motion_ratio = (changed_pixels as f32) / (total_pixels as f32) * 100.0;
+        // 背景の学習は判定の後（このフレームの変化を背景に含めない）
+        if let Some(mut model) = background {
+            model.update(&gray, self.config.background_learning_rate);
+            self.background = Some(model);
+        }
+        self.store_frame(gray, previous);
+        self.last_motion_ratio = motion_ratio;
 
-            self.previous_frame = Some(gray);
+        if motion_detected {
+            self.motion_detected_count += 1;
+        }
 
-            let motion_detected = motion_ratio >= self.config.min_motion_area;
+        // 5. ノイズ除去（オープニング）と連結成分。膨張はマスクのバッファに書き戻す
+        let (width, height) = mask.dimensions();
+        let mut opened = mask.into_raw();
+        morphology(&opened, width as usize, true, &mut self.scratch.eroded);
+        morphology(&self.scratch.eroded, width as usize, false, &mut opened);
+        let mask = GrayImage::from_raw(width, height, opened).expect("mask matches the frame size");
+        let blobs = find_blobs(&mask, &mut self.scratch);
 
-            if motion_detected {
-                self.motion_detected_count += 1;
-            }
+        MotionResult { motion_detected, change, brightness_gain: gain, motion_ratio, mask, blobs }
+    }
 
-            motion_detected
+    /// 現在のフレームを次の比較対象にし、前フレームのバッファを次の輝度に回す
+    fn store_frame(&mut self, gray: GrayImage, previous: Option<GrayImage>) {
+        if let Some(previous) = previous {
+            self.spare_frame = previous.into_raw();
+        }
+        self.previous_frame = Some(gray);
+    }
+
+    /// 差分を閾値処理し、(動き割合, 動きあり, 変化ピクセルのマスク) を返す
+    fn threshold_difference(&mut self, diff: &GrayImage, threshold: u8) -> (f32, bool, GrayImage) {
+        if self.config.zones.is_empty() && self.config.exclusions.is_empty() {
+            let total_pixels = diff.width() * diff.height();
+            let motion_ratio = (Self::count_changed_pixels(diff, threshold) as f32) / (total_pixels as f32) * 100.0;
+            let mask = diff.as_raw().iter().map(|&value| if value > threshold { 255 } else { 0 }).collect();
+            let mask = GrayImage::from_raw(diff.width(), diff.height(), mask).expect("mask matches the frame size");
+            (motion_ratio, motion_ratio >= self.config.min_motion_area, mask)
         } else {
-            // 初回フレーム（比較対象なし）
-            self.previous_frame = Some(gray);
-            false
+            self.detect_in_zones(diff, threshold)
         }
     }
 
+    /// 直近フレームの動き割合（%、マスク外の画面全体に対する変化ピクセルの割合）
+    pub fn last_motion_ratio(&self) -> f32 {
+        self.last_motion_ratio
+    }
+
+    /// 直近フレームのゾーンごとの結果（ゾーンがない場合は空）
+    pub fn zone_results(&self) -> &[ZoneMotion] {
+        &self.zone_results
+    }
+
     /// 設定を更新
     pub fn update_config(&mut self, config: MotionDetectionConfig) {
+        if config.zones != self.config.zones || config.exclusions != self.config.exclusions {
+            self.zone_raster = None;
+            self.zone_results.clear();
+        }
         self.config = config;
     }
 
@@ -123,6 +722,7 @@ impl MotionDetector {
         MotionDetectorStats {
             total_frames: self.total_frames,
             motion_detected_count: self.motion_detected_count,
+            illumination_count: self.illumination_count,
             detection_rate: if self.total_frames > 0 {
                 (self.motion_detected_count as f32) / (self.total_frames as f32) * 100.0
             } else {
@@ -135,11 +735,15 @@ impl MotionDetector {
     pub fn reset_stats(&mut self) {
         self.total_frames = 0;
         self.motion_detected_count = 0;
+        self.illumination_count = 0;
     }
 
-    /// 前フレームをクリア（状態リセット）
+    /// 前フレーム・背景モデルをクリア（状態リセット）
     pub fn reset(&mut self) {
         self.previous_frame = None;
+        self.background = None;
+        self.last_motion_ratio = 0.0;
+        self.zone_results.clear();
         self.reset_stats();
     }
 
@@ -150,24 +754,67 @@ impl MotionDetector {
     /// sensitivity=0.5 → threshold=30 (中感度)
     /// sensitivity=1.0 → threshold=100 (低感度)
     fn compute_threshold(&self) -> u8 {
+        Self::sensitivity_threshold(self.config.sensitivity)
+    }
+
+    fn sensitivity_threshold(sensitivity: f32) -> u8 {
         let min_threshold = 5;
         let max_threshold = 100;
         let range = max_threshold - min_threshold;
 
-        (min_threshold + (self.config.sensitivity * range as f32) as u8).min(max_threshold)
+        (min_threshold + (sensitivity * range as f32) as u8).min(max_threshold)
     }
 
-    /// RGBA → グレースケール変換
+    /// マスク外・ゾーンごとに動きを判定し、(マスク外全体の動き割合, 動きあり, 変化ピクセルのマスク) を返す
     ///
-    /// 輝度計算: Y = 0.299*R + 0.587*G + 0.114*B (ITU-R BT.601)
-    fn rgba_to_gray(rgba: &RgbaImage) -> GrayImage {
-        GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
-            let pixel = rgba.get_pixel(x, y);
-            let gray = (0.299 * pixel[0] as f32 +
-                       0.587 * pixel[1] as f32 +
-                       0.114 * pixel[2] as f32) as u8;
-            Luma([gray])
-        })
+    /// ゾーンがある場合、マスクにはゾーン内でそのゾーンの閾値を超えたピクセルだけが入る。
+    fn detect_in_zones(&mut self, diff: &GrayImage, threshold: u8) -> (f32, bool, GrayImage) {
+        let (width, height) = diff.dimensions();
+        if self.zone_raster.as_ref().is_some_and(|raster| (raster.width, raster.height) != (width, height)) {
+            self.zone_raster = None;
+        }
+        let raster = self.zone_raster.get_or_insert_with(|| ZoneRaster::build(&self.config, width, height));
+
+        let motion_ratio = changed_ratio_in(diff, &raster.active, threshold);
+
+        self.zone_results = self.config.zones.iter().zip(&raster.zones).map(|(zone, pixels)| {
+            let ratio = changed_ratio_in(diff, pixels, Self::sensitivity_threshold(zone.sensitivity));
+            ZoneMotion {
+                name: zone.name.clone(),
+                motion_ratio: ratio,
+                motion_detected: !pixels.is_empty() && ratio >= zone.min_motion_area,
+            }
+        }).collect();
+
+        let mut mask = GrayImage::new(width, height);
+        let mask_raw: &mut [u8] = &mut mask;
+        let mut mark = |pixels: &[u32], threshold: u8| {
+            for &i in pixels {
+                if diff.as_raw()[i as usize] > threshold {
+                    mask_raw[i as usize] = 255;
+                }
+            }
+        };
+        let motion_detected = if self.config.zones.is_empty() {
+            mark(&raster.active, threshold);
+            motion_ratio >= self.config.min_motion_area
+        } else {
+            for (zone, pixels) in self.config.zones.iter().zip(&raster.zones) {
+                mark(pixels, Self::sensitivity_threshold(zone.sensitivity));
+            }
+            self.zone_results.iter().any(|zone| zone.motion_detected)
+        };
+        (motion_ratio, motion_detected, mask)
+    }
+
+    /// 2フレーム間で輝度差が`threshold`を超えたピクセルの割合（%）
+    ///
+    /// 2つの画像は同じサイズであること。
+    pub fn changed_ratio(prev: &GrayImage, current: &GrayImage, threshold: u8) -> f32 {
+        let diff = Self::compute_difference(prev, current);
+        let changed_pixels = Self::count_changed_pixels(&diff, threshold);
+        let total_pixels = current.width() * current.height();
+        (changed_pixels as f32) / (total_pixels as f32) * 100.0
     }
 
     /// フレーム差分計算
@@ -177,7 +824,7 @@ impl MotionDetector {
         GrayImage::from_fn(prev.width(), prev.height(), |x, y| {
             let prev_val = prev.get_pixel(x, y)[0] as i16;
             let curr_val = current.get_pixel(x, y)[0] as i16;
-            let diff = (prev_val - curr_val).abs() as u8;
+            let diff = (prev_val - curr_val).unsigned_abs() as u8;
             Luma([diff])
         })
     }
@@ -197,6 +844,8 @@ pub struct MotionDetectorStats {
     pub total_frames: u64,
     /// 動き検知回数
     pub motion_detected_count: u64,
+    /// 照明の変化と判定した回数（動き検知回数には含まない）
+    pub illumination_count: u64,
     /// 検知率（%）
     pub detection_rate: f32,
 }
@@ -204,7 +853,16 @@ pub struct MotionDetectorStats {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::Rgba;
+
+    /// 元の解像度で判定する設定（画素単位の期待値を使うテスト用）
+    fn full() -> MotionDetectionConfig {
+        MotionDetectionConfig { downscale: 1, ..MotionDetectionConfig::default() }
+    }
+
+    /// 1/8に縮小して判定する設定
+    fn eighth() -> MotionDetectionConfig {
+        MotionDetectionConfig { downscale: 8, ..MotionDetectionConfig::default() }
+    }
 
     fn create_solid_color_image(width: u32, height: u32, color: Rgba<u8>) -> RgbaImage {
         RgbaImage::from_fn(width, height, |_, _| color)
@@ -223,7 +881,7 @@ mod tests {
     #[test]
     fn test_rgba_to_gray() {
         let rgba = create_solid_color_image(10, 10, Rgba([128, 128, 128, 255]));
-        let gray = MotionDetector::rgba_to_gray(&rgba);
+        let gray = luma_from_rgba(&rgba, 1, Vec::new());
 
         // 128の輝度は約128になるはず
         assert_eq!(gray.width(), 10);
@@ -231,6 +889,157 @@ mod tests {
         assert_eq!(gray.get_pixel(0, 0)[0], 128);
     }
 
+    #[test]
+    fn test_luma_from_rgba_downscales() {
+        // 左5列が200、残りが100の10x10 → 8x8ブロックで2x2（端のブロックは2x2・2x8画素）
+        let rgba = RgbaImage::from_fn(10, 10, |x, _| if x < 5 { Rgba([200, 200, 200, 255]) } else { Rgba([100, 100, 100, 255]) });
+        let gray = luma_from_rgba(&rgba, 8, Vec::new());
+        assert_eq!(gray.dimensions(), (2, 2));
+        assert_eq!(gray.as_raw(), &vec![162, 100, 162, 100]);
+    }
+
+    /// VGAの灰色の画面、`object`なら(320, 240)から80x80の明るい正方形
+    fn vga_frame(object: bool) -> RgbaImage {
+        RgbaImage::from_fn(640, 480, |x, y| {
+            if object && (320..400).contains(&x) && (240..320).contains(&y) {
+                Rgba([220, 220, 220, 255])
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        })
+    }
+
+    fn encode_jpeg(image: &RgbaImage) -> Vec<u8> {
+        let rgb = image::DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90).encode_image(&rgb).unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn test_downscaled_detection_matches_full_resolution() {
+        let mut full = MotionDetector::new(MotionDetectionConfig { enabled: true, ..full() });
+        let mut scaled = MotionDetector::new(MotionDetectionConfig { enabled: true, ..eighth() });
+
+        let mut results = Vec::new();
+        for detector in [&mut full, &mut scaled] {
+            detector.detect(&vga_frame(false));
+            results.push(detector.detect(&vga_frame(true)));
+            // 使い回したバッファでも同じ結果になる
+            assert!(!detector.detect(&vga_frame(true)).motion_detected);
+        }
+        let (full, scaled) = (&results[0], &results[1]);
+
+        // 動き割合は同じで、マスクとかたまりは1/8の解像度
+        assert!(full.motion_detected && scaled.motion_detected);
+        assert!((full.motion_ratio - scaled.motion_ratio).abs() < 1e-4);
+        assert_eq!(scaled.mask.dimensions(), (80, 60));
+        assert_eq!(full.blobs[0].bbox, BoundingBox { x: 320, y: 240, width: 80, height: 80 });
+        assert_eq!(scaled.blobs[0].bbox, BoundingBox { x: 40, y: 30, width: 10, height: 10 });
+    }
+
+    #[test]
+    fn test_detect_jpeg() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig { enabled: true, ..eighth() });
+
+        let still = encode_jpeg(&vga_frame(false));
+        let moved = encode_jpeg(&vga_frame(true));
+        assert!(!detector.detect_jpeg(&still).unwrap().motion_detected);
+        let result = detector.detect_jpeg(&moved).unwrap();
+        assert!(result.motion_detected);
+        assert_eq!(result.mask.dimensions(), (80, 60));
+        assert_eq!(result.blobs.len(), 1);
+        assert_eq!(result.blobs[0].bbox, BoundingBox { x: 40, y: 30, width: 10, height: 10 });
+
+        // デコードできないデータはエラーで、フレームに数えない
+        assert!(detector.detect_jpeg(b"not a jpeg").is_err());
+        assert_eq!(detector.stats().total_frames, 2);
+    }
+
+    /// 8の倍数でない150x110の画面、`moved`なら8画素の格子からずれた明るい長方形が2つ
+    /// （1つ目はゾーン"left"の境界をまたぎ、2つ目は除外マスクの中）
+    fn unaligned_frame(moved: bool) -> RgbaImage {
+        RgbaImage::from_fn(150, 110, |x, y| {
+            let object = (37..83).contains(&x) && (29..71).contains(&y);
+            let flicker = (113..131).contains(&x) && (83..101).contains(&y);
+            if moved && (object || flicker) {
+                Rgba([220, 220, 220, 255])
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        })
+    }
+
+    fn unaligned_config(downscale: u32) -> MotionDetectionConfig {
+        let rect = |x0: f32, y0: f32, x1: f32, y1: f32| vec![[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+        let zone = |name: &str, points| MotionZone { name: name.to_string(), points, sensitivity: 0.5, min_motion_area: 1.0 };
+        MotionDetectionConfig {
+            enabled: true,
+            downscale,
+            zones: vec![zone("left", rect(0.0, 0.0, 0.43, 1.0)), zone("right", rect(0.62, 0.0, 1.0, 1.0))],
+            exclusions: vec![ExclusionMask { name: "flicker".to_string(), points: rect(0.71, 0.7, 0.93, 0.97) }],
+            ..full()
+        }
+    }
+
+    #[test]
+    fn test_luma_sizes_match_for_any_downscale() {
+        let rgba = unaligned_frame(true);
+        let jpeg = encode_jpeg(&rgba);
+        for scale in 0..=10 {
+            let from_rgba = luma_from_rgba(&rgba, scale, Vec::new());
+            let from_jpeg = luma_from_jpeg(&jpeg, scale, Vec::new()).unwrap();
+            assert_eq!(from_rgba.dimensions(), from_jpeg.dimensions(), "scale {}", scale);
+        }
+        assert_eq!(luma_from_rgba(&rgba, 3, Vec::new()).dimensions(), (75, 55));
+        assert_eq!(luma_from_rgba(&rgba, 8, Vec::new()).dimensions(), (19, 14));
+    }
+
+    #[test]
+    fn test_unaligned_object_and_zones_match_full_resolution() {
+        let detect = |downscale: u32, jpeg: bool| {
+            let mut detector = MotionDetector::new(unaligned_config(downscale));
+            let result = if jpeg {
+                detector.detect_jpeg(&encode_jpeg(&unaligned_frame(false))).unwrap();
+                detector.detect_jpeg(&encode_jpeg(&unaligned_frame(true))).unwrap()
+            } else {
+                detector.detect(&unaligned_frame(false));
+                detector.detect(&unaligned_frame(true))
+            };
+            (result, detector.zone_results().to_vec())
+        };
+
+        let (full, full_zones) = detect(1, false);
+        assert!(full.motion_detected);
+        assert_eq!(full_zones.iter().map(|zone| zone.motion_detected).collect::<Vec<_>>(), vec![true, false]);
+
+        // 縮小してもRGBAとJPEGのどちらからでも、同じゾーンで動きありになり、
+        // 動き割合は端の半端なブロックの分（2割以内）しか変わらず、かたまりの位置は1ブロック以内
+        for downscale in [2, 4, 8] {
+            for jpeg in [false, true] {
+                let (scaled, zones) = detect(downscale, jpeg);
+                let context = format!("downscale {}, jpeg {}", downscale, jpeg);
+                assert!(scaled.motion_detected, "{}", context);
+                assert!((scaled.motion_ratio - full.motion_ratio).abs() <= full.motion_ratio * 0.2, "{}", context);
+                for (zone, full_zone) in zones.iter().zip(&full_zones) {
+                    assert_eq!(zone.motion_detected, full_zone.motion_detected, "{} {}", context, zone.name);
+                    assert!((zone.motion_ratio - full_zone.motion_ratio).abs() <= full_zone.motion_ratio * 0.2, "{} {}", context, zone.name);
+                }
+
+                assert_eq!(scaled.blobs.len(), 1, "{}", context);
+                let (bbox, full_bbox) = (scaled.blobs[0].bbox, full.blobs[0].bbox);
+                for (edge, full_edge) in [
+                    (bbox.x, full_bbox.x),
+                    (bbox.y, full_bbox.y),
+                    (bbox.x + bbox.width, full_bbox.x + full_bbox.width),
+                    (bbox.y + bbox.height, full_bbox.y + full_bbox.height),
+                ] {
+                    assert!((edge * downscale).abs_diff(full_edge) <= downscale, "{}: {:?} {:?}", context, bbox, full_bbox);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_motion_detector_no_motion() {
         let mut detector = MotionDetector::new(MotionDetectionConfig {
@@ -239,16 +1048,17 @@ mod tests {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         let frame1 = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
         let frame2 = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
 
         // 初回フレーム（常にfalse）
-        assert_eq!(detector.detect(&frame1), false);
+        assert!(!detector.detect(&frame1).motion_detected);
 
         // 同じフレーム（動きなし）
-        assert_eq!(detector.detect(&frame2), false);
+        assert!(!detector.detect(&frame2).motion_detected);
     }
 
     #[test]
@@ -259,16 +1069,80 @@ mod tests {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         let frame1 = create_solid_color_image(100, 100, Rgba([50, 50, 50, 255]));
-        let frame2 = create_solid_color_image(100, 100, Rgba([200, 200, 200, 255]));
+        let frame2 = create_half_split_image(100, 100, Rgba([200, 200, 200, 255]), Rgba([50, 50, 50, 255]));
 
         // 初回フレーム
-        assert_eq!(detector.detect(&frame1), false);
+        assert!(!detector.detect(&frame1).motion_detected);
 
         // 大きな変化（動き検知）
-        assert_eq!(detector.detect(&frame2), true);
+        let result = detector.detect(&frame2);
+        assert!(result.motion_detected);
+        assert_eq!(result.change, SceneChange::Motion);
+        assert_eq!(result.brightness_gain, None);
+    }
+
+    /// 横方向に輝度が60〜140と変わるグラデーションを`gain`倍した画像
+    fn create_gradient_image(gain: f32) -> RgbaImage {
+        RgbaImage::from_fn(100, 100, |x, _| {
+            let v = ((60 + (x * 80) / 100) as f32 * gain).round().min(255.0) as u8;
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_uniform_brightness_change_is_illumination() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig { enabled: true, ..full() });
+
+        // 画面全体が一様に明るくなる（照明の点灯）
+        detector.detect(&create_solid_color_image(100, 100, Rgba([50, 50, 50, 255])));
+        let result = detector.detect(&create_solid_color_image(100, 100, Rgba([200, 200, 200, 255])));
+        assert!(!result.motion_detected);
+        assert_eq!(result.change, SceneChange::Illumination);
+        assert!((result.brightness_gain.unwrap() - 201.0 / 51.0).abs() < 1e-4);
+        assert!(result.blobs.is_empty());
+
+        // 模様のある画面の露出が1.6倍になっても動きではない
+        detector.reset();
+        detector.detect(&create_gradient_image(1.0));
+        let result = detector.detect(&create_gradient_image(1.6));
+        assert_eq!(result.change, SceneChange::Illumination);
+        assert_eq!(detector.stats().illumination_count, 1);
+        assert_eq!(detector.stats().motion_detected_count, 0);
+
+        // 補正を無効にすると従来どおり動きになる
+        let mut config = detector.config().clone();
+        config.suppress_illumination = false;
+        detector.update_config(config);
+        detector.detect(&create_gradient_image(1.0));
+        let result = detector.detect(&create_gradient_image(1.6));
+        assert_eq!((result.motion_detected, result.change), (true, SceneChange::Motion));
+    }
+
+    #[test]
+    fn test_motion_during_illumination_change() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig { enabled: true, ..full() });
+
+        // 照明が点くと同時に、暗い物体（20x20）が現れる
+        let lit_with_object = RgbaImage::from_fn(100, 100, |x, y| {
+            if (40..60).contains(&x) && (40..60).contains(&y) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                *create_gradient_image(1.6).get_pixel(x, y)
+            }
+        });
+        detector.detect(&create_gradient_image(1.0));
+        let result = detector.detect(&lit_with_object);
+
+        // 明るさを補正した後も物体の分だけ動きが残る
+        assert!(result.motion_detected);
+        assert_eq!(result.change, SceneChange::Motion);
+        assert!(result.brightness_gain.is_some());
+        assert_eq!(result.blobs.len(), 1);
+        assert_eq!(result.blobs[0].bbox, BoundingBox { x: 40, y: 40, width: 20, height: 20 });
     }
 
     #[test]
@@ -279,6 +1153,7 @@ mod tests {
             min_motion_area: 10.0, // 10%以上の動きが必要
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         let frame1 = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
@@ -293,7 +1168,8 @@ mod tests {
         detector.detect(&frame1);
 
         // 50%の領域が変化 → 検知される（10%以上）
-        assert_eq!(detector.detect(&frame2), true);
+        assert!(detector.detect(&frame2).motion_detected);
+        assert_eq!(detector.last_motion_ratio(), 50.0);
     }
 
     #[test]
@@ -304,6 +1180,7 @@ mod tests {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         let frame1 = create_solid_color_image(100, 100, Rgba([50, 50, 50, 255]));
@@ -312,7 +1189,7 @@ mod tests {
         detector.detect(&frame1);
 
         // 大きな変化があっても、無効なので検知されない
-        assert_eq!(detector.detect(&frame2), false);
+        assert!(!detector.detect(&frame2).motion_detected);
     }
 
     #[test]
@@ -323,10 +1200,11 @@ mod tests {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         let frame_still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
-        let frame_motion = create_solid_color_image(100, 100, Rgba([200, 200, 200, 255]));
+        let frame_motion = create_half_split_image(100, 100, Rgba([200, 200, 200, 255]), Rgba([100, 100, 100, 255]));
 
         // 初回
         detector.detect(&frame_still);
@@ -372,15 +1250,270 @@ mod tests {
             min_motion_area: 1.0,
             pre_record_seconds: 10,
             post_record_seconds: 30,
+            ..full()
         });
 
         assert_eq!(detector.compute_threshold(), 5);
 
         detector.config.sensitivity = 0.5; // 中感度
         let threshold = detector.compute_threshold();
-        assert!(threshold >= 30 && threshold <= 55);
+        assert!((30..=55).contains(&threshold));
 
         detector.config.sensitivity = 1.0; // 低感度
         assert_eq!(detector.compute_threshold(), 100);
     }
+
+    /// 左半分・右半分の長方形
+    fn half(left: bool) -> Vec<[f32; 2]> {
+        let (x0, x1) = if left { (0.0, 0.5) } else { (0.5, 1.0) };
+        vec![[x0, 0.0], [x1, 0.0], [x1, 1.0], [x0, 1.0]]
+    }
+
+    #[test]
+    fn test_polygon_contains() {
+        let triangle = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        assert!(polygon_contains(&triangle, 0.2, 0.2));
+        assert!(!polygon_contains(&triangle, 0.8, 0.8));
+        assert!(!polygon_contains(&triangle[..2], 0.1, 0.0));
+    }
+
+    #[test]
+    fn test_exclusion_mask_ignores_change() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            exclusions: vec![ExclusionMask { name: "tree".to_string(), points: half(true) }],
+            ..full()
+        });
+
+        let still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
+        let left_changed = create_half_split_image(100, 100, Rgba([200, 200, 200, 255]), Rgba([100, 100, 100, 255]));
+        // マスク外（右半分）の右半分だけが変化
+        let right_changed = RgbaImage::from_fn(100, 100, |x, _| if x >= 75 { Rgba([200, 200, 200, 255]) } else { Rgba([100, 100, 100, 255]) });
+
+        detector.detect(&still);
+        // マスク内（左半分）だけの変化は数えない
+        assert!(!detector.detect(&left_changed).motion_detected);
+        assert_eq!(detector.last_motion_ratio(), 0.0);
+
+        // マスク外の変化の割合はマスク外の面積に対して求める
+        detector.detect(&still);
+        assert!(detector.detect(&right_changed).motion_detected);
+        assert_eq!(detector.last_motion_ratio(), 50.0);
+        assert!(detector.zone_results().is_empty());
+    }
+
+    #[test]
+    fn test_zones_report_per_zone() {
+        let zone = |name: &str, left: bool, min_motion_area: f32| MotionZone {
+            name: name.to_string(),
+            points: half(left),
+            sensitivity: 0.5,
+            min_motion_area,
+        };
+        let mut detector = MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            zones: vec![zone("door", true, 10.0), zone("street", false, 60.0)],
+            ..full()
+        });
+
+        let still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
+        // 右側の40%（右半分の80%）が変化
+        let street = RgbaImage::from_fn(100, 100, |x, _| if x >= 60 { Rgba([200, 200, 200, 255]) } else { Rgba([100, 100, 100, 255]) });
+
+        detector.detect(&still);
+        // 右のゾーンは80%が変化し、最小動き領域（60%）を超える
+        assert!(detector.detect(&street).motion_detected);
+        let results = detector.zone_results();
+        assert_eq!(results.len(), 2);
+        assert_eq!((results[0].name.as_str(), results[0].motion_detected, results[0].motion_ratio), ("door", false, 0.0));
+        assert_eq!((results[1].name.as_str(), results[1].motion_detected, results[1].motion_ratio), ("street", true, 80.0));
+
+        // ゾーンの最小動き領域を上げると、同じ変化でも検知しない
+        let mut config = detector.config().clone();
+        config.zones[1].min_motion_area = 90.0;
+        detector.update_config(config);
+        detector.detect(&still);
+        assert!(!detector.detect(&street).motion_detected);
+        assert!(!detector.zone_results()[1].motion_detected);
+    }
+
+    #[test]
+    fn test_blobs_with_bounding_boxes() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig { enabled: true, ..full() });
+
+        let still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
+        // 10x10と20x5の2つの物体、孤立した1ピクセルのノイズ2つ
+        let moved = RgbaImage::from_fn(100, 100, |x, y| {
+            let square = (10..20).contains(&x) && (10..20).contains(&y);
+            let bar = (60..80).contains(&x) && (70..75).contains(&y);
+            let noise = (x, y) == (50, 5) || (x, y) == (5, 90);
+            if square || bar || noise { Rgba([220, 220, 220, 255]) } else { Rgba([100, 100, 100, 255]) }
+        });
+
+        detector.detect(&still);
+        let result = detector.detect(&moved);
+        assert!(result.motion_detected);
+        assert!((result.motion_ratio - 2.02).abs() < 1e-4);
+        assert_eq!(result.mask.dimensions(), (100, 100));
+        assert_eq!(result.mask.get_pixel(15, 15)[0], 255);
+        assert_eq!(result.mask.get_pixel(50, 5)[0], 0);
+
+        // ノイズは取り除かれ、大きい順に並ぶ
+        assert_eq!(result.blobs, vec![
+            MotionBlob { bbox: BoundingBox { x: 10, y: 10, width: 10, height: 10 }, centroid: (14.5, 14.5), area: 100 },
+            MotionBlob { bbox: BoundingBox { x: 60, y: 70, width: 20, height: 5 }, centroid: (69.5, 72.0), area: 100 },
+        ]);
+
+        // 初回フレームは空の結果
+        detector.reset();
+        let first = detector.detect(&moved);
+        assert!(first.blobs.is_empty() && first.mask.is_empty());
+    }
+
+    #[test]
+    fn test_blobs_respect_zones() {
+        let mut detector = MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            zones: vec![MotionZone { name: "right".to_string(), points: half(false), sensitivity: 0.5, min_motion_area: 1.0 }],
+            ..full()
+        });
+
+        let still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
+        // 左右それぞれに10x10の物体
+        let moved = RgbaImage::from_fn(100, 100, |x, y| {
+            if ((10..20).contains(&x) || (70..80).contains(&x)) && (40..50).contains(&y) {
+                Rgba([220, 220, 220, 255])
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        });
+
+        detector.detect(&still);
+        let result = detector.detect(&moved);
+        // ゾーン外（左）の物体はかたまりにならない
+        assert_eq!(result.blobs.len(), 1);
+        assert_eq!(result.blobs[0].bbox, BoundingBox { x: 70, y: 40, width: 10, height: 10 });
+    }
+
+    fn background_detector(learning_rate: f32, sensitivity: f32) -> MotionDetector {
+        MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            sensitivity,
+            algorithm: MotionAlgorithm::Background,
+            background_learning_rate: learning_rate,
+            ..full()
+        })
+    }
+
+    /// 暗い背景の上を20x20の明るい正方形が1フレームに1ピクセルずつ右へ動く
+    fn slow_object_frame(frame: u32) -> RgbaImage {
+        RgbaImage::from_fn(100, 100, |x, y| {
+            if (10 + frame..30 + frame).contains(&x) && (40..60).contains(&y) {
+                Rgba([200, 200, 200, 255])
+            } else {
+                Rgba([50, 50, 50, 255])
+            }
+        })
+    }
+
+    /// 輝度100に±10の擬似乱数ノイズを乗せたフレーム
+    fn noisy_frame(frame: u32) -> RgbaImage {
+        RgbaImage::from_fn(100, 100, |x, y| {
+            let hash = (x * 31 + y * 3517 + frame * 7919).wrapping_mul(2_654_435_761) >> 16;
+            let noise = (hash % 21) as i32 - 10;
+            let v = (100 + noise) as u8;
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_background_detects_slow_object() {
+        let mut frame_diff = MotionDetector::new(MotionDetectionConfig { enabled: true, ..full() });
+        let mut background = background_detector(0.02, 0.5);
+
+        let empty = create_solid_color_image(100, 100, Rgba([50, 50, 50, 255]));
+        frame_diff.detect(&empty);
+        background.detect(&empty);
+
+        for frame in 0..30 {
+            let image = slow_object_frame(frame);
+            let diff_motion = frame_diff.detect(&image).motion_detected;
+            let background_motion = background.detect(&image).motion_detected;
+
+            // 前フレームとの差は正方形の左右の縁（0.4%）だけで、最小動き領域に届かない
+            if frame > 0 {
+                assert!(!diff_motion, "frame diff detected frame {}", frame);
+                assert!(frame_diff.last_motion_ratio() < 1.0);
+            }
+            // 背景との差は正方形全体（4%以上）
+            assert!(background_motion, "background missed frame {}", frame);
+            assert!(background.last_motion_ratio() >= 4.0);
+        }
+    }
+
+    #[test]
+    fn test_background_smooths_sensor_noise() {
+        let mut frame_diff = MotionDetector::new(MotionDetectionConfig {
+            enabled: true,
+            sensitivity: 0.1,
+            ..full()
+        });
+        let mut background = background_detector(0.1, 0.1);
+
+        // 背景モデルが平均に落ち着くまで学習させる
+        for frame in 0..60 {
+            frame_diff.detect(&noisy_frame(frame));
+            background.detect(&noisy_frame(frame));
+        }
+
+        for frame in 60..90 {
+            // 2フレーム分のノイズの差は閾値（14）を超えるが、平均との差は超えない
+            assert!(frame_diff.detect(&noisy_frame(frame)).motion_detected);
+            assert!(!background.detect(&noisy_frame(frame)).motion_detected, "noise detected at frame {}", frame);
+        }
+    }
+
+    #[test]
+    fn test_background_absorbs_stopped_object() {
+        let mut detector = background_detector(0.1, 0.5);
+
+        let still = create_solid_color_image(100, 100, Rgba([100, 100, 100, 255]));
+        let parked = create_half_split_image(100, 100, Rgba([200, 200, 200, 255]), Rgba([100, 100, 100, 255]));
+
+        // 初回フレームは背景を作るだけ
+        assert!(!detector.detect(&still).motion_detected);
+        assert!(!detector.detect(&still).motion_detected);
+
+        // 止まった物体はしばらく動きとして検知され、やがて背景に取り込まれる
+        let detected: Vec<bool> = (0..20).map(|_| detector.detect(&parked).motion_detected).collect();
+        assert!(detected[..6].iter().all(|&motion| motion));
+        assert!(detected[10..].iter().all(|&motion| !motion));
+
+        // 設定をフレーム間差分に切り替えても動作し、リセットで背景も消える
+        let mut config = detector.config().clone();
+        config.algorithm = MotionAlgorithm::FrameDiff;
+        detector.update_config(config);
+        assert!(detector.detect(&still).motion_detected);
+        detector.reset();
+        assert!(detector.background.is_none());
+    }
+
+    #[test]
+    fn test_algorithm_deserializes() {
+        let config: MotionDetectionConfig = serde_json::from_str(
+            r#"{"enabled":true,"algorithm":"background","background_learning_rate":0.05}"#
+        ).unwrap();
+        assert_eq!(config.algorithm, MotionAlgorithm::Background);
+        assert_eq!(config.background_learning_rate, 0.05);
+        assert_eq!(MotionDetectionConfig::default().algorithm, MotionAlgorithm::FrameDiff);
+    }
+
+    #[test]
+    fn test_config_without_zones_deserializes() {
+        let config: MotionDetectionConfig = serde_json::from_str(
+            r#"{"enabled":true,"sensitivity":0.4,"min_motion_area":2.0,"pre_record_seconds":10,"post_record_seconds":30}"#
+        ).unwrap();
+        assert!(config.zones.is_empty() && config.exclusions.is_empty());
+        assert_eq!(config.sensitivity, 0.4);
+    }
 }
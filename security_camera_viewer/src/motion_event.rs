@@ -0,0 +1,402 @@
+//! 動きイベントのライフサイクル
+//!
+//! フレームごとの検知結果（`MotionResult`）から、ヒステリシス付きで動きイベントを開始・終了する。
+//! - 開始: 直近`window_frames`フレームのうち`trigger_frames`フレーム以上で動きがあったとき
+//!   （1組のフレームのノイズだけでは始まらない）
+//! - 終了: 動きのない状態が`quiet_seconds`続いたとき。ただし開始から`min_event_seconds`経つまでは続ける
+//! - クールダウン: 終了から`cooldown_seconds`の間は次のイベントを開始しない
+//!
+//! 状態が変わるたびに`EventNotification`（開始・更新・終了）を`EventBus`の購読者へ送る。
+//! 録画やカタログ、外部連携は`MotionEventTracker::subscribe`で受け取ったチャネルから読む。
+//! `EventBus`は通知の型を問わず、いたずら検知（`TamperDetector`）の通知も同じ仕組みで配信する。
+
+use crate::catalog::MotionBox;
+use crate::motion_detector::MotionResult;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// イベントに記録する外接矩形の最大数（面積の大きい順）
+const MAX_EVENT_BOXES: usize = 8;
+
+/// イベントの開始・終了条件
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionEventConfig {
+    /// 開始に必要な動きフレーム数（N）
+    pub trigger_frames: u32,
+    /// 動きフレームを数える直近のフレーム数（M）
+    pub window_frames: u32,
+    /// この秒数動きがなければ終了
+    pub quiet_seconds: f32,
+    /// 最短のイベント継続秒数
+    pub min_event_seconds: f32,
+    /// 終了後、次のイベントを開始しない秒数
+    pub cooldown_seconds: f32,
+}
+
+impl Default for MotionEventConfig {
+    fn default() -> Self {
+        Self {
+            trigger_frames: 3,
+            window_frames: 5,
+            quiet_seconds: 1.0,
+            min_event_seconds: 2.0,
+            cooldown_seconds: 2.0,
+        }
+    }
+}
+
+/// 動きイベント
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionEvent {
+    /// 起動からの連番
+    pub id: u64,
+    /// 開始を決めた動きフレームのうち最初のフレームの時刻
+    pub started_at: DateTime<Local>,
+    /// 最後に動きがあった時刻
+    pub last_motion_at: DateTime<Local>,
+    /// 終了時刻（開始から最短継続時間に満たない場合は延ばす）、継続中はNone
+    pub ended_at: Option<DateTime<Local>>,
+    /// 動き割合の最大値（%）
+    pub peak_motion_ratio: f32,
+    /// 動きが検知されたフレーム数
+    pub frame_count: u64,
+    /// 動き割合が最大だったフレームの動きの外接矩形
+    pub boxes: Vec<MotionBox>,
+    /// イベント全体で動きがあった範囲
+    pub extent: Option<MotionBox>,
+}
+
+impl MotionEvent {
+    fn start(id: u64, started_at: DateTime<Local>) -> Self {
+        Self {
+            id,
+            started_at,
+            last_motion_at: started_at,
+            ended_at: None,
+            peak_motion_ratio: 0.0,
+            frame_count: 0,
+            boxes: Vec::new(),
+            extent: None,
+        }
+    }
+
+    /// 動き検知フレームを追加（範囲を広げ、ピークのフレームなら外接矩形を置き換える）
+    fn add_frame(&mut self, result: &MotionResult, now: DateTime<Local>) {
+        self.last_motion_at = now;
+        self.frame_count += 1;
+
+        let (width, height) = result.mask.dimensions();
+        let boxes: Vec<MotionBox> = result.blobs.iter()
+            .take(MAX_EVENT_BOXES)
+            .map(|blob| MotionBox::from_bbox(&blob.bbox, width, height))
+            .collect();
+        for bbox in &boxes {
+            self.extent = Some(self.extent.map_or(*bbox, |extent| extent.union(bbox)));
+        }
+        if self.frame_count == 1 || result.motion_ratio > self.peak_motion_ratio {
+            self.peak_motion_ratio = result.motion_ratio;
+            self.boxes = boxes;
+        }
+    }
+
+    /// イベント継続時間（継続中は最後の動きまで）
+    pub fn duration(&self) -> Duration {
+        self.ended_at.unwrap_or(self.last_motion_at) - self.started_at
+    }
+}
+
+/// イベントの状態変化の通知
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum EventNotification {
+    EventStarted(MotionEvent),
+    /// 継続中のイベントに動きフレームが加わった
+    EventUpdated(MotionEvent),
+    EventEnded(MotionEvent),
+}
+
+impl EventNotification {
+    pub fn event(&self) -> &MotionEvent {
+        match self {
+            EventNotification::EventStarted(event)
+            | EventNotification::EventUpdated(event)
+            | EventNotification::EventEnded(event) => event,
+        }
+    }
+}
+
+/// 通知の購読者へのチャネル（受信側が破棄された購読者は外す）
+pub struct EventBus<T = EventNotification> {
+    subscribers: Vec<Sender<T>>,
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self { subscribers: Vec::new() }
+    }
+}
+
+impl<T: Clone> EventBus<T> {
+    pub fn subscribe(&mut self) -> Receiver<T> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn publish(&mut self, notification: &T) {
+        self.subscribers.retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+    }
+}
+
+/// 検知結果からイベントを開始・更新・終了する
+pub struct MotionEventTracker {
+    config: MotionEventConfig,
+    /// 直近のフレームの動きの時刻（動きなしはNone）
+    window: VecDeque<Option<DateTime<Local>>>,
+    current: Option<MotionEvent>,
+    last_ended_at: Option<DateTime<Local>>,
+    next_id: u64,
+    bus: EventBus,
+}
+
+impl MotionEventTracker {
+    pub fn new(config: MotionEventConfig) -> Self {
+        Self {
+            config,
+            window: VecDeque::new(),
+            current: None,
+            last_ended_at: None,
+            next_id: 1,
+            bus: EventBus::default(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: MotionEventConfig) {
+        self.config = config;
+    }
+
+    /// 通知を受け取るチャネルを追加
+    pub fn subscribe(&mut self) -> Receiver<EventNotification> {
+        self.bus.subscribe()
+    }
+
+    /// 継続中のイベント
+    pub fn current(&self) -> Option<&MotionEvent> {
+        self.current.as_ref()
+    }
+
+    /// 1フレームの検知結果を反映し、状態が変わった場合は通知する（戻り値も同じ通知）
+    pub fn update(&mut self, result: &MotionResult, now: DateTime<Local>) -> Option<EventNotification> {
+        let window_frames = self.config.window_frames.max(1) as usize;
+        self.window.push_back(result.motion_detected.then_some(now));
+        while self.window.len() > window_frames {
+            self.window.pop_front();
+        }
+
+        let notification = match &mut self.current {
+            Some(event) if result.motion_detected => {
+                event.add_frame(result, now);
+                Some(EventNotification::EventUpdated(event.clone()))
+            }
+            Some(event) => {
+                let quiet = now - event.last_motion_at >= seconds(self.config.quiet_seconds);
+                let long_enough = now - event.started_at >= seconds(self.config.min_event_seconds);
+                if quiet && long_enough {
+                    self.finish(now)
+                } else {
+                    None
+                }
+            }
+            None => {
+                let cooling_down = self.last_ended_at
+                    .is_some_and(|ended_at| now - ended_at < seconds(self.config.cooldown_seconds));
+                let motion_frames = self.window.iter().flatten().count();
+                if result.motion_detected && !cooling_down && motion_frames >= self.config.trigger_frames.max(1) as usize {
+                    let started_at = self.window.iter().flatten().next().copied().unwrap_or(now);
+                    let mut event = MotionEvent::start(self.next_id, started_at);
+                    self.next_id += 1;
+                    event.add_frame(result, now);
+                    self.window.clear();
+                    self.current = Some(event.clone());
+                    Some(EventNotification::EventStarted(event))
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(notification) = &notification {
+            self.bus.publish(notification);
+        }
+        notification
+    }
+
+    /// 継続中のイベントをすぐに終了する（録画の停止・検知の無効化）
+    pub fn end(&mut self, now: DateTime<Local>) -> Option<EventNotification> {
+        self.window.clear();
+        let notification = self.finish(now)?;
+        self.bus.publish(&notification);
+        Some(notification)
+    }
+
+    /// 継続中のイベントを閉じる（通知はしない）
+    fn finish(&mut self, now: DateTime<Local>) -> Option<EventNotification> {
+        let mut event = self.current.take()?;
+        let min_end = event.started_at + seconds(self.config.min_event_seconds);
+        event.ended_at = Some(event.last_motion_at.max(min_end).min(now.max(event.last_motion_at)));
+        self.last_ended_at = Some(now);
+        Some(EventNotification::EventEnded(event))
+    }
+}
+
+fn seconds(secs: f32) -> Duration {
+    Duration::milliseconds((secs.max(0.0) * 1000.0) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion_detector::{BoundingBox, MotionBlob};
+    use chrono::TimeZone;
+
+    fn at(ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 2, 22, 0, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    fn still() -> MotionResult {
+        MotionResult::default()
+    }
+
+    /// 100x100のフレームで、指定した外接矩形の動きがあった検知結果
+    fn motion(motion_ratio: f32, bboxes: &[(u32, u32, u32, u32)]) -> MotionResult {
+        MotionResult {
+            motion_detected: true,
+            motion_ratio,
+            mask: image::GrayImage::new(100, 100),
+            blobs: bboxes.iter().map(|&(x, y, width, height)| MotionBlob {
+                bbox: BoundingBox { x, y, width, height },
+                centroid: ((x + width / 2) as f32, (y + height / 2) as f32),
+                area: width * height,
+            }).collect(),
+            ..MotionResult::default()
+        }
+    }
+
+    /// 100ms間隔のフレーム列を流し、通知を(フレーム番号, 通知)で返す
+    fn run(tracker: &mut MotionEventTracker, frames: &[bool]) -> Vec<(usize, EventNotification)> {
+        frames.iter().enumerate()
+            .filter_map(|(i, &moving)| {
+                let result = if moving { motion(5.0, &[(10, 10, 10, 10)]) } else { still() };
+                tracker.update(&result, at(i as i64 * 100)).map(|n| (i, n))
+            })
+            .collect()
+    }
+
+    fn kinds(notifications: &[(usize, EventNotification)]) -> Vec<(usize, &'static str)> {
+        notifications.iter().map(|(i, n)| (*i, match n {
+            EventNotification::EventStarted(_) => "started",
+            EventNotification::EventUpdated(_) => "updated",
+            EventNotification::EventEnded(_) => "ended",
+        })).collect()
+    }
+
+    #[test]
+    fn test_single_noisy_frame_does_not_start() {
+        let mut tracker = MotionEventTracker::new(MotionEventConfig::default());
+        let frames = [false, true, false, false, false, true, false, false, true, false, false];
+        assert!(run(&mut tracker, &frames).is_empty());
+        assert!(tracker.current().is_none());
+    }
+
+    #[test]
+    fn test_n_of_m_start_and_quiet_end() {
+        let mut tracker = MotionEventTracker::new(MotionEventConfig {
+            min_event_seconds: 0.0,
+            ..MotionEventConfig::default()
+        });
+        let receiver = tracker.subscribe();
+
+        // 3/5フレームで開始、2フレーム後に動きが止まり、1秒後に終了
+        let mut frames = vec![true, false, true, true, true];
+        frames.extend([false; 12]);
+        let notifications = run(&mut tracker, &frames);
+        assert_eq!(kinds(&notifications), vec![(3, "started"), (4, "updated"), (14, "ended")]);
+
+        // 開始時刻は最初の動きフレーム、終了時刻は最後の動き
+        let ended = notifications[2].1.event();
+        assert_eq!(ended.id, 1);
+        assert_eq!(ended.started_at, at(0));
+        assert_eq!(ended.ended_at, Some(at(400)));
+        assert_eq!(ended.frame_count, 2);
+
+        // 購読者にも同じ通知が届く
+        let received: Vec<EventNotification> = receiver.try_iter().collect();
+        assert_eq!(received, notifications.into_iter().map(|(_, n)| n).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_min_duration_and_cooldown() {
+        let mut tracker = MotionEventTracker::new(MotionEventConfig {
+            trigger_frames: 1,
+            window_frames: 1,
+            quiet_seconds: 0.2,
+            min_event_seconds: 1.0,
+            cooldown_seconds: 1.0,
+        });
+
+        // 1フレームだけの動きでも開始から1秒は終了しない
+        let mut frames = vec![true];
+        frames.extend([false; 12]);
+        // クールダウン中（終了から1秒以内）の動きでは開始しない
+        frames.extend([true, false, false, false, false, false, false, false]);
+        // クールダウン後は開始する
+        frames.push(true);
+        let notifications = run(&mut tracker, &frames);
+        assert_eq!(kinds(&notifications), vec![(0, "started"), (10, "ended"), (21, "started")]);
+
+        let first = notifications[1].1.event();
+        assert_eq!(first.ended_at, Some(at(1000)));
+        assert_eq!(first.duration(), Duration::seconds(1));
+        assert_eq!(notifications[2].1.event().id, 2);
+    }
+
+    #[test]
+    fn test_event_boxes_and_forced_end() {
+        let mut tracker = MotionEventTracker::new(MotionEventConfig { trigger_frames: 1, ..MotionEventConfig::default() });
+        tracker.update(&motion(2.0, &[(10, 10, 10, 10)]), at(0));
+        tracker.update(&motion(8.5, &[(50, 20, 20, 40)]), at(100));
+        tracker.update(&motion(4.0, &[(80, 80, 10, 10)]), at(200));
+
+        let ended = match tracker.end(at(300)) {
+            Some(EventNotification::EventEnded(event)) => event,
+            other => panic!("unexpected notification: {:?}", other),
+        };
+        assert!(tracker.current().is_none());
+        assert!(tracker.end(at(400)).is_none());
+
+        // ピーク（8.5%）のフレームの矩形と、全フレームを囲む範囲
+        assert_eq!(ended.peak_motion_ratio, 8.5);
+        assert_eq!(ended.frame_count, 3);
+        assert_eq!(ended.boxes, vec![MotionBox { x: 0.5, y: 0.2, width: 0.2, height: 0.4 }]);
+        let extent = ended.extent.unwrap();
+        assert_eq!((extent.x, extent.y), (0.1, 0.1));
+        assert!((extent.width - 0.8).abs() < 1e-6 && (extent.height - 0.8).abs() < 1e-6);
+        // 強制終了では最短継続時間まで延ばさない
+        assert_eq!(ended.ended_at, Some(at(300)));
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_removed() {
+        let mut bus = EventBus::default();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+
+        let event = MotionEvent::start(1, at(0));
+        bus.publish(&EventNotification::EventStarted(event.clone()));
+        assert_eq!(bus.subscribers.len(), 1);
+        assert_eq!(kept.try_recv().unwrap().event(), &event);
+    }
+}
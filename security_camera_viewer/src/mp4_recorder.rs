@@ -1,29 +1,154 @@
-/// MP4録画モジュール
+//! MP4録画モジュール
+//!
+//! ffmpegプロセスを使用してJPEGフレームをリアルタイムでMP4にエンコードする。
+//! Phase 6: MP4直接保存機能
+//!
+//! 出力はフラグメント化MP4（約1秒ごとのmoof+mdat）とし、
+//! クラッシュや電源断で終了処理されなくても書き込み済みの部分は再生できる。
+//!
+//! ffmpegは監視対象として扱う:
+//! - stderrはログに転送し、直近の出力をエラー報告用に保持する
+//! - 書き込み前にプロセスの終了を検出する
+//! - 異常終了時は新しいセグメントファイルでffmpegを再起動して録画を継続する
+//!
+//! 鍵を指定した場合、ffmpegの出力はstdout経由で受け取り、暗号化してファイルに書き込む。
+
+use crate::crypto::{EncryptedWriter, KeySource};
+use crate::mjpeg::DEFAULT_SYNC_INTERVAL;
+use log::{error, warn};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 1録画あたりのffmpeg再起動回数の上限
+const MAX_RESTARTS: u32 = 5;
+/// エラー報告用に保持するstderrの行数
+const STDERR_TAIL_LINES: usize = 20;
+/// Drop後にffmpegの正常終了を待つ時間（待つのは別スレッド）
+const DROP_WAIT: Duration = Duration::from_secs(5);
+
+/// MP4エンコード設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mp4EncoderConfig {
+    /// 出力コーデック（ffmpegのエンコーダー名）
+    pub codec: String,
+    /// エンコード速度/品質バランス（空文字列の場合は指定しない）
+    pub preset: String,
+    /// 品質設定（18-28、低いほど高品質。Noneの場合は指定しない）
+    pub crf: Option<u8>,
+    /// 出力ファイル名の直前に追加するffmpeg引数
+    pub extra_args: Vec<String>,
+}
+
+impl Default for Mp4EncoderConfig {
+    fn default() -> Self {
+        Self {
+            codec: "libx264".to_string(),
+            preset: "medium".to_string(),
+            crf: Some(23),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl Mp4EncoderConfig {
+    /// スペース区切りの追加引数をパース
+    pub fn parse_extra_args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// エンコーダー関連のffmpeg引数
+    fn encoder_args(&self) -> Vec<String> {
+        let mut args = vec!["-c:v".to_string(), self.codec.clone()];
+        if !self.preset.is_empty() {
+            args.extend(["-preset".to_string(), self.preset.clone()]);
+        }
+        if let Some(crf) = self.crf {
+            args.extend(["-crf".to_string(), crf.to_string()]);
+        }
+        args
+    }
+}
+
+/// ffmpegの検出結果
+#[derive(Debug, Clone)]
+pub struct FfmpegProbe {
+    /// バージョン文字列（`ffmpeg -version`の1行目）
+    pub version: String,
+    /// 指定したエンコーダーが利用可能か
+    pub encoder_available: bool,
+}
+
+/// ffmpegのバージョンとエンコーダーの有無を確認
 ///
-/// ffmpegプロセスを使用してJPEGフレームをリアルタイムでMP4にエンコードする。
-/// Phase 6: MP4直接保存機能
+/// # Errors
+/// - ffmpegが見つからない、または起動できない場合
+pub fn probe_ffmpeg(codec: &str) -> io::Result<FfmpegProbe> {
+    let version_output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-version"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound, format!("ffmpeg not found: {}", e)))?;
+    let version = String::from_utf8_lossy(&version_output.stdout)
+        .lines()
+        .next()
+        .unwrap_or("unknown")
+        .to_string();
+
+    let encoders_output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-encoders"])
+        .stdin(Stdio::null())
+        .output()?;
+    let encoder_available = encoder_listed(&String::from_utf8_lossy(&encoders_output.stdout), codec);
 
-use std::io::{self, Write};
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
+    Ok(FfmpegProbe { version, encoder_available })
+}
+
+/// `ffmpeg -encoders`の出力にエンコーダーが含まれるか
+///
+/// 各行は ` V....D libx264    libx264 H.264 / AVC ...` の形式
+fn encoder_listed(encoders: &str, codec: &str) -> bool {
+    encoders.lines().any(|line| line.split_whitespace().nth(1) == Some(codec))
+}
 
 /// MP4レコーダー
 ///
 /// ffmpegプロセスをstdin経由で制御し、JPEGフレームを
 /// リアルタイムでMP4形式にエンコードする。
 pub struct Mp4Recorder {
-    /// ffmpegプロセス
-    ffmpeg_process: Child,
+    /// ffmpegプロセス（`finish`とDropで取り出す）
+    ffmpeg_process: Option<Child>,
     /// ffmpegのstdin（JPEGフレームを書き込む）
     stdin: Option<Box<dyn Write + Send>>,
+    /// ffmpegのstderrの直近の行
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    /// 暗号化時にffmpegのstdoutをファイルへ書き込むスレッド
+    output_thread: Option<JoinHandle<io::Result<()>>>,
     /// 書き込まれたフレーム数
     frame_count: u32,
-    /// 出力ファイルパス
+    /// 出力ファイルパス（現在のセグメント）
     output_path: String,
+    /// 録画の全セグメント（最初の要素は録画開始時のファイル）
+    segments: Vec<PathBuf>,
+    /// フレームレート
+    fps: u32,
+    /// エンコード設定
+    config: Mp4EncoderConfig,
+    /// ffmpeg再起動回数
+    restarts: u32,
+    /// 暗号鍵（Noneの場合は平文で保存）
+    key: Option<KeySource>,
+    /// 最後に書き込んだフレーム（間引いたフレームの代わりに繰り返す）
+    last_frame: Vec<u8>,
 }
 
 impl Mp4Recorder {
-    /// 新しいMP4レコーダーを作成
+    /// 新しいMP4レコーダーを作成（デフォルトのエンコード設定）
     ///
     /// # Arguments
     /// * `output_path` - 出力MP4ファイルのパス
@@ -36,48 +161,47 @@ impl Mp4Recorder {
     /// - ffmpegが見つからない場合
     /// - ffmpegプロセスの起動に失敗した場合
     pub fn new(output_path: &Path, fps: u32) -> io::Result<Self> {
+        Self::with_config(output_path, fps, Mp4EncoderConfig::default(), None)
+    }
+
+    /// エンコード設定と暗号鍵を指定してMP4レコーダーを作成
+    ///
+    /// # Errors
+    /// - ffmpegが見つからない場合
+    /// - ffmpegプロセスの起動に失敗した場合
+    /// - 出力ファイルを作成できない場合
+    pub fn with_config(
+        output_path: &Path,
+        fps: u32,
+        config: Mp4EncoderConfig,
+        key: Option<KeySource>,
+    ) -> io::Result<Self> {
         let output_str = output_path.to_str()
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid output path"))?;
 
-        // ffmpegコマンドを構築
-        let mut ffmpeg = Command::new("ffmpeg")
-            .args(&[
-                "-f", "image2pipe",               // 入力形式: 画像パイプ
-                "-codec:v", "mjpeg",              // 入力コーデック: MJPEG
-                "-framerate", &fps.to_string(),   // フレームレート
-                "-i", "-",                        // 入力: stdin
-                "-c:v", "libx264",                // 出力コーデック: H.264
-                "-preset", "medium",              // エンコード速度/品質バランス
-                "-crf", "23",                     // 品質設定（18-28、低いほど高品質）
-                "-pix_fmt", "yuv420p",            // 互換性のためのピクセルフォーマット
-                "-movflags", "+faststart",        // Web最適化（moovアトムを先頭に移動）
-                "-y",                             // 上書き確認なし
-                output_str,
-            ])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::null())                // ffmpegの標準出力を破棄
-            .stderr(Stdio::null())                // ffmpegの標準エラー出力を破棄
-            .spawn()
-            .map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Failed to start ffmpeg: {}. Please install ffmpeg.", e)
-                )
-            })?;
-
-        let stdin = ffmpeg.stdin.take()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Failed to get ffmpeg stdin"))?;
+        let process = spawn_ffmpeg(output_path, fps, &config, key.as_ref())?;
 
         Ok(Self {
-            ffmpeg_process: ffmpeg,
-            stdin: Some(Box::new(stdin)),
+            ffmpeg_process: Some(process.child),
+            stdin: Some(process.stdin),
+            stderr_tail: process.stderr_tail,
+            output_thread: process.output_thread,
             frame_count: 0,
             output_path: output_str.to_string(),
+            segments: vec![output_path.to_path_buf()],
+            fps,
+            config,
+            restarts: 0,
+            key,
+            last_frame: Vec::new(),
         })
     }
 
     /// JPEGフレームをffmpegに書き込む
     ///
+    /// ffmpegが終了していた場合や書き込みに失敗した場合は、
+    /// 新しいセグメントでffmpegを再起動して同じフレームを書き込み直す。
+    ///
     /// # Arguments
     /// * `jpeg_data` - JPEGフレームのバイトデータ
     ///
@@ -85,17 +209,38 @@ impl Mp4Recorder {
     /// 成功時はOk(())、失敗時はエラー
     ///
     /// # Errors
-    /// - ffmpegプロセスが終了している場合
-    /// - 書き込みに失敗した場合
+    /// - 再起動回数の上限に達した場合
+    /// - 再起動後も書き込みに失敗した場合
     pub fn write_frame(&mut self, jpeg_data: &[u8]) -> io::Result<()> {
-        if let Some(ref mut stdin) = self.stdin {
-            stdin.write_all(jpeg_data)?;
-            stdin.flush()?;
-            self.frame_count += 1;
-            Ok(())
-        } else {
-            Err(io::Error::new(io::ErrorKind::BrokenPipe, "stdin already closed"))
+        if let Err(e) = self.try_write(jpeg_data) {
+            error!("ffmpeg failed while recording {}: {}", self.output_path, e);
+            self.log_stderr_tail();
+            self.restart_segment()?;
+            self.try_write(jpeg_data)?;
         }
+
+        self.frame_count += 1;
+        self.last_frame.clear();
+        self.last_frame.extend_from_slice(jpeg_data);
+        Ok(())
+    }
+
+    /// 直前のフレームをもう一度書き込む（まだフレームがなければ何もしない）
+    ///
+    /// 間引いたフレームの時間を埋め、固定フレームレートの再生時間を実時間に合わせる。
+    ///
+    /// # Errors
+    /// - `write_frame`と同じ
+    pub fn repeat_last_frame(&mut self) -> io::Result<()> {
+        if self.last_frame.is_empty() {
+            return Ok(());
+        }
+        let frame = std::mem::take(&mut self.last_frame);
+        let result = self.write_frame(&frame);
+        if result.is_err() {
+            self.last_frame = frame;
+        }
+        result
     }
 
     /// 録画を終了してffmpegプロセスを正常終了させる
@@ -109,16 +254,19 @@ impl Mp4Recorder {
         // stdinをクローズしてffmpegに終了を通知
         self.stdin.take();
 
-        // ffmpegの終了を待つ
-        let status = self.ffmpeg_process.wait()?;
+        // ffmpegの終了を待つ（取り出しておき、Dropでは何もしない）
+        let mut process = self.ffmpeg_process.take().expect("ffmpeg process is only taken by finish");
+        let status = process.wait()?;
 
-        if status.success() {
-            Ok(())
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("ffmpeg exited with status: {}", status)
-            ))
+        if !status.success() {
+            self.log_stderr_tail();
+            return Err(io::Error::other(format!("ffmpeg exited with status: {}", status)));
+        }
+
+        // 暗号化時は最終チャンクの書き込みを待つ
+        match self.output_thread.take() {
+            Some(handle) => join_output_thread(handle),
+            None => Ok(()),
         }
     }
 
@@ -127,17 +275,262 @@ impl Mp4Recorder {
         self.frame_count
     }
 
-    /// 出力ファイルパスを取得
-    pub fn output_path(&self) -> &str {
-        &self.output_path
+    /// 録画の全セグメントを取得（ffmpeg再起動時に追加される）
+    pub fn segments(&self) -> &[PathBuf] {
+        &self.segments
+    }
+
+    /// 終了を検出してからstdinに書き込む
+    fn try_write(&mut self, jpeg_data: &[u8]) -> io::Result<()> {
+        if let Some(status) = self.process().try_wait()? {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("ffmpeg exited unexpectedly ({})", status),
+            ));
+        }
+
+        if let Some(ref mut stdin) = self.stdin {
+            stdin.write_all(jpeg_data)?;
+            stdin.flush()
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "stdin already closed"))
+        }
+    }
+
+    /// 現在のffmpegを止め、新しいセグメントファイルで再起動する
+    fn restart_segment(&mut self) -> io::Result<()> {
+        if self.restarts >= MAX_RESTARTS {
+            return Err(io::Error::other(format!(
+                "ffmpeg failed {} times, giving up on {}", self.restarts + 1, self.output_path
+            )));
+        }
+        self.restarts += 1;
+
+        // 出力はフラグメント化MP4なので、強制終了しても書き込み済みの部分は再生できる
+        self.stdin.take();
+        let _ = self.process().kill();
+        let _ = self.process().wait();
+        if let Some(handle) = self.output_thread.take() {
+            if let Err(e) = join_output_thread(handle) {
+                warn!("Failed to finish encrypted segment {}: {}", self.output_path, e);
+            }
+        }
+
+        let path = segment_path(&self.segments[0], self.segments.len() + 1);
+        let process = spawn_ffmpeg(&path, self.fps, &self.config, self.key.as_ref())?;
+
+        warn!("Restarted ffmpeg ({}/{}), continuing in new segment {:?}", self.restarts, MAX_RESTARTS, path);
+
+        self.ffmpeg_process = Some(process.child);
+        self.stdin = Some(process.stdin);
+        self.stderr_tail = process.stderr_tail;
+        self.output_thread = process.output_thread;
+        self.output_path = path.to_string_lossy().into_owned();
+        self.segments.push(path);
+        Ok(())
+    }
+
+    /// 実行中のffmpegプロセス
+    fn process(&mut self) -> &mut Child {
+        self.ffmpeg_process.as_mut().expect("ffmpeg process is only taken by finish")
+    }
+
+    /// 直近のstderr出力をエラーログに出す
+    fn log_stderr_tail(&self) {
+        let tail = self.stderr_tail.lock().unwrap();
+        for line in tail.iter() {
+            error!("  ffmpeg: {}", line);
+        }
     }
 }
 
 impl Drop for Mp4Recorder {
     fn drop(&mut self) {
-        // プロセスが残っている場合は強制終了
-        let _ = self.ffmpeg_process.kill();
+        // finishせずに破棄された場合は、stdinをクローズして正常終了を促すだけで戻る。
+        // 終了待ちと暗号化出力の後始末は別スレッドで行い、呼び出し元（UIスレッドなど）を止めない
+        self.stdin.take();
+        let Some(process) = self.ffmpeg_process.take() else {
+            return;
+        };
+        let output_thread = self.output_thread.take();
+        let name = self.output_path.clone();
+        let reaper = thread::Builder::new()
+            .name("ffmpeg-reaper".to_string())
+            .spawn(move || reap_ffmpeg(process, output_thread, &name));
+        if let Err(e) = reaper {
+            warn!("Failed to start a thread to wait for ffmpeg: {}", e);
+        }
+    }
+}
+
+/// ffmpegの終了を`DROP_WAIT`まで待ち（終わらなければ強制終了）、暗号化出力スレッドを終える
+fn reap_ffmpeg(mut process: Child, output_thread: Option<JoinHandle<io::Result<()>>>, name: &str) {
+    let deadline = Instant::now() + DROP_WAIT;
+    let mut exited = false;
+    while Instant::now() < deadline {
+        match process.try_wait() {
+            Ok(Some(_)) => {
+                exited = true;
+                break;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(_) => break,
+        }
+    }
+
+    if !exited {
+        warn!("ffmpeg did not exit within {:?}, killing it ({})", DROP_WAIT, name);
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+    if let Some(handle) = output_thread {
+        if let Err(e) = join_output_thread(handle) {
+            warn!("Failed to finish encrypted segment {}: {}", name, e);
+        }
+    }
+}
+
+/// 起動したffmpegプロセス
+struct FfmpegProcess {
+    child: Child,
+    stdin: Box<dyn Write + Send>,
+    /// stderrの直近の行
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    /// 暗号化時の出力スレッド
+    output_thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+/// ffmpegプロセスを起動する
+///
+/// 鍵が指定された場合、ffmpegはstdoutにMP4を出力し、出力スレッドが暗号化して書き込む。
+fn spawn_ffmpeg(
+    output_path: &Path,
+    fps: u32,
+    config: &Mp4EncoderConfig,
+    key: Option<&KeySource>,
+) -> io::Result<FfmpegProcess> {
+    let fps_str = fps.to_string();
+    // 出力ファイルはffmpegの起動前に作成し、作成エラーをすぐに返す
+    let encrypted_output = match key {
+        Some(key) => Some(EncryptedWriter::new(File::create(output_path)?, key)?),
+        None => None,
+    };
+
+    // ffmpegコマンドを構築
+    let mut command = Command::new("ffmpeg");
+    command
+        .args([
+            "-hide_banner",
+            "-loglevel", "warning",           // 警告とエラーのみstderrに出力
+            "-nostats",
+            "-f", "image2pipe",               // 入力形式: 画像パイプ
+            "-codec:v", "mjpeg",              // 入力コーデック: MJPEG
+            "-framerate", &fps_str,           // フレームレート
+            "-i", "-",                        // 入力: stdin
+        ])
+        .args(config.encoder_args())          // 出力コーデック、preset、CRF
+        .args([
+            "-pix_fmt", "yuv420p",            // 互換性のためのピクセルフォーマット
+            "-g", &fps_str,                   // キーフレーム間隔: 1秒（=フラグメント長）
+            // フラグメント化MP4: moovを先頭に空で書き、キーフレームごとにフラグメントを追記
+            "-movflags", "+frag_keyframe+empty_moov+default_base_moof",
+        ])
+        .args(&config.extra_args);
+    if encrypted_output.is_some() {
+        command
+            .args(["-f", "mp4", "pipe:1"])    // 出力: stdout（暗号化して保存）
+            .stdout(Stdio::piped());
+    } else {
+        command
+            .arg("-y")                        // 上書き確認なし
+            .arg(output_path)
+            .stdout(Stdio::null());           // ffmpegの標準出力を破棄
     }
+    command
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped());              // ffmpegの標準エラー出力はログへ転送
+
+    let mut ffmpeg = command.spawn().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Failed to start ffmpeg: {}. Please install ffmpeg.", e)
+        )
+    })?;
+
+    let stdin = ffmpeg.stdin.take()
+        .ok_or_else(|| io::Error::other("Failed to get ffmpeg stdin"))?;
+
+    let stderr_tail = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LINES)));
+    if let Some(stderr) = ffmpeg.stderr.take() {
+        let name = output_path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        forward_stderr(stderr, name, stderr_tail.clone());
+    }
+
+    let output_thread = match (encrypted_output, ffmpeg.stdout.take()) {
+        (Some(writer), Some(stdout)) => Some(pipe_encrypted(stdout, writer)),
+        _ => None,
+    };
+
+    Ok(FfmpegProcess { child: ffmpeg, stdin: Box::new(stdin), stderr_tail, output_thread })
+}
+
+/// ffmpegのstdoutを暗号化してファイルに書き込むスレッドを起動
+///
+/// MJPEG録画と同じ間隔でチャンクを書き出してディスクに同期する。
+fn pipe_encrypted(mut stdout: ChildStdout, mut writer: EncryptedWriter<File>) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut last_sync = Instant::now();
+        loop {
+            let n = match stdout.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            writer.write_all(&buf[..n])?;
+
+            if last_sync.elapsed() >= DEFAULT_SYNC_INTERVAL {
+                writer.flush_chunk()?;
+                writer.get_ref().sync_data()?;
+                last_sync = Instant::now();
+            }
+        }
+        writer.finish()?.sync_all()
+    })
+}
+
+fn join_output_thread(handle: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    handle.join().unwrap_or_else(|_| Err(io::Error::other("encrypted output thread panicked")))
+}
+
+/// ffmpegのstderrを読み取ってログに転送するスレッドを起動
+fn forward_stderr(stderr: ChildStderr, name: String, tail: Arc<Mutex<VecDeque<String>>>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            warn!("ffmpeg [{}]: {}", name, line);
+
+            let mut tail = tail.lock().unwrap();
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    });
+}
+
+/// セグメントファイルのパス（例: `motion_20260102_021400_seg2.mp4`）
+pub fn segment_path(first: &Path, index: usize) -> PathBuf {
+    let stem = first.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = first.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_else(|| "mp4".to_string());
+    first.with_file_name(format!("{}_seg{}.{}", stem, index, ext))
 }
 
 #[cfg(test)]
@@ -145,6 +538,78 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_default_encoder_args() {
+        let args = Mp4EncoderConfig::default().encoder_args();
+        assert_eq!(args, ["-c:v", "libx264", "-preset", "medium", "-crf", "23"]);
+    }
+
+    #[test]
+    fn test_custom_encoder_args() {
+        let config = Mp4EncoderConfig {
+            codec: "libx265".to_string(),
+            preset: String::new(),
+            crf: None,
+            extra_args: Mp4EncoderConfig::parse_extra_args("  -tune zerolatency  -threads 2 "),
+        };
+
+        assert_eq!(config.encoder_args(), ["-c:v", "libx265"]);
+        assert_eq!(config.extra_args, ["-tune", "zerolatency", "-threads", "2"]);
+    }
+
+    #[test]
+    fn test_encoder_listed() {
+        let encoders = "\
+Encoders:
+ V..... = Video
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 (codec h264)
+ V....D mjpeg                MJPEG (Motion JPEG)
+";
+        assert!(encoder_listed(encoders, "libx264"));
+        assert!(encoder_listed(encoders, "mjpeg"));
+        assert!(!encoder_listed(encoders, "libx265"));
+        assert!(!encoder_listed(encoders, "H.264"));
+    }
+
+    #[test]
+    fn test_segment_path() {
+        let first = PathBuf::from("recordings/motion_20260102_021400.mp4");
+        assert_eq!(segment_path(&first, 2), PathBuf::from("recordings/motion_20260102_021400_seg2.mp4"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_drop_does_not_wait_for_ffmpeg() {
+        // stdinを閉じても終わらないプロセスをffmpegの代わりにする
+        let process = Command::new("sleep").arg("30").stdin(Stdio::null()).spawn().unwrap();
+        let recorder = Mp4Recorder {
+            ffmpeg_process: Some(process),
+            stdin: None,
+            stderr_tail: Arc::new(Mutex::new(VecDeque::new())),
+            output_thread: None,
+            frame_count: 0,
+            output_path: "hung.mp4".to_string(),
+            segments: vec![PathBuf::from("hung.mp4")],
+            fps: 11,
+            config: Mp4EncoderConfig::default(),
+            restarts: 0,
+            key: None,
+            last_frame: Vec::new(),
+        };
+
+        let started = Instant::now();
+        drop(recorder);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    #[ignore] // ffmpegがインストールされていない環境では失敗するため
+    fn test_probe_ffmpeg() {
+        let probe = probe_ffmpeg("libx264").unwrap();
+        assert!(probe.version.starts_with("ffmpeg version"));
+    }
+
     #[test]
     #[ignore] // ffmpegがインストールされていない環境では失敗するため
     fn test_mp4_recorder_creation() {
@@ -182,4 +647,25 @@ mod tests {
         // 録画終了
         recorder.finish().unwrap();
     }
+
+    #[test]
+    #[ignore] // ffmpegがインストールされていない環境では失敗するため
+    fn test_mp4_recorder_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("encrypted.mp4");
+        let key = KeySource::KeyFile([1; 32]);
+        let mut recorder = Mp4Recorder::with_config(&output_path, 11, Mp4EncoderConfig::default(), Some(key.clone())).unwrap();
+
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(64, 48).write_to(&mut io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(80)).unwrap();
+        for _ in 0..22 {
+            recorder.write_frame(&jpeg).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let reader = crate::crypto::open_recording(&output_path, Some(&key)).unwrap();
+        let (valid_len, samples, total_len) = crate::recovery::scan_fragmented_mp4(reader).unwrap();
+        assert_eq!(samples, 22);
+        assert_eq!(valid_len, total_len);
+    }
 }
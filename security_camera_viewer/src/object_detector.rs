@@ -0,0 +1,858 @@
+//! 物体検知（人・車など）モジュール
+//!
+//! 動きのあったフレームだけを、ディスクから読み込んだ小さな物体検知モデル（YOLO系のONNX）でCPU推論する。
+//! 推論は`object-detection`フィーチャーで有効になる純Rustの`tract-onnx`で行い、GPUもネットワークも使わない。
+//! フィーチャーなしでビルドした場合も設定は読み込めるが、モデルの読み込みはエラーになる。
+//!
+//! 入力は`input_size`四方にレターボックス（縦横比を保って縮小し、余白を灰色で埋める）したRGB（0.0-1.0, NCHW）。
+//! 出力は次の2形式に対応する（`ModelFormat::Auto`では出力の形から判定する）:
+//! - YOLOv5: `[1, 候補数, 5 + クラス数]`（中心x, 中心y, 幅, 高さ, 物体らしさ, クラスごとのスコア）
+//! - YOLOv8: `[1, 4 + クラス数, 候補数]`（中心x, 中心y, 幅, 高さ, クラスごとのスコア）
+//!
+//! 座標は入力画像の画素で、クラスごとにNMS（重なりの大きい枠の抑制）をかけてから
+//! 元のフレームに対する0.0-1.0の枠に戻す。
+//!
+//! `trigger_classes`を設定すると、動きイベント中にそのクラスを検知したときだけ録画する（「人がいたときだけ録画」）。
+//!
+//! 推論は検知器ごとの推論スレッドで行い、解析（`FrameAnalyzer::analyze`）はモデルを待たずに戻る。
+//! 推論中に届いたフレームは推論せずに捨て、終わった推論の結果は次に解析するフレームで`Detection::Objects`として返す。
+//! 結果（`ObjectFrame`）には推論したフレームの受信時刻・通し番号と推論時間を付け、
+//! 無効にする・設定を変える前に依頼した推論の結果は返さない。
+
+use crate::frame_analyzer::{AnalyzerFrame, Detection, FrameAnalyzer};
+use chrono::{DateTime, Duration, Local};
+use image::RgbaImage;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Instant;
+
+/// レターボックスの余白の色（YOLOの学習時と同じ灰色）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+const PAD_VALUE: f32 = 114.0 / 255.0;
+
+/// COCOの80クラス（YOLOの学習済みモデルのクラス順）
+pub const COCO_LABELS: [&str; 80] = [
+    "person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck", "boat", "traffic light",
+    "fire hydrant", "stop sign", "parking meter", "bench", "bird", "cat", "dog", "horse", "sheep", "cow",
+    "elephant", "bear", "zebra", "giraffe", "backpack", "umbrella", "handbag", "tie", "suitcase", "frisbee",
+    "skis", "snowboard", "sports ball", "kite", "baseball bat", "baseball glove", "skateboard", "surfboard",
+    "tennis racket", "bottle", "wine glass", "cup", "fork", "knife", "spoon", "bowl", "banana", "apple",
+    "sandwich", "orange", "broccoli", "carrot", "hot dog", "pizza", "donut", "cake", "chair", "couch",
+    "potted plant", "bed", "dining table", "toilet", "tv", "laptop", "mouse", "remote", "keyboard",
+    "cell phone", "microwave", "oven", "toaster", "sink", "refrigerator", "book", "clock", "vase",
+    "scissors", "teddy bear", "hair drier", "toothbrush",
+];
+
+/// 物体検知設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObjectDetectionConfig {
+    /// 物体検知ON/OFF
+    pub enabled: bool,
+    /// ONNXモデルのパス
+    pub model_path: PathBuf,
+    /// モデルの出力形式
+    pub format: ModelFormat,
+    /// モデルの入力の一辺（画素、デフォルト640）
+    pub input_size: u32,
+    /// クラス番号順のラベル（デフォルトはCOCOの80クラス）
+    pub labels: Vec<String>,
+    /// この信頼度（0.0-1.0, デフォルト0.5）以上の検知だけを使う
+    pub min_confidence: f32,
+    /// NMSで同じ物体とみなす枠の重なり（IoU, デフォルト0.45）
+    pub iou_threshold: f32,
+    /// 報告するクラス（空ならすべて）
+    pub classes: Vec<String>,
+    /// 録画のきっかけにするクラス（空なら動きだけで録画する）
+    pub trigger_classes: Vec<String>,
+    /// 動きが続く間、推論する最短の間隔（ms, デフォルト500）
+    pub min_interval_ms: u64,
+}
+
+impl Default for ObjectDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model_path: PathBuf::from("models/yolov8n.onnx"),
+            format: ModelFormat::Auto,
+            input_size: 640,
+            labels: COCO_LABELS.iter().map(|label| label.to_string()).collect(),
+            min_confidence: 0.5,
+            iou_threshold: 0.45,
+            classes: Vec::new(),
+            trigger_classes: Vec::new(),
+            min_interval_ms: 500,
+        }
+    }
+}
+
+impl ObjectDetectionConfig {
+    /// 録画のきっかけになる検知（`trigger_classes`のうち信頼度の最も高いもの）
+    pub fn trigger<'a>(&self, detections: &'a [ObjectDetection]) -> Option<&'a ObjectDetection> {
+        detections
+            .iter()
+            .filter(|detection| self.trigger_classes.contains(&detection.label))
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    #[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+    fn label(&self, class_id: usize) -> String {
+        self.labels.get(class_id).cloned().unwrap_or_else(|| format!("class {}", class_id))
+    }
+}
+
+/// モデルの出力形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFormat {
+    /// 出力の形から判定（候補数の方が多い軸を候補の軸とみなす）
+    #[default]
+    Auto,
+    /// `[1, 候補数, 5 + クラス数]`
+    Yolov5,
+    /// `[1, 4 + クラス数, 候補数]`
+    Yolov8,
+}
+
+/// 検知した物体
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectDetection {
+    pub class_id: usize,
+    pub label: String,
+    /// 信頼度（0.0-1.0）
+    pub confidence: f32,
+    /// 枠（x, y, 幅, 高さ、フレームの幅・高さに対する0.0-1.0）
+    pub bbox: [f32; 4],
+}
+
+impl fmt::Display for ObjectDetection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:.0}%", self.label, self.confidence * 100.0)
+    }
+}
+
+/// 1フレームの推論結果
+///
+/// 推論は後のフレームの解析で返すので、推論したフレームの受信時刻と通し番号を付ける。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectFrame {
+    /// 推論したフレームの受信時刻
+    pub captured_at: DateTime<Local>,
+    /// 推論したフレームの通し番号
+    pub sequence: u64,
+    /// 推論スレッドでの処理時間（ms、CLIではJPEGのデコードを含む）
+    pub inference_ms: f32,
+    pub objects: Vec<ObjectDetection>,
+}
+
+/// フレームをモデルの入力に収めたときの縮小率と余白（入力画像の画素）
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+struct Letterbox {
+    scale: f32,
+    pad_x: f32,
+    pad_y: f32,
+    width: u32,
+    height: u32,
+}
+
+/// フレームを`size`四方にレターボックスし、NCHWのRGB（0.0-1.0）にする
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+fn letterbox(image: &RgbaImage, size: u32) -> (Vec<f32>, Letterbox) {
+    let (width, height) = image.dimensions();
+    let scale = (size as f32 / width as f32).min(size as f32 / height as f32);
+    let scaled_w = ((width as f32 * scale).round() as u32).clamp(1, size);
+    let scaled_h = ((height as f32 * scale).round() as u32).clamp(1, size);
+    let resized = image::imageops::resize(image, scaled_w, scaled_h, image::imageops::FilterType::Triangle);
+    let (pad_x, pad_y) = ((size - scaled_w) / 2, (size - scaled_h) / 2);
+
+    let plane = (size * size) as usize;
+    let mut input = vec![PAD_VALUE; plane * 3];
+    for (x, y, pixel) in resized.enumerate_pixels() {
+        let i = ((y + pad_y) * size + x + pad_x) as usize;
+        for c in 0..3 {
+            input[c * plane + i] = pixel[c] as f32 / 255.0;
+        }
+    }
+    let letterbox = Letterbox { scale, pad_x: pad_x as f32, pad_y: pad_y as f32, width, height };
+    (input, letterbox)
+}
+
+/// モデルの出力を検知結果にする（NMS前）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+fn decode(output: &[f32], shape: &[usize], format: ModelFormat, letterbox: &Letterbox, config: &ObjectDetectionConfig) -> Vec<ObjectDetection> {
+    let (rows, cols) = match shape {
+        [1, rows, cols] | [rows, cols] => (*rows, *cols),
+        _ => return Vec::new(),
+    };
+    if output.len() != rows * cols {
+        return Vec::new();
+    }
+    let format = match format {
+        ModelFormat::Auto if rows > cols => ModelFormat::Yolov5,
+        ModelFormat::Auto => ModelFormat::Yolov8,
+        format => format,
+    };
+    // 候補数と、候補ごとの値（i番目の候補のj番目の値）
+    let (candidates, values) = match format {
+        ModelFormat::Yolov8 => (cols, rows),
+        _ => (rows, cols),
+    };
+    let value = |i: usize, j: usize| match format {
+        ModelFormat::Yolov8 => output[j * cols + i],
+        _ => output[i * cols + j],
+    };
+    let first_class = if format == ModelFormat::Yolov5 { 5 } else { 4 };
+    if values <= first_class {
+        return Vec::new();
+    }
+
+    let mut detections = Vec::new();
+    for i in 0..candidates {
+        let objectness = if format == ModelFormat::Yolov5 { value(i, 4) } else { 1.0 };
+        let (class_id, score) = (first_class..values)
+            .map(|j| (j - first_class, value(i, j)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or_default();
+        let confidence = objectness * score;
+        if confidence < config.min_confidence {
+            continue;
+        }
+        let label = config.label(class_id);
+        if !config.classes.is_empty() && !config.classes.contains(&label) {
+            continue;
+        }
+
+        // 入力画像の中心・大きさ → フレームに対する左上・大きさ
+        let (cx, cy, w, h) = (value(i, 0), value(i, 1), value(i, 2), value(i, 3));
+        let x0 = ((cx - w / 2.0 - letterbox.pad_x) / letterbox.scale / letterbox.width as f32).clamp(0.0, 1.0);
+        let y0 = ((cy - h / 2.0 - letterbox.pad_y) / letterbox.scale / letterbox.height as f32).clamp(0.0, 1.0);
+        let x1 = ((cx + w / 2.0 - letterbox.pad_x) / letterbox.scale / letterbox.width as f32).clamp(0.0, 1.0);
+        let y1 = ((cy + h / 2.0 - letterbox.pad_y) / letterbox.scale / letterbox.height as f32).clamp(0.0, 1.0);
+        detections.push(ObjectDetection { class_id, label, confidence, bbox: [x0, y0, x1 - x0, y1 - y0] });
+    }
+    detections
+}
+
+/// 2つの枠の重なり（共通部分の面積 / 和集合の面積）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let w = ((a[0] + a[2]).min(b[0] + b[2]) - a[0].max(b[0])).max(0.0);
+    let h = ((a[1] + a[3]).min(b[1] + b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = w * h;
+    let union = a[2] * a[3] + b[2] * b[3] - intersection;
+    if union > 0.0 { intersection / union } else { 0.0 }
+}
+
+/// クラスごとに、信頼度の高い枠と大きく重なる枠を除く（信頼度の高い順に返す）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+fn non_max_suppression(mut detections: Vec<ObjectDetection>, iou_threshold: f32) -> Vec<ObjectDetection> {
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<ObjectDetection> = Vec::new();
+    for detection in detections {
+        let suppressed = kept.iter().any(|other| {
+            other.class_id == detection.class_id && iou(&other.bbox, &detection.bbox) > iou_threshold
+        });
+        if !suppressed {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+/// このフレームを推論するか（動きのあったフレームだけ、`min_interval_ms`おき）
+fn wants_frame(config: &ObjectDetectionConfig, last_run: Option<DateTime<Local>>, earlier: &[Detection], now: DateTime<Local>) -> bool {
+    let motion = earlier.iter().any(|detection| matches!(detection, Detection::Motion(result) if result.motion_detected));
+    let due = last_run.is_none_or(|at| now - at >= Duration::milliseconds(config.min_interval_ms as i64));
+    motion && due
+}
+
+#[cfg(feature = "object-detection")]
+type Plan = tract_onnx::prelude::TypedRunnableModel<tract_onnx::prelude::TypedModel>;
+
+/// 1フレームの推論（推論スレッドが持つ）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+type Inference = Box<dyn FnMut(&RgbaImage, &ObjectDetectionConfig) -> io::Result<Vec<ObjectDetection>> + Send>;
+
+/// 推論スレッドに渡すフレーム
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+enum JobFrame {
+    Image(RgbaImage),
+    /// CLIはJPEGだけを渡すので、推論するフレームだけ推論スレッドでデコードする
+    Jpeg(Vec<u8>),
+}
+
+/// 推論スレッドへの依頼
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+struct Job {
+    frame: JobFrame,
+    config: ObjectDetectionConfig,
+    captured_at: DateTime<Local>,
+    sequence: u64,
+    /// 依頼したときの設定の世代（`ObjectDetector::generation`）
+    generation: u64,
+}
+
+/// 推論スレッドからの結果
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+struct JobResult {
+    generation: u64,
+    captured_at: DateTime<Local>,
+    sequence: u64,
+    inference_ms: f32,
+    objects: io::Result<Vec<ObjectDetection>>,
+}
+
+/// 推論スレッド: フレームを受け取った順に推論し、結果を返す（検知器が破棄されたら終わる）
+#[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+fn inference_thread(jobs: Receiver<Job>, results: mpsc::Sender<JobResult>, mut infer: Inference) {
+    for job in jobs {
+        let start = Instant::now();
+        let objects = match job.frame {
+            JobFrame::Image(image) => infer(&image, &job.config),
+            JobFrame::Jpeg(jpeg) => image::load_from_memory(&jpeg)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                .and_then(|image| infer(&image.to_rgba8(), &job.config)),
+        };
+        let result = JobResult {
+            generation: job.generation,
+            captured_at: job.captured_at,
+            sequence: job.sequence,
+            inference_ms: start.elapsed().as_secs_f32() * 1000.0,
+            objects,
+        };
+        if results.send(result).is_err() {
+            break;
+        }
+    }
+}
+
+/// 1フレームを推論する
+#[cfg(feature = "object-detection")]
+fn infer(plan: &Plan, image: &RgbaImage, config: &ObjectDetectionConfig) -> io::Result<Vec<ObjectDetection>> {
+    use tract_onnx::prelude::*;
+
+    let size = config.input_size as usize;
+    let (input, letterbox) = letterbox(image, config.input_size);
+    let to_io = |e: TractError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let input = Tensor::from_shape(&[1, 3, size, size], &input).map_err(to_io)?;
+    let outputs = plan.run(tvec!(input.into())).map_err(to_io)?;
+    let output = outputs.first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "model has no output"))?;
+    let values = output.as_slice::<f32>().map_err(to_io)?;
+    let detections = decode(values, output.shape(), config.format, &letterbox, config);
+    Ok(non_max_suppression(detections, config.iou_threshold))
+}
+
+/// ONNXモデルによる物体検知器（動き検知の後に実行する解析器）
+pub struct ObjectDetector {
+    config: ObjectDetectionConfig,
+    /// 推論スレッドへのフレーム（推論中は送らない）
+    jobs: SyncSender<Job>,
+    /// 推論スレッドからの結果
+    results: Receiver<JobResult>,
+    /// 推論スレッドがフレームを推論中か
+    busy: bool,
+    /// 設定の世代（無効にする・設定を変えるたびに増やし、前の世代の結果は捨てる）
+    generation: u64,
+    last_run: Option<DateTime<Local>>,
+    last_detections: Vec<ObjectDetection>,
+}
+
+impl ObjectDetector {
+    /// `config.model_path`のモデルを読み込む
+    #[cfg(feature = "object-detection")]
+    pub fn load(config: ObjectDetectionConfig) -> io::Result<Self> {
+        use tract_onnx::prelude::*;
+
+        let model = tract_onnx::onnx()
+            .model_for_path(&config.model_path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", config.model_path, e)))?;
+        Self::from_model(model, config)
+    }
+
+    #[cfg(not(feature = "object-detection"))]
+    pub fn load(_config: ObjectDetectionConfig) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "built without the object-detection feature"))
+    }
+
+    /// 読み込んだモデルの入力を`config.input_size`四方に固定して最適化する
+    #[cfg(feature = "object-detection")]
+    fn from_model(model: tract_onnx::prelude::InferenceModel, config: ObjectDetectionConfig) -> io::Result<Self> {
+        use tract_onnx::prelude::*;
+
+        let size = config.input_size as usize;
+        let plan = model
+            .with_input_fact(0, f32::fact([1, 3, size, size]).into())
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Self::with_inference(config, Box::new(move |image, config| infer(&plan, image, config)))
+    }
+
+    /// 推論スレッドを起動する
+    ///
+    /// 推論中に届くフレームは推論しないので、待ちのフレームは最大1つ。
+    #[cfg_attr(not(feature = "object-detection"), allow(dead_code))]
+    fn with_inference(config: ObjectDetectionConfig, infer: Inference) -> io::Result<Self> {
+        let (jobs, job_rx) = mpsc::sync_channel(1);
+        let (result_tx, results) = mpsc::channel();
+        thread::Builder::new()
+            .name("object-detector".to_string())
+            .spawn(move || inference_thread(job_rx, result_tx, infer))?;
+        Ok(Self { config, jobs, results, busy: false, generation: 0, last_run: None, last_detections: Vec::new() })
+    }
+
+    pub fn config(&self) -> &ObjectDetectionConfig {
+        &self.config
+    }
+
+    /// 設定を変更する（モデルと入力の大きさは読み込み時のまま）
+    ///
+    /// 設定が変わったら、前の設定で推論中・結果待ちのフレームの結果は返さない。
+    pub fn set_config(&mut self, config: ObjectDetectionConfig) {
+        let config = ObjectDetectionConfig {
+            model_path: self.config.model_path.clone(),
+            input_size: self.config.input_size,
+            ..config
+        };
+        if config != self.config {
+            self.discard_pending();
+        }
+        self.config = config;
+    }
+
+    /// 推論中・結果待ちのフレームの結果を捨てる
+    fn discard_pending(&mut self) {
+        self.generation += 1;
+        self.last_detections.clear();
+    }
+
+    /// 直近に推論したフレームの検知結果
+    pub fn last_detections(&self) -> &[ObjectDetection] {
+        &self.last_detections
+    }
+}
+
+impl FrameAnalyzer for ObjectDetector {
+    fn name(&self) -> &str {
+        "objects"
+    }
+
+    fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn deferred(&self) -> bool {
+        true
+    }
+
+    fn disabled(&mut self) {
+        self.discard_pending();
+    }
+
+    fn analyze(&mut self, frame: &AnalyzerFrame, earlier: &[Detection]) -> Vec<Detection> {
+        // 前のフレームまでに送った推論のうち、終わったものの結果（無効にする・設定を変える前のものは捨てる）
+        let mut found = Vec::new();
+        for result in self.results.try_iter() {
+            self.busy = false;
+            if result.generation != self.generation {
+                continue;
+            }
+            match result.objects {
+                Ok(objects) => {
+                    self.last_detections = objects.clone();
+                    found.push(Detection::Objects(ObjectFrame {
+                        captured_at: result.captured_at,
+                        sequence: result.sequence,
+                        inference_ms: result.inference_ms,
+                        objects,
+                    }));
+                }
+                Err(e) => warn!("Object detection failed on frame #{}: {}", result.sequence, e),
+            }
+        }
+
+        // 推論中ならこのフレームは推論しない
+        if self.busy || !wants_frame(&self.config, self.last_run, earlier, frame.captured_at) {
+            return found;
+        }
+        let job = match (frame.image, frame.jpeg) {
+            (Some(image), _) => JobFrame::Image(image.clone()),
+            (None, Some(jpeg)) => JobFrame::Jpeg(jpeg.to_vec()),
+            (None, None) => return found,
+        };
+        let job = Job {
+            frame: job,
+            config: self.config.clone(),
+            captured_at: frame.captured_at,
+            sequence: frame.sequence,
+            generation: self.generation,
+        };
+        match self.jobs.try_send(job) {
+            Ok(()) => {
+                self.busy = true;
+                self.last_run = Some(frame.captured_at);
+            }
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => warn!("Object detection thread has stopped"),
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion_detector::MotionResult;
+    use chrono::TimeZone;
+
+    fn at(ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 4, 12, 0, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    fn config(classes: &[&str]) -> ObjectDetectionConfig {
+        ObjectDetectionConfig {
+            labels: vec!["person".to_string(), "car".to_string(), "cat".to_string()],
+            classes: classes.iter().map(|class| class.to_string()).collect(),
+            ..ObjectDetectionConfig::default()
+        }
+    }
+
+    /// 640x480のフレームを640四方に入れたときのレターボックス（上下に80pxの余白）
+    fn vga() -> Letterbox {
+        Letterbox { scale: 1.0, pad_x: 0.0, pad_y: 80.0, width: 640, height: 480 }
+    }
+
+    fn motion(detected: bool) -> Detection {
+        Detection::Motion(MotionResult { motion_detected: detected, ..MotionResult::default() })
+    }
+
+    /// 毎フレーム動きありを返す解析器（物体検知の前に登録する）
+    struct AlwaysMotion;
+
+    impl FrameAnalyzer for AlwaysMotion {
+        fn name(&self) -> &str {
+            "motion"
+        }
+
+        fn analyze(&mut self, _frame: &AnalyzerFrame, _earlier: &[Detection]) -> Vec<Detection> {
+            vec![motion(true)]
+        }
+    }
+
+    /// `release`を受け取るまで終わらない遅いモデル（推論を始めると`started`に画像の大きさを送る）
+    fn slow_model(result: Vec<ObjectDetection>) -> (Inference, mpsc::Sender<()>, Receiver<(u32, u32)>) {
+        let (release_tx, release) = mpsc::channel::<()>();
+        let (started_tx, started) = mpsc::channel();
+        let infer: Inference = Box::new(move |image, _config| {
+            started_tx.send(image.dimensions()).ok();
+            release.recv_timeout(std::time::Duration::from_secs(10)).ok();
+            Ok(result.clone())
+        });
+        (infer, release_tx, started)
+    }
+
+    fn person() -> ObjectDetection {
+        ObjectDetection { class_id: 0, label: "person".to_string(), confidence: 0.9, bbox: [0.1, 0.1, 0.2, 0.4] }
+    }
+
+    /// 推論の結果が返るまで`run`を繰り返す（5秒で諦める）
+    fn next_objects(mut run: impl FnMut() -> Vec<Detection>) -> ObjectFrame {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while std::time::Instant::now() < deadline {
+            if let Some(Detection::Objects(found)) = run().into_iter().find(|detection| matches!(detection, Detection::Objects(_))) {
+                return found;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("no object detection result within 5 s");
+    }
+
+    #[test]
+    fn test_decode_yolov8() {
+        // 4 + 3クラス、8候補（行ごとに候補ごとの値が並ぶ）。値があるのは最初の2候補だけ
+        let rows = [
+            [320.0, 100.0], // 中心x
+            [240.0, 100.0], // 中心y
+            [64.0, 10.0],   // 幅
+            [128.0, 10.0],  // 高さ
+            [0.9, 0.1],     // personのスコア
+            [0.05, 0.2],    // carのスコア
+            [0.0, 0.3],     // catのスコア
+        ];
+        let output: Vec<f32> = rows.iter().flat_map(|row| [row[0], row[1], 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]).collect();
+        let detections = decode(&output, &[1, 7, 8], ModelFormat::Auto, &vga(), &config(&[]));
+        assert_eq!(detections.len(), 1);
+        let person = &detections[0];
+        assert_eq!((person.class_id, person.label.as_str()), (0, "person"));
+        assert!((person.confidence - 0.9).abs() < 1e-6);
+        let expected = [288.0 / 640.0, 96.0 / 480.0, 64.0 / 640.0, 128.0 / 480.0];
+        for (value, expected) in person.bbox.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-5, "{:?}", person.bbox);
+        }
+    }
+
+    #[test]
+    fn test_decode_yolov5_uses_objectness() {
+        // 8候補、それぞれ中心x, 中心y, 幅, 高さ, 物体らしさ, person, car, catのスコア
+        let mut output = vec![0.0; 8 * 8];
+        output[..8].copy_from_slice(&[100.0, 200.0, 50.0, 50.0, 0.9, 0.1, 0.8, 0.1]);
+        output[8..16].copy_from_slice(&[300.0, 200.0, 50.0, 50.0, 0.4, 0.9, 0.0, 0.0]);
+        let detections = decode(&output, &[1, 8, 8], ModelFormat::Yolov5, &vga(), &config(&[]));
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].label, "car");
+        assert!((detections[0].confidence - 0.72).abs() < 1e-6);
+
+        // 想定外の形の出力は無視する
+        assert!(decode(&output, &[8, 8, 1], ModelFormat::Yolov5, &vga(), &config(&[])).is_empty());
+        assert!(decode(&output[..60], &[1, 8, 8], ModelFormat::Yolov5, &vga(), &config(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_decode_filters_classes() {
+        let output = [100.0, 300.0, 100.0, 300.0, 50.0, 50.0, 50.0, 50.0, 0.9, 0.0, 0.0, 0.0, 0.0, 0.9];
+        let all = decode(&output, &[1, 7, 2], ModelFormat::Yolov8, &vga(), &config(&[]));
+        assert_eq!(all.iter().map(|d| d.label.as_str()).collect::<Vec<_>>(), ["person", "cat"]);
+        let people = decode(&output, &[1, 7, 2], ModelFormat::Yolov8, &vga(), &config(&["person"]));
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].label, "person");
+    }
+
+    #[test]
+    fn test_non_max_suppression() {
+        let detection = |class_id: usize, confidence: f32, x: f32| ObjectDetection {
+            class_id,
+            label: String::new(),
+            confidence,
+            bbox: [x, 0.1, 0.2, 0.4],
+        };
+        let kept = non_max_suppression(vec![
+            detection(0, 0.6, 0.11),
+            detection(0, 0.9, 0.1),
+            detection(1, 0.7, 0.1),  // 同じ位置の別クラス
+            detection(0, 0.8, 0.6),  // 別の人
+        ], 0.45);
+        let confidences: Vec<f32> = kept.iter().map(|d| d.confidence).collect();
+        assert_eq!(confidences, [0.9, 0.8, 0.7]);
+
+        assert_eq!(iou(&[0.0, 0.0, 0.5, 0.5], &[0.0, 0.0, 0.5, 0.5]), 1.0);
+        assert_eq!(iou(&[0.0, 0.0, 0.2, 0.2], &[0.5, 0.5, 0.2, 0.2]), 0.0);
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let image = RgbaImage::from_pixel(64, 32, image::Rgba([255, 0, 0, 255]));
+        let (input, letterbox) = letterbox(&image, 32);
+        assert_eq!(input.len(), 3 * 32 * 32);
+        assert_eq!((letterbox.scale, letterbox.pad_x, letterbox.pad_y), (0.5, 0.0, 8.0));
+        // 上は灰色の余白、中央は赤いフレーム
+        assert_eq!(input[0], PAD_VALUE);
+        assert_eq!(input[16 * 32 + 16], 1.0);
+        assert_eq!(input[32 * 32 + 16 * 32 + 16], 0.0);
+    }
+
+    #[test]
+    fn test_trigger() {
+        let mut config = config(&[]);
+        let detection = |label: &str, confidence: f32| ObjectDetection {
+            class_id: 0,
+            label: label.to_string(),
+            confidence,
+            bbox: [0.0; 4],
+        };
+        let detections = [detection("cat", 0.9), detection("person", 0.6), detection("person", 0.8)];
+        assert!(config.trigger(&detections).is_none());
+        config.trigger_classes = vec!["person".to_string()];
+        assert_eq!(config.trigger(&detections).unwrap().confidence, 0.8);
+        assert!(config.trigger(&detections[..1]).is_none());
+    }
+
+    #[test]
+    fn test_runs_only_on_motion_frames() {
+        let config = ObjectDetectionConfig { min_interval_ms: 500, ..ObjectDetectionConfig::default() };
+        assert!(!wants_frame(&config, None, &[], at(0)));
+        assert!(!wants_frame(&config, None, &[motion(false)], at(0)));
+        assert!(wants_frame(&config, None, &[motion(true)], at(0)));
+        assert!(!wants_frame(&config, Some(at(0)), &[motion(true)], at(400)));
+        assert!(wants_frame(&config, Some(at(0)), &[motion(true)], at(500)));
+    }
+
+    #[test]
+    fn test_run_does_not_block_on_the_model() {
+        use crate::frame_analyzer::AnalyzerRegistry;
+        use std::time::{Duration, Instant};
+
+        let (infer, release_tx, started) = slow_model(vec![person()]);
+        let config = ObjectDetectionConfig { enabled: true, min_interval_ms: 0, ..config(&[]) };
+        let mut registry = AnalyzerRegistry::new();
+        registry.register(AlwaysMotion);
+        registry.register(ObjectDetector::with_inference(config, infer).unwrap());
+        let image = RgbaImage::new(8, 6);
+        let frame = |ms| AnalyzerFrame { jpeg: None, image: Some(&image), captured_at: at(ms), sequence: ms as u64 };
+
+        // 推論中も解析はすぐ戻り、その間のフレームは推論しない
+        let start = Instant::now();
+        for ms in [0, 100, 200] {
+            assert!(!registry.run(&frame(ms)).iter().any(|detection| matches!(detection, Detection::Objects(_))));
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(started.recv_timeout(Duration::from_secs(5)).unwrap(), (8, 6));
+        assert!(started.try_recv().is_err());
+
+        // 推論が終わると、後のフレームの解析で推論したフレームの時刻・通し番号と推論時間の付いた結果が返る
+        release_tx.send(()).unwrap();
+        let found = next_objects(|| registry.run(&frame(300)));
+        assert_eq!((found.captured_at, found.sequence, found.objects), (at(0), 0, vec![person()]));
+        assert_eq!(registry.get::<ObjectDetector>().unwrap().last_detections().len(), 1);
+
+        // 統計の処理時間は`analyze`ではなく推論の時間
+        let stats = registry.stats().find(|stats| stats.name == "objects").unwrap();
+        assert_eq!(stats.timed_frames, 1);
+        assert_eq!(stats.last_time_ms, found.inference_ms);
+    }
+
+    #[test]
+    fn test_discards_results_after_disable_or_reconfigure() {
+        use crate::frame_analyzer::{AnalyzerRegistry, AnalyzerSetting};
+        use std::time::Duration;
+
+        let (infer, release_tx, started) = slow_model(vec![person()]);
+        let config = ObjectDetectionConfig { enabled: true, min_interval_ms: 0, ..config(&[]) };
+        let mut registry = AnalyzerRegistry::new();
+        registry.register(AlwaysMotion);
+        registry.register(ObjectDetector::with_inference(config.clone(), infer).unwrap());
+        let image = RgbaImage::new(8, 6);
+        let frame = AnalyzerFrame { jpeg: None, image: Some(&image), captured_at: at(0), sequence: 0 };
+        let objects = |detections: Vec<Detection>| detections.iter().any(|detection| matches!(detection, Detection::Objects(_)));
+
+        // 推論中に無効にして有効に戻す（検知器の設定と登録簿の設定のどちらでも）
+        for via_registry in [false, true] {
+            let toggle = |registry: &mut AnalyzerRegistry, enabled: bool| {
+                if via_registry {
+                    let motion = AnalyzerSetting { name: "motion".to_string(), enabled: true };
+                    registry.configure(&[motion, AnalyzerSetting { name: "objects".to_string(), enabled }]);
+                } else {
+                    let config = ObjectDetectionConfig { enabled, ..config.clone() };
+                    registry.get_mut::<ObjectDetector>().unwrap().set_config(config);
+                }
+            };
+            assert!(!objects(registry.run(&frame)));
+            started.recv_timeout(Duration::from_secs(5)).unwrap();
+            toggle(&mut registry, false);
+            toggle(&mut registry, true);
+            release_tx.send(()).unwrap();
+
+            // 無効にする前に依頼した推論の結果は返らず、次の推論が始まる
+            let deadline = std::time::Instant::now() + Duration::from_secs(5);
+            while started.try_recv().is_err() {
+                assert!(std::time::Instant::now() < deadline, "next inference did not start");
+                assert!(!objects(registry.run(&frame)));
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert!(registry.get::<ObjectDetector>().unwrap().last_detections().is_empty());
+
+            // 有効に戻した後に依頼した推論の結果は返る
+            release_tx.send(()).unwrap();
+            next_objects(|| registry.run(&frame));
+        }
+    }
+
+    #[test]
+    fn test_config_defaults_to_coco() {
+        let config: ObjectDetectionConfig = serde_json::from_str(r#"{"enabled": true, "trigger_classes": ["person"]}"#).unwrap();
+        assert_eq!(config.labels.len(), 80);
+        assert_eq!(config.label(7), "truck");
+        assert_eq!(config.label(80), "class 80");
+        assert_eq!(config.format, ModelFormat::Auto);
+    }
+
+    /// 入力を2回並べて[1, 6, 16]にするだけのモデル（YOLOv8形式の2クラス・16候補に見える）
+    #[cfg(feature = "object-detection")]
+    fn concat_model() -> tract_onnx::prelude::InferenceModel {
+        use tract_onnx::pb::*;
+        use tract_onnx::prelude::Framework;
+
+        let value = |name: &str, dims: &[i64]| ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                    elem_type: tensor_proto::DataType::Float as i32,
+                    shape: Some(TensorShapeProto {
+                        dim: dims.iter().map(|&d| tensor_shape_proto::Dimension {
+                            value: Some(tensor_shape_proto::dimension::Value::DimValue(d)),
+                            ..Default::default()
+                        }).collect(),
+                    }),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = |op: &str, inputs: &[&str], output: &str, attribute: Vec<AttributeProto>| NodeProto {
+            op_type: op.to_string(),
+            input: inputs.iter().map(|input| input.to_string()).collect(),
+            output: vec![output.to_string()],
+            attribute,
+            ..Default::default()
+        };
+        let axis = AttributeProto {
+            name: "axis".to_string(),
+            r#type: attribute_proto::AttributeType::Int as i32,
+            i: 1,
+            ..Default::default()
+        };
+        let graph = GraphProto {
+            node: vec![
+                node("Concat", &["images", "images"], "concat", vec![axis]),
+                node("Reshape", &["concat", "shape"], "output0", Vec::new()),
+            ],
+            initializer: vec![TensorProto {
+                name: "shape".to_string(),
+                dims: vec![3],
+                data_type: tensor_proto::DataType::Int64 as i32,
+                int64_data: vec![1, 6, 16],
+                ..Default::default()
+            }],
+            input: vec![value("images", &[1, 3, 4, 4])],
+            output: vec![value("output0", &[1, 6, 16])],
+            ..Default::default()
+        };
+        let proto = ModelProto {
+            ir_version: 7,
+            opset_import: vec![OperatorSetIdProto { domain: String::new(), version: 13 }],
+            graph: Some(graph),
+            ..Default::default()
+        };
+        tract_onnx::onnx().model_for_proto_model(&proto).unwrap()
+    }
+
+    #[cfg(feature = "object-detection")]
+    #[test]
+    fn test_detect_with_onnx_model() {
+        let config = ObjectDetectionConfig {
+            enabled: true,
+            input_size: 4,
+            labels: vec!["person".to_string(), "cat".to_string()],
+            ..ObjectDetectionConfig::default()
+        };
+        let mut detector = ObjectDetector::from_model(concat_model(), config).unwrap();
+
+        // チャンネルR, G, B, R, G, Bが中心x, 中心y, 幅, 高さ, person, catとして読まれ、どの候補も信頼度90%の人になる
+        let frame = RgbaImage::from_pixel(8, 8, image::Rgba([255, 230, 25, 255]));
+        let analyzer_frame = AnalyzerFrame { jpeg: None, image: Some(&frame), captured_at: at(0), sequence: 0 };
+
+        // 動きのあったフレームだけを推論し、結果は後のフレームの解析で返る
+        assert!(detector.analyze(&analyzer_frame, &[motion(false)]).is_empty());
+        assert!(detector.analyze(&analyzer_frame, &[motion(true)]).is_empty());
+        let detections = next_objects(|| detector.analyze(&analyzer_frame, &[motion(false)])).objects;
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].label, "person");
+        assert!((detections[0].confidence - 230.0 / 255.0).abs() < 1e-3);
+        assert_eq!(detector.last_detections().len(), 1);
+    }
+}
@@ -0,0 +1,349 @@
+//! 映像オーバーレイモジュール
+//!
+//! 日時・カメラ名・フレーム番号を画像に描画する。
+//! フォントファイルに依存しないよう5x7ドットの組み込みビットマップフォントを使用し、
+//! 英大文字・数字・一部の記号のみ対応する（英小文字は大文字で描画）。
+//!
+//! 出力先（ライブ表示・録画・スナップショット）ごとに設定を持ち、
+//! 録画ではJPEGをデコードして描画後に再エンコードする（焼き込み）。
+
+use chrono::{DateTime, Local};
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageBuffer, ImageResult, Pixel, RgbImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// 焼き込み時の再エンコード品質
+pub const BURN_IN_JPEG_QUALITY: u8 = 90;
+
+/// グリフの幅・高さ（ドット）
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// 文字間隔を含む1文字分の幅（ドット）
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+/// 背景ボックスの余白（ドット）
+const BOX_PADDING: u32 = 2;
+/// 画像端からの距離（ピクセル）
+const EDGE_MARGIN: u32 = 4;
+
+/// オーバーレイの表示位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    pub const ALL: [OverlayPosition; 4] = [
+        OverlayPosition::TopLeft,
+        OverlayPosition::TopRight,
+        OverlayPosition::BottomLeft,
+        OverlayPosition::BottomRight,
+    ];
+}
+
+impl fmt::Display for OverlayPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverlayPosition::TopLeft => "top-left",
+            OverlayPosition::TopRight => "top-right",
+            OverlayPosition::BottomLeft => "bottom-left",
+            OverlayPosition::BottomRight => "bottom-right",
+        })
+    }
+}
+
+impl FromStr for OverlayPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|p| p.to_string() == s.to_ascii_lowercase())
+            .ok_or_else(|| format!(
+                "unknown overlay position '{}' (expected: top-left, top-right, bottom-left, bottom-right)", s
+            ))
+    }
+}
+
+/// オーバーレイ設定（出力先1つ分）
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayConfig {
+    /// オーバーレイを描画するか
+    pub enabled: bool,
+    /// 日時を表示
+    pub show_time: bool,
+    /// カメラ名を表示
+    pub show_camera_name: bool,
+    /// フレーム番号を表示
+    pub show_sequence: bool,
+    /// 表示位置
+    pub position: OverlayPosition,
+    /// 文字サイズ（1ドットあたりのピクセル数、文字高さ = 7 × scale）
+    pub scale: u32,
+    /// 半透明の背景ボックスを描画
+    pub background: bool,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            show_time: true,
+            show_camera_name: true,
+            show_sequence: false,
+            position: OverlayPosition::TopLeft,
+            scale: 2,
+            background: true,
+        }
+    }
+}
+
+impl OverlayConfig {
+    /// 表示する文字列を組み立てる
+    pub fn text(&self, camera_name: &str, time: DateTime<Local>, sequence: u64) -> String {
+        let mut parts = Vec::new();
+        if self.show_time {
+            parts.push(time.format("%Y-%m-%d %H:%M:%S").to_string());
+        }
+        if self.show_camera_name && !camera_name.is_empty() {
+            parts.push(camera_name.to_string());
+        }
+        if self.show_sequence {
+            parts.push(format!("#{:06}", sequence));
+        }
+        parts.join("  ")
+    }
+}
+
+/// 出力先ごとのオーバーレイ設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlaySettings {
+    /// カメラ名
+    pub camera_name: String,
+    /// ライブ表示
+    pub live: OverlayConfig,
+    /// 録画（焼き込み）
+    pub recording: OverlayConfig,
+    /// スナップショット
+    pub snapshot: OverlayConfig,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        // 証拠として渡すスナップショットには既定で日時を入れる。
+        // 録画への焼き込みは全フレームを再エンコードする（GUIではUIスレッドで）ため、既定では無効
+        Self {
+            camera_name: "Spresense".to_string(),
+            live: OverlayConfig::default(),
+            recording: OverlayConfig::default(),
+            snapshot: OverlayConfig { enabled: true, ..OverlayConfig::default() },
+        }
+    }
+}
+
+/// 文字列を画像に描画する（8bitのRGB/RGBA画像、ライブ表示はRGBA）
+///
+/// 画像からはみ出す部分は描画しない。
+pub fn draw_text<P>(image: &mut ImageBuffer<P, Vec<u8>>, config: &OverlayConfig, text: &str)
+where
+    P: Pixel<Subpixel = u8>,
+{
+    if text.is_empty() {
+        return;
+    }
+
+    let scale = config.scale.max(1);
+    let chars: Vec<char> = text.chars().collect();
+    let pad = if config.background { BOX_PADDING * scale } else { 0 };
+    let box_width = chars.len() as u32 * CELL_WIDTH * scale - scale + pad * 2;
+    let box_height = GLYPH_HEIGHT * scale + pad * 2;
+
+    let (width, height) = image.dimensions();
+    let x0 = match config.position {
+        OverlayPosition::TopLeft | OverlayPosition::BottomLeft => EDGE_MARGIN,
+        OverlayPosition::TopRight | OverlayPosition::BottomRight => width.saturating_sub(box_width + EDGE_MARGIN),
+    };
+    let y0 = match config.position {
+        OverlayPosition::TopLeft | OverlayPosition::TopRight => EDGE_MARGIN,
+        OverlayPosition::BottomLeft | OverlayPosition::BottomRight => height.saturating_sub(box_height + EDGE_MARGIN),
+    };
+
+    if config.background {
+        for y in y0..(y0 + box_height).min(height) {
+            for x in x0..(x0 + box_width).min(width) {
+                image.get_pixel_mut(x, y).apply_without_alpha(|c| c / 3);
+            }
+        }
+    }
+
+    // 背景ボックスがない場合は先に影を描き、明るい背景でも読めるようにする
+    let passes: &[(u32, u8)] = if config.background { &[(0, 255)] } else { &[(1, 0), (0, 255)] };
+    for &(offset, value) in passes {
+        for (i, &ch) in chars.iter().enumerate() {
+            let gx = x0 + pad + i as u32 * CELL_WIDTH * scale + offset;
+            let gy = y0 + pad + offset;
+
+            for (row, bits) in glyph(ch).iter().enumerate() {
+                for col in (0..GLYPH_WIDTH).filter(|col| bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0) {
+                    fill_dot(image, gx + col * scale, gy + row as u32 * scale, scale, value);
+                }
+            }
+        }
+    }
+}
+
+/// scale×scaleの正方形を塗る
+fn fill_dot<P>(image: &mut ImageBuffer<P, Vec<u8>>, x: u32, y: u32, scale: u32, value: u8)
+where
+    P: Pixel<Subpixel = u8>,
+{
+    let (width, height) = image.dimensions();
+    for dy in y..(y + scale).min(height) {
+        for dx in x..(x + scale).min(width) {
+            image.get_pixel_mut(dx, dy).apply_without_alpha(|_| value);
+        }
+    }
+}
+
+/// JPEGフレームにオーバーレイを焼き込んで再エンコードする
+pub fn burn_into_jpeg(jpeg_data: &[u8], config: &OverlayConfig, text: &str) -> ImageResult<Vec<u8>> {
+    let mut image: RgbImage = image::load_from_memory(jpeg_data)?.to_rgb8();
+    draw_text(&mut image, config, text);
+
+    let mut output = Vec::with_capacity(jpeg_data.len() + jpeg_data.len() / 4);
+    JpegEncoder::new_with_quality(&mut output, BURN_IN_JPEG_QUALITY).encode_image(&image)?;
+    Ok(output)
+}
+
+/// 組み込みフォントのグリフ（各行の下位5ビット、最上位ビットが左端）
+fn glyph(ch: char) -> [u8; 7] {
+    match ch.to_ascii_uppercase() {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        ' ' => [0; 7],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        ':' => [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+        '/' => [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        '#' => [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+        '_' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+        '(' => [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+        ')' => [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+        '%' => [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        // 未対応の文字
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use image::{Rgb, Rgba, RgbaImage};
+
+    fn config(position: OverlayPosition) -> OverlayConfig {
+        OverlayConfig { enabled: true, position, ..OverlayConfig::default() }
+    }
+
+    #[test]
+    fn test_overlay_text() {
+        let time = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let mut config = config(OverlayPosition::TopLeft);
+        assert_eq!(config.text("Front Door", time, 42), "2026-01-02 03:04:05  Front Door");
+
+        config.show_sequence = true;
+        config.show_camera_name = false;
+        assert_eq!(config.text("Front Door", time, 42), "2026-01-02 03:04:05  #000042");
+    }
+
+    #[test]
+    fn test_position_parse() {
+        assert_eq!("bottom-right".parse::<OverlayPosition>(), Ok(OverlayPosition::BottomRight));
+        assert_eq!("Top-Left".parse::<OverlayPosition>(), Ok(OverlayPosition::TopLeft));
+        assert!("center".parse::<OverlayPosition>().is_err());
+    }
+
+    #[test]
+    fn test_draw_position_and_background() {
+        let gray = Rgba([120, 120, 120, 255]);
+
+        let mut image = RgbaImage::from_pixel(200, 100, gray);
+        draw_text(&mut image, &config(OverlayPosition::TopLeft), "1");
+        // 背景ボックスは暗くなり、アルファは変わらない
+        assert_eq!(*image.get_pixel(EDGE_MARGIN, EDGE_MARGIN), Rgba([40, 40, 40, 255]));
+        // 文字部分（"1"の2行目左から2ドット目）は白
+        let pad = BOX_PADDING * 2;
+        assert_eq!(*image.get_pixel(EDGE_MARGIN + pad + 2, EDGE_MARGIN + pad + 2), Rgba([255, 255, 255, 255]));
+        // 反対側の角は変わらない
+        assert_eq!(*image.get_pixel(199, 99), gray);
+
+        let mut image = RgbaImage::from_pixel(200, 100, gray);
+        draw_text(&mut image, &config(OverlayPosition::BottomRight), "1");
+        assert_eq!(*image.get_pixel(200 - EDGE_MARGIN - 1, 100 - EDGE_MARGIN - 1), Rgba([40, 40, 40, 255]));
+        assert_eq!(*image.get_pixel(0, 0), gray);
+    }
+
+    #[test]
+    fn test_draw_clips_to_image() {
+        // 画像より長い文字列でもパニックしない
+        let mut image = RgbImage::from_pixel(32, 16, Rgb([0, 0, 0]));
+        let config = OverlayConfig { scale: 3, ..config(OverlayPosition::BottomRight) };
+        draw_text(&mut image, &config, "2026-01-02 03:04:05  CAMERA");
+    }
+
+    #[test]
+    fn test_burn_into_jpeg() {
+        let source = RgbImage::from_pixel(64, 48, Rgb([100, 100, 100]));
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 90).encode_image(&source).unwrap();
+
+        let burned = burn_into_jpeg(&jpeg, &config(OverlayPosition::TopLeft), "00:00").unwrap();
+        assert_eq!(&burned[..2], &[0xFF, 0xD8]);
+
+        let decoded = image::load_from_memory(&burned).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (64, 48));
+        // 背景ボックスの分だけ暗くなっている
+        assert!(decoded.get_pixel(EDGE_MARGIN + 1, EDGE_MARGIN + 1)[0] < 60);
+        assert!(decoded.get_pixel(60, 44)[0] > 80);
+
+        assert!(burn_into_jpeg(b"not a jpeg", &OverlayConfig::default(), "x").is_err());
+    }
+}
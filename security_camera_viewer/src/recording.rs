@@ -0,0 +1,134 @@
+//! 録画パイプラインモジュール
+//!
+//! 録画フォーマット（MJPEG/MP4）の選択と、フォーマットに応じたライターをまとめる。
+//! 手動録画・動き検知録画・タイムラプスで共通に使う。
+
+use crate::crypto::KeySource;
+use crate::mjpeg::MjpegWriter;
+use crate::mp4_recorder::{Mp4EncoderConfig, Mp4Recorder};
+use chrono::{DateTime, Local};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// 録画フォーマット (Phase 6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordingFormat {
+    /// MJPEG形式（Phase 3-5）
+    Mjpeg,
+    /// MP4形式（Phase 6以降のデフォルト）
+    #[default]
+    Mp4,
+}
+
+impl RecordingFormat {
+    /// ファイルの拡張子（カタログの形式名と同じ）
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::Mjpeg => "mjpeg",
+            RecordingFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+impl fmt::Display for RecordingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mjpeg" => Ok(RecordingFormat::Mjpeg),
+            "mp4" => Ok(RecordingFormat::Mp4),
+            other => Err(format!("unknown recording format '{}' (expected: mjpeg, mp4)", other)),
+        }
+    }
+}
+
+/// フォーマットに応じた録画ライター
+pub enum RecordingWriter {
+    Mjpeg(MjpegWriter),
+    Mp4(Mp4Recorder),
+}
+
+impl RecordingWriter {
+    /// 録画ファイルを作成する
+    ///
+    /// `fps`はMP4の再生フレームレート（MJPEGはフレームごとの撮影時刻をインデックスに記録する）。
+    ///
+    /// # Errors
+    /// - 出力ファイルを作成できない場合
+    /// - MP4でffmpegを起動できない場合
+    pub fn create(
+        path: &Path,
+        format: RecordingFormat,
+        fps: u32,
+        mp4_config: &Mp4EncoderConfig,
+        key: Option<KeySource>,
+    ) -> io::Result<Self> {
+        Ok(match format {
+            RecordingFormat::Mjpeg => RecordingWriter::Mjpeg(MjpegWriter::create_with_key(path, key.as_ref())?),
+            RecordingFormat::Mp4 => RecordingWriter::Mp4(Mp4Recorder::with_config(path, fps, mp4_config.clone(), key)?),
+        })
+    }
+
+    /// 受信したばかりのフレームを書き込む
+    pub fn write_frame(&mut self, jpeg_data: &[u8]) -> io::Result<()> {
+        match self {
+            RecordingWriter::Mjpeg(writer) => writer.write_frame(jpeg_data),
+            RecordingWriter::Mp4(recorder) => recorder.write_frame(jpeg_data),
+        }
+    }
+
+    /// 撮影時刻を指定してフレームを書き込む（MP4では時刻は使わない）
+    pub fn write_frame_at(&mut self, jpeg_data: &[u8], captured_at: DateTime<Local>) -> io::Result<()> {
+        match self {
+            RecordingWriter::Mjpeg(writer) => writer.write_frame_at(jpeg_data, captured_at),
+            RecordingWriter::Mp4(recorder) => recorder.write_frame(jpeg_data),
+        }
+    }
+
+    /// 間引いて書き込まなかったフレームの分の時間を進める
+    ///
+    /// MJPEGはインデックスの撮影時刻で空白が分かるので何もしない。
+    /// MP4は固定フレームレートなので、直前のフレームを繰り返して再生時間を合わせる。
+    pub fn skip_frame(&mut self) -> io::Result<()> {
+        match self {
+            RecordingWriter::Mjpeg(_) => Ok(()),
+            RecordingWriter::Mp4(recorder) => recorder.repeat_last_frame(),
+        }
+    }
+
+    /// 録画を閉じ、ffmpegの再起動で追加されたセグメントを返す
+    pub fn finish(self) -> io::Result<Vec<PathBuf>> {
+        match self {
+            RecordingWriter::Mjpeg(writer) => {
+                writer.finish()?;
+                Ok(Vec::new())
+            }
+            RecordingWriter::Mp4(recorder) => {
+                let segments = recorder.segments()[1..].to_vec();
+                recorder.finish()?;
+                Ok(segments)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_format() {
+        assert_eq!("MP4".parse::<RecordingFormat>().unwrap(), RecordingFormat::Mp4);
+        assert_eq!(RecordingFormat::Mjpeg.to_string().parse::<RecordingFormat>().unwrap(), RecordingFormat::Mjpeg);
+        assert!("avi".parse::<RecordingFormat>().is_err());
+        assert_eq!(RecordingFormat::default(), RecordingFormat::Mp4);
+    }
+}
@@ -0,0 +1,435 @@
+//! 起動時リカバリモジュール
+//!
+//! クラッシュや電源断で終了処理されなかった録画（カタログ上で未終了のもの）を
+//! 検出し、途切れた末尾データを切り詰めてカタログ上で終了済みにする。
+//!
+//! - MJPEG: 最後の完全なJPEGフレームの後ろを切り詰める
+//! - MP4: フラグメント化MP4の最後の完全な`moof`+`mdat`の後ろを切り詰める
+//!   （ffmpeg再起動時の`_segN`セグメントも同様に切り詰め、フレーム数とサイズに含める）
+//! - 暗号化ファイル: 最後の完全なチャンクの後ろを切り詰める（鍵がないため、フレーム数は
+//!   完全なチャンクの平文に収まるインデックスの行数、インデックスがなければ完全なチャンク数とする）
+//!
+//! MJPEGのフレームインデックス（`<ファイル>.idx`）は、切り詰め後の長さに収まる行だけを残す。
+
+use crate::catalog::{Catalog, CatalogRecord, RecordingEntry};
+use crate::crypto;
+use crate::mjpeg;
+use crate::mp4_recorder;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// リカバリ結果（1録画分）
+#[derive(Debug, Clone)]
+pub struct RecoveredRecording {
+    pub path: PathBuf,
+    /// ファイル形式（"mjpeg", "mp4"）
+    pub format: String,
+    /// 復旧後のフレーム数
+    pub frame_count: u64,
+    /// 復旧後のファイルサイズ
+    pub size_bytes: u64,
+    /// 切り詰めたバイト数
+    pub removed_bytes: u64,
+}
+
+/// カタログ上で未終了の録画を復旧する
+///
+/// 各録画はファイルの最終更新時刻を終了時刻として`recovered`付きで終了済みにする。
+pub fn recover_orphaned_recordings(catalog: &Catalog) -> io::Result<Vec<RecoveredRecording>> {
+    let mut recovered = Vec::new();
+
+    for entry in catalog.recordings()?.into_iter().filter(|e| e.ended_at.is_none()) {
+        let result = match recover_file(&entry) {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Recovery of {:?} failed: {}", entry.path, e);
+                continue;
+            }
+        };
+
+        catalog.append(&CatalogRecord::RecordingFinished {
+            id: entry.id.clone(),
+            ended_at: result.ended_at,
+            frame_count: result.frame_count,
+            size_bytes: result.size_bytes,
+            recovered: true,
+            segments: result.segments,
+        })?;
+
+        info!("Recovered orphaned recording {:?}: {} frames, {:.2} MB, {} torn bytes removed",
+              entry.path, result.frame_count, result.size_bytes as f32 / 1_000_000.0, result.removed_bytes);
+
+        recovered.push(RecoveredRecording {
+            path: entry.path,
+            format: entry.format,
+            frame_count: result.frame_count,
+            size_bytes: result.size_bytes,
+            removed_bytes: result.removed_bytes,
+        });
+    }
+
+    Ok(recovered)
+}
+
+struct FileRecovery {
+    ended_at: DateTime<Local>,
+    frame_count: u64,
+    size_bytes: u64,
+    removed_bytes: u64,
+    /// `path`に続く追加セグメント（MP4のffmpeg再起動時）
+    segments: Vec<PathBuf>,
+}
+
+fn recover_file(entry: &RecordingEntry) -> io::Result<FileRecovery> {
+    let mut recovery = FileRecovery {
+        ended_at: entry.started_at,
+        frame_count: 0,
+        size_bytes: 0,
+        removed_bytes: 0,
+        segments: Vec::new(),
+    };
+    if !entry.path.exists() {
+        // ファイル作成前に落ちた場合
+        return Ok(recovery);
+    }
+
+    // MP4はffmpegが再起動するたびに`_seg2`, `_seg3`, ...のセグメントに続く
+    if entry.format == "mp4" {
+        recovery.segments = (2..)
+            .map(|index| mp4_recorder::segment_path(&entry.path, index))
+            .take_while(|path| path.exists())
+            .collect();
+    }
+
+    for path in std::iter::once(&entry.path).chain(&recovery.segments) {
+        let (frame_count, removed_bytes) = recover_segment(path, &entry.format)?;
+        let metadata = fs::metadata(path)?;
+        if let Ok(modified) = metadata.modified() {
+            recovery.ended_at = recovery.ended_at.max(DateTime::<Local>::from(modified));
+        }
+        recovery.frame_count += frame_count;
+        recovery.size_bytes += metadata.len();
+        recovery.removed_bytes += removed_bytes;
+    }
+
+    Ok(recovery)
+}
+
+/// 1ファイルの途切れた末尾を切り詰め、(フレーム数, 削除したバイト数) を返す
+fn recover_segment(path: &Path, format: &str) -> io::Result<(u64, u64)> {
+    match format {
+        _ if crypto::is_encrypted(path)? => {
+            let (chunks, plain_len, removed) = crypto::truncate_torn_chunk(path)?;
+            let frames = mjpeg::truncate_index(path, plain_len)?;
+            Ok((frames.unwrap_or(chunks), removed))
+        }
+        "mjpeg" => {
+            let (scan, removed) = mjpeg::truncate_torn_frames(path)?;
+            mjpeg::truncate_index(path, scan.valid_len)?;
+            Ok((scan.frames.len() as u64, removed))
+        }
+        "mp4" => truncate_torn_fragments(path),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown recording format: {}", other),
+        )),
+    }
+}
+
+/// フラグメント化MP4の途切れた末尾フラグメントを切り詰める
+///
+/// # Returns
+/// 完全なフラグメントに含まれるサンプル（フレーム）数と、削除したバイト数
+pub fn truncate_torn_fragments(path: &Path) -> io::Result<(u64, u64)> {
+    let (valid_len, sample_count, total_len) = scan_fragmented_mp4(File::open(path)?)?;
+    let removed = total_len - valid_len;
+
+    if removed > 0 {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid_len)?;
+        file.sync_all()?;
+    }
+
+    Ok((sample_count, removed))
+}
+
+/// トップレベルのボックスを走査し、(有効長, サンプル数, 総バイト数) を返す
+///
+/// `moof`のサンプル数は、対応する`mdat`が完全に書かれている場合のみ数える。
+/// シークしないため、復号しながら読むストリームにも使える。
+pub fn scan_fragmented_mp4<R: Read>(reader: R) -> io::Result<(u64, u64, u64)> {
+    let mut reader = BufReader::new(reader);
+    let mut offset = 0u64;
+    let mut valid_len = 0u64;
+    let mut sample_count = 0u64;
+    let mut pending_samples = 0u64;
+
+    loop {
+        let header = read_up_to(&mut reader, 8)?;
+        if header.len() < 8 {
+            offset += header.len() as u64;
+            break;
+        }
+        let size32 = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let box_type = [header[4], header[5], header[6], header[7]];
+
+        let (box_size, header_len) = match size32 {
+            0 => {
+                // ファイル末尾まで（書き込み途中）
+                offset += 8;
+                break;
+            }
+            1 => {
+                let large = read_up_to(&mut reader, 8)?;
+                let Ok(large) = <[u8; 8]>::try_from(large.as_slice()) else {
+                    offset += 8 + large.len() as u64;
+                    break;
+                };
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size as u64, 8),
+        };
+
+        if box_size < header_len {
+            offset += header_len;
+            break; // 壊れたボックス
+        }
+        let content_len = box_size - header_len;
+
+        if &box_type == b"moof" {
+            let content = read_up_to(&mut reader, content_len)?;
+            if (content.len() as u64) < content_len {
+                offset += header_len + content.len() as u64;
+                break; // 途切れたボックス
+            }
+            pending_samples = count_moof_samples(&content);
+        } else {
+            let skipped = io::copy(&mut (&mut reader).take(content_len), &mut io::sink())?;
+            if skipped < content_len {
+                offset += header_len + skipped;
+                break; // 途切れたボックス
+            }
+            if &box_type == b"mdat" {
+                sample_count += pending_samples;
+                pending_samples = 0;
+                valid_len = offset + box_size;
+            } else if pending_samples == 0 {
+                // ftyp, moov, mfra など
+                valid_len = offset + box_size;
+            }
+        }
+
+        offset += box_size;
+    }
+
+    let total_len = offset + io::copy(&mut reader, &mut io::sink())?;
+    Ok((valid_len, sample_count, total_len))
+}
+
+/// 最大`len`バイトを読む（末尾に達した場合は短くなる）
+fn read_up_to<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// moofボックス内の全trunのsample_countを合計
+fn count_moof_samples(moof: &[u8]) -> u64 {
+    let mut total = 0u64;
+    for (box_type, content) in child_boxes(moof) {
+        if box_type == *b"traf" {
+            for (child_type, child) in child_boxes(content) {
+                // trun: version(1) + flags(3) + sample_count(4)
+                if child_type == *b"trun" && child.len() >= 8 {
+                    total += u32::from_be_bytes([child[4], child[5], child[6], child[7]]) as u64;
+                }
+            }
+        }
+    }
+    total
+}
+
+/// 子ボックスを (type, content) の列として返す
+fn child_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut offset = 0usize;
+
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        let box_type = [data[offset + 4], data[offset + 5], data[offset + 6], data[offset + 7]];
+        if size < 8 || offset + size > data.len() {
+            break;
+        }
+        boxes.push((box_type, &data[offset + 8..offset + size]));
+        offset += size;
+    }
+
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::RecordingTrigger;
+    use std::io::{Cursor, Write};
+
+    fn mp4_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn fragment(samples: u32, mdat_len: usize) -> Vec<u8> {
+        let mut trun = vec![0, 0, 0, 0];
+        trun.extend_from_slice(&samples.to_be_bytes());
+        let traf = mp4_box(b"traf", &mp4_box(b"trun", &trun));
+        let mut data = mp4_box(b"moof", &traf);
+        data.extend(mp4_box(b"mdat", &vec![0xAA; mdat_len]));
+        data
+    }
+
+    #[test]
+    fn test_scan_fragmented_mp4() {
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"moov", &[0; 16]));
+        let header_len = data.len() as u64;
+        data.extend(fragment(11, 100));
+        data.extend(fragment(11, 100));
+        let complete_len = data.len() as u64;
+
+        let (valid, samples, total) = scan_fragmented_mp4(&mut Cursor::new(&data)).unwrap();
+        assert_eq!((valid, samples, total), (complete_len, 22, complete_len));
+
+        // 3番目のフラグメントのmdatが途中で途切れた
+        let torn = fragment(11, 100);
+        data.extend_from_slice(&torn[..torn.len() - 40]);
+        let (valid, samples, total) = scan_fragmented_mp4(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(valid, complete_len);
+        assert_eq!(samples, 22);
+        assert!(total > complete_len);
+
+        // ヘッダーのみ
+        let (valid, samples, _) = scan_fragmented_mp4(&mut Cursor::new(&data[..header_len as usize + 10])).unwrap();
+        assert_eq!((valid, samples), (header_len, 0));
+    }
+
+    #[test]
+    fn test_recover_orphaned_mjpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+
+        // 正常に終了した録画
+        let done = dir.path().join("manual_done.mjpeg");
+        fs::write(&done, [0xFF, 0xD8, 0xFF, 0xD9]).unwrap();
+        let id = catalog.recording_started(&done, RecordingTrigger::Manual, "mjpeg").unwrap();
+        catalog.recording_finished(&id, 1, 4, &[]).unwrap();
+
+        // 書き込み途中でクラッシュした録画
+        let orphan = dir.path().join("motion_orphan.mjpeg");
+        let mut file = File::create(&orphan).unwrap();
+        file.write_all(&[0xFF, 0xD8, 0x01, 0xFF, 0xD9]).unwrap();
+        file.write_all(&[0xFF, 0xD8, 0x02, 0x03]).unwrap();
+        drop(file);
+        catalog.recording_started(&orphan, RecordingTrigger::Motion, "mjpeg").unwrap();
+
+        // インデックスには途切れたフレームの行も書かれている
+        fs::write(mjpeg::index_path(&orphan), "1767000000000 0 5\n1767000000091 5 4\n").unwrap();
+
+        let recovered = recover_orphaned_recordings(&catalog).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].path, orphan);
+        assert_eq!(recovered[0].frame_count, 1);
+        assert_eq!(recovered[0].size_bytes, 5);
+        assert_eq!(recovered[0].removed_bytes, 4);
+        let index = mjpeg::read_index(&mjpeg::index_path(&orphan)).unwrap();
+        assert_eq!(index.iter().map(|entry| entry.span).collect::<Vec<_>>(), [mjpeg::FrameSpan { offset: 0, len: 5 }]);
+
+        let entries = catalog.recordings().unwrap();
+        assert!(entries.iter().all(|e| e.ended_at.is_some()));
+        assert!(entries.iter().any(|e| e.id == "motion_orphan.mjpeg" && e.recovered));
+        assert!(entries.iter().any(|e| e.id == "manual_done.mjpeg" && !e.recovered));
+
+        // 2回目は何もしない
+        assert!(recover_orphaned_recordings(&catalog).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recover_orphaned_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let key = crypto::KeySource::KeyFile([5; 32]);
+
+        let orphan = dir.path().join("motion_encrypted.mjpeg");
+        let mut writer = mjpeg::MjpegWriter::create_with_key(&orphan, Some(&key)).unwrap();
+        writer.write_frame(&[0xFF, 0xD8, 0x01, 0xFF, 0xD9]).unwrap();
+        writer.write_frame(&[0xFF, 0xD8, 0x02, 0xFF, 0xD9]).unwrap();
+        writer.sync().unwrap();
+        drop(writer);
+        let synced_len = fs::metadata(&orphan).unwrap().len();
+        // 同期後に書いたフレームは、インデックスの行だけが残り本体は途切れた
+        OpenOptions::new().append(true).open(&orphan).unwrap().write_all(&[0, 0, 0, 0, 9, 1, 2]).unwrap();
+        OpenOptions::new().append(true).open(mjpeg::index_path(&orphan)).unwrap().write_all(b"1767000000182 10 5\n").unwrap();
+        catalog.recording_started(&orphan, RecordingTrigger::Motion, "mjpeg").unwrap();
+
+        let recovered = recover_orphaned_recordings(&catalog).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].frame_count, 2);
+        assert_eq!(recovered[0].size_bytes, synced_len);
+        assert_eq!(recovered[0].removed_bytes, 7);
+        assert_eq!(mjpeg::read_index(&mjpeg::index_path(&orphan)).unwrap().len(), 2);
+
+        let scan = mjpeg::scan_frames(crypto::open_recording(&orphan, Some(&key)).unwrap()).unwrap();
+        assert_eq!(scan.frames.len(), 2);
+    }
+
+    #[test]
+    fn test_recover_orphaned_mp4_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        let mut header = mp4_box(b"ftyp", b"isom");
+        header.extend(mp4_box(b"moov", &[0; 16]));
+
+        // ffmpegが2回再起動し、最後のセグメントの書き込み中にクラッシュした録画
+        let orphan = dir.path().join("motion_orphan.mp4");
+        let seg2 = mp4_recorder::segment_path(&orphan, 2);
+        let seg3 = mp4_recorder::segment_path(&orphan, 3);
+        let first = [header.clone(), fragment(11, 100), fragment(11, 100)].concat();
+        let second = [header.clone(), fragment(5, 60)].concat();
+        let torn = fragment(11, 100);
+        let third = [header.clone(), fragment(7, 80), torn[..torn.len() - 30].to_vec()].concat();
+        fs::write(&orphan, &first).unwrap();
+        fs::write(&seg2, &second).unwrap();
+        fs::write(&seg3, &third).unwrap();
+        let id = catalog.recording_started(&orphan, RecordingTrigger::Motion, "mp4").unwrap();
+
+        let recovered = recover_orphaned_recordings(&catalog).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].frame_count, 22 + 5 + 7);
+        let third_len = (third.len() - torn.len() + 30) as u64;
+        assert_eq!(recovered[0].size_bytes, (first.len() + second.len()) as u64 + third_len);
+        assert_eq!(recovered[0].removed_bytes, (torn.len() - 30) as u64);
+        assert_eq!(fs::metadata(&seg3).unwrap().len(), third_len);
+
+        let entry = catalog.recordings().unwrap().into_iter().find(|e| e.id == id).unwrap();
+        assert!(entry.recovered);
+        assert_eq!(entry.segments, [seg2, seg3]);
+        assert_eq!(entry.frame_count, 34);
+        assert_eq!(entry.size_bytes, recovered[0].size_bytes);
+    }
+
+    #[test]
+    fn test_recover_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog = Catalog::new(dir.path());
+        catalog.recording_started(&dir.path().join("manual_missing.mp4"), RecordingTrigger::Manual, "mp4").unwrap();
+
+        let recovered = recover_orphaned_recordings(&catalog).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].frame_count, 0);
+        assert_eq!(recovered[0].size_bytes, 0);
+    }
+}
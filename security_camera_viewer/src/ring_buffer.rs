@@ -2,11 +2,15 @@
 ///
 /// 常に最新N秒分のJPEGフレームをメモリに保持し、
 /// 動き検知時にファイルに書き込むことで「10秒前から録画」を実現する。
+/// 数分単位のプリバッファには、同じ`FrameBuffer`を実装した
+/// ディスク上のリングバッファ（`disk_ring_buffer`）を使う。
+///
+/// 古いフレームの削除は`EvictionPolicy`（フレーム数・実際の受信時刻での経過時間・総バイト数）で決める。
+/// フレームレートが変動しても、保持する時間は受信時刻どおりになる。
 
 use std::collections::VecDeque;
-use std::fs::File;
 use std::io::{self, Write};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// JPEGフレーム
 #[derive(Clone)]
@@ -17,123 +21,254 @@ pub struct JpegFrame {
     pub timestamp: Instant,
 }
 
-/// リングバッファ
-pub struct RingBuffer {
-    /// フレームキュー（古い順）
-    frames: VecDeque<JpegFrame>,
+/// 古いフレームを削除する条件（いずれかを超えたら削除、Noneは無制限）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvictionPolicy {
     /// 最大フレーム数
-    capacity: usize,
-    /// 現在のバッファ内総バイト数
-    total_bytes: usize,
+    pub max_frames: Option<usize>,
+    /// 最も新しいフレームから見た最大経過時間
+    pub max_age: Option<Duration>,
+    /// 最大総バイト数
+    pub max_bytes: Option<usize>,
 }
 
-impl RingBuffer {
-    /// 新しいリングバッファを作成
+impl EvictionPolicy {
+    /// 最新`seconds`秒分のフレームを保持する
+    pub fn seconds(seconds: u32) -> Self {
+        Self { max_age: Some(Duration::from_secs(seconds as u64)), ..Self::default() }
+    }
+
+    /// 最大総バイト数を追加する
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        Self { max_bytes: Some(max_bytes), ..self }
+    }
+}
+
+/// バッファの統計情報
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BufferStats {
+    /// フレーム数
+    pub frames: usize,
+    /// 総バイト数
+    pub bytes: usize,
+    /// 最も古いフレームから最も新しいフレームまでの時間（受信時刻の差）
+    pub duration: Duration,
+    /// 実際の平均フレームレート（2フレーム未満なら0）
+    pub fps: f32,
+}
+
+/// プリバッファの共通インターフェース
+///
+/// メモリ上の`RingBuffer`とディスク上の`DiskRingBuffer`を切り替えて使えるようにする。
+pub trait FrameBuffer {
+    /// 新しいフレームを追加（ポリシーを超えた古いフレームは削除する）
+    fn push(&mut self, frame: JpegFrame);
+
+    /// バッファ内のフレームを古い順に渡す
     ///
-    /// # Arguments
-    /// * `capacity` - 最大フレーム数（例: 110 = 10秒@11fps）
-    pub fn new(capacity: usize) -> Self {
-        Self {
-            frames: VecDeque::with_capacity(capacity),
-            capacity,
-            total_bytes: 0,
-        }
+    /// コールバックがエラーを返した時点で中断する。
+    fn for_each_frame(&self, f: &mut dyn FnMut(&JpegFrame) -> io::Result<()>) -> io::Result<()>;
+
+    /// 最も古いフレームを削除する（空ならfalse）
+    fn pop_oldest(&mut self) -> bool;
+
+    /// バッファクリア
+    fn clear(&mut self);
+
+    /// 現在のフレーム数
+    fn len(&self) -> usize;
+
+    /// 現在の総バイト数
+    fn total_bytes(&self) -> usize;
+
+    /// 削除ポリシー
+    fn policy(&self) -> EvictionPolicy;
+
+    /// 削除ポリシーを変更する（保持中のフレームは残し、超えた分だけ削除する）
+    fn set_policy(&mut self, policy: EvictionPolicy);
+
+    /// 最も古いフレームの受信時刻
+    fn oldest_timestamp(&self) -> Option<Instant>;
+
+    /// 最も新しいフレームの受信時刻
+    fn newest_timestamp(&self) -> Option<Instant>;
+
+    /// バッファが空かどうか
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    /// フレーム数から容量を計算
+    /// ポリシーを超えた古いフレームを削除する
     ///
-    /// # Arguments
-    /// * `seconds` - プリバッファ秒数
-    /// * `fps` - フレームレート
-    pub fn from_seconds(seconds: u32, fps: u32) -> Self {
-        let capacity = (seconds * fps) as usize;
-        Self::new(capacity)
+    /// 経過時間は最も新しいフレームが基準。最も新しいフレームは（単体で上限を超えても）残す。
+    fn enforce_policy(&mut self) {
+        let policy = self.policy();
+        while self.len() > 1 {
+            let over_age = policy.max_age.is_some_and(|max_age| {
+                match (self.oldest_timestamp(), self.newest_timestamp()) {
+                    (Some(oldest), Some(newest)) => newest.saturating_duration_since(oldest) > max_age,
+                    _ => false,
+                }
+            });
+            let over = over_age
+                || policy.max_frames.is_some_and(|max| self.len() > max)
+                || policy.max_bytes.is_some_and(|max| self.total_bytes() > max);
+            if !over || !self.pop_oldest() {
+                break;
+            }
+        }
     }
 
-    /// 新しいフレームを追加
+    /// 現在時刻から見て`max_age`より古いフレームを削除する
     ///
-    /// 容量を超える場合、最も古いフレームを自動削除する。
-    pub fn push(&mut self, frame: JpegFrame) {
-        // 容量チェック
-        if self.frames.len() >= self.capacity {
-            if let Some(old_frame) = self.frames.pop_front() {
-                self.total_bytes = self.total_bytes.saturating_sub(old_frame.jpeg_data.len());
+    /// フレームが届かない間に古くなったフレームを、書き出す前に取り除くために使う。
+    fn expire(&mut self, now: Instant) {
+        let Some(max_age) = self.policy().max_age else { return };
+        while self.oldest_timestamp().is_some_and(|oldest| now.saturating_duration_since(oldest) > max_age) {
+            if !self.pop_oldest() {
+                break;
             }
         }
+    }
 
-        // 新しいフレームを追加
-        self.total_bytes += frame.jpeg_data.len();
-        self.frames.push_back(frame);
+    /// 統計情報
+    fn stats(&self) -> BufferStats {
+        let duration = match (self.oldest_timestamp(), self.newest_timestamp()) {
+            (Some(oldest), Some(newest)) => newest.saturating_duration_since(oldest),
+            _ => Duration::ZERO,
+        };
+        let fps = if self.len() >= 2 && !duration.is_zero() {
+            (self.len() - 1) as f32 / duration.as_secs_f32()
+        } else {
+            0.0
+        };
+        BufferStats { frames: self.len(), bytes: self.total_bytes(), duration, fps }
+    }
+
+    /// バッファの使用率（0.0-1.0、制限のうち最も埋まっているもの）
+    fn usage_ratio(&self) -> f32 {
+        let policy = self.policy();
+        let stats = self.stats();
+        let ratios = [
+            policy.max_frames.map(|max| stats.frames as f32 / max.max(1) as f32),
+            policy.max_bytes.map(|max| stats.bytes as f32 / max.max(1) as f32),
+            policy.max_age.filter(|max| !max.is_zero()).map(|max| stats.duration.as_secs_f32() / max.as_secs_f32()),
+        ];
+        ratios.into_iter().flatten().fold(0.0, f32::max).min(1.0)
+    }
+
+    /// 最も古いフレームの経過時間（秒）
+    fn oldest_frame_age_secs(&self) -> Option<f32> {
+        self.oldest_timestamp().map(|timestamp| timestamp.elapsed().as_secs_f32())
+    }
+
+    /// 最も新しいフレームの経過時間（秒）
+    fn newest_frame_age_secs(&self) -> Option<f32> {
+        self.newest_timestamp().map(|timestamp| timestamp.elapsed().as_secs_f32())
     }
 
     /// バッファ内の全フレームをファイルに書き込み
     ///
     /// # Arguments
-    /// * `file` - 書き込み先ファイル
+    /// * `file` - 書き込み先ファイル（暗号化ライターも可）
     ///
     /// # Returns
     /// 書き込まれたフレーム数と総バイト数
-    pub fn flush_to_file(&self, file: &mut File) -> io::Result<(usize, usize)> {
-        let frame_count = self.frames.len();
+    fn flush_to_file(&self, file: &mut dyn Write) -> io::Result<(usize, usize)> {
+        let mut frame_count = 0;
         let mut bytes_written = 0;
 
-        for frame in &self.frames {
+        self.for_each_frame(&mut |frame| {
             file.write_all(&frame.jpeg_data)?;
+            frame_count += 1;
             bytes_written += frame.jpeg_data.len();
-        }
+            Ok(())
+        })?;
 
         file.flush()?;
 
         Ok((frame_count, bytes_written))
     }
+}
 
-    /// バッファクリア
-    pub fn clear(&mut self) {
+/// リングバッファ
+pub struct RingBuffer {
+    /// フレームキュー（古い順）
+    frames: VecDeque<JpegFrame>,
+    /// 削除ポリシー
+    policy: EvictionPolicy,
+    /// 現在のバッファ内総バイト数
+    total_bytes: usize,
+}
+
+impl RingBuffer {
+    /// フレーム数で制限したリングバッファを作成
+    ///
+    /// # Arguments
+    /// * `capacity` - 最大フレーム数（例: 110）
+    pub fn new(capacity: usize) -> Self {
+        Self::with_policy(EvictionPolicy { max_frames: Some(capacity), ..EvictionPolicy::default() })
+    }
+
+    /// 削除ポリシーを指定してリングバッファを作成
+    pub fn with_policy(policy: EvictionPolicy) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            policy,
+            total_bytes: 0,
+        }
+    }
+}
+
+impl FrameBuffer for RingBuffer {
+    fn push(&mut self, frame: JpegFrame) {
+        self.total_bytes += frame.jpeg_data.len();
+        self.frames.push_back(frame);
+        self.enforce_policy();
+    }
+
+    fn for_each_frame(&self, f: &mut dyn FnMut(&JpegFrame) -> io::Result<()>) -> io::Result<()> {
+        self.frames.iter().try_for_each(f)
+    }
+
+    fn pop_oldest(&mut self) -> bool {
+        match self.frames.pop_front() {
+            Some(old_frame) => {
+                self.total_bytes = self.total_bytes.saturating_sub(old_frame.jpeg_data.len());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
         self.frames.clear();
         self.total_bytes = 0;
     }
 
-    /// 現在のフレーム数
-    pub fn len(&self) -> usize {
+    fn len(&self) -> usize {
         self.frames.len()
     }
 
-    /// バッファが空かどうか
-    pub fn is_empty(&self) -> bool {
-        self.frames.is_empty()
-    }
-
-    /// 現在の総バイト数
-    pub fn total_bytes(&self) -> usize {
+    fn total_bytes(&self) -> usize {
         self.total_bytes
     }
 
-    /// 容量（最大フレーム数）
-    pub fn capacity(&self) -> usize {
-        self.capacity
+    fn policy(&self) -> EvictionPolicy {
+        self.policy
     }
 
-    /// バッファの使用率（0.0-1.0）
-    pub fn usage_ratio(&self) -> f32 {
-        if self.capacity == 0 {
-            0.0
-        } else {
-            self.frames.len() as f32 / self.capacity as f32
-        }
+    fn set_policy(&mut self, policy: EvictionPolicy) {
+        self.policy = policy;
+        self.enforce_policy();
     }
 
-    /// 最も古いフレームの経過時間（秒）
-    pub fn oldest_frame_age_secs(&self) -> Option<f32> {
-        self.frames.front().map(|frame| {
-            frame.timestamp.elapsed().as_secs_f32()
-        })
+    fn oldest_timestamp(&self) -> Option<Instant> {
+        self.frames.front().map(|frame| frame.timestamp)
     }
 
-    /// 最も新しいフレームの経過時間（秒）
-    pub fn newest_frame_age_secs(&self) -> Option<f32> {
-        self.frames.back().map(|frame| {
-            frame.timestamp.elapsed().as_secs_f32()
-        })
+    fn newest_timestamp(&self) -> Option<Instant> {
+        self.frames.back().map(|frame| frame.timestamp)
     }
 }
 
@@ -227,15 +362,67 @@ mod tests {
         assert!(buffer.is_empty());
     }
 
+    fn frame_at(start: Instant, millis: u64, len: usize) -> JpegFrame {
+        JpegFrame { jpeg_data: vec![0; len], timestamp: start + Duration::from_millis(millis) }
+    }
+
+    #[test]
+    fn test_evict_by_age() {
+        let start = Instant::now();
+        let mut buffer = RingBuffer::with_policy(EvictionPolicy::seconds(10));
+
+        // フレームレートが途中で変わっても、保持するのは受信時刻で10秒分
+        for i in 0..110 {
+            buffer.push(frame_at(start, i * 91, 1));
+        }
+        for i in 0..300 {
+            buffer.push(frame_at(start, 10_010 + i * 33, 1));
+        }
+        let stats = buffer.stats();
+        assert!(stats.duration <= Duration::from_secs(10), "{:?}", stats);
+        assert!(stats.duration >= Duration::from_millis(9_900), "{:?}", stats);
+        assert_eq!(stats.frames, 300 + 1);
+        assert!((29.0..31.0).contains(&stats.fps), "{:?}", stats);
+
+        // 届かない間に古くなったフレームは現在時刻で削除する
+        buffer.expire(start + Duration::from_millis(29_800));
+        assert_eq!(buffer.len(), 3);
+        buffer.expire(start + Duration::from_secs(60));
+        assert!(buffer.is_empty());
+    }
+
     #[test]
-    fn test_from_seconds() {
-        // 10秒@11fps = 110フレーム
-        let buffer = RingBuffer::from_seconds(10, 11);
-        assert_eq!(buffer.capacity(), 110);
-
-        // 5秒@30fps = 150フレーム
-        let buffer = RingBuffer::from_seconds(5, 30);
-        assert_eq!(buffer.capacity(), 150);
+    fn test_evict_by_bytes_and_resize() {
+        let start = Instant::now();
+        let mut buffer = RingBuffer::with_policy(EvictionPolicy::seconds(60).with_max_bytes(1000));
+
+        for i in 0..20 {
+            buffer.push(frame_at(start, i * 100, 100));
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.total_bytes(), 1000);
+        assert_eq!(buffer.usage_ratio(), 1.0);
+
+        // 単体で上限を超えるフレームも最新の1枚は残す
+        buffer.push(frame_at(start, 2_000, 5000));
+        assert_eq!(buffer.len(), 1);
+
+        // 大きいフレームも次のフレームで押し出される
+        for i in 0..10 {
+            buffer.push(frame_at(start, 2_100 + i * 100, 100));
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.total_bytes(), 1000);
+
+        // ポリシーの変更は保持中のフレームを残したまま反映する
+        buffer.set_policy(EvictionPolicy::seconds(60));
+        for i in 0..10 {
+            buffer.push(frame_at(start, 3_100 + i * 100, 100));
+        }
+        assert_eq!(buffer.len(), 20);
+        buffer.set_policy(EvictionPolicy::seconds(1));
+        assert_eq!(buffer.len(), 11);
+        assert_eq!(buffer.policy(), EvictionPolicy::seconds(1));
     }
 
     #[test]
@@ -0,0 +1,502 @@
+//! 録画スケジュールモジュール
+//!
+//! 曜日と時間帯のルールで、録画モード（停止・動き検知録画・常時録画）を自動で切り替える。
+//! 1行1ルールのテキスト形式で、先に書いたルールが優先される:
+//!
+//! ```text
+//! mon-fri 09:00-17:00 off      # 営業時間は停止
+//! mon-fri 22:00-06:00 motion   # 平日の夜間は動き検知録画（翌朝まで）
+//! sat,sun continuous           # 週末は終日常時録画
+//! default off                  # どのルールにも当てはまらない時間
+//! ```
+//!
+//! 時間帯は壁時計（ローカル時刻）で判定するため、夏時間の切り替え日も「22:00」は22:00のまま。
+//! 夏時間の開始で存在しない時刻に始まる時間帯は、時計が進んだ直後から有効になる。
+//! 夏時間の終了で繰り返す1時間は、どちらの回も同じ時刻として判定する。
+//! 終了が開始より前の時間帯は翌日にまたがり、曜日は開始した日で判定する
+//! （`fri 22:00-06:00` は土曜の朝まで）。
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use std::fmt;
+use std::str::FromStr;
+
+/// スケジュールで切り替える録画モード
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleMode {
+    /// 録画も動き検知もしない
+    #[default]
+    Off,
+    /// 動き検知録画
+    Motion,
+    /// 常時録画
+    Continuous,
+}
+
+impl ScheduleMode {
+    pub const ALL: [ScheduleMode; 3] = [ScheduleMode::Off, ScheduleMode::Motion, ScheduleMode::Continuous];
+}
+
+impl fmt::Display for ScheduleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScheduleMode::Off => "off",
+            ScheduleMode::Motion => "motion",
+            ScheduleMode::Continuous => "continuous",
+        })
+    }
+}
+
+impl FromStr for ScheduleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|mode| mode.to_string() == s.to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown mode '{}' (expected: off, motion, continuous)", s))
+    }
+}
+
+/// 1日のうちの時間帯（開始を含み、終了を含まない。終了が開始より前なら日付をまたぐ）
+///
+/// 開始と終了が同じ時間帯は、空とも終日とも読めるため読み込み時にエラーにする（終日なら時間帯を省略する）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl DailyWindow {
+    /// 日付をまたぐ時間帯か
+    pub fn wraps(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// 時刻が時間帯に含まれるか
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.wraps() {
+            time >= self.start || time < self.end
+        } else {
+            self.start <= time && time < self.end
+        }
+    }
+}
+
+impl fmt::Display for DailyWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+impl FromStr for DailyWindow {
+    type Err = String;
+
+    /// "HH:MM-HH:MM" 形式
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M")
+            .map_err(|_| format!("invalid time '{}' in window '{}' (expected HH:MM-HH:MM)", t.trim(), s));
+        let (start, end) = s.split_once('-')
+            .ok_or_else(|| format!("invalid window '{}' (expected HH:MM-HH:MM)", s))?;
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            return Err(format!("window '{}' starts and ends at the same time (omit the window for the whole day)", s));
+        }
+        Ok(DailyWindow { start, end })
+    }
+}
+
+/// 曜日の集合
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub const ALL: Weekdays = Weekdays(0b111_1111);
+    pub const WEEKDAYS: Weekdays = Weekdays(0b001_1111);
+    pub const WEEKENDS: Weekdays = Weekdays(0b110_0000);
+
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.num_days_from_monday()) != 0
+    }
+
+    fn with(self, day: Weekday) -> Self {
+        Weekdays(self.0 | (1 << day.num_days_from_monday()))
+    }
+}
+
+impl fmt::Display for Weekdays {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Weekdays::ALL => f.write_str("daily"),
+            Weekdays::WEEKDAYS => f.write_str("mon-fri"),
+            Weekdays::WEEKENDS => f.write_str("sat,sun"),
+            days => {
+                let names: Vec<String> = (0..7)
+                    .map(|i| Weekday::try_from(i as u8).unwrap())
+                    .filter(|&day| days.contains(day))
+                    .map(|day| day.to_string().to_ascii_lowercase())
+                    .collect();
+                f.write_str(&names.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for Weekdays {
+    type Err = String;
+
+    /// `mon-fri`、`sat,sun`、`daily`、`weekdays`、`weekends` など
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let day = |name: &str| name.parse::<Weekday>()
+            .map_err(|_| format!("unknown day '{}' (expected mon..sun, weekdays, weekends or daily)", name));
+
+        let mut days = Weekdays(0);
+        for part in s.to_ascii_lowercase().split(',') {
+            days = match part.trim() {
+                "daily" | "everyday" | "*" => Weekdays::ALL,
+                "weekdays" => Weekdays(days.0 | Weekdays::WEEKDAYS.0),
+                "weekends" => Weekdays(days.0 | Weekdays::WEEKENDS.0),
+                range => match range.split_once('-') {
+                    // 範囲は週をまたいでもよい（fri-mon）
+                    Some((first, last)) => {
+                        let (mut current, last) = (day(first)?, day(last)?);
+                        let mut days = days.with(current);
+                        while current != last {
+                            current = current.succ();
+                            days = days.with(current);
+                        }
+                        days
+                    }
+                    None => days.with(day(range)?),
+                },
+            };
+        }
+        Ok(days)
+    }
+}
+
+/// スケジュールの1ルール
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleRule {
+    pub days: Weekdays,
+    /// 時間帯（Noneなら終日）
+    pub window: Option<DailyWindow>,
+    pub mode: ScheduleMode,
+}
+
+impl ScheduleRule {
+    /// 壁時計の日時がこのルールに当てはまるか
+    pub fn matches(&self, at: NaiveDateTime) -> bool {
+        let (day, time) = (at.weekday(), at.time());
+        match self.window {
+            None => self.days.contains(day),
+            // 日付をまたぐ時間帯の翌朝分は、前日の曜日で判定する
+            Some(window) if window.wraps() => {
+                (self.days.contains(day) && time >= window.start)
+                    || (self.days.contains(day.pred()) && time < window.end)
+            }
+            Some(window) => self.days.contains(day) && window.contains(time),
+        }
+    }
+}
+
+impl fmt::Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.days)?;
+        if let Some(window) = self.window {
+            write!(f, " {}", window)?;
+        }
+        write!(f, " {}", self.mode)
+    }
+}
+
+impl FromStr for ScheduleRule {
+    type Err = String;
+
+    /// `[曜日] [HH:MM-HH:MM] モード`（曜日を省略すると毎日、時間帯を省略すると終日）
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let Some((mode, qualifiers)) = tokens.split_last() else {
+            return Err("empty rule".to_string());
+        };
+        if qualifiers.len() > 2 {
+            return Err(format!("too many fields in '{}' (expected: [days] [HH:MM-HH:MM] mode)", s));
+        }
+
+        let mut rule = ScheduleRule { days: Weekdays::ALL, window: None, mode: mode.parse()? };
+        for token in qualifiers {
+            if token.contains(':') {
+                rule.window = Some(token.parse()?);
+            } else {
+                rule.days = token.parse()?;
+            }
+        }
+        Ok(rule)
+    }
+}
+
+/// 週間スケジュール
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Schedule {
+    /// 先に書いたルールが優先
+    pub rules: Vec<ScheduleRule>,
+    /// どのルールにも当てはまらない時間のモード
+    pub default_mode: ScheduleMode,
+}
+
+impl Schedule {
+    /// 日時のモード
+    pub fn mode_at<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> ScheduleMode {
+        self.mode_at_local(at.naive_local())
+    }
+
+    fn mode_at_local(&self, at: NaiveDateTime) -> ScheduleMode {
+        self.rules.iter()
+            .find(|rule| rule.matches(at))
+            .map_or(self.default_mode, |rule| rule.mode)
+    }
+
+    /// スケジュールでこのモードになる時間があるか
+    pub fn uses(&self, mode: ScheduleMode) -> bool {
+        self.default_mode == mode || self.rules.iter().any(|rule| rule.mode == mode)
+    }
+
+    /// 次にモードが切り替わる日時と切り替わった後のモード（1週間以内に変わらなければNone）
+    pub fn next_change<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<(DateTime<Tz>, ScheduleMode)> {
+        let local_now = now.naive_local();
+        let current = self.mode_at_local(local_now);
+
+        // モードが変わりうるのは、日付の変わり目とルールの開始・終了時刻だけ
+        let mut boundaries: Vec<NaiveDateTime> = (0..=8)
+            .flat_map(|days| {
+                let date = local_now.date() + Duration::days(days);
+                std::iter::once(date.and_time(NaiveTime::MIN)).chain(
+                    self.rules.iter()
+                        .filter_map(|rule| rule.window)
+                        .flat_map(move |window| [date.and_time(window.start), date.and_time(window.end)]),
+                )
+            })
+            .filter(|&boundary| boundary > local_now)
+            .collect();
+        boundaries.sort();
+        boundaries.dedup();
+
+        let (boundary, mode) = boundaries.into_iter()
+            .map(|boundary| (boundary, self.mode_at_local(boundary)))
+            .find(|&(_, mode)| mode != current)?;
+        Some((resolve_local(&now.timezone(), boundary)?, mode))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules {
+            writeln!(f, "{}", rule)?;
+        }
+        write!(f, "default {}", self.default_mode)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    /// 1行1ルール。`#`以降はコメント、`default <モード>`で既定のモードを指定する
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut schedule = Schedule::default();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let result = match line.strip_prefix("default ") {
+                Some(mode) => mode.trim().parse().map(|mode| schedule.default_mode = mode),
+                None => line.parse().map(|rule| schedule.rules.push(rule)),
+            };
+            result.map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(schedule)
+    }
+}
+
+/// 壁時計の日時を実際の日時にする
+///
+/// 夏時間の開始で存在しない時刻は時計が進んだ直後、終了で2回ある時刻は1回目とする。
+fn resolve_local<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(at) => Some(at),
+        LocalResult::Ambiguous(first, _) => Some(first),
+        LocalResult::None => (1..=24 * 60)
+            .find_map(|minutes| tz.from_local_datetime(&(local + Duration::minutes(minutes))).earliest()),
+    }
+}
+
+/// スケジュールに従ってモードの切り替えを検出する
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    schedule: Schedule,
+    current: Option<ScheduleMode>,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule) -> Self {
+        Self { schedule, current: None }
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    /// 現在のモード（最初の`update`まではNone）
+    pub fn current(&self) -> Option<ScheduleMode> {
+        self.current
+    }
+
+    /// 現在時刻でモードを評価し、切り替わった場合（最初の呼び出しを含む）は新しいモードを返す
+    pub fn update<Tz: TimeZone>(&mut self, now: &DateTime<Tz>) -> Option<ScheduleMode> {
+        let mode = self.schedule.mode_at(now);
+        if self.current == Some(mode) {
+            return None;
+        }
+        self.current = Some(mode);
+        Some(mode)
+    }
+
+    /// 表示用の状態（例: "motion until Tue 06:00"）
+    pub fn describe<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let mode = self.schedule.mode_at(now);
+        match self.schedule.next_change(now) {
+            Some((at, next)) => format!("{} until {} (then {})", mode, at.format("%a %H:%M"), next),
+            None => mode.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono_tz::Europe::Berlin;
+
+    const EXAMPLE: &str = "
+        # business hours off, nights motion, weekends continuous
+        mon-fri 09:00-17:00 off
+        mon-fri 22:00-06:00 motion
+        sat,sun continuous
+        default off
+    ";
+
+    /// 2026-01-05 は月曜日
+    fn local(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap().and_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_daily_window() {
+        let day: DailyWindow = "07:00-19:00".parse().unwrap();
+        assert!(day.contains(NaiveTime::from_hms_opt(7, 0, 0).unwrap()));
+        assert!(!day.contains(NaiveTime::from_hms_opt(19, 0, 0).unwrap()));
+        assert_eq!(day.to_string(), "07:00-19:00");
+
+        let night: DailyWindow = "22:00-06:00".parse().unwrap();
+        assert!(night.wraps());
+        assert!(night.contains(NaiveTime::from_hms_opt(23, 30, 0).unwrap()));
+        assert!(night.contains(NaiveTime::from_hms_opt(5, 59, 0).unwrap()));
+        assert!(!night.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+
+        assert!("07:00".parse::<DailyWindow>().is_err());
+        assert!("7-19".parse::<DailyWindow>().is_err());
+
+        // 開始と終了が同じ時間帯は何にも一致しないので読み込まない
+        let error = "08:00-08:00".parse::<DailyWindow>().unwrap_err();
+        assert!(error.contains("omit the window"), "{}", error);
+        assert!("mon 00:00-00:00 continuous".parse::<ScheduleRule>().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let schedule: Schedule = EXAMPLE.parse().unwrap();
+        assert_eq!(schedule.rules.len(), 3);
+        assert_eq!(schedule.rules[1].to_string(), "mon-fri 22:00-06:00 motion");
+        assert_eq!(schedule.rules[2].days, Weekdays::WEEKENDS);
+        assert_eq!(schedule.to_string().parse::<Schedule>().unwrap(), schedule);
+
+        let days: Weekdays = "fri-mon".parse().unwrap();
+        assert_eq!(days.to_string(), "mon,fri,sat,sun");
+        assert_eq!("weekdays".parse::<Weekdays>().unwrap(), Weekdays::WEEKDAYS);
+
+        assert_eq!("continuous".parse::<ScheduleRule>().unwrap().days, Weekdays::ALL);
+        let error = "mon-fri 09:00-17:00 off\nmon-fri 22:00-06:00 record".parse::<Schedule>().unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+        assert!("funday motion".parse::<ScheduleRule>().is_err());
+        assert!("mon 09:00-17:00 extra motion".parse::<ScheduleRule>().is_err());
+    }
+
+    #[test]
+    fn test_mode_at() {
+        let schedule: Schedule = EXAMPLE.parse().unwrap();
+        let mode = |at| schedule.mode_at_local(at);
+
+        assert_eq!(mode(local(5, 12, 0)), ScheduleMode::Off);
+        assert_eq!(mode(local(5, 23, 0)), ScheduleMode::Motion);
+        // 日付をまたぐ時間帯: 月曜の夜から火曜の朝まで
+        assert_eq!(mode(local(6, 5, 59)), ScheduleMode::Motion);
+        assert_eq!(mode(local(6, 6, 0)), ScheduleMode::Off);
+        // 日曜の夜は平日ルールの開始日ではないが、週末ルールで常時録画
+        assert_eq!(mode(local(11, 23, 0)), ScheduleMode::Continuous);
+        // 月曜の朝は日曜の夜に始まる時間帯ではない
+        assert_eq!(mode(local(12, 3, 0)), ScheduleMode::Off);
+        // 土曜の朝は金曜の夜の続きだが、週末ルールより先に書かれた平日ルールが優先
+        assert_eq!(mode(local(10, 3, 0)), ScheduleMode::Motion);
+        assert_eq!(mode(local(10, 12, 0)), ScheduleMode::Continuous);
+    }
+
+    #[test]
+    fn test_next_change() {
+        let schedule: Schedule = EXAMPLE.parse().unwrap();
+        let at = |t: NaiveDateTime| Berlin.from_local_datetime(&t).unwrap();
+
+        let (change, mode) = schedule.next_change(&at(local(5, 12, 0))).unwrap();
+        assert_eq!((change, mode), (at(local(5, 22, 0)), ScheduleMode::Motion));
+        let (change, mode) = schedule.next_change(&at(local(9, 23, 0))).unwrap();
+        assert_eq!((change, mode), (at(local(10, 6, 0)), ScheduleMode::Continuous));
+
+        let always: Schedule = "continuous".parse().unwrap();
+        assert!(always.next_change(&at(local(5, 12, 0))).is_none());
+    }
+
+    #[test]
+    fn test_daylight_saving_time() {
+        let schedule: Schedule = "daily 02:30-04:00 continuous\ndaily 22:00-02:30 motion".parse().unwrap();
+
+        // 2026-03-29 02:00 CET → 03:00 CEST: 02:30 は存在しないので、時計が進んだ直後に切り替わる
+        let before = Berlin.with_ymd_and_hms(2026, 3, 29, 1, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(&before), ScheduleMode::Motion);
+        let (change, mode) = schedule.next_change(&before).unwrap();
+        assert_eq!(mode, ScheduleMode::Continuous);
+        assert_eq!(change, Berlin.with_ymd_and_hms(2026, 3, 29, 3, 0, 0).unwrap());
+        assert_eq!(change - before, Duration::hours(1));
+
+        // 2026-10-25 03:00 CEST → 02:00 CET: 2回ある 02:30 は1回目で切り替わる
+        let before = Berlin.with_ymd_and_hms(2026, 10, 25, 1, 0, 0).unwrap();
+        let (change, mode) = schedule.next_change(&before).unwrap();
+        assert_eq!(mode, ScheduleMode::Continuous);
+        assert_eq!(change.naive_utc(), NaiveDate::from_ymd_opt(2026, 10, 25).unwrap().and_hms_opt(0, 30, 0).unwrap());
+
+        // 夏時間の日も壁時計の時刻で判定する
+        let evening = Berlin.with_ymd_and_hms(2026, 3, 29, 22, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(&evening), ScheduleMode::Motion);
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::new(EXAMPLE.parse().unwrap());
+        let at = |t: NaiveDateTime| Berlin.from_local_datetime(&t).unwrap();
+
+        assert_eq!(scheduler.update(&at(local(5, 21, 0))), Some(ScheduleMode::Off));
+        assert_eq!(scheduler.update(&at(local(5, 21, 30))), None);
+        assert_eq!(scheduler.update(&at(local(5, 22, 0))), Some(ScheduleMode::Motion));
+        assert_eq!(scheduler.current(), Some(ScheduleMode::Motion));
+        assert_eq!(scheduler.describe(&at(local(5, 22, 0))), "motion until Tue 06:00 (then off)");
+    }
+}
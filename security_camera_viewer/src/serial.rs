@@ -7,9 +7,44 @@ use crate::protocol::{
     MJPEG_HEADER_SIZE, SYNC_WORD, METRICS_SYNC_WORD, METRICS_PACKET_SIZE
 };
 use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+
+/// Connected device as seen from the host (recorded in recording sidecars)
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    pub port: String,
+    pub baud_rate: u32,
+    pub usb_vid: Option<u16>,
+    pub usb_pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl DeviceProfile {
+    /// Look up the USB details of `port_name` (left empty when the port is not a USB device)
+    fn lookup(port_name: &str, baud_rate: u32) -> Self {
+        let mut profile = DeviceProfile { port: port_name.to_string(), baud_rate, ..Default::default() };
+        let usb = serialport::available_ports().ok()
+            .and_then(|ports| ports.into_iter().find(|port| port.port_name == port_name))
+            .and_then(|port| match port.port_type {
+                SerialPortType::UsbPort(info) => Some(info),
+                _ => None,
+            });
+        if let Some(info) = usb {
+            profile.usb_vid = Some(info.vid);
+            profile.usb_pid = Some(info.pid);
+            profile.manufacturer = info.manufacturer;
+            profile.product = info.product;
+            profile.serial_number = info.serial_number;
+        }
+        profile
+    }
+}
 
 pub struct SerialConnection {
     port: Box<dyn SerialPort>,
+    profile: DeviceProfile,
 }
 
 impl SerialConnection {
@@ -27,7 +62,12 @@ impl SerialConnection {
 
         info!("Serial port opened successfully");
 
-        Ok(SerialConnection { port })
+        Ok(SerialConnection { port, profile: DeviceProfile::lookup(port_name, baud_rate) })
+    }
+
+    /// Port and USB details of the connected device
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
     }
 
     /// Auto-detect Spresense device by VID/PID
@@ -0,0 +1,142 @@
+//! スナップショットモジュール
+//!
+//! 最新フレームの元のJPEGバイト列を、再エンコードせずにそのまま保存する。
+//! 必要に応じて、デコードしたフレームをPNGでも保存する（スナップショット用オーバーレイ付き）。
+//! ファイル名は撮影時刻（`snapshot_20260102_021400_123.jpg`）。
+//! 鍵を指定した場合は `.jpg.enc` / `.png.enc` として暗号化する。
+
+use crate::crypto::{self, KeySource};
+use crate::overlay::{self, OverlayConfig};
+use chrono::{DateTime, Local};
+use image::{ImageFormat, ImageOutputFormat};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+/// 録画ディレクトリ内のスナップショット保存先
+pub const SNAPSHOT_DIR_NAME: &str = "snapshots";
+
+/// PNG保存の設定
+#[derive(Debug, Clone)]
+pub struct PngOptions<'a> {
+    /// PNGに描画するオーバーレイ（`enabled`がfalseなら描画しない）
+    pub overlay: &'a OverlayConfig,
+    pub camera_name: &'a str,
+    /// オーバーレイに表示するフレーム番号
+    pub sequence: u64,
+}
+
+/// 保存したスナップショット
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// 元のJPEG
+    pub jpeg_path: PathBuf,
+    /// デコードしたフレームのPNG
+    pub png_path: Option<PathBuf>,
+    pub captured_at: DateTime<Local>,
+}
+
+/// スナップショットを`dir`に保存する
+///
+/// # Errors
+/// - 保存先に書き込めない場合
+/// - PNGを保存する場合で、JPEGをデコードできない場合（JPEGは保存済み）
+pub fn save_snapshot(
+    dir: &Path,
+    jpeg_data: &[u8],
+    captured_at: DateTime<Local>,
+    png: Option<&PngOptions>,
+    key: Option<&KeySource>,
+) -> io::Result<Snapshot> {
+    fs::create_dir_all(dir)?;
+
+    let suffix = if key.is_some() { ".enc" } else { "" };
+    let base = format!("snapshot_{}", captured_at.format("%Y%m%d_%H%M%S_%3f"));
+
+    let jpeg_path = dir.join(format!("{}.jpg{}", base, suffix));
+    write_new(&jpeg_path, jpeg_data, key)?;
+
+    let png_path = match png {
+        Some(options) => {
+            let path = dir.join(format!("{}.png{}", base, suffix));
+            write_new(&path, &encode_png(jpeg_data, captured_at, options)?, key)?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    Ok(Snapshot { jpeg_path, png_path, captured_at })
+}
+
+/// JPEGをデコードし、オーバーレイを描画してPNGにエンコードする
+fn encode_png(jpeg_data: &[u8], captured_at: DateTime<Local>, options: &PngOptions) -> io::Result<Vec<u8>> {
+    let mut image = image::load_from_memory_with_format(jpeg_data, ImageFormat::Jpeg)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .to_rgb8();
+    if options.overlay.enabled {
+        let text = options.overlay.text(options.camera_name, captured_at, options.sequence);
+        overlay::draw_text(&mut image, options.overlay, &text);
+    }
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(png)
+}
+
+/// 既存のファイルは上書きしない（同じミリ秒に2回撮った場合など）
+fn write_new(path: &Path, data: &[u8], key: Option<&KeySource>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::options().write(true).create_new(true).open(path)?);
+    crypto::write_with_key(&mut writer, data, key)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use image::{Rgb, RgbImage};
+
+    fn jpeg() -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(64, 32, Rgb([200, 200, 200]))
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_save_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let captured_at = Local.with_ymd_and_hms(2026, 1, 2, 2, 14, 0).unwrap();
+        let overlay = OverlayConfig { enabled: true, scale: 1, ..OverlayConfig::default() };
+        let options = PngOptions { overlay: &overlay, camera_name: "Door", sequence: 1 };
+
+        let snapshot = save_snapshot(dir.path(), &jpeg(), captured_at, Some(&options), None).unwrap();
+        assert_eq!(snapshot.jpeg_path, dir.path().join("snapshot_20260102_021400_000.jpg"));
+        // JPEGは元のバイト列のまま
+        assert_eq!(fs::read(&snapshot.jpeg_path).unwrap(), jpeg());
+
+        // PNGにはオーバーレイが描画される
+        let png = image::open(snapshot.png_path.unwrap()).unwrap().to_rgb8();
+        assert_eq!(png.dimensions(), (64, 32));
+        assert!(png.pixels().any(|p| p[0] > 240));
+
+        // 同じ時刻の2回目は上書きしない
+        assert!(save_snapshot(dir.path(), &jpeg(), captured_at, None, None).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeySource::KeyFile([4; 32]);
+        let snapshot = save_snapshot(dir.path(), &jpeg(), Local::now(), None, Some(&key)).unwrap();
+
+        assert!(snapshot.jpeg_path.to_string_lossy().ends_with(".jpg.enc"));
+        assert!(snapshot.png_path.is_none());
+
+        let mut plain = Vec::new();
+        io::Read::read_to_end(&mut crypto::open_recording(&snapshot.jpeg_path, Some(&key)).unwrap(), &mut plain).unwrap();
+        assert_eq!(plain, jpeg());
+    }
+}
@@ -0,0 +1,595 @@
+//! カメラへのいたずら検知（覆い隠し・ピンぼけ・向きの変更）
+//!
+//! 動き検知とは別に、ゆっくり学習する長期間の基準画像（画素ごとの輝度の移動平均）と
+//! 現在のフレームの縮小輝度を比べて、次の3種類を判定する:
+//! - 覆い隠し（`Covered`）: 平均輝度がごく低いか、輝度ヒストグラムのエントロピーが低い
+//!   （手・布・スプレーで画面が一様になる）。基準画像も同じ状態（夜間の暗い画面など）なら判定しない
+//! - ピンぼけ（`Defocused`）: 鮮鋭度（4近傍ラプラシアンの分散）が基準の`defocus_ratio`倍を下回る
+//! - 向きの変更（`Repositioned`）: 行・列ごとの輝度の平均（投影）を基準と照合した画面全体のずれが大きいか、
+//!   明るさを合わせても基準と大きく異なる画素が画面の大部分を占める
+//!
+//! 人が横切るなどの一時的な遮りで通知しないよう、同じ状態が`confirm_seconds`続いたら
+//! いたずらイベントを開始し、正常な状態が`confirm_seconds`続いたら終了する。
+//! 基準画像は正常と判定したフレームだけで学習する（いたずら中の映像を取り込まない）。
+//! 開始・終了は`EventBus`で購読者に通知する。
+
+use crate::motion_event::EventBus;
+use chrono::{DateTime, Duration, Local};
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::mpsc::Receiver;
+
+/// これより平坦な基準画像ではピンぼけを判定しない（鮮鋭度）
+const MIN_REFERENCE_SHARPNESS: f32 = 4.0;
+/// ずれを探す範囲（画像の幅・高さに対する%）
+const MAX_SEARCH_PERCENT: f32 = 15.0;
+/// ずれの照合で、ずれなしよりこの割合以上よく一致した場合だけずれとみなす
+const SHIFT_MATCH_RATIO: f32 = 0.7;
+/// 基準画像との差がこの値を超える画素を「異なる」とする（明るさ補正後の輝度差）
+const CHANGED_PIXEL_DIFFERENCE: f32 = 40.0;
+
+/// いたずら検知の設定
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TamperConfig {
+    /// いたずら検知ON/OFF
+    pub enabled: bool,
+    /// 解析解像度の縮小率（デフォルト4、ピンぼけを見るため動き検知より細かい）
+    pub downscale: u32,
+    /// この秒数同じ状態が続いたら開始・終了する
+    pub confirm_seconds: f32,
+    /// 基準画像の学習率（1フレームごと、11fpsで約1分かけて馴染む）
+    pub learning_rate: f32,
+    /// 平均輝度がこれ未満なら覆い隠し
+    pub covered_brightness: f32,
+    /// エントロピー（ビット）がこれ未満なら覆い隠し
+    pub covered_entropy: f32,
+    /// 鮮鋭度が基準のこの倍率未満ならピンぼけ
+    pub defocus_ratio: f32,
+    /// 画面全体のずれがこの割合（%）以上なら向きの変更
+    pub shift_percent: f32,
+    /// 基準画像と異なる画素がこの割合（%）以上なら向きの変更
+    pub scene_change_percent: f32,
+}
+
+impl Default for TamperConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            downscale: 4,
+            confirm_seconds: 3.0,
+            learning_rate: 0.0015,
+            covered_brightness: 12.0,
+            covered_entropy: 2.0,
+            defocus_ratio: 0.3,
+            shift_percent: 5.0,
+            scene_change_percent: 70.0,
+        }
+    }
+}
+
+/// いたずらの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TamperKind {
+    /// レンズを覆われた・スプレーされた
+    Covered,
+    /// ピントをずらされた
+    Defocused,
+    /// 向きを変えられた
+    Repositioned,
+}
+
+impl fmt::Display for TamperKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TamperKind::Covered => "covered",
+            TamperKind::Defocused => "defocused",
+            TamperKind::Repositioned => "repositioned",
+        })
+    }
+}
+
+/// 1フレームの測定値
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TamperMeasurements {
+    /// 平均輝度（0-255）
+    pub brightness: f32,
+    /// 輝度ヒストグラムのエントロピー（ビット、0-8）
+    pub entropy: f32,
+    /// 鮮鋭度（4近傍ラプラシアンの分散）
+    pub sharpness: f32,
+    /// 基準画像の鮮鋭度
+    pub reference_sharpness: f32,
+    /// 基準画像に対する画面全体のずれ（画像の幅・高さに対する%、右・下が正）
+    pub shift: (f32, f32),
+    /// 基準画像と大きく異なる画素の割合（%）
+    pub changed_ratio: f32,
+}
+
+/// いたずらイベント
+#[derive(Debug, Clone, PartialEq)]
+pub struct TamperEvent {
+    pub id: u64,
+    pub kind: TamperKind,
+    /// いたずらの状態が始まった時刻（確定した時刻より`confirm_seconds`前）
+    pub started_at: DateTime<Local>,
+    /// 終了時刻（継続中はNone）
+    pub ended_at: Option<DateTime<Local>>,
+    /// 開始を確定したフレームの測定値
+    pub measurements: TamperMeasurements,
+}
+
+/// いたずらイベントの開始・終了の通知
+#[derive(Debug, Clone, PartialEq)]
+pub enum TamperNotification {
+    Started(TamperEvent),
+    Ended(TamperEvent),
+}
+
+impl TamperNotification {
+    pub fn event(&self) -> &TamperEvent {
+        match self {
+            TamperNotification::Started(event) | TamperNotification::Ended(event) => event,
+        }
+    }
+}
+
+/// 長期間の基準画像
+struct ReferenceImage {
+    width: u32,
+    height: u32,
+    mean: Vec<f32>,
+    /// 測定値の移動平均
+    brightness: f32,
+    entropy: f32,
+    sharpness: f32,
+}
+
+impl ReferenceImage {
+    fn new(gray: &GrayImage, brightness: f32, entropy: f32, sharpness: f32) -> Self {
+        Self {
+            width: gray.width(),
+            height: gray.height(),
+            mean: gray.as_raw().iter().map(|&v| v as f32).collect(),
+            brightness,
+            entropy,
+            sharpness,
+        }
+    }
+
+    fn learn(&mut self, gray: &GrayImage, measurements: &TamperMeasurements, learning_rate: f32) {
+        let rate = learning_rate.clamp(0.0, 1.0);
+        for (mean, &value) in self.mean.iter_mut().zip(gray.as_raw()) {
+            *mean += (value as f32 - *mean) * rate;
+        }
+        self.brightness += (measurements.brightness - self.brightness) * rate;
+        self.entropy += (measurements.entropy - self.entropy) * rate;
+        self.sharpness += (measurements.sharpness - self.sharpness) * rate;
+    }
+
+    /// 行・列ごとの平均輝度（投影）
+    fn profiles(&self) -> (Vec<f32>, Vec<f32>) {
+        profiles(self.width, self.height, |i| self.mean[i])
+    }
+}
+
+/// 平均輝度とエントロピー（ビット）
+fn brightness_and_entropy(gray: &GrayImage) -> (f32, f32) {
+    let raw = gray.as_raw();
+    if raw.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mut histogram = [0u32; 256];
+    let mut sum = 0u64;
+    for &value in raw {
+        histogram[value as usize] += 1;
+        sum += value as u64;
+    }
+    let total = raw.len() as f32;
+    let entropy = histogram.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f32 / total;
+            -p * p.log2()
+        })
+        .sum();
+    (sum as f32 / total, entropy)
+}
+
+/// 4近傍ラプラシアンの分散（画像の縁は除く）
+fn laplacian_variance(gray: &GrayImage) -> f32 {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let raw = gray.as_raw();
+    let (mut sum, mut sum_squares) = (0i64, 0i64);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let i = y * width + x;
+            let laplacian = 4 * raw[i] as i64 - raw[i - 1] as i64 - raw[i + 1] as i64
+                - raw[i - width] as i64 - raw[i + width] as i64;
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum as f64 / count;
+    (sum_squares as f64 / count - mean * mean) as f32
+}
+
+/// 列ごと・行ごとの平均輝度
+fn profiles(width: u32, height: u32, value: impl Fn(usize) -> f32) -> (Vec<f32>, Vec<f32>) {
+    let (width, height) = (width as usize, height as usize);
+    let mut columns = vec![0.0; width];
+    let mut rows = vec![0.0; height];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (x, column) in columns.iter_mut().enumerate() {
+            let v = value(y * width + x);
+            *column += v;
+            *row += v;
+        }
+    }
+    columns.iter_mut().for_each(|column| *column /= height.max(1) as f32);
+    rows.iter_mut().for_each(|row| *row /= width.max(1) as f32);
+    (columns, rows)
+}
+
+/// 投影どうしを照合し、`current`が`reference`に対してずれている画素数を返す
+///
+/// 明るさの違いは投影の平均を引いて除く。ずれなしより明らかによく一致するずれがなければ0。
+fn profile_shift(reference: &[f32], current: &[f32], max_shift: usize) -> i32 {
+    let center = |profile: &[f32]| {
+        let mean = profile.iter().sum::<f32>() / profile.len().max(1) as f32;
+        profile.iter().map(|v| v - mean).collect::<Vec<f32>>()
+    };
+    let (reference, current) = (center(reference), center(current));
+    let len = reference.len() as i32;
+    // 重なりが半分未満になるずれは調べない
+    let max_shift = (max_shift as i32).min(len / 2);
+
+    let cost = |shift: i32| {
+        let overlap = (0..len).filter(|&i| (0..len).contains(&(i - shift)));
+        let (total, count) = overlap.fold((0.0, 0), |(total, count), i| {
+            (total + (current[i as usize] - reference[(i - shift) as usize]).abs(), count + 1)
+        });
+        total / count.max(1) as f32
+    };
+    let still = cost(0);
+    let (best_shift, best_cost) = (-max_shift..=max_shift)
+        .map(|shift| (shift, cost(shift)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, still));
+    if best_cost < still * SHIFT_MATCH_RATIO {
+        best_shift
+    } else {
+        0
+    }
+}
+
+/// 縮小輝度のフレームからいたずらを検知し、イベントを開始・終了する
+pub struct TamperDetector {
+    config: TamperConfig,
+    reference: Option<ReferenceImage>,
+    /// 正常でない状態とその開始時刻
+    suspect: Option<(TamperKind, DateTime<Local>)>,
+    /// いたずらイベント中に正常に戻った時刻
+    normal_since: Option<DateTime<Local>>,
+    current: Option<TamperEvent>,
+    last_measurements: Option<TamperMeasurements>,
+    next_id: u64,
+    bus: EventBus<TamperNotification>,
+}
+
+impl TamperDetector {
+    pub fn new(config: TamperConfig) -> Self {
+        Self {
+            config,
+            reference: None,
+            suspect: None,
+            normal_since: None,
+            current: None,
+            last_measurements: None,
+            next_id: 1,
+            bus: EventBus::default(),
+        }
+    }
+
+    pub fn config(&self) -> &TamperConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: TamperConfig) {
+        if config.downscale != self.config.downscale {
+            self.reference = None;
+        }
+        self.config = config;
+    }
+
+    /// 通知を受け取るチャネルを追加
+    pub fn subscribe(&mut self) -> Receiver<TamperNotification> {
+        self.bus.subscribe()
+    }
+
+    /// 継続中のいたずらイベント
+    pub fn current(&self) -> Option<&TamperEvent> {
+        self.current.as_ref()
+    }
+
+    /// 直近フレームの測定値（基準画像ができるまではNone）
+    pub fn measurements(&self) -> Option<&TamperMeasurements> {
+        self.last_measurements.as_ref()
+    }
+
+    /// 基準画像を作り直す（カメラを意図して動かした後など）。継続中のイベントは終了する
+    pub fn reset(&mut self, now: DateTime<Local>) -> Option<TamperNotification> {
+        self.reference = None;
+        self.suspect = None;
+        self.last_measurements = None;
+        let notification = self.finish(now)?;
+        self.bus.publish(&notification);
+        Some(notification)
+    }
+
+    /// 1フレーム（`config.downscale`分の1に縮小した輝度）を反映し、イベントが開始・終了した場合は通知する
+    pub fn update(&mut self, gray: &GrayImage, now: DateTime<Local>) -> Option<TamperNotification> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let (brightness, entropy) = brightness_and_entropy(gray);
+        let sharpness = laplacian_variance(gray);
+        let reference = match &mut self.reference {
+            Some(reference) if (reference.width, reference.height) == gray.dimensions() => reference,
+            _ => {
+                // 最初のフレーム（解像度が変わった場合も）を基準にする
+                self.reference = Some(ReferenceImage::new(gray, brightness, entropy, sharpness));
+                self.suspect = None;
+                return None;
+            }
+        };
+
+        let measurements = measure(reference, gray, brightness, entropy, sharpness);
+        let kind = classify(&self.config, reference, &measurements);
+        if kind.is_none() && self.current.is_none() {
+            reference.learn(gray, &measurements, self.config.learning_rate);
+        }
+        self.last_measurements = Some(measurements);
+
+        let confirm = Duration::milliseconds((self.config.confirm_seconds.max(0.0) * 1000.0) as i64);
+        let notification = match kind {
+            Some(kind) => {
+                self.normal_since = None;
+                let since = match self.suspect {
+                    Some((suspect, since)) if suspect == kind => since,
+                    _ => {
+                        self.suspect = Some((kind, now));
+                        now
+                    }
+                };
+                if self.current.is_none() && now - since >= confirm {
+                    let event = TamperEvent { id: self.next_id, kind, started_at: since, ended_at: None, measurements };
+                    self.next_id += 1;
+                    self.current = Some(event.clone());
+                    Some(TamperNotification::Started(event))
+                } else {
+                    None
+                }
+            }
+            None => {
+                self.suspect = None;
+                let since = *self.normal_since.get_or_insert(now);
+                if now - since >= confirm {
+                    self.normal_since = None;
+                    self.finish(since)
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(notification) = &notification {
+            self.bus.publish(notification);
+        }
+        notification
+    }
+
+    /// 継続中のイベントを`ended_at`で閉じる（通知はしない）
+    fn finish(&mut self, ended_at: DateTime<Local>) -> Option<TamperNotification> {
+        let mut event = self.current.take()?;
+        event.ended_at = Some(ended_at);
+        Some(TamperNotification::Ended(event))
+    }
+}
+
+/// 基準画像と比べた測定値
+fn measure(reference: &ReferenceImage, gray: &GrayImage, brightness: f32, entropy: f32, sharpness: f32) -> TamperMeasurements {
+    let (width, height) = gray.dimensions();
+    let raw = gray.as_raw();
+
+    // 照明の変化で向きの変更にならないよう、明るさの比を合わせて比べる
+    let gain = (brightness + 1.0) / (reference.brightness + 1.0);
+    let changed = raw.iter().zip(&reference.mean)
+        .filter(|&(&value, &mean)| (value as f32 - ((mean + 1.0) * gain - 1.0)).abs() > CHANGED_PIXEL_DIFFERENCE)
+        .count();
+
+    let (reference_columns, reference_rows) = reference.profiles();
+    let (columns, rows) = profiles(width, height, |i| raw[i] as f32);
+    let search = |len: u32| (len as f32 * MAX_SEARCH_PERCENT / 100.0).round() as usize;
+    let dx = profile_shift(&reference_columns, &columns, search(width));
+    let dy = profile_shift(&reference_rows, &rows, search(height));
+
+    TamperMeasurements {
+        brightness,
+        entropy,
+        sharpness,
+        reference_sharpness: reference.sharpness,
+        shift: (dx as f32 / width.max(1) as f32 * 100.0, dy as f32 / height.max(1) as f32 * 100.0),
+        changed_ratio: changed as f32 / raw.len().max(1) as f32 * 100.0,
+    }
+}
+
+/// 測定値からいたずらの種類を判定（覆い隠し > ピンぼけ > 向きの変更の順）
+fn classify(config: &TamperConfig, reference: &ReferenceImage, measurements: &TamperMeasurements) -> Option<TamperKind> {
+    let covered = |brightness: f32, entropy: f32| brightness < config.covered_brightness || entropy < config.covered_entropy;
+    if covered(measurements.brightness, measurements.entropy) && !covered(reference.brightness, reference.entropy) {
+        return Some(TamperKind::Covered);
+    }
+    if reference.sharpness >= MIN_REFERENCE_SHARPNESS && measurements.sharpness < reference.sharpness * config.defocus_ratio {
+        return Some(TamperKind::Defocused);
+    }
+    let (dx, dy) = measurements.shift;
+    if dx.abs().max(dy.abs()) >= config.shift_percent || measurements.changed_ratio >= config.scene_change_percent {
+        return Some(TamperKind::Repositioned);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 2, 22, 0, 0).unwrap() + Duration::milliseconds(ms)
+    }
+
+    fn detector() -> TamperDetector {
+        TamperDetector::new(TamperConfig { enabled: true, ..TamperConfig::default() })
+    }
+
+    /// 8x8画素のブロックが並ぶ160x120の景色（`offset`だけ右の景色を写す）
+    fn scene(offset: u32) -> GrayImage {
+        GrayImage::from_fn(160, 120, |x, y| {
+            let (bx, by) = ((x + offset) / 8, y / 8);
+            let hash = (bx * 7919 + by * 104_729).wrapping_mul(2_654_435_761) >> 16;
+            image::Luma([40 + (hash % 180) as u8])
+        })
+    }
+
+    /// 5x5の平均を2回かけてぼかした景色
+    fn blurred() -> GrayImage {
+        let blur = |image: &GrayImage| GrayImage::from_fn(160, 120, |x, y| {
+            let (mut sum, mut count) = (0u32, 0u32);
+            for ny in y.saturating_sub(2)..(y + 3).min(120) {
+                for nx in x.saturating_sub(2)..(x + 3).min(160) {
+                    sum += image.get_pixel(nx, ny)[0] as u32;
+                    count += 1;
+                }
+            }
+            image::Luma([(sum / count) as u8])
+        });
+        blur(&blur(&scene(0)))
+    }
+
+    /// 布で覆われた画面（わずかなノイズだけ）
+    fn covered() -> GrayImage {
+        GrayImage::from_fn(160, 120, |x, y| image::Luma([30 + ((x + y) % 2) as u8]))
+    }
+
+    /// 基準を学習させてから、`frame`を100msごとに`seconds`秒間入れ、通知を集める
+    fn run(detector: &mut TamperDetector, frame: &GrayImage, start_ms: i64, seconds: i64) -> Vec<TamperNotification> {
+        (0..seconds * 10)
+            .filter_map(|i| detector.update(frame, at(start_ms + i * 100)))
+            .collect()
+    }
+
+    #[test]
+    fn test_normal_scene_is_quiet() {
+        let mut detector = detector();
+        assert!(run(&mut detector, &scene(0), 0, 10).is_empty());
+        let measurements = detector.measurements().unwrap();
+        assert!(measurements.entropy > 6.0 && measurements.sharpness > 100.0);
+        assert_eq!(measurements.shift, (0.0, 0.0));
+        assert_eq!(measurements.changed_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_covered_lens() {
+        let mut detector = detector();
+        let events = detector.subscribe();
+        run(&mut detector, &scene(0), 0, 5);
+
+        // 3秒続いたら開始（開始時刻は覆われた時刻）
+        let started = run(&mut detector, &covered(), 5000, 5);
+        assert_eq!(started.len(), 1);
+        let TamperNotification::Started(event) = &started[0] else { panic!("expected start: {:?}", started) };
+        assert_eq!((event.kind, event.started_at), (TamperKind::Covered, at(5000)));
+        assert_eq!(detector.current().map(|event| event.kind), Some(TamperKind::Covered));
+
+        // 覆いを外して3秒で終了
+        let ended = run(&mut detector, &scene(0), 10_000, 5);
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].event().ended_at, Some(at(10_000)));
+        assert!(detector.current().is_none());
+
+        // 購読者にも同じ通知が届く
+        let received: Vec<TamperNotification> = events.try_iter().collect();
+        assert_eq!(received, vec![started[0].clone(), ended[0].clone()]);
+    }
+
+    #[test]
+    fn test_brief_occlusion_is_ignored() {
+        let mut detector = detector();
+        run(&mut detector, &scene(0), 0, 5);
+        // 人が1秒横切っただけでは通知しない
+        assert!(run(&mut detector, &covered(), 5000, 1).is_empty());
+        assert!(run(&mut detector, &scene(0), 6000, 5).is_empty());
+    }
+
+    #[test]
+    fn test_defocus() {
+        let mut detector = detector();
+        run(&mut detector, &scene(0), 0, 5);
+        let notifications = run(&mut detector, &blurred(), 5000, 4);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].event().kind, TamperKind::Defocused);
+        let measurements = notifications[0].event().measurements;
+        assert!(measurements.sharpness < measurements.reference_sharpness * 0.3);
+    }
+
+    #[test]
+    fn test_repositioned() {
+        let mut detector = detector();
+        run(&mut detector, &scene(0), 0, 5);
+        // 画面の10%（16画素）左へ振られた（景色は右へずれて写る）
+        let notifications = run(&mut detector, &scene(16), 5000, 4);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].event().kind, TamperKind::Repositioned);
+        assert_eq!(detector.measurements().unwrap().shift, (-10.0, 0.0));
+
+        // 向きを直さずに基準を作り直せば、イベントは終わり新しい向きが基準になる
+        assert!(matches!(detector.reset(at(9000)), Some(TamperNotification::Ended(_))));
+        assert!(run(&mut detector, &scene(16), 9000, 5).is_empty());
+    }
+
+    #[test]
+    fn test_lighting_change_is_not_tampering() {
+        let mut detector = detector();
+        run(&mut detector, &scene(0), 0, 5);
+
+        // 照明で全体が1.2倍明るくなっても、向きの変更やピンぼけにはならない
+        let scene = scene(0);
+        let brighter = GrayImage::from_fn(160, 120, |x, y| image::Luma([(scene.get_pixel(x, y)[0] as f32 * 1.2) as u8]));
+        assert!(run(&mut detector, &brighter, 5000, 5).is_empty());
+        assert!(detector.measurements().unwrap().changed_ratio < 5.0);
+    }
+
+    #[test]
+    fn test_dark_reference_is_not_covered() {
+        let mut detector = detector();
+        // 最初から暗く平坦な画面（夜間など）は覆い隠しとみなさない
+        assert!(run(&mut detector, &covered(), 0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_tamper_config_deserializes() {
+        let config: TamperConfig = serde_json::from_str(r#"{"enabled":true,"confirm_seconds":5.0}"#).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.confirm_seconds, 5.0);
+        assert_eq!(config.downscale, 4);
+        assert_eq!(serde_json::to_string(&TamperKind::Repositioned).unwrap(), "\"repositioned\"");
+    }
+}
@@ -0,0 +1,231 @@
+//! タイムラプス録画モジュール
+//!
+//! 受信したフレームからN秒ごとに1フレームだけ残し、指定した再生フレームレートの
+//! MJPEG/MP4として保存する（録画パイプラインは`recording`モジュールと共通）。
+//! 動きのあるフレームだけを残す設定と、1日のうちの時間帯（例: 07:00-19:00）の指定ができる。
+//! 時間帯は終了が開始より前なら日付をまたぐ（例: 22:00-06:00、`schedule::DailyWindow`）。
+
+use crate::crypto::KeySource;
+use crate::mp4_recorder::Mp4EncoderConfig;
+use crate::recording::{RecordingFormat, RecordingWriter};
+use crate::schedule::DailyWindow;
+use chrono::{DateTime, Duration, Local};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// タイムラプス設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelapseConfig {
+    /// 残すフレームの間隔（秒）
+    pub interval_secs: u32,
+    /// 再生フレームレート
+    pub playback_fps: u32,
+    /// 保存形式
+    pub format: RecordingFormat,
+    /// 動きのあるフレームだけ残す
+    pub motion_only: bool,
+    /// 撮影する時間帯（Noneなら終日）
+    pub window: Option<DailyWindow>,
+}
+
+impl Default for TimelapseConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 10,
+            playback_fps: 30,
+            format: RecordingFormat::default(),
+            motion_only: false,
+            window: None,
+        }
+    }
+}
+
+/// 残すフレームを選ぶ
+///
+/// 間隔は最初に残したフレームの時刻を基準に刻むため、
+/// フレームの到着タイミングのずれが長時間の撮影で積み重ならない。
+#[derive(Debug, Clone)]
+pub struct TimelapseSampler {
+    config: TimelapseConfig,
+    /// 次にフレームを残せる時刻
+    next_due: Option<DateTime<Local>>,
+}
+
+impl TimelapseSampler {
+    pub fn new(config: TimelapseConfig) -> Self {
+        Self { config, next_due: None }
+    }
+
+    /// フレームを残すか判定する（残す場合は次の時刻へ進む）
+    pub fn should_keep(&mut self, now: DateTime<Local>, motion: bool) -> bool {
+        if self.config.window.is_some_and(|window| !window.contains(now.time())) {
+            return false;
+        }
+        if self.config.motion_only && !motion {
+            return false;
+        }
+        if self.next_due.is_some_and(|due| now < due) {
+            return false;
+        }
+
+        let interval = Duration::seconds(self.config.interval_secs.max(1) as i64);
+        self.next_due = Some(match self.next_due {
+            // 時間帯外や動きなしで飛ばした間隔も数えて刻みを揃える
+            Some(due) => {
+                let missed = (now - due).num_milliseconds() / interval.num_milliseconds();
+                due + interval * (missed as i32 + 1)
+            }
+            None => now + interval,
+        });
+        true
+    }
+}
+
+/// タイムラプス録画
+pub struct TimelapseRecorder {
+    sampler: TimelapseSampler,
+    writer: RecordingWriter,
+    config: TimelapseConfig,
+    path: PathBuf,
+    frame_count: u64,
+}
+
+impl TimelapseRecorder {
+    /// `dir`にタイムラプスファイル（`timelapse_YYYYMMDD_HHMMSS.<形式>`）を作成する
+    ///
+    /// # Errors
+    /// - 出力ファイルを作成できない場合
+    /// - MP4でffmpegを起動できない場合
+    pub fn start(
+        dir: &Path,
+        config: TimelapseConfig,
+        mp4_config: &Mp4EncoderConfig,
+        key: Option<KeySource>,
+    ) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let filename = format!("timelapse_{}.{}", Local::now().format("%Y%m%d_%H%M%S"), config.format.extension());
+        Self::create(&dir.join(filename), config, mp4_config, key)
+    }
+
+    /// 出力ファイルを指定してタイムラプスを作成する
+    ///
+    /// # Errors
+    /// - 出力ファイルを作成できない場合
+    /// - MP4でffmpegを起動できない場合
+    pub fn create(
+        path: &Path,
+        config: TimelapseConfig,
+        mp4_config: &Mp4EncoderConfig,
+        key: Option<KeySource>,
+    ) -> io::Result<Self> {
+        let writer = RecordingWriter::create(path, config.format, config.playback_fps, mp4_config, key)?;
+        Ok(Self {
+            sampler: TimelapseSampler::new(config.clone()),
+            writer,
+            config,
+            path: path.to_path_buf(),
+            frame_count: 0,
+        })
+    }
+
+    pub fn config(&self) -> &TimelapseConfig {
+        &self.config
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 書き込んだフレーム数
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// このフレームを残すか判定する（trueなら`write_frame`で書き込む）
+    pub fn sample(&mut self, now: DateTime<Local>, motion: bool) -> bool {
+        self.sampler.should_keep(now, motion)
+    }
+
+    /// 残すと判定したフレームを書き込む
+    pub fn write_frame(&mut self, jpeg_data: &[u8], captured_at: DateTime<Local>) -> io::Result<()> {
+        self.writer.write_frame_at(jpeg_data, captured_at)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// タイムラプスを閉じ、ffmpegの再起動で追加されたセグメントを返す
+    pub fn finish(self) -> io::Result<Vec<PathBuf>> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mjpeg;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32, ms: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 2, h, m, s).unwrap() + Duration::milliseconds(ms)
+    }
+
+    #[test]
+    fn test_sampler_interval() {
+        let mut sampler = TimelapseSampler::new(TimelapseConfig { interval_secs: 10, ..TimelapseConfig::default() });
+
+        // 11fpsのフレームから10秒ごとに1枚
+        let kept: Vec<i64> = (0..11 * 35)
+            .map(|i| i * 1000 / 11)
+            .filter(|&ms| sampler.should_keep(at(12, 0, 0, ms), false))
+            .collect();
+        assert_eq!(kept.len(), 4);
+        // 刻みは最初のフレームが基準（到着のずれが積み重ならない）
+        for (i, ms) in kept.iter().enumerate() {
+            assert!((i as i64 * 10_000..i as i64 * 10_000 + 100).contains(ms), "{:?}", kept);
+        }
+    }
+
+    #[test]
+    fn test_sampler_motion_and_window() {
+        let config = TimelapseConfig {
+            interval_secs: 60,
+            motion_only: true,
+            window: Some("08:00-09:00".parse().unwrap()),
+            ..TimelapseConfig::default()
+        };
+        let mut sampler = TimelapseSampler::new(config);
+
+        assert!(!sampler.should_keep(at(7, 59, 0, 0), true));
+        assert!(!sampler.should_keep(at(8, 0, 0, 0), false));
+        assert!(sampler.should_keep(at(8, 0, 5, 0), true));
+        assert!(!sampler.should_keep(at(8, 0, 30, 0), true));
+        // 動きのない間に過ぎた間隔は飛ばす
+        assert!(sampler.should_keep(at(8, 5, 10, 0), true));
+        assert!(!sampler.should_keep(at(8, 6, 0, 0), true));
+        assert!(sampler.should_keep(at(8, 6, 5, 0), true));
+        assert!(!sampler.should_keep(at(9, 0, 0, 0), true));
+    }
+
+    #[test]
+    fn test_mjpeg_timelapse() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TimelapseConfig { interval_secs: 5, format: RecordingFormat::Mjpeg, ..TimelapseConfig::default() };
+        let mut recorder = TimelapseRecorder::start(dir.path(), config, &Mp4EncoderConfig::default(), None).unwrap();
+        assert!(recorder.path().file_name().unwrap().to_string_lossy().starts_with("timelapse_"));
+
+        let frame = [0xFF, 0xD8, 0x00, 0xFF, 0xD9];
+        for second in 0..30 {
+            let now = at(12, 0, second, 0);
+            if recorder.sample(now, false) {
+                recorder.write_frame(&frame, now).unwrap();
+            }
+        }
+        assert_eq!(recorder.frame_count(), 6);
+
+        let path = recorder.path().to_path_buf();
+        assert!(recorder.finish().unwrap().is_empty());
+        assert_eq!(mjpeg::scan_file(&path).unwrap().frames.len(), 6);
+        let index = mjpeg::read_index(&mjpeg::index_path(&path)).unwrap();
+        assert_eq!(index[1].captured_at, at(12, 0, 5, 0));
+    }
+}